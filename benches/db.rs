@@ -0,0 +1,236 @@
+//! Bridge DB benchmarks against the in-memory mock backend, so that only the
+//! bridge's own bookkeeping and storage are measured.
+//!
+//!     cargo bench --features bench -- --save-baseline main
+//!     cargo bench --features bench -- --baseline main
+//!
+//! The second run reports each benchmark's change against the saved
+//! baseline, compare before accepting storage changes. The withdrawal
+//! benchmarks run once on a single thread and once on the default rayon
+//! pool, for the speedup of validating withdrawals in parallel.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use drivechain_cpp::{
+    Drivechain, FlushPolicy, MockBackend, Output, StorageBackendKind, Withdrawal,
+};
+use rayon::ThreadPool;
+use std::time::{Duration, Instant};
+
+const DEPOSITS: usize = 10_000;
+const WITHDRAWALS: usize = 1_000;
+const FLUSH_WITHDRAWALS: usize = 100;
+const BLOCK_WITHDRAWALS: usize = 5_000;
+
+fn backends() -> Vec<(&'static str, StorageBackendKind)> {
+    let mut backends = vec![
+        ("sled", StorageBackendKind::Sled),
+        ("memory", StorageBackendKind::Memory),
+    ];
+    if cfg!(feature = "rocksdb") {
+        backends.push(("rocksdb", StorageBackendKind::RocksDb));
+    }
+    backends
+}
+
+fn open(name: &str, storage_backend: StorageBackendKind) -> (Box<Drivechain>, MockBackend) {
+    let db_path =
+        std::env::temp_dir().join(format!("drivechain-bench-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&db_path);
+    let config = drivechain_cpp::Config {
+        db_path: db_path.to_string_lossy().into_owned(),
+        storage_backend,
+        // Only explicit flushes, so background flushes don't skew results.
+        flush_policy: FlushPolicy {
+            interval_secs: 0,
+            dirty_threshold: 0,
+        },
+        ..drivechain_cpp::default_config()
+    };
+    let mock = MockBackend::new(config.this_sidechain);
+    let drivechain = drivechain_cpp::new_drivechain_with_mock(&config, mock.clone())
+        .expect("opening the bridge DB failed");
+    (drivechain, mock)
+}
+
+// One thread for the serial baseline, and rayon's default of one per core.
+fn pools() -> Vec<(&'static str, ThreadPool)> {
+    let pool = |threads| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("building the thread pool failed")
+    };
+    vec![("serial", pool(1)), ("parallel", pool(0))]
+}
+
+// Deposits made on the mock mainchain, ready to be connected. Addresses are
+// unique per call so repeated rounds don't collide.
+fn deposits(mock: &MockBackend, round: u64, n: usize, amount: u64) -> Vec<Output> {
+    (0..n)
+        .map(|i| {
+            let output = Output {
+                address: format!("address{round}-{i}"),
+                amount,
+            };
+            mock.deposit(&output.address, output.amount);
+            output
+        })
+        .collect()
+}
+
+fn withdrawals(round: u64, n: usize) -> Vec<Withdrawal> {
+    (0..n)
+        .map(|i| {
+            let mut outpoint = [0u8; 36];
+            outpoint[..8].copy_from_slice(&round.to_le_bytes());
+            outpoint[8..16].copy_from_slice(&(i as u64).to_le_bytes());
+            Withdrawal {
+                outpoint: hex::encode(outpoint),
+                main_address: hex::encode([0x11; 20]),
+                main_fee: 1_000,
+                amount: 10_000,
+            }
+        })
+        .collect()
+}
+
+// Connects a block of deposits funding n withdrawals, then one with them.
+// Returns the withdrawn outpoints.
+fn connect_withdrawals(
+    drivechain: &mut Drivechain,
+    mock: &MockBackend,
+    round: u64,
+    n: usize,
+) -> Vec<String> {
+    let deposits = deposits(mock, round, 1, 11_000 * n as u64);
+    assert!(drivechain
+        .connect_block(deposits, Vec::new(), Vec::new(), false)
+        .unwrap());
+    let withdrawals = withdrawals(round, n);
+    let outpoints = withdrawals
+        .iter()
+        .map(|withdrawal| withdrawal.outpoint.clone())
+        .collect();
+    assert!(drivechain
+        .connect_block(Vec::new(), withdrawals, Vec::new(), false)
+        .unwrap());
+    outpoints
+}
+
+fn connect_block(c: &mut Criterion) {
+    let mut group = c.benchmark_group("connect_block_10k_deposits");
+    group.sample_size(10);
+    for (name, storage_backend) in backends() {
+        let (mut drivechain, mock) = open(name, storage_backend);
+        let mut round = 0;
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    round += 1;
+                    let deposits = deposits(&mock, round, DEPOSITS, 1_000);
+                    let start = Instant::now();
+                    assert!(drivechain
+                        .connect_block(deposits, Vec::new(), Vec::new(), false)
+                        .unwrap());
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+fn validate_withdrawals(c: &mut Criterion) {
+    let mut group = c.benchmark_group("validate_block_inputs_5k_withdrawals");
+    let withdrawals = withdrawals(0, BLOCK_WITHDRAWALS);
+    for (name, pool) in pools() {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                pool.install(|| {
+                    drivechain_cpp::validate_block_inputs(&[], &withdrawals, &[]).unwrap()
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+fn connect_withdrawal_block(c: &mut Criterion) {
+    let mut group = c.benchmark_group("connect_block_5k_withdrawals");
+    group.sample_size(10);
+    for (name, pool) in pools() {
+        let (mut drivechain, mock) = open(name, StorageBackendKind::Memory);
+        let mut round = 0;
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    round += 1;
+                    let deposits = deposits(&mock, round, 1, 11_000 * BLOCK_WITHDRAWALS as u64);
+                    assert!(drivechain
+                        .connect_block(deposits, Vec::new(), Vec::new(), false)
+                        .unwrap());
+                    let withdrawals = withdrawals(round, BLOCK_WITHDRAWALS);
+                    let start = Instant::now();
+                    assert!(pool.install(|| drivechain
+                        .connect_block(Vec::new(), withdrawals, Vec::new(), false)
+                        .unwrap()));
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+fn is_outpoint_spent(c: &mut Criterion) {
+    let mut group = c.benchmark_group("is_outpoint_spent");
+    for (name, storage_backend) in backends() {
+        let (mut drivechain, mock) = open(name, storage_backend);
+        let outpoints = connect_withdrawals(&mut drivechain, &mock, 0, WITHDRAWALS);
+        let mut next = 0;
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                next = (next + 1) % outpoints.len();
+                drivechain.is_outpoint_spent(&outpoints[next]).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn flush(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flush_100_withdrawals");
+    group.sample_size(20);
+    for (name, storage_backend) in backends() {
+        let (mut drivechain, mock) = open(name, storage_backend);
+        let mut round = 0;
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                for _ in 0..iters {
+                    round += 1;
+                    connect_withdrawals(&mut drivechain, &mock, round, FLUSH_WITHDRAWALS);
+                    let start = Instant::now();
+                    drivechain.flush().unwrap();
+                    elapsed += start.elapsed();
+                }
+                elapsed
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    connect_block,
+    validate_withdrawals,
+    connect_withdrawal_block,
+    is_outpoint_spent,
+    flush
+);
+criterion_main!(benches);
@@ -1,5 +1,48 @@
 // build.rs
 
+use std::process::Command;
+
 fn main() {
     cxx_build::bridge("src/bridge.rs").compile("drivechain-cpp");
+    library_info();
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/drivechain.proto").unwrap();
+    #[cfg(feature = "capi")]
+    cbindgen::Builder::new()
+        .with_crate(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+        .with_config(cbindgen::Config::from_file("cbindgen.toml").unwrap())
+        .generate()
+        .unwrap()
+        .write_to_file("include/drivechain.h");
+}
+
+// Commit this crate is built from and the drivechain crate revision it
+// depends on, for get_library_info. Empty when they can't be found, a
+// build from a source tarball has no git checkout.
+fn library_info() {
+    let git_commit = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    println!(
+        "cargo:rustc-env=DRIVECHAIN_CPP_GIT_COMMIT={}",
+        git_commit.trim()
+    );
+    let manifest = std::fs::read_to_string("Cargo.toml").unwrap_or_default();
+    let drivechain_rev = manifest
+        .lines()
+        .find(|line| line.starts_with("drivechain = "))
+        .and_then(|line| line.split("rev = \"").nth(1))
+        .and_then(|rev| rev.split('"').next())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=DRIVECHAIN_CPP_DRIVECHAIN_REV={drivechain_rev}");
+    // A missing path would rerun the build script on every build.
+    for path in ["Cargo.toml", ".git/HEAD", ".git/refs/heads"] {
+        if std::path::Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
 }
@@ -0,0 +1,50 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use drivechain_cpp::{Output, Refund, Withdrawal};
+use libfuzzer_sys::fuzz_target;
+
+// Everything connect_block and disconnect_block decode from the C++ side has
+// to be rejected with an error, a panic would unwind across the FFI.
+#[derive(Arbitrary, Debug)]
+struct Input {
+    deposits: Vec<(String, u64)>,
+    withdrawals: Vec<(String, String, u64, u64)>,
+    refunds: Vec<(String, u64)>,
+}
+
+fuzz_target!(|input: Input| {
+    let deposits: Vec<Output> = input
+        .deposits
+        .into_iter()
+        .map(|(address, amount)| Output { address, amount })
+        .collect();
+    let withdrawals: Vec<Withdrawal> = input
+        .withdrawals
+        .into_iter()
+        .map(|(outpoint, main_address, main_fee, amount)| Withdrawal {
+            outpoint,
+            main_address,
+            main_fee,
+            amount,
+        })
+        .collect();
+    let refunds: Vec<Refund> = input
+        .refunds
+        .into_iter()
+        .map(|(outpoint, amount)| Refund { outpoint, amount })
+        .collect();
+
+    for withdrawal in &withdrawals {
+        let _ = drivechain_cpp::parse_withdrawal_dest(&withdrawal.main_address);
+    }
+    let _ = drivechain_cpp::validate_block_inputs(&deposits, &withdrawals, &refunds);
+    let withdrawal_outpoints: Vec<String> =
+        withdrawals.iter().map(|w| w.outpoint.clone()).collect();
+    let refund_outpoints: Vec<String> = refunds.iter().map(|r| r.outpoint.clone()).collect();
+    let _ = drivechain_cpp::validate_disconnect_inputs(
+        &deposits,
+        &withdrawal_outpoints,
+        &refund_outpoints,
+    );
+});
@@ -0,0 +1,164 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use drivechain_cpp::{MockBackend, MockState, Output, Refund, Withdrawal};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    /// Make a mainchain deposit to one of a few addresses.
+    Deposit {
+        address: u8,
+        amount: u32,
+    },
+    /// Connect a block spending a selection of what is available.
+    Connect {
+        deposits: Vec<u8>,
+        withdrawals: Vec<(u32, u16)>,
+        refunds: Vec<u8>,
+    },
+    /// Disconnect the last connected block.
+    Disconnect,
+    Flush,
+}
+
+struct ConnectedBlock {
+    deposits: Vec<Output>,
+    withdrawals: Vec<String>,
+    refunds: Vec<String>,
+    state_before: MockState,
+}
+
+// Withdrawal outpoints are numbered so every one is unique within a run.
+fn outpoint(n: u64) -> String {
+    let mut bytes = [0u8; 36];
+    bytes[..8].copy_from_slice(&n.to_le_bytes());
+    hex::encode(bytes)
+}
+
+fn check_invariants(state: &MockState) {
+    let deposited: u64 = state
+        .connected_deposits
+        .iter()
+        .map(|((_, amount), count)| amount * u64::from(*count))
+        .sum();
+    let withdrawn: u64 = state.withdrawals.values().sum();
+    assert_eq!(state.escrow + withdrawn, deposited);
+    for outpoint in state.withdrawals.keys() {
+        assert!(!state.refunded.contains_key(outpoint));
+    }
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let db_path = std::env::temp_dir().join(format!("drivechain-fuzz-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&db_path);
+    let config = drivechain_cpp::Config {
+        db_path: db_path.to_string_lossy().into_owned(),
+        ..drivechain_cpp::default_config()
+    };
+    let mock = MockBackend::new(config.this_sidechain);
+    let mut drivechain = drivechain_cpp::new_drivechain_with_mock(&config, mock.clone())
+        .expect("opening the bridge DB failed");
+
+    let mut next_outpoint = 0;
+    let mut blocks: Vec<ConnectedBlock> = Vec::new();
+    for op in ops {
+        match op {
+            Op::Deposit { address, amount } => {
+                let address = format!("address{}", address % 4);
+                mock.deposit(&address, u64::from(amount));
+                // Disconnecting doesn't undo mainchain deposits made since.
+                for block in &mut blocks {
+                    *block
+                        .state_before
+                        .pending_deposits
+                        .entry((address.clone(), u64::from(amount)))
+                        .or_default() += 1;
+                }
+            }
+            Op::Connect {
+                deposits,
+                withdrawals,
+                refunds,
+            } => {
+                let mut available = drivechain.get_deposit_outputs().unwrap();
+                let deposits: Vec<Output> = deposits
+                    .iter()
+                    .filter(|_| !available.is_empty())
+                    .map(|index| available.swap_remove(*index as usize % available.len()))
+                    .collect();
+                let withdrawals: Vec<Withdrawal> = withdrawals
+                    .iter()
+                    .map(|(amount, fee)| {
+                        next_outpoint += 1;
+                        // connect_block fails on a fee that isn't below the
+                        // amount.
+                        Withdrawal {
+                            outpoint: outpoint(next_outpoint),
+                            main_address: hex::encode([0x11; 20]),
+                            main_fee: u64::from(*fee),
+                            amount: u64::from(*fee) + u64::from(*amount) + 1,
+                        }
+                    })
+                    .collect();
+                let mut unspent: Vec<(Vec<u8>, u64)> =
+                    mock.state().withdrawals.into_iter().collect();
+                let refunds: Vec<Refund> = refunds
+                    .iter()
+                    .filter(|_| !unspent.is_empty())
+                    .map(|index| {
+                        let (outpoint, amount) =
+                            unspent.swap_remove(*index as usize % unspent.len());
+                        Refund {
+                            outpoint: hex::encode(outpoint),
+                            amount,
+                        }
+                    })
+                    .collect();
+
+                let state_before = mock.state();
+                let checked = drivechain
+                    .connect_block(deposits.clone(), withdrawals.clone(), refunds.clone(), true)
+                    .unwrap();
+                assert_eq!(mock.state(), state_before, "just_check changed state");
+                let connected = drivechain
+                    .connect_block(
+                        deposits.clone(),
+                        withdrawals.clone(),
+                        refunds.clone(),
+                        false,
+                    )
+                    .unwrap();
+                assert_eq!(checked, connected);
+                if connected {
+                    blocks.push(ConnectedBlock {
+                        deposits,
+                        withdrawals: withdrawals.into_iter().map(|w| w.outpoint).collect(),
+                        refunds: refunds.into_iter().map(|r| r.outpoint).collect(),
+                        state_before,
+                    });
+                } else {
+                    assert_eq!(mock.state(), state_before, "failed connect changed state");
+                }
+            }
+            Op::Disconnect => {
+                let Some(block) = blocks.pop() else {
+                    continue;
+                };
+                let disconnected = drivechain
+                    .disconnect_block(block.deposits, block.withdrawals, block.refunds, false)
+                    .unwrap();
+                assert!(disconnected, "disconnecting the last block failed");
+                assert_eq!(
+                    mock.state(),
+                    block.state_before,
+                    "connect then disconnect is not the identity"
+                );
+            }
+            Op::Flush => {
+                drivechain.flush().unwrap();
+            }
+        }
+        check_invariants(&mock.state());
+    }
+});
@@ -0,0 +1,129 @@
+use crate::bridge::ffi;
+use crate::error::Error;
+
+/// 21 million BTC in satoshis.
+pub const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
+const SATS_PER_BTC: u64 = 100_000_000;
+const BTC_DECIMALS: usize = 8;
+
+pub fn check_amount(what: &'static str, amount: u64) -> Result<u64, Error> {
+    if amount > MAX_MONEY {
+        return Err(Error::AmountOutOfRange { what, amount });
+    }
+    Ok(amount)
+}
+
+/// Sum amounts, rejecting any single amount above MAX_MONEY and any total
+/// that overflows or exceeds MAX_MONEY.
+pub fn checked_total(
+    what: &'static str,
+    amounts: impl IntoIterator<Item = u64>,
+) -> Result<u64, Error> {
+    let mut total: u64 = 0;
+    for amount in amounts {
+        let amount = check_amount(what, amount)?;
+        total = total
+            .checked_add(amount)
+            .ok_or(Error::AmountOverflow { what })?;
+    }
+    if total > MAX_MONEY {
+        return Err(Error::AmountOverflow { what });
+    }
+    Ok(total)
+}
+
+/// A bundle pays a withdrawal's amount to its destination and its main_fee
+/// to mainchain miners. A fee that isn't below the amount pays more to
+/// miners than to the payee, and rules out zero amount withdrawals too.
+pub fn check_withdrawal_fee(amount: u64, main_fee: u64) -> Result<(), Error> {
+    if main_fee >= amount {
+        return Err(Error::FeeNotBelowAmount { amount, main_fee });
+    }
+    Ok(())
+}
+
+pub fn amount_from_sats(sats: u64) -> Result<ffi::Amount, Error> {
+    Ok(ffi::Amount {
+        sats: check_amount("amount", sats)?,
+    })
+}
+
+/// Plain decimal BTC like "0.001" or "21", at most 8 decimals. Signs,
+/// exponents and thousands separators are refused rather than guessed at.
+pub fn amount_from_btc_str(btc: &str) -> Result<ffi::Amount, Error> {
+    let invalid = |reason| Error::InvalidAmount {
+        amount: btc.into(),
+        reason,
+    };
+    let (whole, fraction) = btc.split_once('.').unwrap_or((btc, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid("no digits"));
+    }
+    if !whole
+        .bytes()
+        .chain(fraction.bytes())
+        .all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid("only digits and one decimal point are allowed"));
+    }
+    if fraction.len() > BTC_DECIMALS {
+        return Err(invalid("more than 8 decimals"));
+    }
+    let out_of_range = |_| invalid("above 21 million BTC");
+    let whole = match whole {
+        "" => 0,
+        whole => whole.parse::<u64>().map_err(out_of_range)?,
+    };
+    let fraction = match fraction {
+        "" => 0,
+        fraction => {
+            let padded = format!("{fraction:0<BTC_DECIMALS$}");
+            padded.parse::<u64>().map_err(out_of_range)?
+        }
+    };
+    let sats = whole
+        .checked_mul(SATS_PER_BTC)
+        .and_then(|sats| sats.checked_add(fraction))
+        .filter(|sats| *sats <= MAX_MONEY)
+        .ok_or_else(|| invalid("above 21 million BTC"))?;
+    Ok(ffi::Amount { sats })
+}
+
+/// Always with 8 decimals, the way bitcoind formats amounts.
+pub fn amount_to_btc_str(amount: ffi::Amount) -> String {
+    format!(
+        "{}.{:0BTC_DECIMALS$}",
+        amount.sats / SATS_PER_BTC,
+        amount.sats % SATS_PER_BTC
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fee_equal_to_amount_is_rejected() {
+        assert!(matches!(
+            check_withdrawal_fee(10_000, 10_000),
+            Err(Error::FeeNotBelowAmount {
+                amount: 10_000,
+                main_fee: 10_000
+            })
+        ));
+    }
+
+    #[test]
+    fn fee_just_below_amount_is_accepted() {
+        assert!(check_withdrawal_fee(10_000, 9_999).is_ok());
+    }
+
+    #[test]
+    fn zero_amount_is_rejected() {
+        assert!(matches!(
+            check_withdrawal_fee(0, 0),
+            Err(Error::FeeNotBelowAmount { .. })
+        ));
+    }
+}
@@ -0,0 +1,101 @@
+use crate::error::Error;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::runtime::{Handle, Runtime, RuntimeFlavor};
+use tokio::task::JoinSet;
+
+// The async counterpart of MainClient's transport, for sending several
+// requests to the mainchain node at once. The rest of the bridge is
+// synchronous, it gets results through block_on. Failover, the rate limit
+// and disconnect tracking stay with MainClient, which falls back to its own
+// transport when a request here fails.
+#[derive(Clone)]
+pub struct AsyncMainClient {
+    url: String,
+    auth: String,
+    http: reqwest::Client,
+    timeout: Option<Duration>,
+}
+
+static RUNTIME: OnceLock<Result<Runtime, String>> = OnceLock::new();
+// Shared by every AsyncMainClient, for one connection pool.
+static HTTP: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// Run future to completion from synchronous code. Runs on the runtime
+/// the caller is already on, as the gRPC server's handlers are, and on a
+/// runtime started on first use otherwise. A single threaded runtime can't
+/// be blocked on from inside, that fails.
+pub fn block_on<F: Future>(future: F) -> Result<F::Output, Error> {
+    if let Ok(handle) = Handle::try_current() {
+        if handle.runtime_flavor() != RuntimeFlavor::MultiThread {
+            return Err(Error::Transport {
+                message: "can't block on a current thread runtime".into(),
+            });
+        }
+        return Ok(tokio::task::block_in_place(|| handle.block_on(future)));
+    }
+    let runtime = RUNTIME
+        .get_or_init(|| Runtime::new().map_err(|err| err.to_string()))
+        .as_ref()
+        .map_err(|message| Error::Transport {
+            message: format!("failed to start the RPC runtime: {message}"),
+        })?;
+    Ok(runtime.block_on(future))
+}
+
+fn transport(err: reqwest::Error) -> Error {
+    Error::Transport {
+        message: err.to_string(),
+    }
+}
+
+impl AsyncMainClient {
+    pub fn new(endpoint: &str, auth: String, timeout: Option<Duration>) -> Self {
+        Self {
+            url: format!("http://{endpoint}"),
+            auth,
+            http: HTTP.get_or_init(reqwest::Client::new).clone(),
+            timeout,
+        }
+    }
+
+    /// Send one JSON-RPC request or batch, the response is returned
+    /// whatever the HTTP status, bitcoind reports RPC errors in the body.
+    pub async fn post<T: DeserializeOwned>(&self, body: Value) -> Result<T, Error> {
+        let mut request = self
+            .http
+            .post(&self.url)
+            .header("Authorization", &self.auth)
+            .json(&body);
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        let response = request.send().await.map_err(transport)?;
+        response.json().await.map_err(transport)
+    }
+
+    /// post every body at once, results are returned in the order of
+    /// bodies.
+    pub async fn post_all<T>(&self, bodies: Vec<Value>) -> Result<Vec<T>, Error>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let mut requests = JoinSet::new();
+        for (index, body) in bodies.into_iter().enumerate() {
+            let client = self.clone();
+            requests.spawn(async move { (index, client.post::<T>(body).await) });
+        }
+        let mut results = Vec::with_capacity(requests.len());
+        while let Some(joined) = requests.join_next().await {
+            let (index, result) = joined.map_err(|err| Error::Transport {
+                message: err.to_string(),
+            })?;
+            results.push((index, result?));
+        }
+        results.sort_by_key(|(index, _)| *index);
+        Ok(results.into_iter().map(|(_, result)| result).collect())
+    }
+}
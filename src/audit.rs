@@ -0,0 +1,38 @@
+use crate::bridge::ffi;
+
+const CSV_HEADER: &str = "id,at,operation,main_block_hash,detail";
+
+fn operation_name(operation: ffi::AuditOperation) -> &'static str {
+    match operation {
+        ffi::AuditOperation::ConnectBlock => "connect_block",
+        ffi::AuditOperation::DisconnectBlock => "disconnect_block",
+        ffi::AuditOperation::BundleBroadcast => "bundle_broadcast",
+        ffi::AuditOperation::Flush => "flush",
+        _ => "unknown",
+    }
+}
+
+// RFC 4180 quoting, details hold error messages with any character in them.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub(crate) fn audit_log_to_csv(entries: &[ffi::AuditEntry]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    csv.push_str("\r\n");
+    for entry in entries {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\r\n",
+            entry.id,
+            entry.at,
+            operation_name(entry.operation),
+            csv_field(&entry.main_block_hash),
+            csv_field(&entry.detail),
+        ));
+    }
+    csv
+}
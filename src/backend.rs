@@ -0,0 +1,319 @@
+use crate::bridge::ffi::MainBlockChainStatus;
+use crate::client::MainClient;
+use crate::db::BmmOutcome;
+use crate::error::Error;
+use crate::spv::Spv;
+use bitcoin::hash_types::{BlockHash, TxMerkleNode, Txid};
+use bitcoin::{Address, Amount};
+use drivechain as drive;
+use miette::{IntoDiagnostic as _, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+pub type Withdrawals = HashMap<Vec<u8>, drive::Withdrawal>;
+pub type Refunds = HashMap<Vec<u8>, u64>;
+
+// Where the bridge learns about the mainchain, selected by
+// Config::mainchain_backend.
+pub trait MainchainBackend: Send + Sync {
+    fn get_mainchain_tip(&self) -> Result<BlockHash>;
+    /// All zeros for the genesis block.
+    fn get_prev_main_block_hash(&self, main_block_hash: &BlockHash) -> Result<BlockHash>;
+    fn get_block_count(&self) -> Result<u64>;
+    fn get_main_block_chain_status(
+        &self,
+        main_block_hash: &BlockHash,
+    ) -> Result<MainBlockChainStatus>;
+
+    fn is_main_block_connected(&self, main_block_hash: &BlockHash) -> Result<bool> {
+        Ok(self.get_main_block_chain_status(main_block_hash)? == MainBlockChainStatus::ActiveChain)
+    }
+
+    /// is_main_block_connected for each of main_block_hashes, one at a time
+    /// unless the backend can do better.
+    fn are_main_blocks_connected(&self, main_block_hashes: &[BlockHash]) -> Result<Vec<bool>> {
+        main_block_hashes
+            .iter()
+            .map(|main_block_hash| self.is_main_block_connected(main_block_hash))
+            .collect()
+    }
+}
+
+// Everything else the bridge needs from the drivechain crate, so that the
+// bridge can also run against the in-memory MockBackend.
+pub trait Backend: Send {
+    fn confirm_bmm(&mut self) -> Result<BmmOutcome>;
+    fn attempt_bmm(
+        &mut self,
+        critical_hash: &TxMerkleNode,
+        prev_main_block_hash: &BlockHash,
+        amount: Amount,
+    ) -> Result<()>;
+    fn verify_bmm(&self, main_block_hash: &BlockHash, critical_hash: &TxMerkleNode) -> Result<()>;
+    fn get_deposit_outputs(&self) -> Result<Vec<drive::Deposit>>;
+    fn is_outpoint_spent(&self, outpoint: &[u8]) -> Result<bool>;
+    fn attempt_bundle_broadcast(&mut self) -> Result<()>;
+    fn connect_block(
+        &mut self,
+        deposits: &[drive::Deposit],
+        withdrawals: &Withdrawals,
+        refunds: &Refunds,
+        just_check: bool,
+    ) -> Result<()>;
+    fn disconnect_block(
+        &mut self,
+        deposits: &[drive::Deposit],
+        withdrawals: &[Vec<u8>],
+        refunds: &[Vec<u8>],
+        just_check: bool,
+    ) -> Result<()>;
+    fn format_deposit_address(&self, address: &str) -> String;
+    fn get_new_mainchain_address(&self) -> Result<Address>;
+    fn create_deposit(&self, address: &str, amount: Amount, fee: Amount) -> Result<Txid>;
+    fn generate(&self, n: usize) -> Result<Vec<BlockHash>>;
+    fn flush(&mut self) -> Result<usize>;
+}
+
+/// Takes the place of the drivechain crate while it is reopened, and keeps
+/// it if reopening failed.
+pub struct Closed(pub String);
+
+impl Closed {
+    fn error<T>(&self) -> Result<T> {
+        Err(Error::BackendReopenFailed {
+            message: self.0.clone(),
+        }
+        .into())
+    }
+}
+
+impl Backend for Closed {
+    fn confirm_bmm(&mut self) -> Result<BmmOutcome> {
+        self.error()
+    }
+
+    fn attempt_bmm(&mut self, _: &TxMerkleNode, _: &BlockHash, _: Amount) -> Result<()> {
+        self.error()
+    }
+
+    fn verify_bmm(&self, _: &BlockHash, _: &TxMerkleNode) -> Result<()> {
+        self.error()
+    }
+
+    fn get_deposit_outputs(&self) -> Result<Vec<drive::Deposit>> {
+        self.error()
+    }
+
+    fn is_outpoint_spent(&self, _: &[u8]) -> Result<bool> {
+        self.error()
+    }
+
+    fn attempt_bundle_broadcast(&mut self) -> Result<()> {
+        self.error()
+    }
+
+    fn connect_block(
+        &mut self,
+        _: &[drive::Deposit],
+        _: &Withdrawals,
+        _: &Refunds,
+        _: bool,
+    ) -> Result<()> {
+        self.error()
+    }
+
+    fn disconnect_block(
+        &mut self,
+        _: &[drive::Deposit],
+        _: &[Vec<u8>],
+        _: &[Vec<u8>],
+        _: bool,
+    ) -> Result<()> {
+        self.error()
+    }
+
+    fn format_deposit_address(&self, address: &str) -> String {
+        address.to_string()
+    }
+
+    fn get_new_mainchain_address(&self) -> Result<Address> {
+        self.error()
+    }
+
+    fn create_deposit(&self, _: &str, _: Amount, _: Amount) -> Result<Txid> {
+        self.error()
+    }
+
+    fn generate(&self, _: usize) -> Result<Vec<BlockHash>> {
+        self.error()
+    }
+
+    fn flush(&mut self) -> Result<usize> {
+        Ok(0)
+    }
+}
+
+impl MainchainBackend for MainClient {
+    fn get_mainchain_tip(&self) -> Result<BlockHash> {
+        Ok(self.get_best_block_hash()?)
+    }
+
+    fn get_prev_main_block_hash(&self, main_block_hash: &BlockHash) -> Result<BlockHash> {
+        Ok(self.get_prev_block_hash(main_block_hash)?)
+    }
+
+    fn get_block_count(&self) -> Result<u64> {
+        Ok(MainClient::get_block_count(self)?)
+    }
+
+    fn get_main_block_chain_status(
+        &self,
+        main_block_hash: &BlockHash,
+    ) -> Result<MainBlockChainStatus> {
+        Ok(match self.get_block_chain_status(main_block_hash)? {
+            Some(true) => MainBlockChainStatus::ActiveChain,
+            Some(false) => MainBlockChainStatus::StaleBranch,
+            None => MainBlockChainStatus::Unknown,
+        })
+    }
+
+    fn are_main_blocks_connected(&self, main_block_hashes: &[BlockHash]) -> Result<Vec<bool>> {
+        Ok(self
+            .get_block_chain_statuses(main_block_hashes)?
+            .into_iter()
+            .map(|status| status == Some(true))
+            .collect())
+    }
+}
+
+/// Answers from the header chain synced by sync_spv_headers, without asking
+/// the RPC node.
+pub struct SpvMainchain(pub Arc<Mutex<Spv>>);
+
+impl SpvMainchain {
+    fn spv(&self) -> MutexGuard<Spv> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl MainchainBackend for SpvMainchain {
+    fn get_mainchain_tip(&self) -> Result<BlockHash> {
+        Ok(self.spv().headers.tip())
+    }
+
+    fn get_prev_main_block_hash(&self, main_block_hash: &BlockHash) -> Result<BlockHash> {
+        let spv = self.spv();
+        let header = spv
+            .headers
+            .get(main_block_hash)
+            .ok_or(Error::UnknownBlock {
+                block_hash: main_block_hash.to_string(),
+            })?;
+        Ok(header.prev_blockhash)
+    }
+
+    fn get_block_count(&self) -> Result<u64> {
+        Ok(self.spv().headers.height() as u64)
+    }
+
+    // Only headers this node saw reorged out count as stale, a stale branch
+    // the peer never sent is unknown.
+    fn get_main_block_chain_status(
+        &self,
+        main_block_hash: &BlockHash,
+    ) -> Result<MainBlockChainStatus> {
+        let spv = self.spv();
+        Ok(if spv.headers.get(main_block_hash).is_some() {
+            MainBlockChainStatus::ActiveChain
+        } else if spv.headers.is_stale(main_block_hash) {
+            MainBlockChainStatus::StaleBranch
+        } else {
+            MainBlockChainStatus::Unknown
+        })
+    }
+}
+
+impl Backend for drive::Drivechain {
+    fn confirm_bmm(&mut self) -> Result<BmmOutcome> {
+        Ok(match self.confirm_bmm().into_diagnostic()? {
+            drive::BMMState::Succeded => BmmOutcome::Succeded,
+            drive::BMMState::Failed => BmmOutcome::Failed,
+            drive::BMMState::Pending => BmmOutcome::Pending,
+        })
+    }
+
+    fn attempt_bmm(
+        &mut self,
+        critical_hash: &TxMerkleNode,
+        prev_main_block_hash: &BlockHash,
+        amount: Amount,
+    ) -> Result<()> {
+        self.attempt_bmm(critical_hash, prev_main_block_hash, amount)
+            .map(|_| ())
+            .into_diagnostic()
+    }
+
+    fn verify_bmm(&self, main_block_hash: &BlockHash, critical_hash: &TxMerkleNode) -> Result<()> {
+        self.verify_bmm(main_block_hash, critical_hash)
+            .map(|_| ())
+            .into_diagnostic()
+    }
+
+    fn get_deposit_outputs(&self) -> Result<Vec<drive::Deposit>> {
+        self.get_deposit_outputs().into_diagnostic()
+    }
+
+    fn is_outpoint_spent(&self, outpoint: &[u8]) -> Result<bool> {
+        self.is_outpoint_spent(outpoint).into_diagnostic()
+    }
+
+    fn attempt_bundle_broadcast(&mut self) -> Result<()> {
+        self.attempt_bundle_broadcast()
+            .map(|_| ())
+            .into_diagnostic()
+    }
+
+    fn connect_block(
+        &mut self,
+        deposits: &[drive::Deposit],
+        withdrawals: &Withdrawals,
+        refunds: &Refunds,
+        just_check: bool,
+    ) -> Result<()> {
+        self.connect_block(deposits, withdrawals, refunds, just_check)
+            .map(|_| ())
+            .into_diagnostic()
+    }
+
+    fn disconnect_block(
+        &mut self,
+        deposits: &[drive::Deposit],
+        withdrawals: &[Vec<u8>],
+        refunds: &[Vec<u8>],
+        just_check: bool,
+    ) -> Result<()> {
+        self.disconnect_block(deposits, withdrawals, refunds, just_check)
+            .map(|_| ())
+            .into_diagnostic()
+    }
+
+    fn format_deposit_address(&self, address: &str) -> String {
+        self.format_deposit_address(address)
+    }
+
+    fn get_new_mainchain_address(&self) -> Result<Address> {
+        self.get_new_mainchain_address().into_diagnostic()
+    }
+
+    fn create_deposit(&self, address: &str, amount: Amount, fee: Amount) -> Result<Txid> {
+        self.create_deposit(address, amount, fee).into_diagnostic()
+    }
+
+    fn generate(&self, n: usize) -> Result<Vec<BlockHash>> {
+        self.generate(n).into_diagnostic()
+    }
+
+    fn flush(&mut self) -> Result<usize> {
+        self.flush().into_diagnostic()
+    }
+}
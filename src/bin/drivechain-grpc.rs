@@ -0,0 +1,19 @@
+use miette::{IntoDiagnostic as _, Result};
+
+const USAGE: &str = "usage: drivechain-grpc <db_path> <this_sidechain> <main_host> <main_port> <rpcuser> <rpcpassword> <listen_addr>";
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() != 7 {
+        miette::bail!(USAGE);
+    }
+    drivechain_cpp::grpc::serve(
+        &args[0],
+        args[1].parse().into_diagnostic()?,
+        &args[2],
+        args[3].parse().into_diagnostic()?,
+        &args[4],
+        &args[5],
+        args[6].parse().into_diagnostic()?,
+    )
+}
@@ -0,0 +1,62 @@
+use drivechain_cpp::replay;
+use miette::{IntoDiagnostic as _, Result};
+
+const USAGE: &str = "usage: drivechain-replay [--mock] <config_json_file> <replay_file>";
+
+// Replays into the bridge DB at the config's db_path, which should be fresh.
+// With --mock the calls run against the mock mainchain, seeded with every
+// replayed deposit, instead of the node in the config.
+fn main() -> Result<()> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mock = args.first().is_some_and(|arg| arg == "--mock");
+    if mock {
+        args.remove(0);
+    }
+    if args.len() != 2 {
+        miette::bail!(USAGE);
+    }
+    let config = drivechain_cpp::Config {
+        replay_path: String::new(),
+        ..drivechain_cpp::config_from_json(&std::fs::read_to_string(&args[0]).into_diagnostic()?)?
+    };
+    let report = if mock {
+        replay_with_mock(&config, &args[1])?
+    } else {
+        let mut drivechain = drivechain_cpp::new_drivechain_from_config(&config)?;
+        replay::replay(&mut drivechain, &args[1], |_| {})?
+    };
+    println!("{} calls, {} mismatches", report.calls, report.mismatches);
+    if !report.first_mismatch.is_empty() {
+        println!("first mismatch: {}", report.first_mismatch);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "mock")]
+fn replay_with_mock(
+    config: &drivechain_cpp::Config,
+    path: &str,
+) -> Result<drivechain_cpp::ReplayReport> {
+    let mock = drivechain_cpp::MockBackend::new(config.this_sidechain);
+    let mut drivechain = drivechain_cpp::new_drivechain_with_mock(config, mock.clone())?;
+    replay::replay(&mut drivechain, path, |call| {
+        if let replay::Call::ConnectBlock {
+            deposits,
+            just_check: false,
+            ..
+        } = call
+        {
+            for deposit in deposits {
+                mock.deposit(&deposit.address, deposit.amount);
+            }
+        }
+    })
+}
+
+#[cfg(not(feature = "mock"))]
+fn replay_with_mock(
+    _config: &drivechain_cpp::Config,
+    _path: &str,
+) -> Result<drivechain_cpp::ReplayReport> {
+    miette::bail!("--mock needs the mock feature")
+}
@@ -1,44 +1,1032 @@
-use bitcoin::hash_types::{BlockHash, TxMerkleNode};
+use crate::amount::{self, amount_from_btc_str, amount_from_sats, amount_to_btc_str};
+use crate::audit::audit_log_to_csv;
+use crate::backend::{self, Backend, MainchainBackend, SpvMainchain};
+use crate::cache::{HeaderCache, DEFAULT_HEADER_CACHE_CAPACITY};
+use crate::client::{self, MainClient, RpcOptions, UNIX_SOCKET_PREFIX};
+use crate::codec::{self, decode_outpoint, Dest20, Hash32, OutPointBytes};
+use crate::db::{self, Db};
+use crate::deposit_address::DepositAddressFormatter;
+use crate::dest;
+use crate::error::Error;
+use crate::flush;
+// The JSON helpers are bridge functions, cxx looks them up in this module.
+use crate::json::*;
+use crate::maturity;
+use crate::merkle;
+use crate::prefetch::{self, DepositCache};
+use crate::psbt;
+use crate::replay::{self, Recorder};
+use crate::scanner::DepositScanner;
+use crate::scheduler::{self, Scheduler};
+use crate::script;
+use crate::signer::KeySigner;
+use crate::snapshot::{self, ReadHandle, ReadSnapshot};
+use crate::spv::Spv;
+use crate::storage::{self, Storage};
+// Bridge functions as well.
+use crate::trace::{set_trace_level, start_chrome_trace, stop_chrome_trace};
+use crate::vectors::generate_test_vectors;
+use crate::votes;
+use bitcoin::consensus::encode::{deserialize, serialize, serialize_hex};
+use bitcoin::hash_types::{BlockHash, PubkeyHash, TxMerkleNode, Txid};
+use bitcoin::hashes::Hash as _;
+use bitcoin::network::constants::Network;
 use drivechain as drive;
 use miette::{IntoDiagnostic as _, Result};
-use std::collections::HashMap;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Maximum number of mainchain headers included in a deposit proof, starting
+/// at the block containing the deposit.
+const MAX_DEPOSIT_PROOF_HEADERS: u64 = 100;
+/// Maximum number of escrow transactions get_withdrawal_payout walks back
+/// from the CTIP looking for the bundle that paid a withdrawal.
+const MAX_PAYOUT_SCAN_TXS: usize = 1_000;
+/// Withdrawals assumed to fit in one bundle, a 400k weight unit transaction
+/// of 43 byte P2TR outputs, the largest paid. Bundles take the highest
+/// main_fee first.
+const MAX_BUNDLE_WITHDRAWALS: usize = 2_300;
+const DEFAULT_FLUSH_INTERVAL_SECS: u64 = 10;
+const DEFAULT_FLUSH_DIRTY_THRESHOLD: u64 = 1_000;
+const DEFAULT_MAX_BLOCK_ENTRIES: usize = 100_000;
+const DEFAULT_ALERT_BUNDLE_FAILURES: u32 = 3;
+const DEFAULT_ALERT_BMM_FAILURES: u32 = 5;
+const DEFAULT_ALERT_MAINCHAIN_UNREACHABLE_SECS: u64 = 300;
+/// Mainnet BIP300 voting window and the ACKs a bundle needs within it.
+const DEFAULT_BUNDLE_VOTE_PERIOD: u64 = 26_300;
+const DEFAULT_BUNDLE_MIN_WORK_SCORE: u64 = 13_150;
+/// Delay before the first retry of a failed bundle broadcast, doubled after
+/// every further failure up to BROADCAST_RETRY_MAX_SECS.
+const BROADCAST_RETRY_BASE_SECS: u64 = 10;
+const BROADCAST_RETRY_MAX_SECS: u64 = 60 * 60;
+const DEFAULT_BROADCAST_RETRY_INTERVAL_MS: u64 = BROADCAST_RETRY_BASE_SECS * 1_000;
+/// Bid suggested by get_bmm_block_requirements before any BMM attempt has
+/// succeeded.
+const DEFAULT_BMM_AMOUNT: u64 = 10_000;
+/// Number of recent successful bids averaged into the suggested amount.
+const BMM_AMOUNT_SAMPLE: usize = 10;
+/// How long a BMM template is good for, one expected mainchain block
+/// interval.
+const BMM_TEMPLATE_LIFETIME_SECS: u64 = 10 * 60;
+const DEFAULT_BMM_REQUEST_TTL_SECS: u64 = 30 * 60;
+/// Deposit fees below this are refused on mainnet by the network guard.
+const MAINNET_MIN_DEPOSIT_FEE: u64 = 1_000;
+/// Mainchain blocks walked back at most after a reorg.
+const MAX_REORG_DEPTH: usize = 1_000;
+/// verify_db level that adds checking the indexes against the records.
+const VERIFY_LEVEL_REFERENCES: u8 = 1;
+/// verify_db level that adds checking against the drivechain DB and the
+/// mainchain.
+const VERIFY_LEVEL_CHAINSTATE: u8 = 2;
 
 // FIXME: Figure out how to pass std::vector<unsigned char> directly, without
 // hex encoding.
 #[cxx::bridge]
-mod ffi {
-    #[derive(Debug)]
+pub mod ffi {
+    /// Only read by block_to_json and block_from_json. Connecting a block
+    /// takes its deposits, withdrawals and refunds, connect_block_bytes
+    /// borrows the serialized block instead of copying it into one.
+    #[derive(Debug, Serialize, Deserialize)]
     struct Block {
+        /// Empty unless compression is None.
         data: String,
+        compression: BlockCompression,
+        /// The zstd frame of data when compression is Zstd, for sidechains
+        /// already storing their blocks compressed.
+        compressed_data: Vec<u8>,
         time: i64,
         main_block_hash: String,
     }
-    #[derive(Debug)]
+    /// Construction options for new_drivechain_from_config. Start from
+    /// default_config() and override what's needed.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct Config {
+        db_path: String,
+        this_sidechain: usize,
+        /// Host of the mainchain node, or unix:path for RPC over the unix
+        /// socket at path, main_port is unused then. The drivechain crate
+        /// and SPV only speak TCP and go to localhost:main_port and
+        /// localhost:main_p2p_port instead.
+        main_host: String,
+        main_port: u16,
+        rpcuser: String,
+        rpcpassword: String,
+        /// Limit on requests sent to the mainchain node, 0 means unlimited.
+        rpc_requests_per_second: u32,
+        /// Maximum number of calls combined into one JSON-RPC batch.
+        rpc_batch_size: usize,
+        header_cache_capacity: usize,
+        /// "bitcoin", "testnet", "signet" or "regtest" to sync headers over
+        /// P2P from main_host:main_p2p_port, empty to disable SPV.
+        spv_network: String,
+        main_p2p_port: u16,
+        script_template: ScriptTemplate,
+        deposit_address_format: DepositAddressFormat,
+        /// Human readable start of Prefixed deposit addresses.
+        deposit_address_prefix: String,
+        /// Refuse generate and deposits with fees below 1000 sats when the
+        /// mainchain node is on mainnet.
+        network_guard: bool,
+        /// Override network_guard.
+        i_know_what_im_doing: bool,
+        /// Never use the mainchain wallet: create_deposit, attempt_bmm
+        /// without a bmm_signing_key, generate and get_new_mainchain_address
+        /// fail with watch_only.
+        /// Construction checks the read-only calls the bridge makes work,
+        /// so the node has to be reachable.
+        watch_only: bool,
+        /// Source of mainchain tips, headers and block counts.
+        mainchain_backend: MainchainBackendKind,
+        /// Further mainchain RPC endpoints as host:port or unix:path, with
+        /// the same credentials, used in order when main_host:main_port is
+        /// unreachable or lagging. The drivechain crate's own calls for
+        /// BMM, deposits and bundles always use main_host:main_port.
+        main_endpoints: Vec<String>,
+        /// host:port of a SOCKS5 proxy, Tor's for one, that RPC to main_host
+        /// and main_endpoints goes through, empty to connect directly. Host
+        /// names are resolved by the proxy, so they can be onion addresses.
+        /// The drivechain crate's own calls and SPV don't use it.
+        rpc_proxy: String,
+        /// Credentials for rpc_proxy, empty if it doesn't ask for any. Tor
+        /// uses them to isolate streams.
+        rpc_proxy_user: String,
+        rpc_proxy_password: String,
+        /// Passphrase the bridge's own DB is encrypted with, empty for an
+        /// unencrypted DB. An unencrypted DB is encrypted when opened with
+        /// one. Only the bridge's DB under db_path/drivechain-cpp is
+        /// covered: the drivechain crate's DB at db_path, which holds the
+        /// deposits and withdrawals themselves, is never encrypted. In the
+        /// bridge's DB addresses in keys are blinded with a keyed hash,
+        /// while outpoints, txids and block hashes in keys stay readable.
+        db_passphrase: String,
+        /// File holding the passphrase, read when db_passphrase is empty.
+        /// A trailing newline is ignored.
+        db_key_file: String,
+        /// Store for the bridge's own DB. The drivechain crate's DB is
+        /// always sled.
+        storage_backend: StorageBackendKind,
+        flush_policy: FlushPolicy,
+        scheduler: SchedulerConfig,
+        /// Have attempt_bundle_broadcast build bundles for co-signing
+        /// instead of letting the drivechain crate broadcast them, see
+        /// get_unsigned_bundle.
+        external_bundle_signing: bool,
+        /// Have attempt_bundle_broadcast run test_bundle_acceptance first
+        /// and count a rejected bundle as a failed broadcast, instead of
+        /// spending the bundle slot on it.
+        check_bundle_acceptance: bool,
+        /// Blocks a bundle is voted on for, and the work score it needs to
+        /// pay out. Mainnet values by default, other networks use less.
+        bundle_vote_period: u64,
+        bundle_min_work_score: u64,
+        bundle_policy: BundlePolicy,
+        dust_policy: DustPolicy,
+        alert_policy: AlertPolicy,
+        block_limits: BlockLimits,
+        /// Append every connect_block and disconnect_block call with its
+        /// result to this file, for drivechain-replay. Empty to not record.
+        replay_path: String,
+        /// How often the scheduler refreshes the deposits returned by
+        /// get_prefetched_deposit_outputs when the mainchain tip moved, 0 to
+        /// only fetch them on demand.
+        deposit_prefetch_interval_ms: u64,
+        /// Mainchain blocks a deposit has to have been pending for before
+        /// connect_block accepts it, see get_immature_deposits. 0 to accept
+        /// deposits right away.
+        deposit_maturity: u64,
+        /// Mainchain blocks after which get_withdrawal_status reports a
+        /// connected withdrawal as mature. The drivechain crate has no
+        /// maturity rule for withdrawals, 0 reports them mature right away.
+        withdrawal_maturity: u64,
+        /// WIF of a P2WPKH key attempt_bmm funds and signs BMM requests
+        /// with, instead of the mainchain wallet. Its coins are found with
+        /// scantxoutset, so only confirmed ones are spent. Empty to use the
+        /// wallet.
+        bmm_signing_key: String,
+        /// Seconds after which confirm_bmm gives up on a pending BMM request
+        /// and reports Expired, 0 to wait for as long as it takes.
+        bmm_request_ttl_secs: u64,
+    }
+    /// A pending deposit that connect_block doesn't accept yet.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct ImmatureDeposit {
+        address: String,
+        amount: u64,
+        /// Mainchain block count when the deposit was first seen pending.
+        first_seen_height: u64,
+        blocks_until_mature: u64,
+    }
+    /// A pending deposit from get_unacked_deposit_outputs. id stays the
+    /// same for as long as the deposit is pending, a deposit pending again
+    /// after its block was disconnected gets a new one.
+    #[derive(Debug, Clone)]
+    struct DepositOutput {
+        id: u64,
+        output: Output,
+    }
+    /// Deposit outputs as of main_block_hash, fetched at a unix time.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct PrefetchedDeposits {
+        outputs: Vec<Output>,
+        main_block_hash: String,
+        fetched_at: u64,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct ReplayReport {
+        calls: u64,
+        /// Calls that succeeded where the recorded one failed, returned
+        /// a different result or failed where it succeeded.
+        mismatches: u64,
+        /// Empty if everything matched.
+        first_mismatch: String,
+    }
+    /// Most entries connect_block and disconnect_block accept per block,
+    /// checked before any of them is decoded. A block over a limit fails
+    /// with block_too_large.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct BlockLimits {
+        max_deposits: usize,
+        max_withdrawals: usize,
+        max_refunds: usize,
+    }
+    /// When alerts go off, 0 disables a trigger.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct AlertPolicy {
+        /// Failed broadcasts of the same bundle.
+        bundle_failures: u32,
+        /// BMM attempts failed in a row.
+        bmm_failures: u32,
+        mainchain_unreachable_secs: u64,
+        /// Alert on bundles for this sidechain this node didn't propose,
+        /// see list_proposed_bundles.
+        foreign_bundles: bool,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    enum AlertKind {
+        BundleFailed,
+        BmmFailed,
+        MainchainUnreachable,
+        ForeignBundle,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    enum AuditOperation {
+        ConnectBlock,
+        DisconnectBlock,
+        BundleBroadcast,
+        Flush,
+        SlotMigration,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct AuditEntry {
+        id: u64,
+        /// Unix time.
+        at: u64,
+        operation: AuditOperation,
+        /// Mainchain tip at the time, empty for flushes, connected and
+        /// disconnected blocks, which don't query the mainchain, and when
+        /// the node didn't answer.
+        main_block_hash: String,
+        detail: String,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Alert {
+        kind: AlertKind,
+        message: String,
+        /// Unix time the condition started.
+        since: u64,
+    }
+    /// How the scheduled tasks are run, see tick.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum SchedulerMode {
+        /// Flushes and deposit prefetching run on a thread the bridge
+        /// owns, BMM confirmation and broadcast retries when tick is
+        /// called.
+        Thread,
+        /// Every task runs when tick is called, nothing runs in the
+        /// background. The default.
+        Tick,
+    }
+    /// Flushes follow flush_policy and deposit prefetching
+    /// deposit_prefetch_interval_ms, whichever mode they run in.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct SchedulerConfig {
+        mode: SchedulerMode,
+        /// How often confirm_bmm is run while a BMM request is pending, 0
+        /// to leave it to the caller.
+        confirm_bmm_interval_ms: u64,
+        /// How often tick retries bundle broadcasts due for a retry, 0 to
+        /// leave them to attempt_bundle_broadcast.
+        broadcast_retry_interval_ms: u64,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    enum TaskKind {
+        ConfirmBmm,
+        RetryBroadcasts,
+        PrefetchDeposits,
+        Flush,
+    }
+    /// A task tick ran.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct TaskRun {
+        kind: TaskKind,
+        /// Empty if it succeeded.
+        error: String,
+    }
+    /// What get_library_info reports, for getinfo style RPCs and bug
+    /// reports.
+    #[derive(Debug, Clone)]
+    struct LibraryInfo {
+        /// Version of this crate.
+        version: String,
+        /// Commit it was built from, empty if built outside a git checkout.
+        git_commit: String,
+        /// Optional cargo features built in: grpc, capi, async-rpc, mock,
+        /// rocksdb, zstd or refund_amount_check. SPV is always built.
+        features: Vec<String>,
+        /// Git revision of the drivechain crate this one wraps.
+        drivechain_rev: String,
+    }
+    /// When the scheduler flushes. flush() can still be called at any
+    /// time.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct FlushPolicy {
+        /// Seconds between flushes, 0 to not flush on a timer.
+        interval_secs: u64,
+        /// Flush once this many bridge DB records were written since the
+        /// last flush, 0 to not flush on writes.
+        dirty_threshold: u64,
+    }
+    /// Order withdrawals enter bundles in, when there are more than fit in
+    /// one. HighestFeeFirst by default.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum BundleOrdering {
+        HighestFeeFirst,
+        /// By the mainchain height the withdrawal was connected at.
+        OldestFirst,
+        /// Oldest first among withdrawals paying at least fee_floor, then
+        /// the others highest fee first.
+        Hybrid,
+    }
+    /// Any ordering but HighestFeeFirst has the bridge build bundles instead
+    /// of the drivechain crate, as a withdrawal filter does. Those bundles
+    /// can only pay withdrawals connected since payees are recorded.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct BundlePolicy {
+        ordering: BundleOrdering,
+        /// main_fee a withdrawal needs for Hybrid to take it by age.
+        fee_floor: u64,
+    }
+    /// Where withdrawals below DustPolicy::min_amount are turned away.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum DustMode {
+        /// connect_block refuses blocks containing them.
+        Connect,
+        /// They stay connected but out of the bundles this node builds,
+        /// which has the bridge build bundles as a withdrawal filter does.
+        Bundle,
+    }
+    /// min_amount 0 turns no withdrawal away. Mainchain nodes treat P2PKH
+    /// outputs below 546 sats as dust.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct DustPolicy {
+        min_amount: u64,
+        mode: DustMode,
+    }
+    /// A withdrawal turned away for being below the minimum amount.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct DustWithdrawal {
+        withdrawal: Withdrawal,
+        mode: DustMode,
+        /// Unix time it was first turned away.
+        rejected_at: u64,
+    }
+    /// Sled under db_path/drivechain-cpp, RocksDb under
+    /// db_path/drivechain-cpp-rocksdb (needs the rocksdb feature) or Memory,
+    /// which keeps nothing across restarts.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum StorageBackendKind {
+        Sled,
+        RocksDb,
+        Memory,
+    }
+    /// Approved once the work score is reached, Failing when the blocks left
+    /// can't make up for the missing ACKs, otherwise OnTrack or Stalling by
+    /// whether the ACK rate so far would get there in time.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum VoteOutcome {
+        Approved,
+        OnTrack,
+        Stalling,
+        Failing,
+    }
+    /// Ack and Nack change the work score by one, Abstain leaves it. The
+    /// first sample of a bundle and samples that are more than one block
+    /// apart are Unknown, see score_change then.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum BundleVoteKind {
+        Ack,
+        Nack,
+        Abstain,
+        Unknown,
+    }
+    /// A bundle being or having been voted on for this sidechain. ours is
+    /// set for the first bundle seen after this node broadcast one, every
+    /// other bundle was proposed by someone else.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct ProposedBundle {
+        hash: String,
+        work_score: u64,
+        blocks_remaining: u64,
+        first_seen_height: u64,
+        first_seen_at: u64,
+        last_seen_height: u64,
+        ours: bool,
+        /// Still being voted on when last seen.
+        active: bool,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct BundleVote {
+        main_height: u64,
+        main_block_hash: String,
+        work_score: u64,
+        blocks_remaining: u64,
+        /// Blocks since the previous sample and the work score change over
+        /// them, 0 for the first sample.
+        blocks: u64,
+        score_change: i64,
+        vote: BundleVoteKind,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct VoteStatus {
+        bundle_hash: String,
+        work_score: u64,
+        min_work_score: u64,
+        blocks_remaining: u64,
+        outcome: VoteOutcome,
+    }
+    /// Activation proposal for this sidechain. hash_id_1 and hash_id_2 are
+    /// the optional 32 and 20 byte hex hashes identifying the sidechain's
+    /// software, leave them empty to not set them.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct SidechainProposal {
+        title: String,
+        description: String,
+        version: i32,
+        hash_id_1: String,
+        hash_id_2: String,
+    }
+    /// Proposed until a mainchain block includes the proposal, Voting
+    /// while miners ACK it and Failed once it is no longer voted on without
+    /// having activated.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum ActivationState {
+        NotProposed,
+        Proposed,
+        Voting,
+        Active,
+        Failed,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct ActivationStatus {
+        state: ActivationState,
+        title: String,
+        /// Blocks the proposal has been voted on for and how many of them
+        /// didn't ACK it, 0 unless Voting.
+        age: u64,
+        fails: u64,
+        /// Unix time bootstrap_sidechain submitted the proposal.
+        proposed_at: u64,
+    }
+    /// What testmempoolaccept says about a bundle, reject_reason is empty
+    /// if it is allowed.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct AcceptanceResult {
+        allowed: bool,
+        reject_reason: String,
+        txid: String,
+        vsize: u64,
+    }
+    /// Zstd needs the zstd feature.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum BlockCompression {
+        None,
+        Zstd,
+    }
+    /// Rpc asks the mainchain node, Spv the P2P header chain (spv_network
+    /// has to be set), Mock an in-memory chain for the mock feature. With
+    /// Spv, verify_bmm and get_bmm_proof only trust mainchain blocks from
+    /// the RPC node that match a synced header. Deposits still come from
+    /// the drivechain crate over RPC, get_deposit_proof and
+    /// verify_tx_inclusion check one against the header chain.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum MainchainBackendKind {
+        Rpc,
+        Spv,
+        Mock,
+    }
+    /// Where a mainchain block is as far as the mainchain backend knows.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum MainBlockChainStatus {
+        ActiveChain,
+        /// Known, but reorged out of the best chain.
+        StaleBranch,
+        Unknown,
+    }
+    /// Encoding of the sidechain escrow script and deposit addresses, for
+    /// testnets and BIP300 revisions that differ from the defaults.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct ScriptTemplate {
+        /// Opcode standing in for OP_DRIVECHAIN, 0xb4 (OP_NOP5) under BIP300.
+        drivechain_opcode: u8,
+        /// Opcode after the sidechain number, 0x51 (OP_TRUE) under BIP300.
+        trailing_opcode: u8,
+        /// Deposit address with {sidechain} and {address} substituted, empty
+        /// for Config::deposit_address_format.
+        deposit_address_format: String,
+    }
+    /// Built-in deposit address formats, for when
+    /// ScriptTemplate::deposit_address_format is empty.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum DepositAddressFormat {
+        /// The drivechain crate's s{sidechain}_{address}_{checksum}.
+        Drivechain,
+        /// deposit_address_prefix, then {sidechain}_{address}_ and the
+        /// first 6 hex digits of the SHA256 of everything before.
+        Prefixed,
+    }
+    /// An amount in sats. Takes the guesswork out of whether a bare u64 is
+    /// sats or BTC, see amount_from_btc_str.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    struct Amount {
+        sats: u64,
+    }
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
     struct Output {
         address: String,
         amount: u64,
     }
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
     struct Withdrawal {
         outpoint: String,
+        /// Hex P2PKH hash or a mainchain address, see parse_withdrawal_dest.
         main_address: String,
         main_fee: u64,
         amount: u64,
     }
-    #[derive(Debug)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
     struct Refund {
         outpoint: String,
         amount: u64,
     }
-    #[derive(Debug)]
+    #[derive(Debug, Serialize, Deserialize)]
+    struct WithdrawalStatus {
+        /// Mainchain block count when the withdrawal was connected, or when
+        /// its status was first asked for if connect_block was given none.
+        height: u64,
+        spent: bool,
+        confirmations: u64,
+        blocks_until_mature: u64,
+    }
+    /// The mainchain bundle output that paid a withdrawal.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct PayoutInfo {
+        outpoint: String,
+        mainchain_txid: String,
+        vout: u32,
+        main_block_hash: String,
+        amount: u64,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct DepositScanProgress {
+        scanned_height: u64,
+        target_height: u64,
+        failed_ranges: u64,
+        running: bool,
+    }
+    /// An active sidechain's escrow, ctip_txid is empty and value 0 before
+    /// its first deposit.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct EscrowInfo {
+        sidechain: usize,
+        title: String,
+        value: u64,
+        ctip_txid: String,
+        ctip_vout: u32,
+    }
+    /// A BIP301 commitment in a mainchain coinbase.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct BmmCommitment {
+        sidechain: u8,
+        critical_hash: String,
+        /// Coinbase output the commitment is in.
+        vout: u32,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct BmmProof {
+        main_block_hash: String,
+        header_hex: String,
+        coinbase_hex: String,
+        coinbase_txid: String,
+        merkle_branch: Vec<String>,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct DepositProof {
+        txid: String,
+        tx_hex: String,
+        main_block_hash: String,
+        index: u32,
+        merkle_branch: Vec<String>,
+        headers: Vec<String>,
+    }
+    /// Verified when a scanned mainchain deposit to address grew the CTIP
+    /// by exactly amount, Mismatch when every scanned deposit to address
+    /// grew it by something else, Unscanned when no deposit to address was
+    /// scanned, Unavailable when the previous CTIP couldn't be looked up.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum DepositVerificationStatus {
+        Verified,
+        Mismatch,
+        Unscanned,
+        Unavailable,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct DepositVerification {
+        address: String,
+        amount: u64,
+        /// The matching mainchain deposit, or the last one checked.
+        mainchain_txid: String,
+        ctip_delta: u64,
+        status: DepositVerificationStatus,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct BroadcastEntry {
+        id: u64,
+        created_at: u64,
+        attempts: u32,
+        next_attempt_at: u64,
+        last_error: String,
+    }
+    /// One attempt_bmm call, txid is empty because the drivechain crate
+    /// doesn't report the critical data transaction it creates.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct BmmAttempt {
+        id: u64,
+        created_at: u64,
+        critical_hash: String,
+        prev_main_block_hash: String,
+        amount: u64,
+        txid: String,
+        state: BMMState,
+        /// Mainchain tip when confirm_bmm resolved the attempt, empty while
+        /// pending.
+        main_block_hash: String,
+    }
+    /// BMM bids made in [from_time, to_time], amounts in sats. Bids that
+    /// failed count as wasted, pending ones only towards total_spent.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct SpendSummary {
+        from_time: u64,
+        to_time: u64,
+        attempts: u64,
+        succeeded: u64,
+        failed: u64,
+        pending: u64,
+        total_spent: u64,
+        successful_spent: u64,
+        wasted_spent: u64,
+        average_bid: u64,
+    }
+    /// Withdrawals with main_fee in [min_fee, 2 * min_fee), or a main_fee
+    /// of 0 for min_fee 0.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct FeeBucket {
+        min_fee: u64,
+        count: u64,
+        total_amount: u64,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct PendingWithdrawalsSummary {
+        count: u64,
+        total_amount: u64,
+        total_fees: u64,
+        /// Non-empty buckets by increasing min_fee.
+        fee_histogram: Vec<FeeBucket>,
+        /// Lowest main_fee a new withdrawal needs to make it into the next
+        /// bundle, 0 while all pending withdrawals fit or when the bundle
+        /// policy doesn't order by fee alone.
+        next_bundle_min_fee: u64,
+    }
+    /// Sats moved through this sidechain's escrow. Totals only count
+    /// deposits and withdrawals connected since they're indexed, a DB
+    /// written by older versions shows an escrow_difference for those.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct PegSummary {
+        total_pegged_in: u64,
+        /// Paid out withdrawals, main fees included since the escrow pays
+        /// them too.
+        total_withdrawn: u64,
+        pending_withdrawals: u64,
+        /// Pending amounts and main fees, still in the escrow.
+        pending_amount: u64,
+        /// Value of the CTIP, 0 before the first deposit.
+        escrow_value: u64,
+        /// escrow_value less what total_pegged_in and total_withdrawn say
+        /// it should hold, 0 when the DB accounts for the whole escrow.
+        escrow_difference: i64,
+    }
+    /// Everything a miner needs for attempt_bmm. The template is stale once
+    /// the mainchain tip changes or at expires_at, whichever comes first.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct BmmTemplate {
+        main_block_hash: String,
+        prev_main_block_hash: Vec<u8>,
+        suggested_amount: u64,
+        expires_at: u64,
+    }
+    /// A deposit connected to a sidechain address, mainchain_txid is empty
+    /// if the mainchain deposit hasn't been scanned.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct DepositRecord {
+        id: u64,
+        amount: u64,
+        mainchain_txid: String,
+        main_height: u64,
+        connected_at: u64,
+    }
+    /// A deposit made by a mainchain transaction and the sidechain address
+    /// it was made to.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct IndexedDeposit {
+        sidechain_address: String,
+        deposit: DepositRecord,
+    }
+    /// A deposit to a watched sidechain address, connected is false when
+    /// the block containing it was disconnected again.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct DepositEvent {
+        id: u64,
+        created_at: u64,
+        address: String,
+        amount: u64,
+        mainchain_txid: String,
+        connected: bool,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    enum CheckItem {
+        Deposit,
+        Withdrawal,
+        Refund,
+        /// The drivechain crate rejected the block as a whole.
+        Block,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    enum CheckFailureReason {
+        InvalidAddress,
+        InvalidOutpoint,
+        AmountOutOfRange,
+        AmountMismatch,
+        UnknownDeposit,
+        DuplicateOutpoint,
+        OutpointSpent,
+        UnknownWithdrawal,
+        Rejected,
+        ImmatureDeposit,
+        BelowMinimum,
+        /// A withdrawal's main_fee isn't below its amount.
+        FeeNotBelowAmount,
+        /// main_fee is below BundlePolicy::fee_floor, only check_withdrawal
+        /// reports it.
+        BelowFeeFloor,
+        /// The block couldn't be checked, a mainchain RPC call, decoding or
+        /// the bridge DB failed. The message starts with the error code.
+        CheckFailed,
+    }
+    /// Why one input of a block failed validation, index is its position in
+    /// the deposits, withdrawals or refunds passed in.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct CheckFailure {
+        item: CheckItem,
+        index: u64,
+        reason: CheckFailureReason,
+        message: String,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct CheckResult {
+        valid: bool,
+        failures: Vec<CheckFailure>,
+    }
+    /// Why check_withdrawal turned a withdrawal away.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct WithdrawalRejection {
+        reason: CheckFailureReason,
+        message: String,
+        /// connect_block would take the withdrawal anyway, it is only
+        /// turned away by the dust or bundle policy: it would never be
+        /// bundled, or bundled last.
+        policy_only: bool,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct CheckOutcome {
+        accepted: bool,
+        rejections: Vec<WithdrawalRejection>,
+    }
+    /// A sidechain block as the node identifies it.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct SidechainBlockRef {
+        hash: String,
+        height: u64,
+    }
+    /// What verify_and_repair found. Outpoints are hex encoded.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct RepairReport {
+        checked: u64,
+        /// Withdrawals the chainstate has that were missing from the bridge
+        /// DB and have been recorded again.
+        restored: Vec<String>,
+        /// Withdrawal records the chainstate doesn't know about, removed.
+        removed: Vec<String>,
+        /// Withdrawals recorded at a mainchain height above the current tip,
+        /// reset to the tip.
+        adjusted: Vec<String>,
+        /// Unspent in the chainstate but spent in the drivechain DB. These
+        /// can't be repaired here, the blocks have to be reconnected.
+        conflicts: Vec<String>,
+    }
+    /// A deposit address migrate_sidechain_slot rewrote.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct DepositAddressRewrite {
+        old_address: String,
+        new_address: String,
+        /// Scanned mainchain deposits to it.
+        deposits: u64,
+    }
+    /// What migrate_sidechain_slot changed, or would change with dry_run.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct SlotMigrationReport {
+        old_slot: usize,
+        new_slot: usize,
+        dry_run: bool,
+        rewritten: Vec<DepositAddressRewrite>,
+        /// Txids of scanned mainchain deposits to addresses that aren't
+        /// formatted for old_slot, left as they are.
+        skipped: Vec<String>,
+    }
+    /// Something verify_db found wrong. key is hex encoded, and empty for
+    /// problems that aren't about a single record.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct VerifyProblem {
+        tree: String,
+        key: String,
+        message: String,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    struct VerifyReport {
+        level: u8,
+        /// Records and index entries checked.
+        checked: u64,
+        valid: bool,
+        problems: Vec<VerifyProblem>,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
+    enum DestKind {
+        P2pkh,
+        P2sh,
+        P2wpkh,
+        P2tr,
+    }
+    /// A mainchain withdrawal destination, hash is the pubkey, script or
+    /// witness program hash for kind.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct DestAddress {
+        kind: DestKind,
+        hash: Vec<u8>,
+    }
+    /// A mainchain address saved with add_withdrawal_destination.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct WithdrawalDestination {
+        label: String,
+        address: String,
+        dest: DestAddress,
+        /// Unix time the destination was saved.
+        added_at: u64,
+    }
+    #[derive(Debug, Serialize, Deserialize)]
     enum BMMState {
         Succeded,
         Failed,
         Pending,
+        /// Pending for longer than bmm_request_ttl_secs without being
+        /// confirmed, and given up on.
+        Expired,
+    }
+    /// Where the current withdrawal bundle is. Failed means the last
+    /// broadcast failed and is waiting for its retry, or that the vote on
+    /// it failed and its withdrawals are bundled again, Voting that a
+    /// mainchain block was mined since the broadcast, AwaitingSignatures
+    /// that an externally signed bundle hasn't been submitted yet.
+    #[derive(Debug, Serialize, Deserialize)]
+    enum BundleState {
+        Idle,
+        Created,
+        Broadcast,
+        Voting,
+        Failed,
+        PaidOut,
+        AwaitingSignatures,
+    }
+    extern "Rust" {
+        /// Bridge state as of open_read_snapshot. The mainchain height is
+        /// the block count at that time, confirmations count up to it.
+        /// Queries wait for a block being applied and fail with
+        /// snapshot_stale once one was connected or disconnected since.
+        type ReadSnapshot;
+        fn get_main_height(&self) -> u64;
+        fn get_deposit_outputs(&self) -> Result<Vec<Output>>;
+        fn is_outpoint_spent(&self, outpoint: &str) -> Result<bool>;
+        fn get_withdrawal_status(&self, outpoint: &str) -> Result<WithdrawalStatus>;
+    }
+    extern "Rust" {
+        /// Read access for threads other than the one calling
+        /// connect_block and disconnect_block. It may be used concurrently
+        /// with the Drivechain it came from, and from several threads.
+        type ReadHandle;
+        fn open_read_snapshot(&self) -> Result<Box<ReadSnapshot>>;
     }
     extern "Rust" {
         type Drivechain;
+        fn default_config() -> Config;
+        fn get_library_info() -> LibraryInfo;
+        /// Level of the spans and events logged to stderr, "off" until set.
+        /// connect_block, disconnect_block and flush are traced with their
+        /// inputs and durations.
+        fn set_trace_level(level: &str) -> Result<()>;
+        /// Additionally write spans to a Chrome trace file.
+        fn start_chrome_trace(path: &str) -> Result<()>;
+        fn stop_chrome_trace() -> Result<()>;
+        /// Parse a Config from JSON, fields that are left out keep their
+        /// default_config() values.
+        fn config_from_json(json: &str) -> Result<Config>;
+        /// Written out decompressed.
+        fn block_to_json(block: &Block) -> Result<String>;
+        fn block_from_json(json: &str) -> Result<Block>;
+        fn output_to_json(output: &Output) -> Result<String>;
+        fn output_from_json(json: &str) -> Result<Output>;
+        fn outputs_to_json(outputs: &[Output]) -> Result<String>;
+        fn outputs_from_json(json: &str) -> Result<Vec<Output>>;
+        fn withdrawal_to_json(withdrawal: &Withdrawal) -> Result<String>;
+        fn withdrawal_from_json(json: &str) -> Result<Withdrawal>;
+        fn withdrawals_to_json(withdrawals: &[Withdrawal]) -> Result<String>;
+        fn withdrawals_from_json(json: &str) -> Result<Vec<Withdrawal>>;
+        fn refund_to_json(refund: &Refund) -> Result<String>;
+        fn refund_from_json(json: &str) -> Result<Refund>;
+        fn refunds_to_json(refunds: &[Refund]) -> Result<String>;
+        fn refunds_from_json(json: &str) -> Result<Vec<Refund>>;
+        fn withdrawal_status_to_json(status: &WithdrawalStatus) -> Result<String>;
+        fn vote_status_to_json(status: &VoteStatus) -> Result<String>;
+        fn payout_info_to_json(payout: &PayoutInfo) -> Result<String>;
+        fn pending_withdrawals_summary_to_json(
+            summary: &PendingWithdrawalsSummary,
+        ) -> Result<String>;
+        fn peg_summary_to_json(summary: &PegSummary) -> Result<String>;
+        fn deposit_scan_progress_to_json(progress: &DepositScanProgress) -> Result<String>;
+        fn bmm_commitments_to_json(commitments: &[BmmCommitment]) -> Result<String>;
+        fn bmm_proof_to_json(proof: &BmmProof) -> Result<String>;
+        fn deposit_proof_to_json(proof: &DepositProof) -> Result<String>;
+        fn broadcast_queue_to_json(entries: &[BroadcastEntry]) -> Result<String>;
+        fn bmm_history_to_json(attempts: &[BmmAttempt]) -> Result<String>;
+        fn spend_summary_to_json(summary: &SpendSummary) -> Result<String>;
+        fn bmm_template_to_json(template: &BmmTemplate) -> Result<String>;
+        fn deposit_events_to_json(events: &[DepositEvent]) -> Result<String>;
+        fn alerts_to_json(alerts: &[Alert]) -> Result<String>;
+        fn audit_log_to_json(entries: &[AuditEntry]) -> Result<String>;
+        /// With a header row, id,at,operation,main_block_hash,detail.
+        fn audit_log_to_csv(entries: &[AuditEntry]) -> String;
+        /// JSON test vectors for integrating connect_block,
+        /// disconnect_block and compute_critical_hash, the same for the
+        /// same seed.
+        fn generate_test_vectors(seed: u64) -> Result<String>;
+        fn check_result_to_json(result: &CheckResult) -> Result<String>;
+        fn repair_report_to_json(report: &RepairReport) -> Result<String>;
+        fn verify_report_to_json(report: &VerifyReport) -> Result<String>;
+        /// Fails above MAX_MONEY, like every other Amount constructor.
+        fn amount_from_sats(sats: u64) -> Result<Amount>;
+        /// "1.5" is 150000000 sats. At most 8 decimals, no sign or exponent.
+        fn amount_from_btc_str(btc: &str) -> Result<Amount>;
+        /// With all 8 decimals, as bitcoind prints amounts.
+        fn amount_to_btc_str(amount: Amount) -> String;
+        fn new_drivechain_from_config(config: &Config) -> Result<Box<Drivechain>>;
+        /// Switch to new mainchain RPC credentials without recreating the
+        /// Drivechain. They are checked against the node first, and the
+        /// old ones stay in use if the node refuses them or the drivechain
+        /// crate fails to reopen with them. A BMM request pending through
+        /// the crate is confirmed from the mainchain after the switch.
+        fn update_rpc_credentials(&self, rpcuser: &str, rpcpassword: &str) -> Result<()>;
         fn new_drivechain(
             db_path: &str,
             this_sidechain: usize,
@@ -47,15 +1035,86 @@ mod ffi {
             rpcuser: &str,
             rpcpassword: &str,
         ) -> Result<Box<Drivechain>>;
+        fn new_drivechain_with_spv(
+            db_path: &str,
+            this_sidechain: usize,
+            main_host: &str,
+            main_port: u16,
+            rpcuser: &str,
+            rpcpassword: &str,
+            network: &str,
+            main_p2p_port: u16,
+        ) -> Result<Box<Drivechain>>;
         fn get_mainchain_tip(&self) -> Result<String>;
         fn get_prev_main_block_hash(&self, main_block_hash: &str) -> Result<Vec<u8>>;
+        /// host:port of the mainchain endpoint in use, see
+        /// Config::main_endpoints.
+        fn get_active_endpoint(&self) -> String;
+        /// Variants of the calls above for latency sensitive callers, failing
+        /// once timeout_ms has passed, 0 means no timeout. They query the
+        /// mainchain node directly instead of through the drivechain crate.
+        fn get_mainchain_tip_with_timeout(&self, timeout_ms: u64) -> Result<String>;
+        fn get_prev_main_block_hash_with_timeout(
+            &self,
+            main_block_hash: &str,
+            timeout_ms: u64,
+        ) -> Result<Vec<u8>>;
+        fn get_bmm_block_requirements_with_timeout(&self, timeout_ms: u64) -> Result<BmmTemplate>;
+        /// Expired is reported until the next attempt_bmm. Requests are
+        /// kept in the DB, one still pending from before a restart is
+        /// confirmed from the mainchain.
         fn confirm_bmm(&mut self) -> Result<BMMState>;
+        /// confirm_bmm for the latest request made for critical_hash, which
+        /// needn't be the latest request. An expired one is reported as
+        /// Expired every time.
+        fn confirm_bmm_for(&mut self, critical_hash: &str) -> Result<BMMState>;
+        /// Every BMM request still pending, most recent first. Several can
+        /// be, for different candidate blocks in the same mainchain block.
+        fn get_pending_bmm_attempts(&self) -> Result<Vec<BmmAttempt>>;
+        /// Seconds since the pending BMM request was made, 0 when none is
+        /// pending.
+        fn get_bmm_request_age(&self) -> Result<u64>;
+        /// Most recent BMM attempts first, at most limit of them or all if
+        /// limit is 0.
+        fn get_bmm_history(&self, limit: usize) -> Result<Vec<BmmAttempt>>;
+        /// Mainchain tip, its prev hash and a suggested bid, replacing separate
+        /// get_mainchain_tip and get_prev_main_block_hash calls.
+        fn get_bmm_block_requirements(&self) -> Result<BmmTemplate>;
+        /// Aggregate the BMM history between two unix times, inclusive.
+        fn get_bmm_spend_summary(&self, from_time: u64, to_time: u64) -> Result<SpendSummary>;
         fn attempt_bmm(
             &mut self,
             critical_hash: &str,
             prev_main_block_hash: &str,
             amount: u64,
         ) -> Result<()>;
+        /// attempt_bmm with a typed amount.
+        fn attempt_bmm_with_amount(
+            &mut self,
+            critical_hash: &str,
+            prev_main_block_hash: &str,
+            amount: Amount,
+        ) -> Result<()>;
+        /// attempt_bmm bidding sat_per_vb times the estimated size of the
+        /// BMM request transaction. Returns the amount bid, which is what
+        /// the BMM history records.
+        fn attempt_bmm_with_feerate(
+            &mut self,
+            critical_hash: &str,
+            prev_main_block_hash: &str,
+            sat_per_vb: u64,
+        ) -> Result<u64>;
+        /// Base64 PSBT of a BMM request bidding amount, for a signer outside
+        /// the bridge such as a hardware wallet to fund and sign.
+        fn create_bmm_psbt(
+            &self,
+            critical_hash: &str,
+            prev_main_block_hash: &str,
+            amount: u64,
+        ) -> Result<String>;
+        /// Broadcast a finalized BMM request PSBT and record it like
+        /// attempt_bmm does, returning the mainchain txid.
+        fn broadcast_bmm_psbt(&self, psbt: &str) -> Result<String>;
         fn connect_block(
             &mut self,
             deposits: Vec<Output>,
@@ -63,6 +1122,59 @@ mod ffi {
             refunds: Vec<Refund>,
             just_check: bool,
         ) -> Result<bool>;
+        /// connect_block recording main_height, the mainchain block count
+        /// when the block was first connected, for its withdrawals and
+        /// deposits. connect_block doesn't query the mainchain, the
+        /// withdrawals it connects get the block count the first time
+        /// get_withdrawal_status or a new bundle needs it, and their
+        /// deposits 0. Snapshots take it to be their own height until then.
+        fn connect_block_at_height(
+            &mut self,
+            main_height: u64,
+            deposits: Vec<Output>,
+            withdrawals: Vec<Withdrawal>,
+            refunds: Vec<Refund>,
+            just_check: bool,
+        ) -> Result<bool>;
+        /// Cross-check the bridge DB against the withdrawal outpoints the
+        /// caller's chainstate has unspent and against the mainchain,
+        /// repairing what can be repaired.
+        fn verify_and_repair(&mut self, unspent_withdrawals: Vec<String>) -> Result<RepairReport>;
+        /// After the sidechain was proposed again on a new slot, rewrite
+        /// the bridge DB entries keyed by old_slot's deposit addresses for
+        /// new_slot, which the node has to be opened on. With dry_run
+        /// nothing is written. The drivechain crate's DB isn't touched.
+        fn migrate_sidechain_slot(
+            &mut self,
+            old_slot: usize,
+            new_slot: usize,
+            dry_run: bool,
+        ) -> Result<SlotMigrationReport>;
+        /// Check the bridge DB without changing it, like bitcoind's
+        /// verifychain. Level 0 reads and decodes every record, level 1
+        /// also checks the indexes against the records, level 2 and above
+        /// also check withdrawals against the drivechain DB and the
+        /// mainchain escrow.
+        fn verify_db(&self, level: u8) -> Result<VerifyReport>;
+        /// JSON dump of the bridge's internal state for support requests:
+        /// the pending BMM request, the bundle, cache sizes, the last flush
+        /// and the last connected block. The layout may change between
+        /// versions.
+        fn dump_internal_state(&self) -> Result<String>;
+        /// Same as connect_block with just_check, but reports every input
+        /// that failed and why. Only fails if the drivechain crate is
+        /// closed, other errors are reported as a CheckFailed failure.
+        fn check_connect_block(
+            &mut self,
+            deposits: Vec<Output>,
+            withdrawals: Vec<Withdrawal>,
+            refunds: Vec<Refund>,
+        ) -> Result<CheckResult>;
+        /// Check a single withdrawal against the rules connect_block
+        /// applies, its destination, amounts and outpoint, and against the
+        /// dust and bundle policies, for a mempool to turn bad withdrawals
+        /// away on entry.
+        fn check_withdrawal(&self, withdrawal: &Withdrawal) -> Result<CheckOutcome>;
         fn disconnect_block(
             &mut self,
             deposits: Vec<Output>,
@@ -70,23 +1182,577 @@ mod ffi {
             refunds: Vec<String>,
             just_check: bool,
         ) -> Result<bool>;
+        /// connect_block and disconnect_block for the given sidechain block,
+        /// which has to be the next one or the last connected one. The
+        /// position is only tracked by these two, so use them for every
+        /// block.
+        fn connect_sidechain_block(
+            &mut self,
+            block: &SidechainBlockRef,
+            deposits: Vec<Output>,
+            withdrawals: Vec<Withdrawal>,
+            refunds: Vec<Refund>,
+            just_check: bool,
+        ) -> Result<bool>;
+        /// connect_sidechain_block for the serialized sidechain block at
+        /// height, borrowed rather than copied across. Its first header_len
+        /// bytes are its header, the block hash is their double SHA256
+        /// like the critical hash attempt_bmm takes. The rest of the block
+        /// is the sidechain's own and isn't read. Fails if header_len is 0
+        /// or longer than the block.
+        fn connect_block_bytes(
+            &mut self,
+            block: &[u8],
+            header_len: usize,
+            height: u64,
+            deposits: Vec<Output>,
+            withdrawals: Vec<Withdrawal>,
+            refunds: Vec<Refund>,
+            just_check: bool,
+        ) -> Result<bool>;
+        fn disconnect_sidechain_block(
+            &mut self,
+            block: &SidechainBlockRef,
+            deposits: Vec<Output>,
+            withdrawals: Vec<String>,
+            refunds: Vec<String>,
+            just_check: bool,
+        ) -> Result<bool>;
+        /// For checking on startup that the bridge DB is where the node
+        /// expects it.
+        fn get_last_connected_sidechain_block(&self) -> Result<SidechainBlockRef>;
+        /// disconnect_sidechain_block with the deposits, withdrawals and
+        /// refunds the block was connected with, which connect_sidechain_block
+        /// keeps. Fails with no_undo_data for blocks connected before.
+        fn disconnect_block_by_hash(
+            &mut self,
+            sidechain_hash: &str,
+            just_check: bool,
+        ) -> Result<bool>;
+        /// Re-execute the calls in a replay file recorded with replay_path,
+        /// against this bridge's DB, which should be fresh.
+        fn replay_calls(&mut self, path: &str) -> Result<ReplayReport>;
+        /// Safe to call every block, a bundle is broadcast once and then
+        /// only tracked until it pays out or its vote fails.
         fn attempt_bundle_broadcast(&mut self) -> Result<()>;
+        /// Sample the work score of this sidechain's bundles at the
+        /// mainchain tip. attempt_bundle_broadcast does it too, whether or
+        /// not it broadcasts.
+        fn record_bundle_votes(&self) -> Result<()>;
+        /// The work score samples recorded for bundle_hash, oldest first,
+        /// with the vote each block cast when samples are one block apart.
+        fn get_bundle_vote_history(&self, bundle_hash: &str) -> Result<Vec<BundleVote>>;
+        /// Every bundle seen proposed for this sidechain, whoever proposed
+        /// it, after checking the mainchain for new ones.
+        fn list_proposed_bundles(&self) -> Result<Vec<ProposedBundle>>;
+        /// Run testmempoolaccept on the bundle the bridge would build from
+        /// the pending withdrawals now, without broadcasting it.
+        fn test_bundle_acceptance(&self) -> Result<AcceptanceResult>;
+        fn get_bundle_state(&self) -> Result<BundleState>;
+        /// Hex of the bundle waiting for co-signers when
+        /// external_bundle_signing is set. Its payouts are the highest fee
+        /// withdrawals that fit, and the CTIP input is left unsigned.
+        fn get_unsigned_bundle(&self) -> Result<String>;
+        /// Propose the co-signed bundle from get_unsigned_bundle on the
+        /// mainchain. Anything but added signatures is rejected.
+        fn submit_signed_bundle(&mut self, tx_hex: &str) -> Result<()>;
+        /// Keep withdrawals to these mainchain destinations out of bundles
+        /// this node broadcasts, they stay pending. An empty list allows
+        /// every destination again.
+        fn set_withdrawal_denylist(&mut self, main_addresses: Vec<String>) -> Result<()>;
+        /// Pending withdrawals the filter keeps out of bundles.
+        fn get_filtered_withdrawals(&self) -> Result<Vec<Withdrawal>>;
+        fn get_broadcast_queue(&self) -> Result<Vec<BroadcastEntry>>;
         fn is_outpoint_spent(&self, outpoint: &str) -> Result<bool>;
+        /// False both for blocks on a stale branch and for ones the node
+        /// doesn't know, see get_main_block_chain_status.
         fn is_main_block_connected(&self, main_block_hash: &str) -> Result<bool>;
+        /// is_main_block_connected for each of main_block_hashes, in order.
+        /// Blocks missing from the header cache are looked up together, in
+        /// batch requests.
+        fn filter_connected_main_blocks(&self, main_block_hashes: Vec<String>)
+            -> Result<Vec<bool>>;
+        fn get_main_block_chain_status(
+            &self,
+            main_block_hash: &str,
+        ) -> Result<MainBlockChainStatus>;
+        /// Asks the mainchain node, a commitment it finds is remembered for
+        /// verify_bmm_cached.
         fn verify_bmm(&self, main_block_hash: &str, critical_hash: &str) -> Result<bool>;
+        /// verify_bmm without an RPC call for commitments already verified,
+        /// for block relay.
+        fn verify_bmm_cached(&self, main_block_hash: &str, critical_hash: &str) -> Result<bool>;
+        /// Forget the commitments verified in main_block_hash. Done for the
+        /// blocks a reorg disconnects once get_mainchain_tip sees it, call
+        /// this for the ones reorged out while the bridge wasn't running,
+        /// or verify_bmm_cached keeps accepting them. Returns how many
+        /// there were.
+        fn invalidate_verified_bmm(&self, main_block_hash: &str) -> Result<usize>;
+        fn get_bmm_proof(&self, main_block_hash: &str, critical_hash: &str) -> Result<BmmProof>;
+        /// Every pending deposit, mature or not.
         fn get_deposit_outputs(&self) -> Result<Vec<Output>>;
+        /// Pending deposits, mature or not, that haven't been acknowledged
+        /// with ack_deposits.
+        fn get_unacked_deposit_outputs(&self) -> Result<Vec<DepositOutput>>;
+        /// Mark deposits from get_unacked_deposit_outputs as credited, so
+        /// that it leaves them out. Fails without acknowledging any if one
+        /// of ids isn't pending.
+        fn ack_deposits(&self, ids: Vec<u64>) -> Result<()>;
+        /// Pending deposits younger than deposit_maturity.
+        fn get_immature_deposits(&self) -> Result<Vec<ImmatureDeposit>>;
+        /// get_deposit_outputs from the cache kept by the scheduler, see
+        /// deposit_prefetch_interval_ms. Fetched now if the cache is
+        /// empty, as it is after a block was connected or disconnected.
+        fn get_prefetched_deposit_outputs(&self) -> Result<PrefetchedDeposits>;
+        /// Snapshot get_deposit_outputs for reading in chunks with
+        /// next_deposits, returns the cursor.
+        fn open_deposit_cursor(&self) -> Result<u64>;
+        /// Up to n more deposits from cursor. An empty result means the
+        /// cursor is done, it is closed then.
+        fn next_deposits(&self, cursor: u64, n: usize) -> Result<Vec<Output>>;
+        /// Close cursor before reading it to the end.
+        fn close_deposit_cursor(&self, cursor: u64) -> Result<()>;
+        /// Check every deposit from get_deposit_outputs against the CTIP
+        /// value change of the mainchain deposits found by the deposit scan,
+        /// instead of trusting the amount the node reports. Previous CTIPs
+        /// that aren't scanned deposits need -txindex to look up.
+        fn verify_deposit_amounts(&self) -> Result<Vec<DepositVerification>>;
+        fn get_withdrawal_status(&self, outpoint: &str) -> Result<WithdrawalStatus>;
+        /// A consistent view of deposits, spent outpoints and withdrawal
+        /// statuses, the same as open_read_handle().open_read_snapshot().
+        fn open_read_snapshot(&self) -> Result<Box<ReadSnapshot>>;
+        /// For RPC threads to open snapshots without going through the
+        /// Drivechain, and so without waiting for the caller's lock on it.
+        fn open_read_handle(&self) -> Box<ReadHandle>;
+        /// Mainchain voting on a bundle, by its hash as listed by
+        /// listwithdrawalstatus.
+        fn get_bundle_vote_status(&self, bundle_hash: &str) -> Result<VoteStatus>;
+        /// Fails with withdrawal_not_paid until the withdrawal is spent.
+        /// Looking up bundles needs -txindex on the mainchain node.
+        fn get_withdrawal_payout(&self, outpoint: &str) -> Result<PayoutInfo>;
+        /// Withdrawals connected and not yet spent, for withdrawal fee
+        /// recommendations.
+        fn get_pending_withdrawals_summary(&self) -> Result<PendingWithdrawalsSummary>;
+        /// Deposits, paid and pending withdrawals and the escrow they
+        /// should add up to, checked against the CTIP.
+        fn get_peg_summary(&self) -> Result<PegSummary>;
+        fn start_deposit_scan(&self, start_height: u64) -> Result<()>;
+        fn get_deposit_scan_progress(&self) -> DepositScanProgress;
+        fn get_deposit_proof(&self, mainchain_txid: &str) -> Result<DepositProof>;
+        fn watch_deposit_address(&self, address: &str) -> Result<()>;
+        fn unwatch_deposit_address(&self, address: &str) -> Result<()>;
+        /// Save address under label for withdrawal UIs, replacing what was
+        /// saved under it. The address has to be for the mainchain node's
+        /// network and a destination withdrawals can pay.
+        fn add_withdrawal_destination(&self, label: &str, address: &str) -> Result<()>;
+        fn remove_withdrawal_destination(&self, label: &str) -> Result<()>;
+        /// Saved withdrawal destinations, by label.
+        fn list_withdrawal_destinations(&self) -> Result<Vec<WithdrawalDestination>>;
+        /// Deposit events for watched addresses since the previous call,
+        /// oldest first. Returned events are removed from the DB.
+        fn get_watched_deposit_events(&self) -> Result<Vec<DepositEvent>>;
+        /// Deposits connected to sidechain_address and not disconnected
+        /// since, oldest first. Only deposits connected since the index was
+        /// added are in it.
+        fn get_deposit_history(&self, sidechain_address: &str) -> Result<Vec<DepositRecord>>;
+        /// Deposits made by mainchain transaction mainchain_txid that are
+        /// in get_deposit_history. Fails with index_unavailable unless
+        /// built with the explorer-index feature.
+        fn get_deposits_by_main_txid(&self, mainchain_txid: &str) -> Result<Vec<IndexedDeposit>>;
+        /// Withdrawals bundle_txid paid out, as far as get_withdrawal_payout
+        /// has found them. Fails with index_unavailable unless built with
+        /// the explorer-index feature.
+        fn get_bundle_withdrawals(&self, bundle_txid: &str) -> Result<Vec<PayoutInfo>>;
+        /// Index the deposit history and withdrawal payouts again, returns
+        /// how many entries were indexed. Done on open when the index is
+        /// missing, so only needed after repairing the DB by hand.
+        fn rebuild_explorer_index(&self) -> Result<u64>;
+        /// Alerts whose condition holds right now.
+        fn get_active_alerts(&self) -> Result<Vec<Alert>>;
+        /// Alerts that went off since the previous call, oldest first, for
+        /// paging. Each is reported once until its condition clears.
+        fn get_alert_events(&self) -> Result<Vec<Alert>>;
+        /// Audit log entries made between two unix times, inclusive, oldest
+        /// first. Every connected or disconnected block, bundle broadcast
+        /// and flush is logged, checks with just_check aren't.
+        fn get_audit_log(&self, from_time: u64, to_time: u64) -> Result<Vec<AuditEntry>>;
+        fn set_alert_policy(&mut self, policy: AlertPolicy);
         fn format_deposit_address(&self, address: &str) -> String;
-        fn extract_mainchain_address_bytes(address: &str) -> Result<Vec<u8>>;
+        /// The sidechain address a deposit address was formatted from.
+        /// Fails unless formatting it gives deposit_address back, checksum
+        /// and all.
+        fn parse_deposit_address(&self, deposit_address: &str) -> Result<String>;
+        /// Hex encoded script of this sidechain's escrow output.
+        fn get_escrow_script(&self) -> Result<String>;
+        /// Escrows of every active sidechain on the mainchain, this one
+        /// included.
+        fn list_sidechain_escrows(&self) -> Result<Vec<EscrowInfo>>;
+        /// Have the mainchain node propose activating this sidechain in the
+        /// blocks it mines. Progress is tracked by get_activation_status.
+        fn bootstrap_sidechain(&self, proposal: SidechainProposal) -> Result<()>;
+        fn get_activation_status(&self) -> Result<ActivationStatus>;
+        /// Script of the coinbase output committing to critical_hash, for
+        /// pools building their own coinbase instead of using attempt_bmm.
+        fn get_bmm_commitment_script(&self, critical_hash: &str) -> Result<Vec<u8>>;
+        /// The hash a mainchain address pays to and its type. network is
+        /// bitcoin, testnet, signet or regtest, addresses for another
+        /// network and other script types are rejected.
+        fn extract_mainchain_address_bytes(address: &str, network: &str) -> Result<DestAddress>;
+        /// BMM commitments of every sidechain in a hex serialized mainchain
+        /// block, read from its coinbase without asking the node.
+        fn extract_bmm_commitments(main_block_hex: &str) -> Result<Vec<BmmCommitment>>;
+        /// The critical hash of a serialized sidechain block header, as
+        /// attempt_bmm and verify_bmm take it: double SHA256 of the bytes,
+        /// hex encoded in reverse byte order like a txid. The commitment
+        /// script holds the hash in the unreversed order.
+        fn compute_critical_hash(sidechain_header: &[u8]) -> Result<String>;
+        /// Block data, decompressed if needed.
+        fn block_data(block: &Block) -> Result<String>;
+        /// Validate a withdrawal main_address. The drivechain crate only pays
+        /// P2PKH, bundles paying P2SH, P2WPKH or P2TR are built by the
+        /// bridge.
+        fn parse_withdrawal_dest(main_address: &str) -> Result<DestAddress>;
+        /// Hex outpoints, destinations and hashes checked for their length
+        /// the way the bridge decodes them. parse_* fail on bad hex or a
+        /// wrong length, format_* on a wrong length.
+        fn parse_outpoint(outpoint: &str) -> Result<Vec<u8>>;
+        fn format_outpoint(outpoint: &[u8]) -> Result<String>;
+        fn parse_dest20(dest: &str) -> Result<Vec<u8>>;
+        fn format_dest20(dest: &[u8]) -> Result<String>;
+        fn parse_hash32(hash: &str) -> Result<Vec<u8>>;
+        fn format_hash32(hash: &[u8]) -> Result<String>;
         fn get_new_mainchain_address(&self) -> Result<String>;
         fn create_deposit(&self, address: &str, amount: u64, fee: u64) -> Result<String>;
+        /// create_deposit and create_deposit_psbt with typed amounts, the
+        /// u64 ones stay for existing callers.
+        fn create_deposit_with_amount(
+            &self,
+            address: &str,
+            amount: Amount,
+            fee: Amount,
+        ) -> Result<String>;
+        fn create_deposit_psbt_with_amount(
+            &self,
+            address: &str,
+            amount: Amount,
+            fee: Amount,
+        ) -> Result<String>;
+        /// Base64 PSBT for a deposit the mainchain node doesn't fund, to be
+        /// funded and signed by an external wallet. The fee is left to the
+        /// funding wallet and checked by broadcast_deposit_psbt.
+        fn create_deposit_psbt(&self, address: &str, amount: u64, fee: u64) -> Result<String>;
+        /// Broadcast a finalized deposit PSBT, returning the mainchain txid.
+        fn broadcast_deposit_psbt(&self, psbt: &str) -> Result<String>;
         fn generate(&self, n: u64) -> Result<Vec<String>>;
+        /// Regtest helper mining n blocks paying the coinbase to address.
+        fn generate_to_address(&self, n: u64, address: &str) -> Result<Vec<String>>;
+        /// Regtest helper invalidating the last depth mainchain blocks, as a
+        /// reorg would disconnect them. Returns their hashes, tip first, for
+        /// disconnecting the sidechain blocks that depend on them.
+        fn simulate_reorg(&self, depth: u64) -> Result<Vec<String>>;
         fn flush(&mut self) -> Result<usize>;
+        fn set_flush_policy(&self, policy: FlushPolicy);
+        /// Run the scheduled tasks that are due, see SchedulerConfig. Meant
+        /// to be called from the sidechain's main loop, as often as the
+        /// shortest task interval. Flushes, deposit prefetching and broadcast
+        /// retries that didn't do anything aren't reported.
+        fn tick(&mut self) -> Vec<TaskRun>;
+        fn set_bundle_policy(&mut self, policy: BundlePolicy);
+        fn get_bundle_policy(&self) -> BundlePolicy;
+        fn set_dust_policy(&mut self, policy: DustPolicy);
+        fn get_dust_policy(&self) -> DustPolicy;
+        /// Withdrawals connect_block refused or bundles left out for being
+        /// below the minimum amount. Ones left out of bundles are only
+        /// listed while pending.
+        fn get_dust_withdrawals(&self) -> Result<Vec<DustWithdrawal>>;
+        /// Bridge DB records written since the last flush. Writes to the
+        /// drivechain crate's DB aren't counted.
+        fn get_dirty_count(&self) -> usize;
+        /// Encrypt the bridge's own DB in place. Later opens need
+        /// db_passphrase or db_key_file set to passphrase. The drivechain
+        /// crate's DB stays plaintext, see Config::db_passphrase.
+        fn encrypt_db(&self, passphrase: &str) -> Result<()>;
+        fn set_header_cache_capacity(&self, capacity: usize);
+        fn clear_caches(&self);
+        fn sync_spv_headers(&self) -> Result<u64>;
+        fn get_spv_tip(&self) -> Result<String>;
+        fn verify_tx_inclusion(
+            &self,
+            main_block_hash: &str,
+            txid: &str,
+            merkle_branch: Vec<String>,
+            index: u32,
+        ) -> Result<bool>;
+    }
+}
+
+pub struct Drivechain {
+    // Shared with the scheduler thread.
+    inner: Arc<Mutex<Box<dyn Backend>>>,
+    flush_policy: Arc<Mutex<ffi::FlushPolicy>>,
+    // Shared with read handles.
+    mainchain: Arc<dyn MainchainBackend>,
+    this_sidechain: usize,
+    script_template: ffi::ScriptTemplate,
+    deposit_addresses: DepositAddressFormatter,
+    network_guard: bool,
+    watch_only: bool,
+    external_bundle_signing: bool,
+    check_bundle_acceptance: bool,
+    deposit_maturity: u64,
+    withdrawal_maturity: u64,
+    bmm_signer: Option<KeySigner>,
+    bmm_request_ttl_secs: u64,
+    // Id of the BMM attempt the drivechain crate holds. It keeps requests in
+    // memory only, so the ones pending from before a restart or a reopen
+    // are confirmed from the mainchain.
+    inner_bmm_attempt: Mutex<Option<u64>>,
+    // The RPC user and password the drivechain crate was last opened with.
+    rpc_credentials: Mutex<(String, String)>,
+    withdrawal_filter: Option<WithdrawalFilter>,
+    bundle_vote_period: u64,
+    bundle_min_work_score: u64,
+    bundle_policy: ffi::BundlePolicy,
+    dust_policy: ffi::DustPolicy,
+    alert_policy: ffi::AlertPolicy,
+    // Alerts active at the last check, an event is raised for the others.
+    raised_alerts: Mutex<Vec<db::AlertKind>>,
+    recorder: Option<Recorder>,
+    block_limits: ffi::BlockLimits,
+    // Shared with the scheduler thread, if there is one.
+    deposit_cache: DepositCache,
+    // The tasks tick runs, the scheduler thread has the others.
+    scheduler: Scheduler,
+    deposit_cursors: Mutex<DepositCursors>,
+    // Whether the mainchain node is on mainnet, asked once on first use.
+    mainnet: Mutex<Option<bool>>,
+    // Held while mining, concurrent generate calls race on the mainchain
+    // wallet.
+    mining: Mutex<()>,
+    // Number of blocks applied by connect_block or disconnect_block,
+    // written while one is applied, so that snapshots never read half of
+    // one and can tell they are stale.
+    applying: Arc<RwLock<u64>>,
+    client: MainClient,
+    db: Db,
+    scanner: DepositScanner,
+    header_cache: Mutex<HeaderCache>,
+    spv: Option<Arc<Mutex<Spv>>>,
+    // MainchainBackendKind::Spv, BMM commitments are then checked in blocks
+    // matched to the header chain rather than by the drivechain crate.
+    spv_verify: bool,
+    // Opens the drivechain crate again with other RPC credentials, None for
+    // backends that don't hold any.
+    reopen_inner: Option<ReopenBackend>,
+}
+
+type ReopenBackend = Box<dyn Fn(&str, &str) -> Result<Box<dyn Backend>> + Send>;
+
+/// Whether a withdrawal may go in the bundles this node broadcasts, given
+/// with main_address as the hex pubkey hash.
+pub type WithdrawalFilter = Box<dyn Fn(&ffi::Withdrawal) -> bool + Send>;
+
+// The drivechain crate hands out all the deposits at once, so a cursor owns
+// them and only the chunks cross the FFI.
+#[derive(Default)]
+struct DepositCursors {
+    next_cursor: u64,
+    open: HashMap<u64, std::vec::IntoIter<drive::Deposit>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn decode_tx(tx_hex: &str) -> Result<bitcoin::Transaction> {
+    let invalid = |message: String| Error::InvalidEncoding { message };
+    let tx = hex::decode(tx_hex).map_err(|err| invalid(err.to_string()))?;
+    Ok(deserialize(&tx).map_err(|err| invalid(err.to_string()))?)
+}
+
+fn bmm_attempt(id: u64, record: db::BmmAttemptRecord) -> ffi::BmmAttempt {
+    ffi::BmmAttempt {
+        id,
+        created_at: record.created_at,
+        critical_hash: record.critical_hash,
+        prev_main_block_hash: record.prev_main_block_hash,
+        amount: record.amount,
+        txid: record.txid,
+        state: bmm_state(record.outcome),
+        main_block_hash: record.main_block_hash,
+    }
+}
+
+fn bmm_state(outcome: db::BmmOutcome) -> ffi::BMMState {
+    match outcome {
+        db::BmmOutcome::Succeded => ffi::BMMState::Succeded,
+        db::BmmOutcome::Failed => ffi::BMMState::Failed,
+        db::BmmOutcome::Pending => ffi::BMMState::Pending,
+        db::BmmOutcome::Expired => ffi::BMMState::Expired,
+    }
+}
+
+fn alert(record: db::AlertRecord) -> ffi::Alert {
+    ffi::Alert {
+        kind: match record.kind {
+            db::AlertKind::BundleFailed => ffi::AlertKind::BundleFailed,
+            db::AlertKind::BmmFailed => ffi::AlertKind::BmmFailed,
+            db::AlertKind::MainchainUnreachable => ffi::AlertKind::MainchainUnreachable,
+            db::AlertKind::ForeignBundle => ffi::AlertKind::ForeignBundle,
+        },
+        message: record.message,
+        since: record.since,
+    }
+}
+
+fn audit_entry(id: u64, record: db::AuditRecord) -> ffi::AuditEntry {
+    ffi::AuditEntry {
+        id,
+        at: record.at,
+        operation: match record.operation {
+            db::AuditOperation::ConnectBlock => ffi::AuditOperation::ConnectBlock,
+            db::AuditOperation::DisconnectBlock => ffi::AuditOperation::DisconnectBlock,
+            db::AuditOperation::BundleBroadcast => ffi::AuditOperation::BundleBroadcast,
+            db::AuditOperation::Flush => ffi::AuditOperation::Flush,
+            db::AuditOperation::SlotMigration => ffi::AuditOperation::SlotMigration,
+        },
+        main_block_hash: record.main_block_hash,
+        detail: record.detail,
+    }
+}
+
+// Payees with the main height their withdrawal was connected at. Sorts are
+// stable, equal withdrawals stay in outpoint order.
+fn order_payees(
+    policy: &ffi::BundlePolicy,
+    payees: &mut [(Vec<u8>, db::WithdrawalPayeeRecord, u64)],
+) {
+    match policy.ordering {
+        ffi::BundleOrdering::OldestFirst => payees.sort_by_key(|(_, _, main_height)| *main_height),
+        ffi::BundleOrdering::Hybrid => payees.sort_by(|(_, a, a_height), (_, b, b_height)| {
+            let floor = policy.fee_floor;
+            match (a.main_fee >= floor, b.main_fee >= floor) {
+                (true, true) => a_height.cmp(b_height),
+                (false, false) => b.main_fee.cmp(&a.main_fee),
+                (a_above, b_above) => b_above.cmp(&a_above),
+            }
+        }),
+        _ => payees.sort_by(|(_, a, _), (_, b, _)| b.main_fee.cmp(&a.main_fee)),
+    }
+}
+
+fn bundle_state(stage: db::BundleStage) -> ffi::BundleState {
+    match stage {
+        db::BundleStage::Idle => ffi::BundleState::Idle,
+        db::BundleStage::Created => ffi::BundleState::Created,
+        db::BundleStage::Broadcast => ffi::BundleState::Broadcast,
+        db::BundleStage::Voting => ffi::BundleState::Voting,
+        db::BundleStage::Failed => ffi::BundleState::Failed,
+        db::BundleStage::PaidOut => ffi::BundleState::PaidOut,
+        db::BundleStage::AwaitingSignatures => ffi::BundleState::AwaitingSignatures,
+    }
+}
+
+pub(crate) fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn get_library_info() -> ffi::LibraryInfo {
+    let features = [
+        ("grpc", cfg!(feature = "grpc")),
+        ("capi", cfg!(feature = "capi")),
+        ("async-rpc", cfg!(feature = "async-rpc")),
+        ("mock", cfg!(feature = "mock")),
+        ("rocksdb", cfg!(feature = "rocksdb")),
+        ("zstd", cfg!(feature = "zstd")),
+        ("refund_amount_check", cfg!(feature = "refund_amount_check")),
+        ("explorer-index", cfg!(feature = "explorer-index")),
+    ];
+    ffi::LibraryInfo {
+        version: env!("CARGO_PKG_VERSION").into(),
+        git_commit: env!("DRIVECHAIN_CPP_GIT_COMMIT").into(),
+        features: features
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(feature, _)| feature.to_string())
+            .collect(),
+        drivechain_rev: env!("DRIVECHAIN_CPP_DRIVECHAIN_REV").into(),
     }
 }
 
-pub struct Drivechain(drive::Drivechain);
+pub fn default_config() -> ffi::Config {
+    let rpc_options = RpcOptions::default();
+    ffi::Config {
+        db_path: String::new(),
+        this_sidechain: 0,
+        main_host: "localhost".into(),
+        main_port: 18443,
+        rpcuser: String::new(),
+        rpcpassword: String::new(),
+        rpc_requests_per_second: rpc_options.requests_per_second,
+        rpc_batch_size: rpc_options.batch_size,
+        header_cache_capacity: DEFAULT_HEADER_CACHE_CAPACITY,
+        spv_network: String::new(),
+        main_p2p_port: 18444,
+        script_template: script::bip300_script_template(),
+        deposit_address_format: ffi::DepositAddressFormat::Drivechain,
+        deposit_address_prefix: "s".into(),
+        network_guard: true,
+        i_know_what_im_doing: false,
+        watch_only: false,
+        mainchain_backend: ffi::MainchainBackendKind::Rpc,
+        main_endpoints: Vec::new(),
+        rpc_proxy: String::new(),
+        rpc_proxy_user: String::new(),
+        rpc_proxy_password: String::new(),
+        db_passphrase: String::new(),
+        db_key_file: String::new(),
+        storage_backend: ffi::StorageBackendKind::Sled,
+        flush_policy: ffi::FlushPolicy {
+            interval_secs: DEFAULT_FLUSH_INTERVAL_SECS,
+            dirty_threshold: DEFAULT_FLUSH_DIRTY_THRESHOLD,
+        },
+        scheduler: ffi::SchedulerConfig {
+            mode: ffi::SchedulerMode::Tick,
+            confirm_bmm_interval_ms: 0,
+            broadcast_retry_interval_ms: DEFAULT_BROADCAST_RETRY_INTERVAL_MS,
+        },
+        external_bundle_signing: false,
+        check_bundle_acceptance: false,
+        bundle_vote_period: DEFAULT_BUNDLE_VOTE_PERIOD,
+        bundle_min_work_score: DEFAULT_BUNDLE_MIN_WORK_SCORE,
+        bundle_policy: ffi::BundlePolicy {
+            ordering: ffi::BundleOrdering::HighestFeeFirst,
+            fee_floor: 0,
+        },
+        dust_policy: ffi::DustPolicy {
+            min_amount: 0,
+            mode: ffi::DustMode::Bundle,
+        },
+        alert_policy: ffi::AlertPolicy {
+            bundle_failures: DEFAULT_ALERT_BUNDLE_FAILURES,
+            bmm_failures: DEFAULT_ALERT_BMM_FAILURES,
+            mainchain_unreachable_secs: DEFAULT_ALERT_MAINCHAIN_UNREACHABLE_SECS,
+            foreign_bundles: true,
+        },
+        replay_path: String::new(),
+        block_limits: ffi::BlockLimits {
+            max_deposits: DEFAULT_MAX_BLOCK_ENTRIES,
+            max_withdrawals: DEFAULT_MAX_BLOCK_ENTRIES,
+            max_refunds: DEFAULT_MAX_BLOCK_ENTRIES,
+        },
+        deposit_prefetch_interval_ms: 0,
+        deposit_maturity: 0,
+        withdrawal_maturity: 0,
+        bmm_signing_key: String::new(),
+        bmm_request_ttl_secs: DEFAULT_BMM_REQUEST_TTL_SECS,
+    }
+}
 
-fn new_drivechain(
+pub fn new_drivechain(
     db_path: &str,
     this_sidechain: usize,
     main_host: &str,
@@ -94,210 +1760,3884 @@ fn new_drivechain(
     rpcuser: &str,
     rpcpassword: &str,
 ) -> Result<Box<Drivechain>> {
-    let drivechain = drive::Drivechain::new(
-        db_path,
+    new_drivechain_from_config(&ffi::Config {
+        db_path: db_path.into(),
+        this_sidechain,
+        main_host: main_host.into(),
+        main_port,
+        rpcuser: rpcuser.into(),
+        rpcpassword: rpcpassword.into(),
+        ..default_config()
+    })
+}
+
+// Same as new_drivechain, but additionally syncs mainchain headers over P2P
+// from main_host:main_p2p_port so inclusion proofs can be checked locally.
+#[allow(clippy::too_many_arguments)]
+pub fn new_drivechain_with_spv(
+    db_path: &str,
+    this_sidechain: usize,
+    main_host: &str,
+    main_port: u16,
+    rpcuser: &str,
+    rpcpassword: &str,
+    network: &str,
+    main_p2p_port: u16,
+) -> Result<Box<Drivechain>> {
+    new_drivechain_from_config(&ffi::Config {
+        db_path: db_path.into(),
         this_sidechain,
-        main_host,
+        main_host: main_host.into(),
         main_port,
-        rpcuser.into(),
-        rpcpassword.into(),
+        rpcuser: rpcuser.into(),
+        rpcpassword: rpcpassword.into(),
+        spv_network: network.into(),
+        main_p2p_port,
+        ..default_config()
+    })
+}
+
+pub fn new_drivechain_from_config(config: &ffi::Config) -> Result<Box<Drivechain>> {
+    if config.mainchain_backend == ffi::MainchainBackendKind::Mock {
+        #[cfg(feature = "mock")]
+        return new_drivechain_with_mock(
+            config,
+            crate::mock::MockBackend::new(config.this_sidechain),
+        );
+        #[cfg(not(feature = "mock"))]
+        return Err(Error::BackendUnavailable { backend: "mock" }.into());
+    }
+    let drivechain = drive::Drivechain::new(
+        &config.db_path,
+        config.this_sidechain,
+        tcp_host(config),
+        config.main_port,
+        config.rpcuser.clone(),
+        config.rpcpassword.clone(),
     )
     .into_diagnostic()?;
-    Ok(Box::new(Drivechain(drivechain)))
+    let client = MainClient::new(
+        &config.main_host,
+        config.main_port,
+        &config.rpcuser,
+        &config.rpcpassword,
+        RpcOptions {
+            requests_per_second: config.rpc_requests_per_second,
+            batch_size: config.rpc_batch_size,
+        },
+    )
+    .with_fallbacks(&config.main_endpoints);
+    let client = match config.rpc_proxy.as_str() {
+        "" => client,
+        proxy => client.with_proxy(proxy, &config.rpc_proxy_user, &config.rpc_proxy_password)?,
+    };
+    let spv = open_spv(config)?;
+    let mainchain: Box<dyn MainchainBackend> = match (config.mainchain_backend, &spv) {
+        (ffi::MainchainBackendKind::Spv, Some(spv)) => Box::new(SpvMainchain(spv.clone())),
+        (ffi::MainchainBackendKind::Spv, None) => return Err(Error::SpvDisabled.into()),
+        _ => Box::new(client.clone()),
+    };
+    let mut drivechain = open_drivechain(config, Box::new(drivechain), mainchain, client, spv)?;
+    let config = config.clone();
+    drivechain.reopen_inner = Some(Box::new(move |rpcuser: &str, rpcpassword: &str| {
+        let drivechain = drive::Drivechain::new(
+            &config.db_path,
+            config.this_sidechain,
+            tcp_host(&config),
+            config.main_port,
+            rpcuser.to_string(),
+            rpcpassword.to_string(),
+        )
+        .into_diagnostic()?;
+        Ok(Box::new(drivechain) as Box<dyn Backend>)
+    }));
+    Ok(drivechain)
 }
 
-impl Drivechain {
-    fn get_mainchain_tip(&self) -> Result<String> {
-        let tip = self.0.get_mainchain_tip().into_diagnostic()?;
-        Ok(tip.to_string())
+fn open_spv(config: &ffi::Config) -> Result<Option<Arc<Mutex<Spv>>>> {
+    match config.spv_network.as_str() {
+        "" => Ok(None),
+        network => {
+            let network = Network::from_str(network).into_diagnostic()?;
+            Ok(Some(Arc::new(Mutex::new(Spv::new(
+                network,
+                tcp_host(config),
+                config.main_p2p_port,
+            )))))
+        }
     }
-    fn get_prev_main_block_hash(&self, main_block_hash: &str) -> Result<Vec<u8>> {
-        let main_block_hash = BlockHash::from_str(main_block_hash).into_diagnostic()?;
-        let prev_hash = self
-            .0
-            .get_prev_main_block_hash(&main_block_hash)
-            .into_diagnostic()?;
-        Ok(prev_hash.to_vec())
+}
+
+fn tcp_host(config: &ffi::Config) -> &str {
+    match config.main_host.starts_with(UNIX_SOCKET_PREFIX) {
+        true => "localhost",
+        false => &config.main_host,
     }
-    fn confirm_bmm(&mut self) -> Result<ffi::BMMState> {
-        self.0
-            .confirm_bmm()
-            .map(|state| match state {
-                drivechain::BMMState::Succeded => ffi::BMMState::Succeded,
-                drivechain::BMMState::Failed => ffi::BMMState::Failed,
-                drivechain::BMMState::Pending => ffi::BMMState::Pending,
-            })
-            .into_diagnostic()
+}
+
+/// A drivechain backed by mock instead of the drivechain crate and a
+/// mainchain node, whatever config.mainchain_backend says. Keep a clone of
+/// mock to inspect its state.
+#[cfg(feature = "mock")]
+pub fn new_drivechain_with_mock(
+    config: &ffi::Config,
+    mock: crate::mock::MockBackend,
+) -> Result<Box<Drivechain>> {
+    let client = MainClient::new(
+        &config.main_host,
+        config.main_port,
+        &config.rpcuser,
+        &config.rpcpassword,
+        RpcOptions::default(),
+    );
+    let spv = open_spv(config)?;
+    open_drivechain(config, Box::new(mock.clone()), Box::new(mock), client, spv)
+}
+
+fn open_storage(config: &ffi::Config) -> Result<Arc<dyn Storage>> {
+    let db_path = Path::new(&config.db_path);
+    match config.storage_backend {
+        #[cfg(feature = "rocksdb")]
+        ffi::StorageBackendKind::RocksDb => Ok(Arc::new(storage::RocksStorage::open(
+            &db_path.join("drivechain-cpp-rocksdb"),
+        )?)),
+        #[cfg(not(feature = "rocksdb"))]
+        ffi::StorageBackendKind::RocksDb => {
+            Err(Error::BackendUnavailable { backend: "rocksdb" }.into())
+        }
+        ffi::StorageBackendKind::Memory => Ok(Arc::new(storage::MemoryStorage::default())),
+        _ => Ok(Arc::new(storage::SledStorage::open(
+            &db_path.join("drivechain-cpp"),
+        )?)),
     }
+}
 
-    fn attempt_bmm(
-        &mut self,
-        critical_hash: &str,
-        prev_main_block_hash: &str,
-        amount: u64,
-    ) -> Result<()> {
-        let critical_hash = TxMerkleNode::from_str(critical_hash).into_diagnostic()?;
-        let prev_main_block_hash = BlockHash::from_str(prev_main_block_hash).into_diagnostic()?;
-        let amount = bitcoin::Amount::from_sat(amount);
-        self.0
-            .attempt_bmm(&critical_hash, &prev_main_block_hash, amount)
-            .into_diagnostic()?;
-        Ok(())
+fn db_passphrase(config: &ffi::Config) -> Result<Option<String>> {
+    if !config.db_passphrase.is_empty() {
+        return Ok(Some(config.db_passphrase.clone()));
+    }
+    if config.db_key_file.is_empty() {
+        return Ok(None);
     }
+    let passphrase = std::fs::read_to_string(&config.db_key_file).into_diagnostic()?;
+    Ok(Some(passphrase.trim_end_matches(['\r', '\n']).to_owned()))
+}
 
-    fn is_main_block_connected(&self, main_block_hash: &str) -> Result<bool> {
-        let main_block_hash = BlockHash::from_str(main_block_hash).into_diagnostic()?;
-        self.0
-            .is_main_block_connected(&main_block_hash)
-            .into_diagnostic()
+// What a node with only read-only RPC methods allowed has to answer.
+fn probe_read_only(client: &MainClient) -> Result<(), Error> {
+    let probe = |method: &'static str, result: Result<(), Error>| {
+        result.map_err(|err| Error::WatchOnlyProbe {
+            method,
+            message: err.to_string(),
+        })
+    };
+    probe("getblockcount", client.get_block_count().map(drop))?;
+    probe("getbestblockhash", client.get_best_block_hash().map(drop))?;
+    probe(
+        "listactivesidechains",
+        client.list_active_sidechains().map(drop),
+    )?;
+    Ok(())
+}
+
+fn open_drivechain(
+    config: &ffi::Config,
+    inner: Box<dyn Backend>,
+    mainchain: Box<dyn MainchainBackend>,
+    client: MainClient,
+    spv: Option<Arc<Mutex<Spv>>>,
+) -> Result<Box<Drivechain>> {
+    let db = Db::open(open_storage(config)?, db_passphrase(config)?.as_deref())?;
+    if config.watch_only {
+        probe_read_only(&client)?;
+    }
+    let inner = Arc::new(Mutex::new(inner));
+    let flush_policy = Arc::new(Mutex::new(config.flush_policy.clone()));
+    let deposit_cache = DepositCache::default();
+    let mut tick_tasks = Vec::new();
+    for (kind, interval_ms) in [
+        (
+            ffi::TaskKind::ConfirmBmm,
+            config.scheduler.confirm_bmm_interval_ms,
+        ),
+        (
+            ffi::TaskKind::RetryBroadcasts,
+            config.scheduler.broadcast_retry_interval_ms,
+        ),
+    ] {
+        if interval_ms > 0 {
+            tick_tasks.push((kind, Duration::from_millis(interval_ms)));
+        }
+    }
+    let mut background_tasks = Vec::new();
+    if config.deposit_prefetch_interval_ms > 0 {
+        background_tasks.push((
+            ffi::TaskKind::PrefetchDeposits,
+            Duration::from_millis(config.deposit_prefetch_interval_ms),
+        ));
+    }
+    background_tasks.push((ffi::TaskKind::Flush, Duration::ZERO));
+    if config.scheduler.mode == ffi::SchedulerMode::Tick {
+        tick_tasks.append(&mut background_tasks);
+    } else {
+        let shared = scheduler::Shared {
+            inner: Arc::downgrade(&inner),
+            db: db.clone(),
+            client: client.clone(),
+            deposit_cache: deposit_cache.clone(),
+            flush_policy: flush_policy.clone(),
+        };
+        scheduler::spawn(shared, Scheduler::new(background_tasks));
+    }
+    let mut restored = 0;
+    for entry in db.iter_bmm_attempts() {
+        if entry?.1.outcome == db::BmmOutcome::Pending {
+            restored += 1;
+        }
+    }
+    if restored > 0 {
+        tracing::info!("restored {restored} pending BMM requests");
     }
+    Ok(Box::new(Drivechain {
+        inner,
+        flush_policy,
+        mainchain: Arc::from(mainchain),
+        this_sidechain: config.this_sidechain,
+        script_template: config.script_template.clone(),
+        deposit_addresses: DepositAddressFormatter::new(config),
+        network_guard: config.network_guard && !config.i_know_what_im_doing,
+        watch_only: config.watch_only,
+        external_bundle_signing: config.external_bundle_signing,
+        check_bundle_acceptance: config.check_bundle_acceptance,
+        deposit_maturity: config.deposit_maturity,
+        withdrawal_maturity: config.withdrawal_maturity,
+        bmm_signer: match config.bmm_signing_key.as_str() {
+            "" => None,
+            wif => Some(KeySigner::from_wif(wif)?),
+        },
+        bmm_request_ttl_secs: config.bmm_request_ttl_secs,
+        inner_bmm_attempt: Mutex::new(None),
+        rpc_credentials: Mutex::new((config.rpcuser.clone(), config.rpcpassword.clone())),
+        withdrawal_filter: None,
+        bundle_vote_period: config.bundle_vote_period,
+        bundle_min_work_score: config.bundle_min_work_score,
+        bundle_policy: config.bundle_policy.clone(),
+        dust_policy: config.dust_policy.clone(),
+        alert_policy: config.alert_policy.clone(),
+        raised_alerts: Mutex::new(Vec::new()),
+        deposit_cursors: Mutex::default(),
+        block_limits: config.block_limits.clone(),
+        deposit_cache,
+        scheduler: Scheduler::new(tick_tasks),
+        recorder: match config.replay_path.as_str() {
+            "" => None,
+            path => Some(Recorder::open(path)?),
+        },
+        mainnet: Mutex::new(None),
+        mining: Mutex::new(()),
+        applying: Arc::default(),
+        scanner: DepositScanner::new(client.clone(), db.clone(), config.this_sidechain),
+        client,
+        db,
+        header_cache: Mutex::new(HeaderCache::new(config.header_cache_capacity)),
+        spv,
+        spv_verify: config.mainchain_backend == ffi::MainchainBackendKind::Spv,
+        reopen_inner: None,
+    }))
+}
 
-    fn verify_bmm(&self, main_block_hash: &str, critical_hash: &str) -> Result<bool> {
-        let main_block_hash = BlockHash::from_str(main_block_hash).into_diagnostic()?;
-        let critical_hash = TxMerkleNode::from_str(critical_hash).into_diagnostic()?;
-        Ok(self.0.verify_bmm(&main_block_hash, &critical_hash).is_ok())
+impl Drivechain {
+    fn inner(&self) -> MutexGuard<Box<dyn Backend>> {
+        lock(&self.inner)
     }
 
-    fn get_deposit_outputs(&self) -> Result<Vec<ffi::Output>> {
-        Ok(self
-            .0
-            .get_deposit_outputs()
-            .into_diagnostic()?
-            .iter()
-            .map(|output| ffi::Output {
-                address: output.address.clone(),
-                amount: output.amount,
-            })
-            .collect())
+    fn header_cache(&self) -> MutexGuard<HeaderCache> {
+        lock(&self.header_cache)
     }
 
-    fn attempt_bundle_broadcast(&mut self) -> Result<()> {
-        Ok(self.0.attempt_bundle_broadcast().into_diagnostic()?)
+    fn mainchain_network(&self) -> Result<Network> {
+        let chain = self.client.get_chain()?;
+        match chain.as_str() {
+            "main" => Ok(Network::Bitcoin),
+            "test" => Ok(Network::Testnet),
+            "signet" => Ok(Network::Signet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(Error::UnknownChain { chain }.into()),
+        }
     }
 
-    fn is_outpoint_spent(&self, outpoint: &str) -> Result<bool> {
-        let outpoint = hex::decode(outpoint).into_diagnostic()?;
-        self.0
-            .is_outpoint_spent(outpoint.as_slice())
-            .into_diagnostic()
+    fn guard_mainnet(&self, operation: &'static str) -> Result<()> {
+        if !self.network_guard {
+            return Ok(());
+        }
+        let mut mainnet = lock(&self.mainnet);
+        let is_mainnet = match *mainnet {
+            Some(is_mainnet) => is_mainnet,
+            None => *mainnet.insert(self.client.get_chain()? == "main"),
+        };
+        if is_mainnet {
+            return Err(Error::MainnetGuard { operation }.into());
+        }
+        Ok(())
     }
 
-    fn connect_block(
-        &mut self,
-        deposits: Vec<ffi::Output>,
-        withdrawals: Vec<ffi::Withdrawal>,
-        refunds: Vec<ffi::Refund>,
-        just_check: bool,
-    ) -> Result<bool> {
-        let deposits: Vec<drive::Deposit> = deposits
+    fn guard_wallet(&self, operation: &'static str) -> Result<()> {
+        if self.watch_only {
+            return Err(Error::WatchOnly { operation }.into());
+        }
+        Ok(())
+    }
+
+    fn guard_deposit_fee(&self, fee: u64) -> Result<()> {
+        if fee < MAINNET_MIN_DEPOSIT_FEE {
+            self.guard_mainnet("a deposit with a fee below 1000 sats")?;
+        }
+        Ok(())
+    }
+
+    fn spv(&self) -> Result<MutexGuard<Spv>> {
+        Ok(lock(self.spv.as_deref().ok_or(Error::SpvDisabled)?))
+    }
+
+    // After the mainchain node went away, check that it still has our
+    // sidechain slot active and drop cached chain state before talking to it
+    // again, the tip may have moved or reorged in the meantime.
+    fn ensure_connected(&self) -> Result<()> {
+        self.client.check_health();
+        if !self.client.is_disconnected() {
+            return Ok(());
+        }
+        if !self.client.is_sidechain_active(self.this_sidechain)? {
+            return Err(Error::SidechainInactive {
+                sidechain: self.this_sidechain,
+            }
+            .into());
+        }
+        self.header_cache().clear();
+        let tip = self.mainchain.get_mainchain_tip()?;
+        self.header_cache().observe_tip(tip);
+        self.client.mark_connected();
+        Ok(())
+    }
+
+    // The drivechain crate only takes credentials when it is opened, and
+    // its DB is locked while it is open, so the new handle can't be opened
+    // before the old one is gone. If it fails to open, the crate is opened
+    // again with the old credentials, and only left closed if that fails
+    // too.
+    pub fn update_rpc_credentials(&self, rpcuser: &str, rpcpassword: &str) -> Result<()> {
+        self.client
+            .with_credentials(rpcuser, rpcpassword)
+            .get_block_count()?;
+        if let Some(reopen_inner) = &self.reopen_inner {
+            let mut inner = self.inner();
+            inner.flush()?;
+            let (old_rpcuser, old_rpcpassword) = lock(&self.rpc_credentials).clone();
+            *inner = Box::new(backend::Closed(String::new()));
+            // Neither handle holds the request the old one did.
+            *lock(&self.inner_bmm_attempt) = None;
+            match reopen_inner(rpcuser, rpcpassword) {
+                Ok(reopened) => *inner = reopened,
+                Err(err) => {
+                    *inner = match reopen_inner(&old_rpcuser, &old_rpcpassword) {
+                        Ok(restored) => restored,
+                        Err(restore_err) => Box::new(backend::Closed(restore_err.to_string())),
+                    };
+                    return Err(err);
+                }
+            }
+            *lock(&self.rpc_credentials) = (rpcuser.into(), rpcpassword.into());
+        }
+        self.client.set_credentials(rpcuser, rpcpassword);
+        Ok(())
+    }
+
+    pub fn get_mainchain_tip(&self) -> Result<String> {
+        self.ensure_connected()?;
+        let tip = self.client.track(self.mainchain.get_mainchain_tip())?;
+        self.observe_tip(&self.client, tip)?;
+        Ok(tip.to_string())
+    }
+    pub fn get_prev_main_block_hash(&self, main_block_hash: &str) -> Result<Vec<u8>> {
+        self.ensure_connected()?;
+        let main_block_hash = BlockHash::from_str(main_block_hash).into_diagnostic()?;
+        let cached = self.header_cache().get_prev(&main_block_hash);
+        if let Some(prev_hash) = cached {
+            return Ok(prev_hash.to_vec());
+        }
+        let prev_hash = self
+            .client
+            .track(self.mainchain.get_prev_main_block_hash(&main_block_hash))?;
+        self.header_cache().put_prev(main_block_hash, prev_hash);
+        Ok(prev_hash.to_vec())
+    }
+
+    // The node doesn't announce reorgs, and invalidateblock reorgs without
+    // a new block. A new tip is checked for having disconnected the last
+    // one, and what is cached about the disconnected blocks is dropped.
+    // Returns the disconnected blocks, tip first.
+    fn observe_tip(&self, client: &MainClient, tip: BlockHash) -> Result<Vec<BlockHash>> {
+        let mut disconnected = Vec::new();
+        let Some(last_tip) = self.header_cache().observe_tip(tip) else {
+            return Ok(disconnected);
+        };
+        let mut block_hash = last_tip;
+        while disconnected.len() < MAX_REORG_DEPTH && !client.is_block_in_best_chain(&block_hash)? {
+            self.db.remove_verified_bmm(&block_hash.to_string())?;
+            disconnected.push(block_hash);
+            block_hash = client.get_prev_block_hash(&block_hash)?;
+        }
+        if !disconnected.is_empty() {
+            *lock(&self.deposit_cache) = None;
+            tracing::info!(
+                "mainchain reorg to {tip} disconnected {} blocks",
+                disconnected.len()
+            );
+        }
+        Ok(disconnected)
+    }
+
+    pub fn get_active_endpoint(&self) -> String {
+        self.client.active_endpoint().into()
+    }
+
+    fn timed_client(&self, timeout_ms: u64) -> MainClient {
+        match timeout_ms {
+            0 => self.client.clone(),
+            timeout_ms => self.client.with_timeout(Duration::from_millis(timeout_ms)),
+        }
+    }
+
+    pub fn get_mainchain_tip_with_timeout(&self, timeout_ms: u64) -> Result<String> {
+        let client = self.timed_client(timeout_ms);
+        let tip = client.get_best_block_hash()?;
+        self.observe_tip(&client, tip)?;
+        Ok(tip.to_string())
+    }
+
+    pub fn get_prev_main_block_hash_with_timeout(
+        &self,
+        main_block_hash: &str,
+        timeout_ms: u64,
+    ) -> Result<Vec<u8>> {
+        let main_block_hash = BlockHash::from_str(main_block_hash).into_diagnostic()?;
+        let cached = self.header_cache().get_prev(&main_block_hash);
+        if let Some(prev_hash) = cached {
+            return Ok(prev_hash.to_vec());
+        }
+        let prev_hash = self
+            .timed_client(timeout_ms)
+            .get_prev_block_hash(&main_block_hash)?;
+        self.header_cache().put_prev(main_block_hash, prev_hash);
+        Ok(prev_hash.to_vec())
+    }
+
+    pub fn confirm_bmm(&mut self) -> Result<ffi::BMMState> {
+        self.ensure_connected()?;
+        let latest = self.db.iter_bmm_attempts().next().transpose()?;
+        let outcome = match &latest {
+            Some((_, record))
+                if record.outcome == db::BmmOutcome::Pending && self.is_bmm_expired(record) =>
+            {
+                db::BmmOutcome::Expired
+            }
+            // The drivechain crate still holds the expired request, and
+            // keeps it until the next attempt_bmm replaces it, so it isn't
+            // asked again.
+            Some((_, record)) if record.outcome == db::BmmOutcome::Expired => {
+                db::BmmOutcome::Expired
+            }
+            // The drivechain crate doesn't know about requests the bridge
+            // signed, nor about the ones it made before it was last opened.
+            Some((id, record))
+                if record.outcome == db::BmmOutcome::Pending
+                    && *lock(&self.inner_bmm_attempt) != Some(*id) =>
+            {
+                self.client.track(self.confirm_by_commitment(record))?
+            }
+            _ => self.client.track(self.inner().confirm_bmm())?,
+        };
+        if outcome != db::BmmOutcome::Pending {
+            if let Some((id, record)) =
+                latest.filter(|(_, record)| record.outcome == db::BmmOutcome::Pending)
+            {
+                self.resolve_bmm_attempt(id, record, outcome)?;
+            }
+            self.raise_alerts()?;
+        }
+        Ok(bmm_state(outcome))
+    }
+
+    // The drivechain crate only holds the latest request made through it,
+    // earlier ones are confirmed from the mainchain like signed ones.
+    pub fn confirm_bmm_for(&mut self, critical_hash: &str) -> Result<ffi::BMMState> {
+        let critical_hash = TxMerkleNode::from_str(critical_hash)
+            .into_diagnostic()?
+            .to_string();
+        let mut latest = true;
+        let mut found = None;
+        for entry in self.db.iter_bmm_attempts() {
+            let (id, record) = entry?;
+            if record.critical_hash == critical_hash {
+                found = Some((id, record));
+                break;
+            }
+            latest = false;
+        }
+        let (id, record) = found.ok_or(Error::UnknownBmmRequest { critical_hash })?;
+        if record.outcome != db::BmmOutcome::Pending {
+            return Ok(bmm_state(record.outcome));
+        }
+        if latest {
+            return self.confirm_bmm();
+        }
+        self.ensure_connected()?;
+        let outcome = match self.is_bmm_expired(&record) {
+            true => db::BmmOutcome::Expired,
+            false => self.client.track(self.confirm_by_commitment(&record))?,
+        };
+        if outcome != db::BmmOutcome::Pending {
+            self.resolve_bmm_attempt(id, record, outcome)?;
+            self.raise_alerts()?;
+        }
+        Ok(bmm_state(outcome))
+    }
+
+    fn is_bmm_expired(&self, record: &db::BmmAttemptRecord) -> bool {
+        self.bmm_request_ttl_secs > 0
+            && unix_time().saturating_sub(record.created_at) > self.bmm_request_ttl_secs
+    }
+
+    pub fn get_bmm_request_age(&self) -> Result<u64> {
+        match self.db.iter_bmm_attempts().next().transpose()? {
+            Some((_, record)) if record.outcome == db::BmmOutcome::Pending => {
+                Ok(unix_time().saturating_sub(record.created_at))
+            }
+            _ => Ok(0),
+        }
+    }
+
+    // A BMM request is for the block right after its prev_main_block_hash,
+    // it succeeded if that block commits to its critical hash.
+    fn confirm_by_commitment(
+        &self,
+        record: &db::BmmAttemptRecord,
+    ) -> Result<db::BmmOutcome, Error> {
+        let invalid = |message: String| Error::InvalidEncoding { message };
+        let prev = BlockHash::from_str(&record.prev_main_block_hash)
+            .map_err(|err| invalid(err.to_string()))?;
+        let critical_hash = TxMerkleNode::from_str(&record.critical_hash)
+            .map_err(|err| invalid(err.to_string()))?;
+        if self.client.get_best_block_hash()? == prev {
+            return Ok(db::BmmOutcome::Pending);
+        }
+        if !self.client.is_block_in_best_chain(&prev)? {
+            return Ok(db::BmmOutcome::Failed);
+        }
+        let height = self.client.get_block_height(&prev)? + 1;
+        let Some(next) = self.client.get_block_hashes(&[height])?.pop() else {
+            return Ok(db::BmmOutcome::Pending);
+        };
+        let block = self.client.get_block(&next)?;
+        let this_sidechain = u8::try_from(self.this_sidechain).ok();
+        let committed = block.txdata.first().is_some_and(|coinbase| {
+            coinbase.output.iter().any(|output| {
+                script::parse_bmm_commitment(&output.script_pubkey)
+                    == this_sidechain.map(|sidechain| (sidechain, critical_hash))
+            })
+        });
+        Ok(match committed {
+            true => db::BmmOutcome::Succeded,
+            false => db::BmmOutcome::Failed,
+        })
+    }
+
+    fn resolve_bmm_attempt(
+        &self,
+        id: u64,
+        mut record: db::BmmAttemptRecord,
+        outcome: db::BmmOutcome,
+    ) -> Result<()> {
+        record.outcome = outcome;
+        record.main_block_hash = self.get_mainchain_tip()?;
+        self.db.put_bmm_attempt(id, &record)
+    }
+
+    pub fn get_bmm_block_requirements(&self) -> Result<ffi::BmmTemplate> {
+        let main_block_hash = self.get_mainchain_tip()?;
+        let prev_main_block_hash = self.get_prev_main_block_hash(&main_block_hash)?;
+        self.bmm_template(main_block_hash, prev_main_block_hash)
+    }
+
+    pub fn get_bmm_block_requirements_with_timeout(
+        &self,
+        timeout_ms: u64,
+    ) -> Result<ffi::BmmTemplate> {
+        let main_block_hash = self.get_mainchain_tip_with_timeout(timeout_ms)?;
+        let prev_main_block_hash =
+            self.get_prev_main_block_hash_with_timeout(&main_block_hash, timeout_ms)?;
+        self.bmm_template(main_block_hash, prev_main_block_hash)
+    }
+
+    fn bmm_template(
+        &self,
+        main_block_hash: String,
+        prev_main_block_hash: Vec<u8>,
+    ) -> Result<ffi::BmmTemplate> {
+        Ok(ffi::BmmTemplate {
+            main_block_hash,
+            prev_main_block_hash,
+            suggested_amount: self.suggested_bmm_amount()?,
+            expires_at: unix_time() + BMM_TEMPLATE_LIFETIME_SECS,
+        })
+    }
+
+    // Average of the most recent successful bids.
+    fn suggested_bmm_amount(&self) -> Result<u64> {
+        let mut amounts = Vec::with_capacity(BMM_AMOUNT_SAMPLE);
+        for entry in self.db.iter_bmm_attempts() {
+            let (_, record) = entry?;
+            if record.outcome == db::BmmOutcome::Succeded {
+                amounts.push(record.amount);
+                if amounts.len() == BMM_AMOUNT_SAMPLE {
+                    break;
+                }
+            }
+        }
+        if amounts.is_empty() {
+            return Ok(DEFAULT_BMM_AMOUNT);
+        }
+        let total = amount::checked_total("bmm amount", amounts.iter().copied())?;
+        Ok(total / amounts.len() as u64)
+    }
+
+    pub fn get_bmm_spend_summary(&self, from_time: u64, to_time: u64) -> Result<ffi::SpendSummary> {
+        let mut attempts = Vec::new();
+        for entry in self.db.iter_bmm_attempts() {
+            let (_, record) = entry?;
+            // Ids follow creation order, but the clock may have gone back,
+            // so every attempt is checked.
+            if (from_time..=to_time).contains(&record.created_at) {
+                attempts.push((record.outcome, record.amount));
+            }
+        }
+        let spent = |outcome: Option<db::BmmOutcome>| {
+            amount::checked_total(
+                "bmm spend",
+                attempts
+                    .iter()
+                    .filter(|(o, _)| outcome.is_none() || outcome == Some(*o))
+                    .map(|(_, amount)| *amount),
+            )
+        };
+        let count =
+            |outcome: db::BmmOutcome| attempts.iter().filter(|(o, _)| *o == outcome).count();
+        let total_spent = spent(None)?;
+        Ok(ffi::SpendSummary {
+            from_time,
+            to_time,
+            attempts: attempts.len() as u64,
+            succeeded: count(db::BmmOutcome::Succeded) as u64,
+            failed: count(db::BmmOutcome::Failed) as u64,
+            pending: count(db::BmmOutcome::Pending) as u64,
+            total_spent,
+            successful_spent: spent(Some(db::BmmOutcome::Succeded))?,
+            wasted_spent: spent(Some(db::BmmOutcome::Failed))?,
+            average_bid: total_spent.checked_div(attempts.len() as u64).unwrap_or(0),
+        })
+    }
+
+    pub fn get_bmm_history(&self, limit: usize) -> Result<Vec<ffi::BmmAttempt>> {
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        self.db
+            .iter_bmm_attempts()
+            .take(limit)
+            .map(|entry| {
+                let (id, record) = entry?;
+                Ok(bmm_attempt(id, record))
+            })
+            .collect()
+    }
+
+    pub fn get_pending_bmm_attempts(&self) -> Result<Vec<ffi::BmmAttempt>> {
+        let mut pending = Vec::new();
+        for entry in self.db.iter_bmm_attempts() {
+            let (id, record) = entry?;
+            if record.outcome == db::BmmOutcome::Pending {
+                pending.push(bmm_attempt(id, record));
+            }
+        }
+        Ok(pending)
+    }
+
+    pub fn attempt_bmm_with_amount(
+        &mut self,
+        critical_hash: &str,
+        prev_main_block_hash: &str,
+        amount: ffi::Amount,
+    ) -> Result<()> {
+        self.attempt_bmm(critical_hash, prev_main_block_hash, amount.sats)
+    }
+
+    pub fn attempt_bmm_with_feerate(
+        &mut self,
+        critical_hash: &str,
+        prev_main_block_hash: &str,
+        sat_per_vb: u64,
+    ) -> Result<u64> {
+        let amount = sat_per_vb
+            .checked_mul(script::BMM_REQUEST_VSIZE)
+            .ok_or(Error::AmountOverflow { what: "bmm bid" })?;
+        let amount = amount::check_amount("bmm bid", amount)?;
+        self.attempt_bmm(critical_hash, prev_main_block_hash, amount)?;
+        Ok(amount)
+    }
+
+    pub fn attempt_bmm(
+        &mut self,
+        critical_hash: &str,
+        prev_main_block_hash: &str,
+        amount: u64,
+    ) -> Result<()> {
+        if let Some(signer) = &self.bmm_signer {
+            let mut psbt = self.bmm_psbt(critical_hash, prev_main_block_hash, amount)?;
+            self.ensure_connected()?;
+            self.client
+                .track(signer.fund_and_sign(&self.client, &mut psbt, amount))?;
+            self.broadcast_bmm_request(psbt)?;
+            return Ok(());
+        }
+        self.guard_wallet("attempt_bmm")?;
+        let critical_hash = TxMerkleNode::from_str(critical_hash).into_diagnostic()?;
+        let prev_main_block_hash = BlockHash::from_str(prev_main_block_hash).into_diagnostic()?;
+        self.ensure_connected()?;
+        let result = self.inner().attempt_bmm(
+            &critical_hash,
+            &prev_main_block_hash,
+            bitcoin::Amount::from_sat(amount),
+        );
+        self.client.track(result)?;
+        let record = db::BmmAttemptRecord {
+            created_at: unix_time(),
+            critical_hash: critical_hash.to_string(),
+            prev_main_block_hash: prev_main_block_hash.to_string(),
+            amount,
+            txid: String::new(),
+            outcome: db::BmmOutcome::Pending,
+            main_block_hash: String::new(),
+        };
+        let id = self.db.generate_id()?;
+        self.db.put_bmm_attempt(id, &record)?;
+        *lock(&self.inner_bmm_attempt) = Some(id);
+        Ok(())
+    }
+
+    pub fn create_bmm_psbt(
+        &self,
+        critical_hash: &str,
+        prev_main_block_hash: &str,
+        amount: u64,
+    ) -> Result<String> {
+        let psbt = self.bmm_psbt(critical_hash, prev_main_block_hash, amount)?;
+        Ok(base64::encode(serialize(&psbt)))
+    }
+
+    pub fn broadcast_bmm_psbt(&self, psbt: &str) -> Result<String> {
+        let invalid = |message: String| Error::InvalidEncoding { message };
+        let psbt = base64::decode(psbt).map_err(|err| invalid(err.to_string()))?;
+        let psbt = deserialize(&psbt).map_err(|err| invalid(err.to_string()))?;
+        Ok(self.broadcast_bmm_request(psbt)?.to_string())
+    }
+
+    fn bmm_psbt(
+        &self,
+        critical_hash: &str,
+        prev_main_block_hash: &str,
+        amount: u64,
+    ) -> Result<bitcoin::util::psbt::PartiallySignedTransaction> {
+        let critical_hash = TxMerkleNode::from_str(critical_hash).into_diagnostic()?;
+        let prev_main_block_hash = BlockHash::from_str(prev_main_block_hash).into_diagnostic()?;
+        let amount = amount::check_amount("bmm bid", amount)?;
+        let this_sidechain = u8::try_from(self.this_sidechain).into_diagnostic()?;
+        let request =
+            script::bmm_request_script(this_sidechain, &critical_hash, &prev_main_block_hash);
+        Ok(psbt::bmm_psbt(request, amount)?)
+    }
+
+    // Recorded with its txid, which is how confirm_bmm tells requests the
+    // drivechain crate doesn't know about apart.
+    fn broadcast_bmm_request(
+        &self,
+        psbt: bitcoin::util::psbt::PartiallySignedTransaction,
+    ) -> Result<Txid> {
+        let amount = psbt::requested_fee(&psbt).unwrap_or(0);
+        let tx = psbt::finalized(psbt)?;
+        let this_sidechain = u8::try_from(self.this_sidechain).ok();
+        let (critical_hash, prev_main_block_hash) = tx
+            .output
+            .iter()
+            .filter_map(|output| script::parse_bmm_request(&output.script_pubkey))
+            .find(|(sidechain, _, _)| Some(*sidechain) == this_sidechain)
+            .map(|(_, critical_hash, prev)| (critical_hash, prev))
+            .ok_or(Error::InvalidEncoding {
+                message: "transaction has no BMM request for this sidechain".into(),
+            })?;
+        let txid = self.client.send_raw_transaction(&serialize_hex(&tx))?;
+        let record = db::BmmAttemptRecord {
+            created_at: unix_time(),
+            critical_hash: critical_hash.to_string(),
+            prev_main_block_hash: prev_main_block_hash.to_string(),
+            amount,
+            txid: txid.to_string(),
+            outcome: db::BmmOutcome::Pending,
+            main_block_hash: String::new(),
+        };
+        self.db.put_bmm_attempt(self.db.generate_id()?, &record)?;
+        Ok(txid)
+    }
+
+    pub fn get_main_block_chain_status(
+        &self,
+        main_block_hash: &str,
+    ) -> Result<ffi::MainBlockChainStatus> {
+        self.ensure_connected()?;
+        let main_block_hash = BlockHash::from_str(main_block_hash).into_diagnostic()?;
+        self.client
+            .track(self.mainchain.get_main_block_chain_status(&main_block_hash))
+    }
+
+    pub fn is_main_block_connected(&self, main_block_hash: &str) -> Result<bool> {
+        self.ensure_connected()?;
+        let main_block_hash = BlockHash::from_str(main_block_hash).into_diagnostic()?;
+        let cached = self.header_cache().get_connected(&main_block_hash);
+        if let Some(connected) = cached {
+            return Ok(connected);
+        }
+        let connected = self
+            .client
+            .track(self.mainchain.is_main_block_connected(&main_block_hash))?;
+        self.header_cache()
+            .put_connected(main_block_hash, connected);
+        Ok(connected)
+    }
+
+    pub fn filter_connected_main_blocks(
+        &self,
+        main_block_hashes: Vec<String>,
+    ) -> Result<Vec<bool>> {
+        self.ensure_connected()?;
+        let main_block_hashes = main_block_hashes
+            .iter()
+            .map(|main_block_hash| BlockHash::from_str(main_block_hash))
+            .collect::<Result<Vec<_>, _>>()
+            .into_diagnostic()?;
+        let mut connected: Vec<Option<bool>> = {
+            let mut cache = self.header_cache();
+            main_block_hashes
+                .iter()
+                .map(|main_block_hash| cache.get_connected(main_block_hash))
+                .collect()
+        };
+        let missing: Vec<BlockHash> = main_block_hashes
+            .iter()
+            .zip(&connected)
+            .filter(|(_, connected)| connected.is_none())
+            .map(|(main_block_hash, _)| *main_block_hash)
+            .collect();
+        if !missing.is_empty() {
+            let found = self
+                .client
+                .track(self.mainchain.are_main_blocks_connected(&missing))?;
+            let mut cache = self.header_cache();
+            let mut found = missing.into_iter().zip(found);
+            for slot in connected.iter_mut().filter(|slot| slot.is_none()) {
+                if let Some((main_block_hash, is_connected)) = found.next() {
+                    cache.put_connected(main_block_hash, is_connected);
+                    *slot = Some(is_connected);
+                }
+            }
+        }
+        Ok(connected
+            .into_iter()
+            .map(Option::unwrap_or_default)
+            .collect())
+    }
+
+    pub fn verify_bmm(&self, main_block_hash: &str, critical_hash: &str) -> Result<bool> {
+        let main_block_hash = BlockHash::from_str(main_block_hash).into_diagnostic()?;
+        let critical_hash = TxMerkleNode::from_str(critical_hash).into_diagnostic()?;
+        let verified = self.has_bmm_commitment(&main_block_hash, &critical_hash)?;
+        // Only found commitments are kept, a block the node hasn't seen yet
+        // fails to verify too.
+        if verified {
+            self.db.put_verified_bmm(
+                &main_block_hash.to_string(),
+                &critical_hash.to_string(),
+                &db::VerifiedBmmRecord {
+                    verified_at: unix_time(),
+                },
+            )?;
+        }
+        Ok(verified)
+    }
+
+    fn has_bmm_commitment(
+        &self,
+        main_block_hash: &BlockHash,
+        critical_hash: &TxMerkleNode,
+    ) -> Result<bool> {
+        if !self.spv_verify {
+            return Ok(self
+                .inner()
+                .verify_bmm(main_block_hash, critical_hash)
+                .is_ok());
+        }
+        // The block from the RPC node is only taken once it hashes to a
+        // synced header and its transactions to that header's merkle root.
+        if self.spv()?.headers.get(main_block_hash).is_none() {
+            return Ok(false);
+        }
+        let block = self.client.get_block(main_block_hash)?;
+        if block.block_hash() != *main_block_hash || !block.check_merkle_root() {
+            return Ok(false);
+        }
+        Ok(block.txdata.first().is_some_and(|coinbase| {
+            coinbase.output.iter().any(|output| {
+                script::parse_bmm_commitment(&output.script_pubkey).is_some_and(
+                    |(sidechain, commitment)| {
+                        usize::from(sidechain) == self.this_sidechain
+                            && commitment == *critical_hash
+                    },
+                )
+            })
+        }))
+    }
+
+    pub fn verify_bmm_cached(&self, main_block_hash: &str, critical_hash: &str) -> Result<bool> {
+        let main = BlockHash::from_str(main_block_hash).into_diagnostic()?;
+        let critical = TxMerkleNode::from_str(critical_hash).into_diagnostic()?;
+        if self
+            .db
+            .is_verified_bmm(&main.to_string(), &critical.to_string())?
+        {
+            return Ok(true);
+        }
+        self.verify_bmm(main_block_hash, critical_hash)
+    }
+
+    pub fn invalidate_verified_bmm(&self, main_block_hash: &str) -> Result<usize> {
+        let main_block_hash = BlockHash::from_str(main_block_hash).into_diagnostic()?;
+        self.db.remove_verified_bmm(&main_block_hash.to_string())
+    }
+
+    pub fn get_bmm_proof(
+        &self,
+        main_block_hash: &str,
+        critical_hash: &str,
+    ) -> Result<ffi::BmmProof> {
+        let main_block_hash = BlockHash::from_str(main_block_hash).into_diagnostic()?;
+        let critical_hash = TxMerkleNode::from_str(critical_hash).into_diagnostic()?;
+        if !self.has_bmm_commitment(&main_block_hash, &critical_hash)? {
+            return Err(Error::NoBmmCommitment {
+                main_block_hash: main_block_hash.to_string(),
+                critical_hash: critical_hash.to_string(),
+            }
+            .into());
+        }
+        let block = self.client.get_block(&main_block_hash)?;
+        let coinbase = block.txdata.first().ok_or(Error::InvalidEncoding {
+            message: format!("mainchain block {main_block_hash} has no coinbase"),
+        })?;
+        let txids: Vec<Txid> = block.txdata.iter().map(|tx| tx.txid()).collect();
+        Ok(ffi::BmmProof {
+            main_block_hash: main_block_hash.to_string(),
+            header_hex: serialize_hex(&block.header),
+            coinbase_hex: serialize_hex(coinbase),
+            coinbase_txid: coinbase.txid().to_string(),
+            merkle_branch: merkle::merkle_branch(&txids, 0)
+                .iter()
+                .map(|node| node.to_string())
+                .collect(),
+        })
+    }
+
+    pub fn get_deposit_outputs(&self) -> Result<Vec<ffi::Output>> {
+        let deposits = self.inner().get_deposit_outputs()?;
+        if self.deposit_maturity > 0 {
+            self.track_deposits(&deposits)?;
+        }
+        Ok(deposits.iter().map(ffi::Output::from).collect())
+    }
+
+    pub fn get_unacked_deposit_outputs(&self) -> Result<Vec<ffi::DepositOutput>> {
+        let deposits = self.inner().get_deposit_outputs()?;
+        let (pending, _) = self.track_deposits(&deposits)?;
+        let mut outputs = Vec::new();
+        for pending in pending {
+            if !self.db.is_deposit_acked(pending.id)? {
+                outputs.push(ffi::DepositOutput {
+                    id: pending.id,
+                    output: ffi::Output {
+                        address: pending.address,
+                        amount: pending.amount,
+                    },
+                });
+            }
+        }
+        Ok(outputs)
+    }
+
+    pub fn ack_deposits(&self, ids: Vec<u64>) -> Result<()> {
+        let pending: HashSet<u64> = self
+            .db
+            .list_pending_deposits()?
+            .into_iter()
+            .map(|(_, id, _)| id)
+            .collect();
+        if let Some(id) = ids.iter().find(|id| !pending.contains(id)) {
+            return Err(Error::UnknownPendingDeposit { id: *id }.into());
+        }
+        for id in ids {
+            self.db.ack_deposit(id)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_immature_deposits(&self) -> Result<Vec<ffi::ImmatureDeposit>> {
+        if self.deposit_maturity == 0 {
+            return Ok(Vec::new());
+        }
+        let deposits = self.inner().get_deposit_outputs()?;
+        let (pending, main_height) = self.track_deposits(&deposits)?;
+        Ok(maturity::immature(
+            pending,
+            main_height,
+            self.deposit_maturity,
+        ))
+    }
+
+    // Deposits get their ids here. Their age is counted from the block they
+    // were first seen at, so they have to be seen as often as possible.
+    fn track_deposits(&self, deposits: &[drive::Deposit]) -> Result<(Vec<maturity::Pending>, u64)> {
+        let main_height = self.mainchain.get_block_count()?;
+        let pending = maturity::track(&self.db, deposits, main_height)?;
+        Ok((pending, main_height))
+    }
+
+    pub fn get_prefetched_deposit_outputs(&self) -> Result<ffi::PrefetchedDeposits> {
+        // The cache isn't held while inner is locked, the scheduler
+        // locks them the other way around.
+        if lock(&self.deposit_cache).is_none() {
+            self.ensure_connected()?;
+            let main_block_hash = self.client.track(self.mainchain.get_mainchain_tip())?;
+            let deposits = self.inner().get_deposit_outputs()?;
+            *lock(&self.deposit_cache) = Some(prefetch::Prefetched {
+                deposits,
+                main_block_hash,
+                fetched_at: unix_time(),
+            });
+        }
+        let cache = lock(&self.deposit_cache);
+        let prefetched = cache.as_ref().expect("filled above");
+        Ok(ffi::PrefetchedDeposits {
+            outputs: prefetched.deposits.iter().map(ffi::Output::from).collect(),
+            main_block_hash: prefetched.main_block_hash.to_string(),
+            fetched_at: prefetched.fetched_at,
+        })
+    }
+
+    pub fn open_deposit_cursor(&self) -> Result<u64> {
+        let deposits = self.inner().get_deposit_outputs()?;
+        let mut cursors = lock(&self.deposit_cursors);
+        let cursor = cursors.next_cursor;
+        cursors.next_cursor += 1;
+        cursors.open.insert(cursor, deposits.into_iter());
+        Ok(cursor)
+    }
+
+    pub fn next_deposits(&self, cursor: u64, n: usize) -> Result<Vec<ffi::Output>> {
+        let mut cursors = lock(&self.deposit_cursors);
+        let deposits = cursors
+            .open
+            .get_mut(&cursor)
+            .ok_or(Error::UnknownCursor { cursor })?;
+        let chunk: Vec<ffi::Output> = deposits.take(n).map(ffi::Output::from).collect();
+        if chunk.is_empty() {
+            cursors.open.remove(&cursor);
+        }
+        Ok(chunk)
+    }
+
+    pub fn close_deposit_cursor(&self, cursor: u64) -> Result<()> {
+        lock(&self.deposit_cursors)
+            .open
+            .remove(&cursor)
+            .ok_or(Error::UnknownCursor { cursor })?;
+        Ok(())
+    }
+
+    pub fn verify_deposit_amounts(&self) -> Result<Vec<ffi::DepositVerification>> {
+        let deposits = self.inner().get_deposit_outputs()?;
+        let escrow_script = self.escrow_script()?;
+        // Each mainchain deposit backs at most one sidechain deposit.
+        let mut matched = HashSet::new();
+        let mut verifications = Vec::new();
+        for deposit in deposits {
+            let mut verification = ffi::DepositVerification {
+                address: deposit.address.clone(),
+                amount: deposit.amount,
+                mainchain_txid: String::new(),
+                ctip_delta: 0,
+                status: ffi::DepositVerificationStatus::Unscanned,
+            };
+            let mut unavailable = false;
+            for (txid, record) in self.db.list_main_deposits_to(&deposit.address)? {
+                if matched.contains(&txid) {
+                    continue;
+                }
+                verification.mainchain_txid = txid.clone();
+                match self.ctip_delta(&record, &escrow_script)? {
+                    Some(delta) if delta == deposit.amount => {
+                        verification.ctip_delta = delta;
+                        verification.status = ffi::DepositVerificationStatus::Verified;
+                        matched.insert(txid);
+                        break;
+                    }
+                    Some(delta) => {
+                        verification.ctip_delta = delta;
+                        verification.status = ffi::DepositVerificationStatus::Mismatch;
+                    }
+                    None => unavailable = true,
+                }
+            }
+            if unavailable && verification.status != ffi::DepositVerificationStatus::Verified {
+                verification.status = ffi::DepositVerificationStatus::Unavailable;
+            }
+            verifications.push(verification);
+        }
+        Ok(verifications)
+    }
+
+    // Value the deposit added to the escrow, None if the input spending the
+    // previous CTIP can't be told apart because an input can't be looked up.
+    fn ctip_delta(
+        &self,
+        record: &db::MainDepositRecord,
+        escrow_script: &bitcoin::Script,
+    ) -> Result<Option<u64>> {
+        let tx = decode_tx(&record.tx_hex)?;
+        let ctip_value = tx
+            .output
+            .get(record.burn_index as usize)
+            .ok_or(Error::InvalidEncoding {
+                message: format!("deposit has no output {}", record.burn_index),
+            })?
+            .value;
+        let mut complete = true;
+        for input in &tx.input {
+            let prev = input.previous_output;
+            let prev_tx = match self.db.get_main_deposit(&prev.txid.to_string())? {
+                Some(prev_record) => decode_tx(&prev_record.tx_hex)?,
+                None => match self.client.get_transaction(&prev.txid) {
+                    Ok((prev_tx, _)) => prev_tx,
+                    Err(_) => {
+                        complete = false;
+                        continue;
+                    }
+                },
+            };
+            if let Some(output) = prev_tx.output.get(prev.vout as usize) {
+                if output.script_pubkey == *escrow_script {
+                    return Ok(Some(ctip_value.saturating_sub(output.value)));
+                }
+            }
+        }
+        // Without a previous CTIP this is the sidechain's first deposit.
+        Ok(complete.then_some(ctip_value))
+    }
+
+    pub fn get_withdrawal_status(&self, outpoint: &str) -> Result<ffi::WithdrawalStatus> {
+        let outpoint_bytes = decode_outpoint("outpoint", outpoint)?;
+        let block_count = self.mainchain.get_block_count()?;
+        self.resolve_withdrawal_heights(block_count)?;
+        let record = self
+            .db
+            .get_withdrawal(&outpoint_bytes)?
+            .ok_or(Error::UnknownWithdrawal {
+                outpoint: outpoint.into(),
+            })?;
+        let spent = self.is_withdrawal_spent(&outpoint_bytes)?;
+        Ok(withdrawal_status(
+            record.main_height,
+            spent,
+            block_count,
+            self.withdrawal_maturity,
+        ))
+    }
+
+    // Withdrawals connected without a mainchain height get the block count
+    // the first time they need one, so that connect_block works without
+    // mainchain RPC.
+    fn resolve_withdrawal_heights(&self, block_count: u64) -> Result<()> {
+        for outpoint in self.db.list_unresolved_withdrawals()? {
+            if let Some(mut record) = self.db.get_withdrawal(&outpoint)? {
+                record.main_height = block_count;
+                self.db.put_withdrawal(&outpoint, &record)?;
+            }
+            self.db.remove_unresolved_withdrawal(&outpoint)?;
+        }
+        Ok(())
+    }
+
+    pub fn open_read_snapshot(&self) -> Result<Box<ReadSnapshot>> {
+        self.open_read_handle().open_read_snapshot()
+    }
+
+    pub fn open_read_handle(&self) -> Box<ReadHandle> {
+        Box::new(ReadHandle::new(snapshot::Shared {
+            inner: Arc::clone(&self.inner),
+            mainchain: Arc::clone(&self.mainchain),
+            db: self.db.clone(),
+            applying: Arc::clone(&self.applying),
+            withdrawal_maturity: self.withdrawal_maturity,
+        }))
+    }
+
+    pub fn get_bundle_vote_status(&self, bundle_hash: &str) -> Result<ffi::VoteStatus> {
+        let votes = self
+            .client
+            .list_withdrawal_status(self.this_sidechain)?
+            .into_iter()
+            .find(|votes| votes.hash == bundle_hash)
+            .ok_or(Error::UnknownBundle {
+                hash: bundle_hash.into(),
+            })?;
+        let outcome = vote_outcome(
+            votes.nworkscore,
+            votes.nblocksleft,
+            self.bundle_min_work_score,
+            self.bundle_vote_period,
+        );
+        Ok(ffi::VoteStatus {
+            bundle_hash: votes.hash,
+            work_score: votes.nworkscore,
+            min_work_score: self.bundle_min_work_score,
+            blocks_remaining: votes.nblocksleft,
+            outcome,
+        })
+    }
+
+    // Only withdrawals connected since payees are recorded are counted.
+    pub fn get_pending_withdrawals_summary(&self) -> Result<ffi::PendingWithdrawalsSummary> {
+        let mut fees = Vec::new();
+        let mut summary = ffi::PendingWithdrawalsSummary {
+            count: 0,
+            total_amount: 0,
+            total_fees: 0,
+            fee_histogram: Vec::new(),
+            next_bundle_min_fee: 0,
+        };
+        let mut buckets: BTreeMap<u64, ffi::FeeBucket> = BTreeMap::new();
+        for (outpoint, payee) in self.db.list_unpaid_payees()? {
+            if self.is_withdrawal_spent(&outpoint)? {
+                continue;
+            }
+            summary.count += 1;
+            summary.total_amount = summary.total_amount.saturating_add(payee.amount);
+            summary.total_fees = summary.total_fees.saturating_add(payee.main_fee);
+            let min_fee = match payee.main_fee {
+                0 => 0,
+                fee => 1 << fee.ilog2(),
+            };
+            let bucket = buckets.entry(min_fee).or_insert(ffi::FeeBucket {
+                min_fee,
+                count: 0,
+                total_amount: 0,
+            });
+            bucket.count += 1;
+            bucket.total_amount = bucket.total_amount.saturating_add(payee.amount);
+            fees.push(payee.main_fee);
+        }
+        let by_fee = self.bundle_policy.ordering == ffi::BundleOrdering::HighestFeeFirst;
+        if by_fee && fees.len() >= MAX_BUNDLE_WITHDRAWALS {
+            // A new withdrawal has to outbid the lowest one still included.
+            fees.sort_unstable_by(|a, b| b.cmp(a));
+            summary.next_bundle_min_fee = fees[MAX_BUNDLE_WITHDRAWALS - 1].saturating_add(1);
+        }
+        summary.fee_histogram = buckets.into_values().collect();
+        Ok(summary)
+    }
+
+    pub fn get_peg_summary(&self) -> Result<ffi::PegSummary> {
+        let mut summary = ffi::PegSummary {
+            total_pegged_in: self.db.deposit_history_total()?,
+            total_withdrawn: 0,
+            pending_withdrawals: 0,
+            pending_amount: 0,
+            escrow_value: 0,
+            escrow_difference: 0,
+        };
+        for (outpoint, payee) in self.db.list_payees()? {
+            let amount = payee.amount.saturating_add(payee.main_fee);
+            if self.is_withdrawal_spent(&outpoint)? {
+                summary.total_withdrawn = summary.total_withdrawn.saturating_add(amount);
+            } else {
+                summary.pending_withdrawals += 1;
+                summary.pending_amount = summary.pending_amount.saturating_add(amount);
+            }
+        }
+        summary.escrow_value = match self.client.find_ctip(self.this_sidechain)? {
+            Some((_, output)) => output.value,
+            None => 0,
+        };
+        let expected = summary.total_pegged_in as i128 - summary.total_withdrawn as i128;
+        let difference = summary.escrow_value as i128 - expected;
+        summary.escrow_difference = difference.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+        Ok(summary)
+    }
+
+    pub fn get_withdrawal_payout(&self, outpoint: &str) -> Result<ffi::PayoutInfo> {
+        let outpoint_bytes = decode_outpoint("outpoint", outpoint)?;
+        let unknown = || Error::UnknownWithdrawal {
+            outpoint: outpoint.into(),
+        };
+        // Withdrawals connected by older versions have no payee recorded.
+        let payee = self.db.get_payee(&outpoint_bytes)?.ok_or_else(unknown)?;
+        let record = self
+            .db
+            .get_withdrawal(&outpoint_bytes)?
+            .ok_or_else(unknown)?;
+        if self.db.get_payout(&outpoint_bytes)?.is_none() {
+            if !self.is_withdrawal_spent(&outpoint_bytes)? {
+                return Err(Error::WithdrawalNotPaid {
+                    outpoint: outpoint.into(),
+                }
+                .into());
+            }
+            self.scan_payouts(record.main_height)?;
+        }
+        let payout = self
+            .db
+            .get_payout(&outpoint_bytes)?
+            .ok_or(Error::PayoutNotFound {
+                outpoint: outpoint.into(),
+            })?;
+        Ok(ffi::PayoutInfo {
+            outpoint: outpoint.into(),
+            mainchain_txid: payout.txid,
+            vout: payout.vout,
+            main_block_hash: payout.main_block_hash,
+            amount: payee.amount,
+        })
+    }
+
+    // Every escrow transaction, bundles included, spends the previous CTIP,
+    // so walking back from the current CTIP until min_height finds all
+    // bundles since. Outputs paying an unpaid withdrawal's destination its
+    // amount are recorded as that withdrawal's payout.
+    fn scan_payouts(&self, min_height: u64) -> Result<()> {
+        let mut payees = self
+            .db
+            .list_unpaid_payees()?
+            .into_iter()
+            .map(|(outpoint, payee)| {
+                let script = self.payout_script(&outpoint, &payee)?;
+                Ok((outpoint, payee, script))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let escrow_script = self.escrow_script()?;
+        let (mut ctip, _) = self.client.get_ctip(self.this_sidechain)?;
+        for _ in 0..MAX_PAYOUT_SCAN_TXS {
+            if payees.is_empty() {
+                break;
+            }
+            let (tx, block_hash) = self.client.get_transaction(&ctip.txid)?;
+            let is_escrow = tx
+                .output
+                .get(ctip.vout as usize)
+                .is_some_and(|output| output.script_pubkey == escrow_script);
+            if !is_escrow {
+                break;
+            }
+            if let Some(block_hash) = block_hash {
+                if self.client.get_block_height(&block_hash)? < min_height {
+                    break;
+                }
+                let txid = tx.txid().to_string();
+                for (vout, output) in tx.output.iter().enumerate() {
+                    let vout = vout as u32;
+                    if self.db.is_payout_output(&txid, vout)? {
+                        continue;
+                    }
+                    let position = payees.iter().position(|(_, payee, script)| {
+                        output.value == payee.amount && output.script_pubkey == *script
+                    });
+                    if let Some(position) = position {
+                        let (outpoint, _, _) = payees.swap_remove(position);
+                        let payout = db::PayoutRecord {
+                            txid: txid.clone(),
+                            vout,
+                            main_block_hash: block_hash.to_string(),
+                        };
+                        self.db.put_payout(&outpoint, &payout)?;
+                    }
+                }
+            }
+            ctip = match tx.input.first() {
+                Some(input) => input.previous_output,
+                None => break,
+            };
+        }
+        Ok(())
+    }
+
+    pub fn start_deposit_scan(&self, start_height: u64) -> Result<()> {
+        Ok(self.scanner.start(start_height)?)
+    }
+
+    pub fn get_deposit_scan_progress(&self) -> ffi::DepositScanProgress {
+        let progress = self.scanner.progress();
+        ffi::DepositScanProgress {
+            scanned_height: progress.scanned_height.load(Ordering::SeqCst),
+            target_height: progress.target_height.load(Ordering::SeqCst),
+            failed_ranges: progress.failed_ranges.load(Ordering::SeqCst),
+            running: progress.running.load(Ordering::SeqCst),
+        }
+    }
+
+    pub fn get_deposit_proof(&self, mainchain_txid: &str) -> Result<ffi::DepositProof> {
+        let txid = Txid::from_str(mainchain_txid).into_diagnostic()?;
+        let deposit =
+            self.db
+                .get_main_deposit(&txid.to_string())?
+                .ok_or(Error::UnknownDeposit {
+                    txid: txid.to_string(),
+                })?;
+        let main_block_hash = BlockHash::from_str(&deposit.block_hash).into_diagnostic()?;
+        let block = self.client.get_block(&main_block_hash)?;
+        let txids: Vec<Txid> = block.txdata.iter().map(|tx| tx.txid()).collect();
+        let index = txids
+            .iter()
+            .position(|block_txid| *block_txid == txid)
+            .ok_or(Error::UnknownDeposit {
+                txid: txid.to_string(),
+            })?;
+        let height = self.client.get_block_height(&main_block_hash)?;
+        let tip_height = self.client.get_block_count()?;
+        let last_height = tip_height.min(height + MAX_DEPOSIT_PROOF_HEADERS - 1);
+        let heights: Vec<u64> = (height..=last_height).collect();
+        let block_hashes = self.client.get_block_hashes(&heights)?;
+        let headers = self.client.get_block_headers_hex(&block_hashes)?;
+        Ok(ffi::DepositProof {
+            txid: txid.to_string(),
+            tx_hex: deposit.tx_hex,
+            main_block_hash: main_block_hash.to_string(),
+            index: index as u32,
+            merkle_branch: merkle::merkle_branch(&txids, index)
+                .iter()
+                .map(|node| node.to_string())
+                .collect(),
+            headers,
+        })
+    }
+
+    pub fn attempt_bundle_broadcast(&mut self) -> Result<()> {
+        self.ensure_connected()?;
+        // Recorded first, advance_bundle finds how the vote on the bundle
+        // broadcast went in it. Like the recorder, the vote history must not
+        // break broadcasting.
+        if let Err(err) = self.record_bundle_votes() {
+            tracing::warn!("failed to record bundle votes: {err}");
+        }
+        let result = self.advance_bundle()?;
+        self.raise_alerts()?;
+        match result {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_bundle_votes(&self) -> Result<()> {
+        let bundle = self.db.get_bundle()?;
+        let own_bundle_height = matches!(
+            bundle.stage,
+            db::BundleStage::Broadcast | db::BundleStage::Voting
+        )
+        .then_some(bundle.main_height);
+        votes::record(
+            &self.db,
+            &self.client,
+            self.this_sidechain,
+            own_bundle_height,
+        )
+    }
+
+    pub fn list_proposed_bundles(&self) -> Result<Vec<ffi::ProposedBundle>> {
+        self.record_bundle_votes()?;
+        votes::proposed_bundles(&self.db)
+    }
+
+    pub fn get_bundle_vote_history(&self, bundle_hash: &str) -> Result<Vec<ffi::BundleVote>> {
+        votes::history(&self.db, bundle_hash)
+    }
+
+    pub fn get_bundle_state(&self) -> Result<ffi::BundleState> {
+        Ok(bundle_state(self.db.get_bundle()?.stage))
+    }
+
+    // Moves the bundle at most one step: Idle, PaidOut or a due Failed to
+    // Created and then Broadcast or Failed, Broadcast to Voting and either to
+    // PaidOut once its withdrawals are spent or to Failed once its vote has
+    // failed. A bundle is only broadcast from Created, so repeated calls
+    // never submit it twice, and a failed one is rebuilt from the
+    // withdrawals still unspent. Returns the broadcast error, already
+    // recorded in the broadcast queue.
+    fn advance_bundle(&mut self) -> Result<Option<miette::Report>> {
+        let now = unix_time();
+        let mut bundle = self.db.get_bundle()?;
+        match bundle.stage {
+            db::BundleStage::Broadcast | db::BundleStage::Voting => {
+                let stage = self.voted_bundle_stage(&mut bundle)?;
+                if stage != bundle.stage {
+                    bundle.stage = stage;
+                    bundle.updated_at = now;
+                    self.db.put_bundle(&bundle)?;
+                }
+                return Ok(None);
+            }
+            db::BundleStage::Failed if !self.is_broadcast_due(now)? => return Ok(None),
+            db::BundleStage::AwaitingSignatures => return Ok(None),
+            _ => {}
+        }
+        let (withdrawals, filtered) = self.bundle_withdrawals()?;
+        if withdrawals.is_empty() {
+            if bundle.stage != db::BundleStage::PaidOut {
+                self.db.put_bundle(&db::BundleRecord {
+                    updated_at: now,
+                    ..db::BundleRecord::default()
+                })?;
+            }
+            return Ok(None);
+        }
+        // Persisted before broadcasting so a crash in between retries.
+        let main_height = self.mainchain.get_block_count()?;
+        self.resolve_withdrawal_heights(main_height)?;
+        bundle = db::BundleRecord {
+            stage: db::BundleStage::Created,
+            withdrawals,
+            main_height,
+            updated_at: now,
+        };
+        self.db.put_bundle(&bundle)?;
+        // Withdrawals without payees can't be tested, the check is skipped
+        // for bundles made of them only.
+        if self.check_bundle_acceptance && !self.external_bundle_signing {
+            let (tx, tested) = self.unsigned_bundle(&bundle.withdrawals)?;
+            let acceptance = match tested.is_empty() {
+                true => None,
+                false => Some(self.mempool_acceptance(&tx)?),
+            };
+            if let Some(acceptance) = acceptance.filter(|acceptance| !acceptance.allowed) {
+                let err = miette::Report::from(Error::BundleRejected {
+                    reason: acceptance.reject_reason,
+                });
+                self.record_broadcast_result(Some(err.to_string()))?;
+                bundle.stage = db::BundleStage::Failed;
+                self.db.put_bundle(&bundle)?;
+                return Ok(Some(err));
+            }
+        }
+        let ordered = self.bundle_policy.ordering != ffi::BundleOrdering::HighestFeeFirst;
+        let result = if self.external_bundle_signing || filtered || ordered {
+            let (tx, withdrawals) = self.unsigned_bundle(&bundle.withdrawals)?;
+            bundle.withdrawals = withdrawals;
+            if self.external_bundle_signing {
+                self.db.put_unsigned_bundle(&db::UnsignedBundleRecord {
+                    tx_hex: serialize_hex(&tx),
+                    created_at: now,
+                })?;
+                bundle.stage = db::BundleStage::AwaitingSignatures;
+                self.db.put_bundle(&bundle)?;
+                return Ok(None);
+            }
+            // The drivechain crate builds its bundle from every pending
+            // withdrawal, filtered ones included, in its own order.
+            self.client
+                .receive_withdrawal_bundle(self.this_sidechain, &serialize_hex(&tx))
+                .map_err(miette::Report::from)
+        } else {
+            self.client.track(self.inner().attempt_bundle_broadcast())
+        };
+        self.record_broadcast_result(result.as_ref().err().map(|err| err.to_string()))?;
+        bundle.stage = match result {
+            Ok(()) => db::BundleStage::Broadcast,
+            Err(_) => db::BundleStage::Failed,
+        };
+        self.db.put_bundle(&bundle)?;
+        Ok(result.err())
+    }
+
+    // The vote ends with the bundle leaving listwithdrawalstatus, paid out
+    // or not, or with no blocks left before it reached the work score. The
+    // drivechain crate may have left withdrawals out of its bundle, so once
+    // the vote has ended the withdrawals found spent are kept as the ones
+    // the bundle paid, and a bundle that paid none has failed. An approved
+    // bundle gone from the list is waited on until its payout shows up.
+    fn voted_bundle_stage(&self, bundle: &mut db::BundleRecord) -> Result<db::BundleStage> {
+        let mut spent = Vec::new();
+        for outpoint in &bundle.withdrawals {
+            if self.is_withdrawal_spent(outpoint)? {
+                spent.push(outpoint.clone());
+            }
+        }
+        if spent.len() == bundle.withdrawals.len() {
+            return Ok(db::BundleStage::PaidOut);
+        }
+        let votes = self
+            .db
+            .list_proposed_bundles()?
+            .into_iter()
+            .map(|(_, record)| record)
+            .find(|record| record.ours && record.first_seen_height >= bundle.main_height);
+        let approved =
+            |record: &db::ProposedBundleRecord| record.work_score >= self.bundle_min_work_score;
+        let ended = votes.as_ref().is_some_and(|record| {
+            !record.active || (record.blocks_remaining == 0 && !approved(record))
+        });
+        // Payouts of bundles the bridge built are only found on the
+        // mainchain. A scan that fails leaves the bundle voting rather than
+        // have its withdrawals bundled again.
+        if ended {
+            self.scan_payouts(bundle.main_height)?;
+            for outpoint in &bundle.withdrawals {
+                if !spent.contains(outpoint) && self.db.get_payout(outpoint)?.is_some() {
+                    self.db.put_paid_withdrawal(outpoint)?;
+                    spent.push(outpoint.clone());
+                }
+            }
+        }
+        if ended && !spent.is_empty() {
+            bundle.withdrawals = spent;
+            return Ok(db::BundleStage::PaidOut);
+        }
+        if ended && !votes.as_ref().is_some_and(approved) {
+            return Ok(db::BundleStage::Failed);
+        }
+        if votes.is_some() || self.mainchain.get_block_count()? > bundle.main_height {
+            return Ok(db::BundleStage::Voting);
+        }
+        Ok(bundle.stage)
+    }
+
+    // Unspent withdrawals the filter lets through, and whether it held any
+    // back.
+    // filtered is also set when a withdrawal the drivechain crate still has
+    // pending was paid by a bundle the bridge built, so that the crate
+    // isn't left to bundle it again, and when one pays anything but P2PKH,
+    // which the crate would pay to the wrong script.
+    fn bundle_withdrawals(&self) -> Result<(Vec<Vec<u8>>, bool)> {
+        let mut withdrawals = Vec::new();
+        let mut filtered = false;
+        for (outpoint, _) in self.db.list_withdrawals()? {
+            if self.db.is_withdrawal_paid(&outpoint)? {
+                filtered |= !self.inner().is_outpoint_spent(&outpoint)?;
+                continue;
+            }
+            if self.inner().is_outpoint_spent(&outpoint)? {
+                continue;
+            }
+            if self.is_filtered(&outpoint)? || self.is_bundle_dust(&outpoint)? {
+                filtered = true;
+            } else {
+                filtered |= self.db.get_withdrawal_address(&outpoint)?.is_some();
+                withdrawals.push(outpoint);
+            }
+        }
+        Ok((withdrawals, filtered))
+    }
+
+    // The drivechain crate builds its own bundles, the one tested is the
+    // bridge's equivalent of it.
+    pub fn test_bundle_acceptance(&self) -> Result<ffi::AcceptanceResult> {
+        let (withdrawals, _) = self.bundle_withdrawals()?;
+        let (tx, withdrawals) = self.unsigned_bundle(&withdrawals)?;
+        if withdrawals.is_empty() {
+            return Err(Error::EmptyBundle.into());
+        }
+        self.mempool_acceptance(&tx)
+    }
+
+    fn mempool_acceptance(&self, tx: &bitcoin::Transaction) -> Result<ffi::AcceptanceResult> {
+        let accept = self.client.test_mempool_accept(&serialize_hex(tx))?;
+        Ok(ffi::AcceptanceResult {
+            allowed: accept.allowed,
+            reject_reason: accept.reject_reason,
+            txid: tx.txid().to_string(),
+            vsize: accept.vsize,
+        })
+    }
+
+    // Withdrawals connected before payees were recorded can't be paid by an
+    // externally signed bundle and are left to a later one.
+    fn unsigned_bundle(
+        &self,
+        withdrawals: &[Vec<u8>],
+    ) -> Result<(bitcoin::Transaction, Vec<Vec<u8>>)> {
+        let mut payees = Vec::new();
+        for outpoint in withdrawals {
+            let payee = self.db.get_payee(outpoint)?;
+            let record = self.db.get_withdrawal(outpoint)?;
+            if let (Some(payee), Some(record)) = (payee, record) {
+                payees.push((outpoint.clone(), payee, record.main_height));
+            }
+        }
+        order_payees(&self.bundle_policy, &mut payees);
+        payees.truncate(MAX_BUNDLE_WITHDRAWALS);
+        let payees: Vec<(Vec<u8>, db::WithdrawalPayeeRecord)> = payees
+            .into_iter()
+            .map(|(outpoint, payee, _)| (outpoint, payee))
+            .collect();
+        let fee = amount::checked_total("bundle fee", payees.iter().map(|(_, p)| p.main_fee))?;
+        let payouts = payees
+            .iter()
+            .map(|(outpoint, payee)| {
+                Ok(bitcoin::TxOut {
+                    value: payee.amount,
+                    script_pubkey: self.payout_script(outpoint, payee)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let (ctip, ctip_output) = self.client.get_ctip(self.this_sidechain)?;
+        let tx = psbt::unsigned_bundle(ctip, ctip_output, self.escrow_script()?, payouts, fee)?;
+        Ok((
+            tx,
+            payees.into_iter().map(|(outpoint, _)| outpoint).collect(),
+        ))
+    }
+
+    // The payee's P2PKH script, unless the withdrawal was connected to
+    // another type of address.
+    fn payout_script(
+        &self,
+        outpoint: &[u8],
+        payee: &db::WithdrawalPayeeRecord,
+    ) -> Result<bitcoin::Script> {
+        match self.db.get_withdrawal_address(outpoint)? {
+            Some(record) => Ok(dest::payout_script(&dest::parse_dest(
+                &record.main_address,
+            )?)?),
+            None => Ok(bitcoin::Script::new_p2pkh(&PubkeyHash::from_inner(
+                payee.dest,
+            ))),
+        }
+    }
+
+    /// Replaces the bundle policy from the config, for the next bundle.
+    pub fn set_bundle_policy(&mut self, policy: ffi::BundlePolicy) {
+        self.bundle_policy = policy;
+    }
+
+    pub fn get_bundle_policy(&self) -> ffi::BundlePolicy {
+        self.bundle_policy.clone()
+    }
+
+    /// Replaces the dust policy from the config.
+    pub fn set_dust_policy(&mut self, policy: ffi::DustPolicy) {
+        self.dust_policy = policy;
+    }
+
+    pub fn get_dust_policy(&self) -> ffi::DustPolicy {
+        self.dust_policy.clone()
+    }
+
+    fn is_bundle_dust(&self, outpoint: &[u8]) -> Result<bool> {
+        if self.dust_policy.mode != ffi::DustMode::Bundle {
+            return Ok(false);
+        }
+        match self.payee_withdrawal(outpoint)? {
+            Some(withdrawal) if withdrawal.amount < self.dust_policy.min_amount => {
+                self.record_dust(outpoint, &withdrawal, db::DustStage::Bundle)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn record_dust(
+        &self,
+        outpoint: &[u8],
+        withdrawal: &ffi::Withdrawal,
+        stage: db::DustStage,
+    ) -> Result<()> {
+        let record = db::DustWithdrawalRecord {
+            main_address: withdrawal.main_address.clone(),
+            amount: withdrawal.amount,
+            main_fee: withdrawal.main_fee,
+            stage,
+            rejected_at: unix_time(),
+        };
+        self.db.put_dust_withdrawal(outpoint, &record)
+    }
+
+    pub fn get_dust_withdrawals(&self) -> Result<Vec<ffi::DustWithdrawal>> {
+        let mut dust = Vec::new();
+        for (outpoint, record) in self.db.list_dust_withdrawals()? {
+            let mode = match record.stage {
+                db::DustStage::Connect => ffi::DustMode::Connect,
+                db::DustStage::Bundle => {
+                    let pending = self.db.get_withdrawal(&outpoint)?.is_some()
+                        && !self.is_withdrawal_spent(&outpoint)?;
+                    if !pending {
+                        continue;
+                    }
+                    ffi::DustMode::Bundle
+                }
+            };
+            dust.push(ffi::DustWithdrawal {
+                withdrawal: ffi::Withdrawal {
+                    outpoint: hex::encode(&outpoint),
+                    main_address: record.main_address,
+                    main_fee: record.main_fee,
+                    amount: record.amount,
+                },
+                mode,
+                rejected_at: record.rejected_at,
+            });
+        }
+        Ok(dust)
+    }
+
+    // Withdrawals connected before payees were recorded have no known
+    // destination and aren't filtered.
+    fn is_filtered(&self, outpoint: &[u8]) -> Result<bool> {
+        Ok(
+            match (&self.withdrawal_filter, self.payee_withdrawal(outpoint)?) {
+                (Some(filter), Some(withdrawal)) => !filter(&withdrawal),
+                _ => false,
+            },
+        )
+    }
+
+    fn payee_withdrawal(&self, outpoint: &[u8]) -> Result<Option<ffi::Withdrawal>> {
+        let Some(payee) = self.db.get_payee(outpoint)? else {
+            return Ok(None);
+        };
+        let main_address = match self.db.get_withdrawal_address(outpoint)? {
+            Some(record) => record.main_address,
+            None => hex::encode(payee.dest),
+        };
+        Ok(Some(ffi::Withdrawal {
+            outpoint: hex::encode(outpoint),
+            main_address,
+            main_fee: payee.main_fee,
+            amount: payee.amount,
+        }))
+    }
+
+    /// Replaces the current filter, see WithdrawalFilter.
+    pub fn set_withdrawal_filter(&mut self, filter: Option<WithdrawalFilter>) {
+        self.withdrawal_filter = filter;
+    }
+
+    pub fn set_withdrawal_denylist(&mut self, main_addresses: Vec<String>) -> Result<()> {
+        if main_addresses.is_empty() {
+            self.withdrawal_filter = None;
+            return Ok(());
+        }
+        let denied = main_addresses
+            .iter()
+            .map(|main_address| dest::withdrawal_dest(main_address))
+            .collect::<Result<HashSet<_>, _>>()?;
+        self.withdrawal_filter = Some(Box::new(move |withdrawal| {
+            dest::withdrawal_dest(&withdrawal.main_address)
+                .map_or(true, |dest| !denied.contains(&dest))
+        }));
+        Ok(())
+    }
+
+    pub fn get_filtered_withdrawals(&self) -> Result<Vec<ffi::Withdrawal>> {
+        let filter = match &self.withdrawal_filter {
+            Some(filter) => filter,
+            None => return Ok(Vec::new()),
+        };
+        let mut filtered = Vec::new();
+        for (outpoint, _) in self.db.list_withdrawals()? {
+            if self.is_withdrawal_spent(&outpoint)? {
+                continue;
+            }
+            if let Some(withdrawal) = self.payee_withdrawal(&outpoint)? {
+                if !filter(&withdrawal) {
+                    filtered.push(withdrawal);
+                }
+            }
+        }
+        Ok(filtered)
+    }
+
+    pub fn get_unsigned_bundle(&self) -> Result<String> {
+        match self.db.get_unsigned_bundle()? {
+            Some(record) => Ok(record.tx_hex),
+            None => Err(Error::NoUnsignedBundle.into()),
+        }
+    }
+
+    pub fn submit_signed_bundle(&mut self, tx_hex: &str) -> Result<()> {
+        let record = self
+            .db
+            .get_unsigned_bundle()?
+            .ok_or(Error::NoUnsignedBundle)?;
+        let signed = decode_tx(tx_hex)?;
+        if !psbt::is_signed_bundle(&decode_tx(&record.tx_hex)?, &signed) {
+            return Err(Error::BundleMismatch {
+                txid: signed.txid().to_string(),
+            }
+            .into());
+        }
+        let result = self
+            .client
+            .receive_withdrawal_bundle(self.this_sidechain, tx_hex);
+        self.record_broadcast_result(result.as_ref().err().map(|err| err.to_string()))?;
+        let mut bundle = self.db.get_bundle()?;
+        bundle.stage = match result {
+            Ok(()) => db::BundleStage::Broadcast,
+            Err(_) => db::BundleStage::Failed,
+        };
+        bundle.updated_at = unix_time();
+        self.db.put_bundle(&bundle)?;
+        // A failed bundle is rebuilt for signing once its retry is due.
+        self.db.remove_unsigned_bundle()?;
+        Ok(result?)
+    }
+
+    fn is_broadcast_due(&self, now: u64) -> Result<bool> {
+        let broadcasts = self.db.list_broadcasts()?;
+        Ok(broadcasts.is_empty()
+            || broadcasts
+                .iter()
+                .any(|(_, record)| record.next_attempt_at <= now))
+    }
+
+    // A failed broadcast stays queued in the DB and is retried with
+    // exponential backoff from tick until it goes through.
+    fn record_broadcast_result(&self, error: Option<String>) -> Result<()> {
+        let detail = match &error {
+            Some(err) => format!("failed: {err}"),
+            None => "broadcast".to_string(),
+        };
+        self.audit(db::AuditOperation::BundleBroadcast, true, detail)?;
+        let pending = self.db.list_broadcasts()?;
+        let error = match error {
+            Some(error) => error,
+            None => {
+                for (id, _) in pending {
+                    self.db.remove_broadcast(id)?;
+                }
+                return Ok(());
+            }
+        };
+        let now = unix_time();
+        let (id, mut record) = match pending.into_iter().next() {
+            Some(entry) => entry,
+            None => (
+                self.db.generate_id()?,
+                db::BroadcastRecord {
+                    created_at: now,
+                    attempts: 0,
+                    next_attempt_at: now,
+                    last_error: String::new(),
+                },
+            ),
+        };
+        let backoff =
+            (BROADCAST_RETRY_BASE_SECS << record.attempts.min(16)).min(BROADCAST_RETRY_MAX_SECS);
+        record.attempts += 1;
+        record.next_attempt_at = now + backoff;
+        record.last_error = error;
+        self.db.put_broadcast(id, &record)
+    }
+
+    // A failed broadcast is recorded in the queue by advance_bundle. One
+    // that failed before getting there, e.g. on an RPC call while building
+    // the bundle, is recorded here so that it backs off too.
+    fn retry_due_broadcasts(&mut self) -> Result<bool> {
+        if self.db.get_bundle()?.stage != db::BundleStage::Failed
+            || !self.is_broadcast_due(unix_time())?
+        {
+            return Ok(false);
+        }
+        if let Err(err) = self.advance_bundle() {
+            self.record_broadcast_result(Some(err.to_string()))?;
+            return Err(err);
+        }
+        Ok(true)
+    }
+
+    pub fn get_broadcast_queue(&self) -> Result<Vec<ffi::BroadcastEntry>> {
+        Ok(self
+            .db
+            .list_broadcasts()?
+            .into_iter()
+            .map(|(id, record)| ffi::BroadcastEntry {
+                id,
+                created_at: record.created_at,
+                attempts: record.attempts,
+                next_attempt_at: record.next_attempt_at,
+                last_error: record.last_error,
+            })
+            .collect())
+    }
+
+    pub fn is_outpoint_spent(&self, outpoint: &str) -> Result<bool> {
+        let outpoint = decode_outpoint("outpoint", outpoint)?;
+        self.is_withdrawal_spent(outpoint.as_slice())
+    }
+
+    // Spent as far as the drivechain crate knows, or paid by a bundle it
+    // never saw because the bridge built it.
+    fn is_withdrawal_spent(&self, outpoint: &[u8]) -> Result<bool> {
+        Ok(self.db.is_withdrawal_paid(outpoint)? || self.inner().is_outpoint_spent(outpoint)?)
+    }
+
+    pub fn connect_block(
+        &mut self,
+        deposits: Vec<ffi::Output>,
+        withdrawals: Vec<ffi::Withdrawal>,
+        refunds: Vec<ffi::Refund>,
+        just_check: bool,
+    ) -> Result<bool> {
+        self.connect(None, deposits, withdrawals, refunds, just_check)
+    }
+
+    pub fn connect_block_at_height(
+        &mut self,
+        main_height: u64,
+        deposits: Vec<ffi::Output>,
+        withdrawals: Vec<ffi::Withdrawal>,
+        refunds: Vec<ffi::Refund>,
+        just_check: bool,
+    ) -> Result<bool> {
+        self.connect(
+            Some(main_height),
+            deposits,
+            withdrawals,
+            refunds,
+            just_check,
+        )
+    }
+
+    // Without main_height the withdrawals' heights are resolved later, see
+    // resolve_withdrawal_heights.
+    fn connect(
+        &mut self,
+        main_height: Option<u64>,
+        deposits: Vec<ffi::Output>,
+        withdrawals: Vec<ffi::Withdrawal>,
+        refunds: Vec<ffi::Refund>,
+        just_check: bool,
+    ) -> Result<bool> {
+        self.check_block_limits(deposits.len(), withdrawals.len(), refunds.len())?;
+        let call = self.recorder.is_some().then(|| replay::Call::ConnectBlock {
+            main_height,
+            deposits: deposits.clone(),
+            withdrawals: withdrawals.clone(),
+            refunds: refunds.clone(),
+            just_check,
+        });
+        let main_height_detail = match main_height {
+            Some(main_height) => main_height.to_string(),
+            None => "unknown".into(),
+        };
+        let detail = format!(
+            "main height {main_height_detail}, {} deposits, {} withdrawals, {} refunds",
+            deposits.len(),
+            withdrawals.len(),
+            refunds.len()
+        );
+        let applying = Arc::clone(&self.applying);
+        let mut applied =
+            (!just_check).then(|| applying.write().unwrap_or_else(PoisonError::into_inner));
+        let start = Instant::now();
+        let result =
+            self.connect_unrecorded(main_height, deposits, withdrawals, refunds, just_check);
+        // Also when it failed, it may have been applied partly.
+        if let Some(applied) = &mut applied {
+            **applied += 1;
+        }
+        self.record(call, start, &result);
+        // Best effort, the block is connected either way.
+        if !just_check && matches!(result, Ok(true)) {
+            if let Err(err) = self.audit(db::AuditOperation::ConnectBlock, false, detail) {
+                tracing::warn!("failed to write the audit log: {err}");
+            }
+        }
+        result
+    }
+
+    fn check_block_limits(
+        &self,
+        deposits: usize,
+        withdrawals: usize,
+        refunds: usize,
+    ) -> Result<()> {
+        let limits = &self.block_limits;
+        for (what, count, max) in [
+            ("deposits", deposits, limits.max_deposits),
+            ("withdrawals", withdrawals, limits.max_withdrawals),
+            ("refunds", refunds, limits.max_refunds),
+        ] {
+            if count > max {
+                return Err(Error::BlockTooLarge { what, count, max }.into());
+            }
+        }
+        Ok(())
+    }
+
+    fn record(&self, call: Option<replay::Call>, start: Instant, result: &Result<bool>) {
+        if let (Some(recorder), Some(call)) = (&self.recorder, call) {
+            recorder.record(call, start.elapsed(), result);
+        }
+    }
+
+    #[tracing::instrument(
+        name = "connect_block",
+        skip_all,
+        fields(
+            deposits = deposits.len(),
+            withdrawals = withdrawals.len(),
+            refunds = refunds.len(),
+            just_check,
+            main_height,
+            connected = tracing::field::Empty,
+        )
+    )]
+    fn connect_unrecorded(
+        &mut self,
+        main_height: Option<u64>,
+        deposits: Vec<ffi::Output>,
+        withdrawals: Vec<ffi::Withdrawal>,
+        refunds: Vec<ffi::Refund>,
+        just_check: bool,
+    ) -> Result<bool> {
+        validate_block_inputs(&deposits, &withdrawals, &refunds)?;
+        let deposits: Vec<drive::Deposit> = deposits.iter().map(drive::Deposit::from).collect();
+        if self.deposit_maturity > 0 && !deposits.is_empty() {
+            let pending = self.inner().get_deposit_outputs()?;
+            let (pending, tip) = self.track_deposits(&pending)?;
+            if let Some((deposit, blocks)) =
+                maturity::first_immature(&pending, &deposits, tip, self.deposit_maturity)
+            {
+                tracing::info!(
+                    "deposit of {} sats to {} is mature in {blocks} blocks",
+                    deposit.amount,
+                    deposit.address
+                );
+                tracing::Span::current().record("connected", false);
+                return Ok(false);
+            }
+        }
+        if self.dust_policy.mode == ffi::DustMode::Connect {
+            let min_amount = self.dust_policy.min_amount;
+            let mut dust = 0;
+            for w in withdrawals.iter().filter(|w| w.amount < min_amount) {
+                // check_connect_block must not write to the bridge DB.
+                if !just_check {
+                    let outpoint = decode_outpoint("withdrawal outpoint", &w.outpoint)?;
+                    self.record_dust(&outpoint, w, db::DustStage::Connect)?;
+                }
+                dust += 1;
+            }
+            if dust > 0 {
+                tracing::info!("{dust} withdrawals are below the minimum of {min_amount} sats");
+                tracing::Span::current().record("connected", false);
+                return Ok(false);
+            }
+        }
+
+        // Kept for the bundles the bridge builds, the drivechain crate only
+        // pays P2PKH.
+        let mut main_addresses = Vec::new();
+        for w in &withdrawals {
+            if dest::parse_dest(&w.main_address)?.kind != ffi::DestKind::P2pkh {
+                let outpoint = decode_outpoint("withdrawal outpoint", &w.outpoint)?;
+                main_addresses.push((outpoint, w.main_address.clone()));
+            }
+        }
+        let withdrawals: HashMap<Vec<u8>, drive::Withdrawal> = withdrawals
+            .par_iter()
+            .map(<(Vec<u8>, drive::Withdrawal)>::try_from)
+            .collect::<Result<_, Error>>()?;
+        let refunds: HashMap<Vec<u8>, u64> = refunds
+            .par_iter()
+            .map(<(Vec<u8>, u64)>::try_from)
+            .collect::<Result<_, Error>>()?;
+        // The drivechain crate would refund a withdrawal a bundle built by
+        // the bridge already paid.
+        for outpoint in refunds.keys() {
+            if self.db.is_withdrawal_paid(outpoint)? {
+                tracing::info!("refunded withdrawal {} was paid out", hex::encode(outpoint));
+                tracing::Span::current().record("connected", false);
+                return Ok(false);
+            }
+        }
+        let connected = tracing::info_span!("drivechain_connect_block").in_scope(|| {
+            self.inner()
+                .connect_block(deposits.as_slice(), &withdrawals, &refunds, just_check)
+                .is_ok()
+        });
+        tracing::Span::current().record("connected", connected);
+        if connected && !just_check {
+            *lock(&self.deposit_cache) = None;
+            let _span = tracing::info_span!("bridge_db").entered();
+            for (outpoint, withdrawal) in &withdrawals {
+                let record = db::WithdrawalRecord {
+                    main_height: main_height.unwrap_or_default(),
+                };
+                self.db.put_withdrawal(outpoint, &record)?;
+                if main_height.is_none() {
+                    self.db.put_unresolved_withdrawal(outpoint)?;
+                }
+                let payee = db::WithdrawalPayeeRecord {
+                    dest: withdrawal.dest,
+                    amount: withdrawal.amount,
+                    main_fee: withdrawal.mainchain_fee,
+                };
+                self.db.put_payee(outpoint, &payee)?;
+            }
+            for (outpoint, main_address) in main_addresses {
+                let record = db::WithdrawalAddressRecord { main_address };
+                self.db.put_withdrawal_address(&outpoint, &record)?;
+            }
+            self.record_deposits(&deposits, Some(main_height.unwrap_or_default()))?;
+            // The block is connected, a mainchain that can't be reached must
+            // not make it look rejected.
+            if let Err(err) = self.raise_alerts() {
+                tracing::warn!("failed to raise alerts: {err}");
+            }
+        }
+        Ok(connected)
+    }
+
+    // Deposit addresses embed the slot, so scanned mainchain deposits and
+    // their claims are found again by reformatting the sidechain address in
+    // each one with the slot the node is opened on.
+    pub fn migrate_sidechain_slot(
+        &mut self,
+        old_slot: usize,
+        new_slot: usize,
+        dry_run: bool,
+    ) -> Result<ffi::SlotMigrationReport> {
+        if new_slot != self.this_sidechain {
+            return Err(Error::WrongSlot {
+                slot: new_slot,
+                this_sidechain: self.this_sidechain,
+            }
+            .into());
+        }
+        let mut report = ffi::SlotMigrationReport {
+            old_slot,
+            new_slot,
+            dry_run,
+            rewritten: Vec::new(),
+            skipped: Vec::new(),
+        };
+        for (txid, record) in self.db.list_main_deposits()? {
+            let Some(address) = self.deposit_addresses.address(old_slot, &record.address) else {
+                report.skipped.push(txid);
+                continue;
+            };
+            let new_address = self.format_deposit_address(&address);
+            if new_address == record.address {
+                continue;
+            }
+            if !dry_run {
+                self.db
+                    .set_main_deposit_address(&txid, &record, &new_address)?;
+            }
+            match report
+                .rewritten
+                .iter_mut()
+                .find(|rewrite| rewrite.old_address == record.address)
+            {
+                Some(rewrite) => rewrite.deposits += 1,
+                None => report.rewritten.push(ffi::DepositAddressRewrite {
+                    old_address: record.address,
+                    new_address,
+                    deposits: 1,
+                }),
+            }
+        }
+        if !dry_run {
+            self.audit(
+                db::AuditOperation::SlotMigration,
+                true,
+                format!(
+                    "slot {old_slot} to {new_slot}, {} deposit addresses",
+                    report.rewritten.len()
+                ),
+            )?;
+        }
+        Ok(report)
+    }
+
+    // Needed after a crash between the drivechain crate's DB and the bridge
+    // DB being updated, e.g. in the middle of disconnect_block.
+    pub fn verify_and_repair(
+        &mut self,
+        unspent_withdrawals: Vec<String>,
+    ) -> Result<ffi::RepairReport> {
+        let unspent: Result<HashSet<Vec<u8>>> = unspent_withdrawals
             .iter()
-            .map(|output| drive::Deposit {
-                address: output.address.clone(),
-                amount: output.amount,
-            })
+            .map(|outpoint| Ok(decode_outpoint("withdrawal outpoint", outpoint)?))
             .collect();
+        let unspent = unspent?;
+        let main_height = self.mainchain.get_block_count()?;
+        self.resolve_withdrawal_heights(main_height)?;
+        let mut report = ffi::RepairReport {
+            checked: 0,
+            restored: Vec::new(),
+            removed: Vec::new(),
+            adjusted: Vec::new(),
+            conflicts: Vec::new(),
+        };
+        for (outpoint, mut record) in self.db.list_withdrawals()? {
+            report.checked += 1;
+            if !unspent.contains(&outpoint) && !self.is_withdrawal_spent(&outpoint)? {
+                self.db.remove_withdrawal(&outpoint)?;
+                report.removed.push(hex::encode(&outpoint));
+            } else if record.main_height > main_height {
+                record.main_height = main_height;
+                self.db.put_withdrawal(&outpoint, &record)?;
+                report.adjusted.push(hex::encode(&outpoint));
+            }
+        }
+        for outpoint in &unspent {
+            report.checked += 1;
+            if self.is_withdrawal_spent(outpoint)? {
+                report.conflicts.push(hex::encode(outpoint));
+            } else if self.db.get_withdrawal(outpoint)?.is_none() {
+                self.db
+                    .put_withdrawal(outpoint, &db::WithdrawalRecord { main_height })?;
+                report.restored.push(hex::encode(outpoint));
+            }
+        }
+        Ok(report)
+    }
+
+    pub fn dump_internal_state(&self) -> Result<String> {
+        let pending_bmm = match self.db.iter_bmm_attempts().next().transpose()? {
+            Some((id, record)) if record.outcome == db::BmmOutcome::Pending => json!({
+                "id": id,
+                "created_at": record.created_at,
+                "age_secs": unix_time().saturating_sub(record.created_at),
+                "critical_hash": record.critical_hash,
+                "prev_main_block_hash": record.prev_main_block_hash,
+                "amount": record.amount,
+                "txid": record.txid,
+            }),
+            _ => Value::Null,
+        };
+        let bundle = self.db.get_bundle()?;
+        let (prev_hashes, connected) = self.header_cache().entries();
+        let tip = self.header_cache().tip().map(|tip| tip.to_string());
+        let deposit_cache = match &*lock(&self.deposit_cache) {
+            Some(prefetched) => json!({
+                "deposits": prefetched.deposits.len(),
+                "main_block_hash": prefetched.main_block_hash.to_string(),
+                "fetched_at": prefetched.fetched_at,
+            }),
+            None => Value::Null,
+        };
+        let last_block = match self.db.last_sidechain_block()? {
+            Some((height, record)) => json!({
+                "height": height,
+                "hash": record.hash,
+                "withdrawals": record.withdrawals.len(),
+                "connected_at": record.connected_at,
+            }),
+            None => Value::Null,
+        };
+        let dump = json!({
+            "this_sidechain": self.this_sidechain,
+            "dumped_at": unix_time(),
+            "pending_bmm": pending_bmm,
+            "bundle": {
+                "stage": bundle.stage,
+                "withdrawals": bundle.withdrawals.len(),
+                "main_height": bundle.main_height,
+                "updated_at": bundle.updated_at,
+                "queued_broadcasts": self.db.list_broadcasts()?.len(),
+            },
+            "caches": {
+                "header_prev_hashes": prev_hashes,
+                "header_connected": connected,
+                "header_tip": tip,
+                "deposits": deposit_cache,
+            },
+            "flush": {
+                "unflushed": self.db.unflushed(),
+                "flushed_at": self.db.flushed_at(),
+                "policy": *lock(&self.flush_policy),
+            },
+            "last_connected_block": last_block,
+            "mainchain_disconnected_since": self.client.disconnected_since(),
+        });
+        Ok(dump.to_string())
+    }
 
-        let withdrawals: Result<HashMap<Vec<u8>, drive::Withdrawal>> = withdrawals
+    pub fn verify_db(&self, level: u8) -> Result<ffi::VerifyReport> {
+        let mut problems = Vec::new();
+        let mut checked = self.db.verify_records(&mut problems)?;
+        if level >= VERIFY_LEVEL_REFERENCES {
+            checked += self.db.verify_references(&mut problems)?;
+        }
+        if level >= VERIFY_LEVEL_CHAINSTATE {
+            checked += self.verify_withdrawals(&mut problems)?;
+        }
+        let problems: Vec<ffi::VerifyProblem> = problems
             .into_iter()
-            .map(|w| {
-                let mut dest: [u8; 20] = Default::default();
-                dest.copy_from_slice(hex::decode(w.main_address).into_diagnostic()?.as_slice());
-                let mainchain_fee = w.main_fee;
-                Ok((
-                    hex::decode(w.outpoint).into_diagnostic()?,
-                    drive::Withdrawal {
-                        amount: w.amount,
-                        dest,
-                        mainchain_fee,
-                        // height is set later in Db::connect_withdrawals.
-                        height: 0,
-                    },
-                ))
+            .map(|problem| ffi::VerifyProblem {
+                tree: problem.tree.into(),
+                key: hex::encode(problem.key),
+                message: problem.message,
             })
             .collect();
+        Ok(ffi::VerifyReport {
+            level,
+            checked,
+            valid: problems.is_empty(),
+            problems,
+        })
+    }
 
-        let refunds: Result<HashMap<Vec<u8>, u64>> = refunds
-            .iter()
-            .map(|r| {
-                Ok((
-                    hex::decode(&r.outpoint).into_diagnostic()?.to_vec(),
-                    r.amount,
-                ))
+    // Withdrawals with a payout have to be spent, and the ones still
+    // pending can't add up to more than the escrow holds. Only withdrawals
+    // connected since payees are recorded count towards the total.
+    fn verify_withdrawals(&self, problems: &mut Vec<db::RecordProblem>) -> Result<u64> {
+        let main_height = self.mainchain.get_block_count()?;
+        let mut checked = 0;
+        let mut pending = 0u64;
+        for (outpoint, record) in self.db.list_withdrawals()? {
+            checked += 1;
+            if record.main_height > main_height {
+                problems.push(db::RecordProblem::new(
+                    "withdrawals",
+                    &outpoint,
+                    format!(
+                        "connected at mainchain height {}, above the tip at {main_height}",
+                        record.main_height
+                    ),
+                ));
+            }
+            let spent = self.is_withdrawal_spent(&outpoint)?;
+            if self.db.get_payout(&outpoint)?.is_some() {
+                if !spent {
+                    problems.push(db::RecordProblem::new(
+                        "withdrawals",
+                        &outpoint,
+                        "paid out but unspent in the drivechain DB",
+                    ));
+                }
+            } else if let (false, Some(payee)) = (spent, self.db.get_payee(&outpoint)?) {
+                pending = pending
+                    .saturating_add(payee.amount)
+                    .saturating_add(payee.main_fee);
+            }
+        }
+        let escrow = match self.client.find_ctip(self.this_sidechain)? {
+            Some((_, output)) => output.value,
+            None => 0,
+        };
+        if pending > escrow {
+            problems.push(db::RecordProblem::new(
+                "withdrawal_payees",
+                &[],
+                format!("pending withdrawals total {pending} sats, the escrow holds {escrow}"),
+            ));
+        }
+        Ok(checked)
+    }
+
+    pub fn check_connect_block(
+        &mut self,
+        deposits: Vec<ffi::Output>,
+        withdrawals: Vec<ffi::Withdrawal>,
+        refunds: Vec<ffi::Refund>,
+    ) -> Result<ffi::CheckResult> {
+        let failures = match self.check_failures(deposits, withdrawals, refunds) {
+            Ok(failures) => failures,
+            Err(err)
+                if matches!(
+                    err.downcast_ref::<Error>(),
+                    Some(Error::BackendReopenFailed { .. })
+                ) =>
+            {
+                return Err(err)
+            }
+            Err(err) => vec![ffi::CheckFailure {
+                item: ffi::CheckItem::Block,
+                index: 0,
+                reason: ffi::CheckFailureReason::CheckFailed,
+                message: err.to_string(),
+            }],
+        };
+        Ok(ffi::CheckResult {
+            valid: failures.is_empty(),
+            failures,
+        })
+    }
+
+    fn check_failures(
+        &mut self,
+        deposits: Vec<ffi::Output>,
+        withdrawals: Vec<ffi::Withdrawal>,
+        refunds: Vec<ffi::Refund>,
+    ) -> Result<Vec<ffi::CheckFailure>> {
+        let mut failures = Vec::new();
+        let mut fail = |item, index: usize, reason, message: String| {
+            failures.push(ffi::CheckFailure {
+                item,
+                index: index as u64,
+                reason,
+                message,
+            })
+        };
+
+        let mut expected: Vec<drive::Deposit> = self.inner().get_deposit_outputs()?;
+        let (mut pending, tip) = match self.deposit_maturity {
+            0 => (Vec::new(), 0),
+            _ => self.track_deposits(&expected)?,
+        };
+        for (index, deposit) in deposits.iter().enumerate() {
+            if let Err(err) = amount::check_amount("deposit", deposit.amount) {
+                fail(
+                    ffi::CheckItem::Deposit,
+                    index,
+                    ffi::CheckFailureReason::AmountOutOfRange,
+                    err.to_string(),
+                );
+                continue;
+            }
+            let position = expected
+                .iter()
+                .position(|e| e.address == deposit.address && e.amount == deposit.amount);
+            if let Some(position) = position {
+                expected.swap_remove(position);
+                // Of several equal deposits the oldest is taken.
+                let oldest = pending
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, p)| p.address == deposit.address && p.amount == deposit.amount)
+                    .min_by_key(|(_, p)| p.first_seen_height)
+                    .map(|(position, _)| position);
+                if let Some(oldest) = oldest {
+                    let blocks = pending
+                        .swap_remove(oldest)
+                        .blocks_until_mature(tip, self.deposit_maturity);
+                    if blocks > 0 {
+                        fail(
+                            ffi::CheckItem::Deposit,
+                            index,
+                            ffi::CheckFailureReason::ImmatureDeposit,
+                            format!(
+                                "deposit to {} is mature in {blocks} blocks",
+                                deposit.address
+                            ),
+                        );
+                    }
+                }
+            } else if let Some(e) = expected.iter().find(|e| e.address == deposit.address) {
+                fail(
+                    ffi::CheckItem::Deposit,
+                    index,
+                    ffi::CheckFailureReason::AmountMismatch,
+                    format!(
+                        "deposit to {} is {} sats, mainchain has {}",
+                        deposit.address, deposit.amount, e.amount
+                    ),
+                );
+            } else {
+                fail(
+                    ffi::CheckItem::Deposit,
+                    index,
+                    ffi::CheckFailureReason::UnknownDeposit,
+                    format!("no mainchain deposit to {}", deposit.address),
+                );
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for (index, w) in withdrawals.iter().enumerate() {
+            for (reason, message) in self.withdrawal_failures(w, &mut seen)? {
+                fail(ffi::CheckItem::Withdrawal, index, reason, message);
+            }
+        }
+
+        for (index, r) in refunds.iter().enumerate() {
+            let item = ffi::CheckItem::Refund;
+            let outpoint = match decode_outpoint("refund outpoint", &r.outpoint) {
+                Ok(outpoint) => outpoint,
+                Err(err) => {
+                    fail(
+                        item,
+                        index,
+                        ffi::CheckFailureReason::InvalidOutpoint,
+                        err.to_string(),
+                    );
+                    continue;
+                }
+            };
+            if let Err(err) = amount::check_amount("refund", r.amount) {
+                fail(
+                    item,
+                    index,
+                    ffi::CheckFailureReason::AmountOutOfRange,
+                    err.to_string(),
+                );
+            }
+            if self.db.get_withdrawal(&outpoint)?.is_none() {
+                fail(
+                    item,
+                    index,
+                    ffi::CheckFailureReason::UnknownWithdrawal,
+                    format!("refunded withdrawal {} is not connected", r.outpoint),
+                );
+            } else if self.is_withdrawal_spent(&outpoint)? {
+                fail(
+                    item,
+                    index,
+                    ffi::CheckFailureReason::OutpointSpent,
+                    format!("refunded withdrawal {} is already spent", r.outpoint),
+                );
+            }
+        }
+
+        // Per item checks passed, let the drivechain crate have the final
+        // word on the block as a whole.
+        if failures.is_empty() && !self.connect_block(deposits, withdrawals, refunds, true)? {
+            failures.push(ffi::CheckFailure {
+                item: ffi::CheckItem::Block,
+                index: 0,
+                reason: ffi::CheckFailureReason::Rejected,
+                message: "block rejected by drivechain".into(),
+            });
+        }
+        Ok(failures)
+    }
+
+    pub fn check_withdrawal(&self, withdrawal: &ffi::Withdrawal) -> Result<ffi::CheckOutcome> {
+        let mut rejections: Vec<ffi::WithdrawalRejection> = self
+            .withdrawal_failures(withdrawal, &mut HashSet::new())?
+            .into_iter()
+            .map(|(reason, message)| ffi::WithdrawalRejection {
+                reason,
+                message,
+                policy_only: false,
             })
             .collect();
-        Ok(self
-            .0
-            .connect_block(deposits.as_slice(), &withdrawals?, &refunds?, just_check)
-            .is_ok())
+        let min_amount = self.dust_policy.min_amount;
+        if self.dust_policy.mode == ffi::DustMode::Bundle && withdrawal.amount < min_amount {
+            rejections.push(ffi::WithdrawalRejection {
+                reason: ffi::CheckFailureReason::BelowMinimum,
+                message: format!(
+                    "withdrawal of {} sats is below the minimum of {min_amount} and is never \
+                     bundled",
+                    withdrawal.amount
+                ),
+                policy_only: true,
+            });
+        }
+        let fee_floor = self.bundle_policy.fee_floor;
+        if withdrawal.main_fee < fee_floor {
+            rejections.push(ffi::WithdrawalRejection {
+                reason: ffi::CheckFailureReason::BelowFeeFloor,
+                message: format!(
+                    "withdrawal fee of {} sats is below the fee floor of {fee_floor}",
+                    withdrawal.main_fee
+                ),
+                policy_only: true,
+            });
+        }
+        Ok(ffi::CheckOutcome {
+            accepted: rejections.is_empty(),
+            rejections,
+        })
+    }
+
+    // What check_connect_block checks a withdrawal for, seen holds the
+    // outpoints of the withdrawals before it in the block.
+    fn withdrawal_failures(
+        &self,
+        w: &ffi::Withdrawal,
+        seen: &mut HashSet<Vec<u8>>,
+    ) -> Result<Vec<(ffi::CheckFailureReason, String)>> {
+        let mut failures = Vec::new();
+        let outpoint = match decode_outpoint("withdrawal outpoint", &w.outpoint) {
+            Ok(outpoint) => outpoint,
+            Err(err) => {
+                failures.push((ffi::CheckFailureReason::InvalidOutpoint, err.to_string()));
+                return Ok(failures);
+            }
+        };
+        if let Err(err) = dest::withdrawal_dest(&w.main_address) {
+            failures.push((ffi::CheckFailureReason::InvalidAddress, err.to_string()));
+        }
+        if self.dust_policy.mode == ffi::DustMode::Connect && w.amount < self.dust_policy.min_amount
+        {
+            failures.push((
+                ffi::CheckFailureReason::BelowMinimum,
+                format!(
+                    "withdrawal of {} sats is below the minimum of {}",
+                    w.amount, self.dust_policy.min_amount
+                ),
+            ));
+        }
+        let total = amount::check_amount("withdrawal", w.amount)
+            .and_then(|_| amount::check_amount("withdrawal fee", w.main_fee))
+            .and_then(|_| amount::checked_total("withdrawal", [w.amount, w.main_fee]));
+        if let Err(err) = total {
+            failures.push((ffi::CheckFailureReason::AmountOutOfRange, err.to_string()));
+        } else if let Err(err) = amount::check_withdrawal_fee(w.amount, w.main_fee) {
+            failures.push((ffi::CheckFailureReason::FeeNotBelowAmount, err.to_string()));
+        }
+        if !seen.insert(outpoint.clone()) || self.db.get_withdrawal(&outpoint)?.is_some() {
+            failures.push((
+                ffi::CheckFailureReason::DuplicateOutpoint,
+                format!("withdrawal {} is already connected", w.outpoint),
+            ));
+        } else if self.is_withdrawal_spent(&outpoint)? {
+            failures.push((
+                ffi::CheckFailureReason::OutpointSpent,
+                format!("outpoint {} is already spent", w.outpoint),
+            ));
+        }
+        Ok(failures)
+    }
+
+    #[tracing::instrument(skip_all, fields(hash = %block.hash, height = block.height))]
+    pub fn connect_sidechain_block(
+        &mut self,
+        block: &ffi::SidechainBlockRef,
+        deposits: Vec<ffi::Output>,
+        withdrawals: Vec<ffi::Withdrawal>,
+        refunds: Vec<ffi::Refund>,
+        just_check: bool,
+    ) -> Result<bool> {
+        if let Some((height, _)) = self.db.last_sidechain_block()? {
+            if block.height != height + 1 {
+                return Err(Error::SidechainBlockMismatch {
+                    expected: format!("a block at height {}", height + 1),
+                    actual: format!("block {} at height {}", block.hash, block.height),
+                }
+                .into());
+            }
+        }
+        let outpoints: Vec<String> = withdrawals.iter().map(|w| w.outpoint.clone()).collect();
+        let undo = db::BlockUndoRecord {
+            height: block.height,
+            deposits: deposits
+                .iter()
+                .map(|output| (output.address.clone(), output.amount))
+                .collect(),
+            withdrawals: outpoints.clone(),
+            refunds: refunds.iter().map(|r| r.outpoint.clone()).collect(),
+        };
+        let connected = self.connect(None, deposits, withdrawals, refunds, just_check)?;
+        if connected && !just_check {
+            let record = db::SidechainBlockRecord {
+                hash: block.hash.clone(),
+                withdrawals: outpoints,
+                connected_at: unix_time(),
+            };
+            self.db.put_sidechain_block(block.height, &record)?;
+            self.db.put_block_undo(&block.hash, &undo)?;
+        }
+        Ok(connected)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn connect_block_bytes(
+        &mut self,
+        block: &[u8],
+        header_len: usize,
+        height: u64,
+        deposits: Vec<ffi::Output>,
+        withdrawals: Vec<ffi::Withdrawal>,
+        refunds: Vec<ffi::Refund>,
+        just_check: bool,
+    ) -> Result<bool> {
+        let header = block
+            .get(..header_len)
+            .filter(|header| !header.is_empty())
+            .ok_or_else(|| Error::InvalidEncoding {
+                message: format!(
+                    "header of {header_len} bytes doesn't fit a block of {} bytes",
+                    block.len()
+                ),
+            })?;
+        let block = ffi::SidechainBlockRef {
+            hash: TxMerkleNode::hash(header).to_string(),
+            height,
+        };
+        self.connect_sidechain_block(&block, deposits, withdrawals, refunds, just_check)
+    }
+
+    pub fn disconnect_block_by_hash(
+        &mut self,
+        sidechain_hash: &str,
+        just_check: bool,
+    ) -> Result<bool> {
+        let undo = self
+            .db
+            .get_block_undo(sidechain_hash)?
+            .ok_or_else(|| Error::NoUndoData {
+                hash: sidechain_hash.to_string(),
+            })?;
+        let block = ffi::SidechainBlockRef {
+            hash: sidechain_hash.to_string(),
+            height: undo.height,
+        };
+        let deposits = undo
+            .deposits
+            .into_iter()
+            .map(|(address, amount)| ffi::Output { address, amount })
+            .collect();
+        self.disconnect_sidechain_block(
+            &block,
+            deposits,
+            undo.withdrawals,
+            undo.refunds,
+            just_check,
+        )
     }
 
-    fn disconnect_block(
+    #[tracing::instrument(skip_all, fields(hash = %block.hash, height = block.height))]
+    pub fn disconnect_sidechain_block(
         &mut self,
+        block: &ffi::SidechainBlockRef,
         deposits: Vec<ffi::Output>,
         withdrawals: Vec<String>,
         refunds: Vec<String>,
         just_check: bool,
     ) -> Result<bool> {
-        let deposits: Vec<drive::Deposit> = deposits
-            .iter()
-            .map(|deposit| drive::Deposit {
-                address: deposit.address.clone(),
-                amount: deposit.amount,
-            })
-            .collect();
+        let last = self.get_last_connected_sidechain_block()?;
+        if last.hash != block.hash || last.height != block.height {
+            return Err(Error::SidechainBlockMismatch {
+                expected: format!("block {} at height {}", last.hash, last.height),
+                actual: format!("block {} at height {}", block.hash, block.height),
+            }
+            .into());
+        }
+        let disconnected = self.disconnect_block(deposits, withdrawals, refunds, just_check)?;
+        if disconnected && !just_check {
+            self.db.remove_sidechain_block(block.height)?;
+            self.db.remove_block_undo(&block.hash)?;
+        }
+        Ok(disconnected)
+    }
+
+    pub fn disconnect_block(
+        &mut self,
+        deposits: Vec<ffi::Output>,
+        withdrawals: Vec<String>,
+        refunds: Vec<String>,
+        just_check: bool,
+    ) -> Result<bool> {
+        self.check_block_limits(deposits.len(), withdrawals.len(), refunds.len())?;
+        let call = self
+            .recorder
+            .is_some()
+            .then(|| replay::Call::DisconnectBlock {
+                deposits: deposits.clone(),
+                withdrawals: withdrawals.clone(),
+                refunds: refunds.clone(),
+                just_check,
+            });
+        let detail = format!(
+            "{} deposits, {} withdrawals, {} refunds",
+            deposits.len(),
+            withdrawals.len(),
+            refunds.len()
+        );
+        let applying = Arc::clone(&self.applying);
+        let mut applied =
+            (!just_check).then(|| applying.write().unwrap_or_else(PoisonError::into_inner));
+        let start = Instant::now();
+        let result = self.disconnect_unrecorded(deposits, withdrawals, refunds, just_check);
+        // Also when it failed, it may have been applied partly.
+        if let Some(applied) = &mut applied {
+            **applied += 1;
+        }
+        self.record(call, start, &result);
+        // Best effort, the block is disconnected either way.
+        if !just_check && matches!(result, Ok(true)) {
+            if let Err(err) = self.audit(db::AuditOperation::DisconnectBlock, false, detail) {
+                tracing::warn!("failed to write the audit log: {err}");
+            }
+        }
+        result
+    }
+
+    // The tip is asked for best effort, an unreachable node doesn't keep
+    // the entry from being written.
+    fn audit(&self, operation: db::AuditOperation, with_tip: bool, detail: String) -> Result<()> {
+        let main_block_hash = if with_tip {
+            self.mainchain
+                .get_mainchain_tip()
+                .map(|tip| tip.to_string())
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+        let record = db::AuditRecord {
+            at: unix_time(),
+            operation,
+            main_block_hash,
+            detail,
+        };
+        self.db.put_audit_entry(self.db.generate_id()?, &record)
+    }
+
+    pub fn get_audit_log(&self, from_time: u64, to_time: u64) -> Result<Vec<ffi::AuditEntry>> {
+        let mut entries = Vec::new();
+        for entry in self.db.iter_audit_entries() {
+            let (id, record) = entry?;
+            if (from_time..=to_time).contains(&record.at) {
+                entries.push(audit_entry(id, record));
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn replay_calls(&mut self, path: &str) -> Result<ffi::ReplayReport> {
+        replay::replay(self, path, |_| {})
+    }
+
+    pub fn get_last_connected_sidechain_block(&self) -> Result<ffi::SidechainBlockRef> {
+        let (height, record) = self
+            .db
+            .last_sidechain_block()?
+            .ok_or(Error::NoSidechainBlock)?;
+        Ok(ffi::SidechainBlockRef {
+            hash: record.hash,
+            height,
+        })
+    }
+
+    #[tracing::instrument(
+        name = "disconnect_block",
+        skip_all,
+        fields(
+            deposits = deposits.len(),
+            withdrawals = withdrawals.len(),
+            refunds = refunds.len(),
+            just_check,
+            connected = tracing::field::Empty,
+        )
+    )]
+    fn disconnect_unrecorded(
+        &mut self,
+        deposits: Vec<ffi::Output>,
+        withdrawals: Vec<String>,
+        refunds: Vec<String>,
+        just_check: bool,
+    ) -> Result<bool> {
+        validate_disconnect_inputs(&deposits, &withdrawals, &refunds)?;
+        let deposits: Vec<drive::Deposit> = deposits.iter().map(drive::Deposit::from).collect();
         let withdrawals: Result<Vec<Vec<u8>>> = withdrawals
             .iter()
-            .map(|o| Ok(hex::decode(o).into_diagnostic()?.to_vec()))
+            .map(|o| Ok(decode_outpoint("withdrawal outpoint", o)?))
             .collect();
         let refunds: Result<Vec<Vec<u8>>> = refunds
             .iter()
-            .map(|r| Ok(hex::decode(r).into_diagnostic()?.to_vec()))
+            .map(|r| Ok(decode_outpoint("refund outpoint", r)?))
             .collect();
+        let withdrawals = withdrawals?;
+        let refunds = refunds?;
+        let connected = tracing::info_span!("drivechain_disconnect_block").in_scope(|| {
+            self.inner()
+                .disconnect_block(
+                    deposits.as_slice(),
+                    withdrawals.as_slice(),
+                    refunds.as_slice(),
+                    just_check,
+                )
+                .is_ok()
+        });
+        tracing::Span::current().record("connected", connected);
+        if connected && !just_check {
+            *lock(&self.deposit_cache) = None;
+            let _span = tracing::info_span!("bridge_db").entered();
+            for outpoint in &withdrawals {
+                self.db.remove_withdrawal(outpoint)?;
+            }
+            self.record_deposits(&deposits, None)?;
+        }
+        Ok(connected)
+    }
+
+    // The mainchain txid comes from the deposit scanner, matched by the
+    // formatted deposit address.
+    // Updates the deposit history and queues events for watched addresses.
+    // main_height is None for a disconnected block.
+    fn record_deposits(&self, deposits: &[drive::Deposit], main_height: Option<u64>) -> Result<()> {
+        let connected = main_height.is_some();
+        for deposit in deposits {
+            let strdest = self.format_deposit_address(&deposit.address);
+            let mainchain_txid = if connected {
+                self.db.claim_main_deposit(&strdest)?
+            } else {
+                self.db.unclaim_main_deposit(&strdest)?
+            }
+            .unwrap_or_default();
+            match main_height {
+                Some(main_height) => {
+                    let record = db::DepositHistoryRecord {
+                        amount: deposit.amount,
+                        mainchain_txid: mainchain_txid.clone(),
+                        main_height,
+                        connected_at: unix_time(),
+                    };
+                    self.db.put_deposit_history(
+                        &deposit.address,
+                        self.db.generate_id()?,
+                        &record,
+                    )?;
+                }
+                None => self
+                    .db
+                    .remove_last_deposit_history(&deposit.address, deposit.amount)?,
+            }
+            if !self.db.is_watched(&deposit.address)? {
+                continue;
+            }
+            let record = db::DepositEventRecord {
+                created_at: unix_time(),
+                address: deposit.address.clone(),
+                amount: deposit.amount,
+                mainchain_txid,
+                connected,
+            };
+            self.db.put_deposit_event(self.db.generate_id()?, &record)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_deposit_history(&self, sidechain_address: &str) -> Result<Vec<ffi::DepositRecord>> {
         Ok(self
-            .0
-            .disconnect_block(
-                deposits.as_slice(),
-                withdrawals?.as_slice(),
-                refunds?.as_slice(),
-                just_check,
-            )
-            .is_ok())
+            .db
+            .list_deposit_history(sidechain_address)?
+            .into_iter()
+            .map(|(id, record)| ffi::DepositRecord {
+                id,
+                amount: record.amount,
+                mainchain_txid: record.mainchain_txid,
+                main_height: record.main_height,
+                connected_at: record.connected_at,
+            })
+            .collect())
+    }
+
+    #[cfg(feature = "explorer-index")]
+    pub fn get_deposits_by_main_txid(
+        &self,
+        mainchain_txid: &str,
+    ) -> Result<Vec<ffi::IndexedDeposit>> {
+        let mut deposits = Vec::new();
+        for (address, id) in self.db.list_main_txid_deposits(mainchain_txid)? {
+            let Some(record) = self.db.get_deposit_history(&address, id)? else {
+                continue;
+            };
+            deposits.push(ffi::IndexedDeposit {
+                sidechain_address: address,
+                deposit: ffi::DepositRecord {
+                    id,
+                    amount: record.amount,
+                    mainchain_txid: record.mainchain_txid,
+                    main_height: record.main_height,
+                    connected_at: record.connected_at,
+                },
+            });
+        }
+        Ok(deposits)
+    }
+
+    #[cfg(not(feature = "explorer-index"))]
+    pub fn get_deposits_by_main_txid(
+        &self,
+        _mainchain_txid: &str,
+    ) -> Result<Vec<ffi::IndexedDeposit>> {
+        Err(Error::IndexUnavailable.into())
+    }
+
+    #[cfg(feature = "explorer-index")]
+    pub fn get_bundle_withdrawals(&self, bundle_txid: &str) -> Result<Vec<ffi::PayoutInfo>> {
+        let mut payouts = Vec::new();
+        for outpoint in self.db.list_bundle_withdrawals(bundle_txid)? {
+            let (Some(payout), Some(payee)) = (
+                self.db.get_payout(&outpoint)?,
+                self.db.get_payee(&outpoint)?,
+            ) else {
+                continue;
+            };
+            payouts.push(ffi::PayoutInfo {
+                outpoint: hex::encode(outpoint),
+                mainchain_txid: payout.txid,
+                vout: payout.vout,
+                main_block_hash: payout.main_block_hash,
+                amount: payee.amount,
+            });
+        }
+        Ok(payouts)
+    }
+
+    #[cfg(not(feature = "explorer-index"))]
+    pub fn get_bundle_withdrawals(&self, _bundle_txid: &str) -> Result<Vec<ffi::PayoutInfo>> {
+        Err(Error::IndexUnavailable.into())
+    }
+
+    #[cfg(feature = "explorer-index")]
+    pub fn rebuild_explorer_index(&self) -> Result<u64> {
+        self.db.rebuild_explorer_index()
+    }
+
+    #[cfg(not(feature = "explorer-index"))]
+    pub fn rebuild_explorer_index(&self) -> Result<u64> {
+        Err(Error::IndexUnavailable.into())
+    }
+
+    pub fn watch_deposit_address(&self, address: &str) -> Result<()> {
+        self.db.watch_address(address)
+    }
+
+    pub fn unwatch_deposit_address(&self, address: &str) -> Result<()> {
+        self.db.unwatch_address(address)
+    }
+
+    pub fn add_withdrawal_destination(&self, label: &str, address: &str) -> Result<()> {
+        if label.is_empty() {
+            return Err(Error::EmptyLabel.into());
+        }
+        dest::parse_address(address, self.mainchain_network()?)?;
+        dest::withdrawal_dest(address)?;
+        let record = db::WithdrawalDestinationRecord {
+            address: address.to_string(),
+            added_at: unix_time(),
+        };
+        self.db.put_withdrawal_destination(label, &record)
+    }
+
+    pub fn remove_withdrawal_destination(&self, label: &str) -> Result<()> {
+        self.db.remove_withdrawal_destination(label)
+    }
+
+    pub fn list_withdrawal_destinations(&self) -> Result<Vec<ffi::WithdrawalDestination>> {
+        self.db
+            .list_withdrawal_destinations()?
+            .into_iter()
+            .map(|(label, record)| {
+                Ok(ffi::WithdrawalDestination {
+                    label,
+                    dest: dest::parse_dest(&record.address)?,
+                    address: record.address,
+                    added_at: record.added_at,
+                })
+            })
+            .collect()
+    }
+
+    pub fn get_watched_deposit_events(&self) -> Result<Vec<ffi::DepositEvent>> {
+        Ok(self
+            .db
+            .take_deposit_events()?
+            .into_iter()
+            .map(|(id, record)| ffi::DepositEvent {
+                id,
+                created_at: record.created_at,
+                address: record.address,
+                amount: record.amount,
+                mainchain_txid: record.mainchain_txid,
+                connected: record.connected,
+            })
+            .collect())
+    }
+
+    // Conditions past the alert policy's thresholds right now.
+    fn active_alerts(&self) -> Result<Vec<db::AlertRecord>> {
+        let policy = &self.alert_policy;
+        let mut alerts = Vec::new();
+        if policy.bundle_failures > 0 {
+            for (_, record) in self.db.list_broadcasts()? {
+                if record.attempts >= policy.bundle_failures {
+                    alerts.push(db::AlertRecord {
+                        kind: db::AlertKind::BundleFailed,
+                        message: format!(
+                            "bundle broadcast failed {} times: {}",
+                            record.attempts, record.last_error
+                        ),
+                        since: record.created_at,
+                    });
+                }
+            }
+        }
+        if policy.bmm_failures > 0 {
+            let mut failed = 0;
+            let mut since = 0;
+            for entry in self.db.iter_bmm_attempts() {
+                let (_, record) = entry?;
+                match record.outcome {
+                    db::BmmOutcome::Pending => {}
+                    db::BmmOutcome::Failed | db::BmmOutcome::Expired => {
+                        failed += 1;
+                        since = record.created_at;
+                    }
+                    db::BmmOutcome::Succeded => break,
+                }
+            }
+            if failed >= policy.bmm_failures {
+                alerts.push(db::AlertRecord {
+                    kind: db::AlertKind::BmmFailed,
+                    message: format!("last {failed} BMM attempts failed"),
+                    since,
+                });
+            }
+        }
+        if policy.foreign_bundles {
+            for (hash, record) in self.db.list_proposed_bundles()? {
+                if record.active && !record.ours {
+                    alerts.push(db::AlertRecord {
+                        kind: db::AlertKind::ForeignBundle,
+                        message: format!(
+                            "bundle {hash} proposed by another node, work score {}",
+                            record.work_score
+                        ),
+                        since: record.first_seen_at,
+                    });
+                }
+            }
+        }
+        if let Some(since) = self.client.disconnected_since() {
+            let secs = unix_time().saturating_sub(since);
+            if policy.mainchain_unreachable_secs > 0 && secs >= policy.mainchain_unreachable_secs {
+                alerts.push(db::AlertRecord {
+                    kind: db::AlertKind::MainchainUnreachable,
+                    message: format!(
+                        "mainchain node {} unreachable for {secs} seconds",
+                        self.client.active_endpoint()
+                    ),
+                    since,
+                });
+            }
+        }
+        Ok(alerts)
+    }
+
+    // Checked after the operations that can set off an alert and whenever
+    // alerts are asked for.
+    fn raise_alerts(&self) -> Result<Vec<db::AlertRecord>> {
+        let alerts = self.active_alerts()?;
+        let mut raised = lock(&self.raised_alerts);
+        for alert in &alerts {
+            if !raised.contains(&alert.kind) {
+                self.db.put_alert_event(self.db.generate_id()?, alert)?;
+            }
+        }
+        *raised = alerts.iter().map(|alert| alert.kind).collect();
+        Ok(alerts)
+    }
+
+    pub fn get_active_alerts(&self) -> Result<Vec<ffi::Alert>> {
+        Ok(self.raise_alerts()?.into_iter().map(alert).collect())
+    }
+
+    pub fn get_alert_events(&self) -> Result<Vec<ffi::Alert>> {
+        self.raise_alerts()?;
+        Ok(self
+            .db
+            .take_alert_events()?
+            .into_iter()
+            .map(|(_, record)| alert(record))
+            .collect())
+    }
+
+    pub fn set_alert_policy(&mut self, policy: ffi::AlertPolicy) {
+        self.alert_policy = policy;
+    }
+
+    pub fn format_deposit_address(&self, address: &str) -> String {
+        self.deposit_addresses
+            .format(self.this_sidechain, address)
+            .unwrap_or_else(|| self.inner().format_deposit_address(address))
+    }
+
+    pub fn parse_deposit_address(&self, deposit_address: &str) -> Result<String> {
+        match self
+            .deposit_addresses
+            .address(self.this_sidechain, deposit_address)
+        {
+            Some(address) if self.format_deposit_address(&address) == deposit_address => {
+                Ok(address)
+            }
+            _ => Err(Error::InvalidDepositAddress {
+                address: deposit_address.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    pub fn bootstrap_sidechain(&self, proposal: ffi::SidechainProposal) -> Result<()> {
+        self.guard_mainnet("bootstrap_sidechain")?;
+        if self.client.is_sidechain_active(self.this_sidechain)? {
+            return Err(Error::SidechainAlreadyActive {
+                sidechain: self.this_sidechain,
+            }
+            .into());
+        }
+        let mut hash_ids = Vec::new();
+        for (field, hash_id, len) in [
+            ("hash_id_1", &proposal.hash_id_1, 32),
+            ("hash_id_2", &proposal.hash_id_2, 20),
+        ] {
+            if hash_id.is_empty() {
+                break;
+            }
+            codec::decode_len(field, hash_id, len)?;
+            hash_ids.push(hash_id.as_str());
+        }
+        self.client.create_sidechain_proposal(
+            self.this_sidechain,
+            &proposal.title,
+            &proposal.description,
+            proposal.version,
+            &hash_ids,
+        )?;
+        self.db
+            .put_sidechain_proposal(&db::SidechainProposalRecord {
+                title: proposal.title,
+                proposed_at: unix_time(),
+            })
+    }
+
+    // Proposals are told apart by title, the RPCs don't report the slot.
+    pub fn get_activation_status(&self) -> Result<ffi::ActivationStatus> {
+        let mut status = ffi::ActivationStatus {
+            state: ffi::ActivationState::NotProposed,
+            title: String::new(),
+            age: 0,
+            fails: 0,
+            proposed_at: 0,
+        };
+        if let Some(record) = self.db.get_sidechain_proposal()? {
+            status.state = ffi::ActivationState::Failed;
+            status.title = record.title;
+            status.proposed_at = record.proposed_at;
+        }
+        if self.client.is_sidechain_active(self.this_sidechain)? {
+            status.state = ffi::ActivationState::Active;
+            return Ok(status);
+        }
+        if status.state == ffi::ActivationState::NotProposed {
+            return Ok(status);
+        }
+        let votes = self
+            .client
+            .list_sidechain_activation_status()?
+            .into_iter()
+            .find(|votes| votes.title == status.title);
+        if let Some(votes) = votes {
+            status.state = ffi::ActivationState::Voting;
+            status.age = votes.nage;
+            status.fails = votes.nfail;
+        } else if self
+            .client
+            .list_sidechain_proposals()?
+            .iter()
+            .any(|queued| queued.title == status.title)
+        {
+            status.state = ffi::ActivationState::Proposed;
+        }
+        Ok(status)
+    }
+
+    fn escrow_script(&self) -> Result<bitcoin::Script> {
+        let this_sidechain = u8::try_from(self.this_sidechain).into_diagnostic()?;
+        Ok(script::escrow_script(&self.script_template, this_sidechain))
+    }
+
+    pub fn get_escrow_script(&self) -> Result<String> {
+        Ok(hex::encode(self.escrow_script()?.as_bytes()))
+    }
+
+    pub fn list_sidechain_escrows(&self) -> Result<Vec<ffi::EscrowInfo>> {
+        let mut escrows = Vec::new();
+        for sidechain in self.client.list_active_sidechains()? {
+            let (ctip_txid, ctip_vout, value) = match self.client.find_ctip(sidechain.nsidechain)? {
+                Some((ctip, output)) => (ctip.txid.to_string(), ctip.vout, output.value),
+                None => (String::new(), 0, 0),
+            };
+            escrows.push(ffi::EscrowInfo {
+                sidechain: sidechain.nsidechain,
+                title: sidechain.title,
+                value,
+                ctip_txid,
+                ctip_vout,
+            });
+        }
+        Ok(escrows)
     }
 
-    fn format_deposit_address(&self, address: &str) -> String {
-        self.0.format_deposit_address(address)
+    pub fn get_bmm_commitment_script(&self, critical_hash: &str) -> Result<Vec<u8>> {
+        let critical_hash = TxMerkleNode::from_str(critical_hash).into_diagnostic()?;
+        let this_sidechain = u8::try_from(self.this_sidechain).into_diagnostic()?;
+        Ok(script::bmm_commitment_script(this_sidechain, &critical_hash).to_bytes())
     }
 
-    fn get_new_mainchain_address(&self) -> Result<String> {
-        let address = self.0.get_new_mainchain_address().into_diagnostic()?;
+    pub fn get_new_mainchain_address(&self) -> Result<String> {
+        self.guard_wallet("get_new_mainchain_address")?;
+        let address = self.inner().get_new_mainchain_address()?;
         Ok(address.to_string())
     }
 
-    fn create_deposit(&self, address: &str, amount: u64, fee: u64) -> Result<String> {
-        self.0
+    pub fn create_deposit(&self, address: &str, amount: u64, fee: u64) -> Result<String> {
+        self.guard_wallet("create_deposit")?;
+        self.guard_deposit_fee(fee)?;
+        self.inner()
             .create_deposit(
                 address,
                 bitcoin::Amount::from_sat(amount),
                 bitcoin::Amount::from_sat(fee),
             )
             .map(|txid| txid.to_string())
-            .into_diagnostic()
     }
 
-    fn generate(&self, n: u64) -> Result<Vec<String>> {
-        self.0
+    pub fn create_deposit_with_amount(
+        &self,
+        address: &str,
+        amount: ffi::Amount,
+        fee: ffi::Amount,
+    ) -> Result<String> {
+        self.create_deposit(address, amount.sats, fee.sats)
+    }
+
+    pub fn create_deposit_psbt_with_amount(
+        &self,
+        address: &str,
+        amount: ffi::Amount,
+        fee: ffi::Amount,
+    ) -> Result<String> {
+        self.create_deposit_psbt(address, amount.sats, fee.sats)
+    }
+
+    pub fn create_deposit_psbt(&self, address: &str, amount: u64, fee: u64) -> Result<String> {
+        let amount = amount::check_amount("deposit", amount)?;
+        let fee = amount::check_amount("deposit fee", fee)?;
+        self.guard_deposit_fee(fee)?;
+        let escrow_script = self.escrow_script()?;
+        let (ctip, ctip_output) = self.client.get_ctip(self.this_sidechain)?;
+        let strdest = self.format_deposit_address(address);
+        let psbt = psbt::deposit_psbt(ctip, ctip_output, escrow_script, &strdest, amount, fee)?;
+        Ok(base64::encode(serialize(&psbt)))
+    }
+
+    pub fn broadcast_deposit_psbt(&self, psbt: &str) -> Result<String> {
+        let invalid = |message: String| Error::InvalidEncoding { message };
+        let psbt = base64::decode(psbt).map_err(|err| invalid(err.to_string()))?;
+        let psbt = deserialize(&psbt).map_err(|err| invalid(err.to_string()))?;
+        let tx = psbt::finalized(psbt)?;
+        let txid = self.client.send_raw_transaction(&serialize_hex(&tx))?;
+        Ok(txid.to_string())
+    }
+
+    pub fn generate(&self, n: u64) -> Result<Vec<String>> {
+        self.guard_wallet("generate")?;
+        self.guard_mainnet("generate")?;
+        let _mining = lock(&self.mining);
+        self.inner()
             .generate(n as usize)
             .map(|hashes| hashes.iter().map(|hash| hash.to_string()).collect())
-            .into_diagnostic()
     }
 
-    fn flush(&mut self) -> Result<usize> {
-        self.0.flush().into_diagnostic()
+    pub fn generate_to_address(&self, n: u64, address: &str) -> Result<Vec<String>> {
+        self.guard_mainnet("generate")?;
+        let address = bitcoin::Address::from_str(address).into_diagnostic()?;
+        let _mining = lock(&self.mining);
+        let hashes = self.client.generate_to_address(n, &address)?;
+        Ok(hashes.iter().map(|hash| hash.to_string()).collect())
+    }
+
+    pub fn simulate_reorg(&self, depth: u64) -> Result<Vec<String>> {
+        let chain = self.client.get_chain()?;
+        if chain != "regtest" {
+            return Err(Error::RegtestOnly {
+                operation: "simulate_reorg",
+                chain,
+            }
+            .into());
+        }
+        if depth == 0 {
+            return Ok(Vec::new());
+        }
+        // Seen before invalidating, so that observe_tip walks back from it.
+        let tip = self.client.get_best_block_hash()?;
+        self.observe_tip(&self.client, tip)?;
+        let mut block_hash = tip;
+        for _ in 1..depth {
+            block_hash = self.client.get_prev_block_hash(&block_hash)?;
+        }
+        let _mining = lock(&self.mining);
+        self.client.invalidate_block(&block_hash)?;
+        let tip = self.client.get_best_block_hash()?;
+        let disconnected = self.observe_tip(&self.client, tip)?;
+        Ok(disconnected.iter().map(|hash| hash.to_string()).collect())
+    }
+
+    #[tracing::instrument(skip_all, fields(flushed = tracing::field::Empty))]
+    pub fn flush(&mut self) -> Result<usize> {
+        // Logged first so that the entry is flushed too.
+        self.audit(
+            db::AuditOperation::Flush,
+            false,
+            format!("flush of {} records", self.db.unflushed()),
+        )?;
+        let flushed = self.inner().flush()? + self.db.flush()?;
+        tracing::Span::current().record("flushed", flushed);
+        Ok(flushed)
+    }
+
+    pub fn encrypt_db(&self, passphrase: &str) -> Result<()> {
+        self.db.encrypt(passphrase)
+    }
+
+    pub fn set_flush_policy(&self, policy: ffi::FlushPolicy) {
+        *lock(&self.flush_policy) = policy;
+    }
+
+    pub fn tick(&mut self) -> Vec<ffi::TaskRun> {
+        let mut runs = Vec::new();
+        for kind in self.scheduler.due(Instant::now()) {
+            let ran = match kind {
+                ffi::TaskKind::ConfirmBmm => self.confirm_pending_bmm(),
+                ffi::TaskKind::RetryBroadcasts => self.retry_due_broadcasts(),
+                ffi::TaskKind::PrefetchDeposits => Ok(prefetch::refill(
+                    &self.inner,
+                    &self.client,
+                    &self.deposit_cache,
+                )),
+                ffi::TaskKind::Flush => {
+                    let policy = lock(&self.flush_policy).clone();
+                    Ok(flush::flush_if_due(
+                        &self.inner,
+                        &self.db,
+                        &policy,
+                        &mut self.scheduler.last_flush,
+                    ))
+                }
+                _ => Ok(false),
+            };
+            match ran {
+                Ok(false) => {}
+                Ok(true) => runs.push(ffi::TaskRun {
+                    kind,
+                    error: String::new(),
+                }),
+                Err(err) => runs.push(ffi::TaskRun {
+                    kind,
+                    error: err.to_string(),
+                }),
+            }
+        }
+        runs
+    }
+
+    // Every pending attempt, there is nothing to confirm without one.
+    fn confirm_pending_bmm(&mut self) -> Result<bool> {
+        let pending = self.get_pending_bmm_attempts()?;
+        for attempt in &pending {
+            self.confirm_bmm_for(&attempt.critical_hash)?;
+        }
+        Ok(!pending.is_empty())
+    }
+
+    pub fn get_dirty_count(&self) -> usize {
+        usize::try_from(self.db.unflushed()).unwrap_or(usize::MAX)
+    }
+
+    pub fn set_header_cache_capacity(&self, capacity: usize) {
+        self.header_cache().resize(capacity);
+    }
+
+    pub fn clear_caches(&self) {
+        self.header_cache().clear();
+    }
+
+    pub fn sync_spv_headers(&self) -> Result<u64> {
+        Ok(self.spv()?.sync()? as u64)
     }
+
+    pub fn get_spv_tip(&self) -> Result<String> {
+        Ok(self.spv()?.headers.tip().to_string())
+    }
+
+    pub fn verify_tx_inclusion(
+        &self,
+        main_block_hash: &str,
+        txid: &str,
+        merkle_branch: Vec<String>,
+        index: u32,
+    ) -> Result<bool> {
+        let main_block_hash = BlockHash::from_str(main_block_hash).into_diagnostic()?;
+        let txid = Txid::from_str(txid).into_diagnostic()?;
+        let merkle_branch: Result<Vec<TxMerkleNode>> = merkle_branch
+            .iter()
+            .map(|node| TxMerkleNode::from_str(node).into_diagnostic())
+            .collect();
+        let spv = self.spv()?;
+        let header = spv
+            .headers
+            .get(&main_block_hash)
+            .ok_or(Error::UnknownBlock {
+                block_hash: main_block_hash.to_string(),
+            })?;
+        Ok(merkle::verify_merkle_branch(
+            &txid,
+            &merkle_branch?,
+            index,
+            &header.merkle_root,
+        ))
+    }
+}
+
+pub fn block_data(block: &ffi::Block) -> Result<String> {
+    match block.compression {
+        ffi::BlockCompression::None => Ok(block.data.clone()),
+        ffi::BlockCompression::Zstd => decompress_zstd(&block.compressed_data),
+        _ => Err(Error::BlockCompression {
+            message: "unknown compression".into(),
+        }
+        .into()),
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn decompress_zstd(compressed: &[u8]) -> Result<String> {
+    let data = zstd::decode_all(compressed).map_err(|err| Error::BlockCompression {
+        message: err.to_string(),
+    })?;
+    String::from_utf8(data).map_err(|err| {
+        Error::BlockCompression {
+            message: err.to_string(),
+        }
+        .into()
+    })
+}
+
+#[cfg(not(feature = "zstd"))]
+fn decompress_zstd(_compressed: &[u8]) -> Result<String> {
+    Err(Error::BlockCompression {
+        message: "built without the zstd feature".into(),
+    }
+    .into())
+}
+
+pub fn extract_bmm_commitments(main_block_hex: &str) -> Result<Vec<ffi::BmmCommitment>> {
+    let block: bitcoin::Block = client::decode_hex(main_block_hex)?;
+    let coinbase = block.txdata.first().ok_or(Error::InvalidEncoding {
+        message: "main block has no coinbase".into(),
+    })?;
+    Ok(coinbase
+        .output
+        .iter()
+        .enumerate()
+        .filter_map(|(vout, output)| {
+            let (sidechain, critical_hash) = script::parse_bmm_commitment(&output.script_pubkey)?;
+            Some(ffi::BmmCommitment {
+                sidechain,
+                critical_hash: critical_hash.to_string(),
+                vout: vout as u32,
+            })
+        })
+        .collect())
+}
+
+pub fn compute_critical_hash(sidechain_header: &[u8]) -> Result<String> {
+    if sidechain_header.is_empty() {
+        return Err(Error::InvalidEncoding {
+            message: "sidechain header is empty".into(),
+        }
+        .into());
+    }
+    Ok(TxMerkleNode::hash(sidechain_header).to_string())
+}
+
+pub fn extract_mainchain_address_bytes(address: &str, network: &str) -> Result<ffi::DestAddress> {
+    let network = Network::from_str(network).into_diagnostic()?;
+    Ok(dest::parse_address(address, network)?)
+}
+
+pub fn parse_withdrawal_dest(main_address: &str) -> Result<ffi::DestAddress> {
+    Ok(dest::parse_dest(main_address)?)
+}
+
+pub fn parse_outpoint(outpoint: &str) -> Result<Vec<u8>> {
+    Ok(OutPointBytes::decode("outpoint", outpoint)?.to_vec())
+}
+
+pub fn format_outpoint(outpoint: &[u8]) -> Result<String> {
+    Ok(OutPointBytes::from_slice("outpoint", outpoint)?.to_string())
+}
+
+pub fn parse_dest20(dest: &str) -> Result<Vec<u8>> {
+    Ok(Dest20::decode("dest", dest)?.0.to_vec())
 }
 
-fn extract_mainchain_address_bytes(address: &str) -> Result<Vec<u8>> {
-    let address = bitcoin::Address::from_str(&address).into_diagnostic()?;
-    let bytes = drive::Drivechain::extract_mainchain_address_bytes(&address).into_diagnostic()?;
-    Ok(bytes.to_vec())
+pub fn format_dest20(dest: &[u8]) -> Result<String> {
+    Ok(Dest20::from_slice("dest", dest)?.to_string())
+}
+
+pub fn parse_hash32(hash: &str) -> Result<Vec<u8>> {
+    Ok(Hash32::decode("hash", hash)?.0.to_vec())
+}
+
+pub fn format_hash32(hash: &[u8]) -> Result<String> {
+    Ok(Hash32::from_slice("hash", hash)?.to_string())
+}
+
+pub(crate) fn withdrawal_status(
+    main_height: u64,
+    spent: bool,
+    block_count: u64,
+    maturity: u64,
+) -> ffi::WithdrawalStatus {
+    let confirmations = block_count.saturating_sub(main_height);
+    ffi::WithdrawalStatus {
+        height: main_height,
+        spent,
+        confirmations,
+        blocks_until_mature: maturity.saturating_sub(confirmations),
+    }
+}
+
+// Where a bundle at work_score with blocks_remaining of vote_period left
+// is headed.
+fn vote_outcome(
+    work_score: u64,
+    blocks_remaining: u64,
+    min_work_score: u64,
+    vote_period: u64,
+) -> ffi::VoteOutcome {
+    let missing = min_work_score.saturating_sub(work_score);
+    let elapsed = vote_period.saturating_sub(blocks_remaining);
+    if missing == 0 {
+        ffi::VoteOutcome::Approved
+    } else if missing > blocks_remaining {
+        ffi::VoteOutcome::Failing
+    } else if elapsed > 0
+        && u128::from(work_score) * u128::from(blocks_remaining)
+            >= u128::from(missing) * u128::from(elapsed)
+    {
+        ffi::VoteOutcome::OnTrack
+    } else {
+        ffi::VoteOutcome::Stalling
+    }
+}
+
+/// Check every amount, outpoint and destination connect_block would decode,
+/// so that malformed input is an error rather than a panic.
+pub fn validate_block_inputs(
+    deposits: &[ffi::Output],
+    withdrawals: &[ffi::Withdrawal],
+    refunds: &[ffi::Refund],
+) -> Result<()> {
+    amount::checked_total("deposit", deposits.iter().map(|output| output.amount))?;
+    // Checked on the rayon pool, then taken in order so that the first bad
+    // withdrawal is the one reported whichever thread got to it first.
+    let withdrawal_totals: Vec<Result<u64>> = withdrawals
+        .par_iter()
+        .map(|w| {
+            decode_outpoint("withdrawal outpoint", &w.outpoint)?;
+            dest::withdrawal_dest(&w.main_address)?;
+            let amount = amount::check_amount("withdrawal", w.amount)?;
+            let main_fee = amount::check_amount("withdrawal fee", w.main_fee)?;
+            amount::check_withdrawal_fee(amount, main_fee)?;
+            Ok(amount
+                .checked_add(main_fee)
+                .ok_or(Error::AmountOverflow { what: "withdrawal" })?)
+        })
+        .collect();
+    let withdrawal_totals: Vec<u64> = withdrawal_totals.into_iter().collect::<Result<_>>()?;
+    amount::checked_total("withdrawal", withdrawal_totals)?;
+    let refund_outpoints: Vec<Result<Vec<u8>, Error>> = refunds
+        .par_iter()
+        .map(|refund| decode_outpoint("refund outpoint", &refund.outpoint))
+        .collect();
+    for outpoint in refund_outpoints {
+        outpoint?;
+    }
+    amount::checked_total("refund", refunds.iter().map(|r| r.amount))?;
+    Ok(())
+}
+
+/// Same as validate_block_inputs for disconnect_block.
+pub fn validate_disconnect_inputs(
+    deposits: &[ffi::Output],
+    withdrawals: &[String],
+    refunds: &[String],
+) -> Result<()> {
+    amount::checked_total("deposit", deposits.iter().map(|output| output.amount))?;
+    for outpoint in withdrawals {
+        decode_outpoint("withdrawal outpoint", outpoint)?;
+    }
+    for outpoint in refunds {
+        decode_outpoint("refund outpoint", outpoint)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN_WORK_SCORE: u64 = 13_150;
+    const VOTE_PERIOD: u64 = 26_300;
+
+    fn outcome(work_score: u64, blocks_remaining: u64) -> ffi::VoteOutcome {
+        vote_outcome(work_score, blocks_remaining, MIN_WORK_SCORE, VOTE_PERIOD)
+    }
+
+    #[test]
+    fn bundle_reaching_the_work_score_is_approved() {
+        assert_eq!(outcome(MIN_WORK_SCORE, 1_000), ffi::VoteOutcome::Approved);
+        assert_eq!(outcome(MIN_WORK_SCORE + 1, 0), ffi::VoteOutcome::Approved);
+    }
+
+    #[test]
+    fn bundle_missing_more_acks_than_blocks_left_is_failing() {
+        assert_eq!(outcome(MIN_WORK_SCORE - 11, 10), ffi::VoteOutcome::Failing);
+        assert_eq!(outcome(0, 0), ffi::VoteOutcome::Failing);
+    }
+
+    #[test]
+    fn bundle_acked_at_the_rate_it_needs_is_on_track() {
+        // Half the period gone with half the work score, every block ACKed.
+        let half = VOTE_PERIOD / 2;
+        assert_eq!(outcome(MIN_WORK_SCORE / 2, half), ffi::VoteOutcome::OnTrack);
+        // ACKed at half the blocks so far, with twice the missing ACKs left
+        // to vote on.
+        assert_eq!(outcome(MIN_WORK_SCORE - 10, 20), ffi::VoteOutcome::OnTrack);
+        assert_eq!(outcome(MIN_WORK_SCORE - 10, 19), ffi::VoteOutcome::Stalling);
+    }
+
+    #[test]
+    fn bundle_acked_below_the_rate_it_needs_is_stalling() {
+        let half = VOTE_PERIOD / 2;
+        assert_eq!(
+            outcome(MIN_WORK_SCORE / 2 - 1, half),
+            ffi::VoteOutcome::Stalling
+        );
+        assert_eq!(outcome(0, VOTE_PERIOD - 100), ffi::VoteOutcome::Stalling);
+    }
+
+    #[test]
+    fn new_bundle_is_stalling_until_a_block_was_voted_on() {
+        assert_eq!(outcome(0, VOTE_PERIOD), ffi::VoteOutcome::Stalling);
+    }
 }
@@ -0,0 +1,76 @@
+use bitcoin::hash_types::BlockHash;
+use lru::LruCache;
+use std::num::NonZeroUsize;
+
+pub const DEFAULT_HEADER_CACHE_CAPACITY: usize = 1024;
+
+// The previous block hash of a mainchain block never changes, so those entries
+// stay valid forever. Whether a block is connected depends on the active chain
+// and is dropped as soon as a new tip is observed.
+pub struct HeaderCache {
+    prev_hashes: LruCache<BlockHash, BlockHash>,
+    connected: LruCache<BlockHash, bool>,
+    tip: Option<BlockHash>,
+}
+
+impl HeaderCache {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = Self::capacity(capacity);
+        Self {
+            prev_hashes: LruCache::new(capacity),
+            connected: LruCache::new(capacity),
+            tip: None,
+        }
+    }
+
+    // A capacity of zero is treated as one.
+    fn capacity(capacity: usize) -> NonZeroUsize {
+        NonZeroUsize::new(capacity.max(1)).unwrap()
+    }
+
+    pub fn resize(&mut self, capacity: usize) {
+        let capacity = Self::capacity(capacity);
+        self.prev_hashes.resize(capacity);
+        self.connected.resize(capacity);
+    }
+
+    pub fn get_prev(&mut self, hash: &BlockHash) -> Option<BlockHash> {
+        self.prev_hashes.get(hash).copied()
+    }
+
+    pub fn put_prev(&mut self, hash: BlockHash, prev_hash: BlockHash) {
+        self.prev_hashes.put(hash, prev_hash);
+    }
+
+    pub fn get_connected(&mut self, hash: &BlockHash) -> Option<bool> {
+        self.connected.get(hash).copied()
+    }
+
+    pub fn put_connected(&mut self, hash: BlockHash, connected: bool) {
+        self.connected.put(hash, connected);
+    }
+
+    /// Returns the tip tip replaced, if there was one.
+    pub fn observe_tip(&mut self, tip: BlockHash) -> Option<BlockHash> {
+        if self.tip == Some(tip) {
+            return None;
+        }
+        self.connected.clear();
+        self.tip.replace(tip)
+    }
+
+    /// Number of cached prev hashes and connected flags.
+    pub fn entries(&self) -> (usize, usize) {
+        (self.prev_hashes.len(), self.connected.len())
+    }
+
+    pub fn tip(&self) -> Option<BlockHash> {
+        self.tip
+    }
+
+    pub fn clear(&mut self) {
+        self.prev_hashes.clear();
+        self.connected.clear();
+        self.tip = None;
+    }
+}
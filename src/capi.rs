@@ -0,0 +1,594 @@
+//! Flat C ABI over the bridge for consumers that can't use the cxx generated
+//! C++ API, e.g. C or Go through cgo. The header is generated by cbindgen into
+//! include/drivechain.h.
+//!
+//! Every function returns DRIVECHAIN_OK or a negative error code; on error
+//! drivechain_last_error() describes what went wrong. Strings are passed in
+//! as NUL terminated UTF-8 and returned as DrivechainBuffer, which the caller
+//! releases with drivechain_buffer_free. Pointers passed in must be valid for
+//! the duration of the call, out pointers must point to writable memory.
+#![allow(clippy::missing_safety_doc)]
+
+use crate::bridge::{self, ffi, Drivechain};
+use crate::error::Error;
+use miette::{IntoDiagnostic as _, Result};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+pub const DRIVECHAIN_OK: c_int = 0;
+pub const DRIVECHAIN_ERROR: c_int = -1;
+pub const DRIVECHAIN_NULL_ARGUMENT: c_int = -2;
+pub const DRIVECHAIN_PANIC: c_int = -3;
+
+pub const DRIVECHAIN_BMM_SUCCEDED: c_int = 0;
+pub const DRIVECHAIN_BMM_FAILED: c_int = 1;
+pub const DRIVECHAIN_BMM_PENDING: c_int = 2;
+pub const DRIVECHAIN_BMM_EXPIRED: c_int = 3;
+
+pub const DRIVECHAIN_DEST_P2PKH: c_int = 0;
+pub const DRIVECHAIN_DEST_P2SH: c_int = 1;
+pub const DRIVECHAIN_DEST_P2WPKH: c_int = 2;
+pub const DRIVECHAIN_DEST_P2TR: c_int = 3;
+
+pub const DRIVECHAIN_CHAIN_ACTIVE: c_int = 0;
+pub const DRIVECHAIN_CHAIN_STALE: c_int = 1;
+pub const DRIVECHAIN_CHAIN_UNKNOWN: c_int = 2;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// Bytes owned by the library, release with drivechain_buffer_free.
+#[repr(C)]
+pub struct DrivechainBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+#[repr(C)]
+pub struct DrivechainBufferList {
+    pub data: *mut DrivechainBuffer,
+    pub len: usize,
+}
+
+#[repr(C)]
+pub struct DrivechainOutput {
+    pub address: *const c_char,
+    pub amount: u64,
+}
+
+#[repr(C)]
+pub struct DrivechainWithdrawal {
+    pub outpoint: *const c_char,
+    pub main_address: *const c_char,
+    pub main_fee: u64,
+    pub amount: u64,
+}
+
+#[repr(C)]
+pub struct DrivechainRefund {
+    pub outpoint: *const c_char,
+    pub amount: u64,
+}
+
+#[repr(C)]
+pub struct DrivechainOwnedOutput {
+    pub address: DrivechainBuffer,
+    pub amount: u64,
+}
+
+#[repr(C)]
+pub struct DrivechainOutputList {
+    pub data: *mut DrivechainOwnedOutput,
+    pub len: usize,
+}
+
+fn into_raw_parts<T>(items: Vec<T>) -> (*mut T, usize) {
+    let items = items.into_boxed_slice();
+    let len = items.len();
+    (Box::into_raw(items) as *mut T, len)
+}
+
+unsafe fn from_raw_parts<T>(data: *mut T, len: usize) -> Box<[T]> {
+    Box::from_raw(ptr::slice_from_raw_parts_mut(data, len))
+}
+
+impl DrivechainBuffer {
+    fn new(bytes: Vec<u8>) -> Self {
+        let (data, len) = into_raw_parts(bytes);
+        Self { data, len }
+    }
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+// Runs f, turning errors and panics into error codes so that nothing unwinds
+// across the C ABI.
+fn call(f: impl FnOnce() -> Result<()>) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => DRIVECHAIN_OK,
+        Ok(Err(err)) => {
+            set_last_error(err.to_string());
+            match err.downcast_ref::<Error>() {
+                Some(Error::NullArgument { .. }) => DRIVECHAIN_NULL_ARGUMENT,
+                _ => DRIVECHAIN_ERROR,
+            }
+        }
+        Err(_) => {
+            set_last_error("panic in drivechain".into());
+            DRIVECHAIN_PANIC
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(name: &'static str, ptr: *const c_char) -> Result<&'a str> {
+    if ptr.is_null() {
+        return Err(Error::NullArgument { name }.into());
+    }
+    CStr::from_ptr(ptr).to_str().into_diagnostic()
+}
+
+unsafe fn slice_arg<'a, T>(name: &'static str, ptr: *const T, len: usize) -> Result<&'a [T]> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if ptr.is_null() {
+        return Err(Error::NullArgument { name }.into());
+    }
+    Ok(std::slice::from_raw_parts(ptr, len))
+}
+
+unsafe fn handle<'a>(drivechain: *mut Drivechain) -> Result<&'a mut Drivechain> {
+    drivechain
+        .as_mut()
+        .ok_or_else(|| Error::NullArgument { name: "drivechain" }.into())
+}
+
+unsafe fn write<T>(name: &'static str, out: *mut T, value: T) -> Result<()> {
+    if out.is_null() {
+        return Err(Error::NullArgument { name }.into());
+    }
+    out.write(value);
+    Ok(())
+}
+
+/// Message describing the last error on this thread, or NULL. The string is
+/// valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn drivechain_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_buffer_free(buffer: DrivechainBuffer) {
+    if !buffer.data.is_null() {
+        drop(from_raw_parts(buffer.data, buffer.len));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_buffer_list_free(list: DrivechainBufferList) {
+    if !list.data.is_null() {
+        for buffer in from_raw_parts(list.data, list.len).into_vec() {
+            drivechain_buffer_free(buffer);
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_output_list_free(list: DrivechainOutputList) {
+    if !list.data.is_null() {
+        for output in from_raw_parts(list.data, list.len).into_vec() {
+            drivechain_buffer_free(output.address);
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_new(
+    db_path: *const c_char,
+    this_sidechain: usize,
+    main_host: *const c_char,
+    main_port: u16,
+    rpcuser: *const c_char,
+    rpcpassword: *const c_char,
+    out: *mut *mut Drivechain,
+) -> c_int {
+    call(|| {
+        let drivechain = bridge::new_drivechain(
+            str_arg("db_path", db_path)?,
+            this_sidechain,
+            str_arg("main_host", main_host)?,
+            main_port,
+            str_arg("rpcuser", rpcuser)?,
+            str_arg("rpcpassword", rpcpassword)?,
+        )?;
+        write("out", out, Box::into_raw(drivechain))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_free(drivechain: *mut Drivechain) {
+    if !drivechain.is_null() {
+        drop(Box::from_raw(drivechain));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_get_mainchain_tip(
+    drivechain: *mut Drivechain,
+    out: *mut DrivechainBuffer,
+) -> c_int {
+    call(|| {
+        let tip = handle(drivechain)?.get_mainchain_tip()?;
+        write("out", out, DrivechainBuffer::new(tip.into_bytes()))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_get_prev_main_block_hash(
+    drivechain: *mut Drivechain,
+    main_block_hash: *const c_char,
+    out: *mut DrivechainBuffer,
+) -> c_int {
+    call(|| {
+        let prev_hash = handle(drivechain)?
+            .get_prev_main_block_hash(str_arg("main_block_hash", main_block_hash)?)?;
+        write("out", out, DrivechainBuffer::new(prev_hash))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_confirm_bmm(
+    drivechain: *mut Drivechain,
+    out_state: *mut c_int,
+) -> c_int {
+    call(|| {
+        let state = match handle(drivechain)?.confirm_bmm()? {
+            ffi::BMMState::Succeded => DRIVECHAIN_BMM_SUCCEDED,
+            ffi::BMMState::Failed => DRIVECHAIN_BMM_FAILED,
+            ffi::BMMState::Expired => DRIVECHAIN_BMM_EXPIRED,
+            _ => DRIVECHAIN_BMM_PENDING,
+        };
+        write("out_state", out_state, state)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_attempt_bmm(
+    drivechain: *mut Drivechain,
+    critical_hash: *const c_char,
+    prev_main_block_hash: *const c_char,
+    amount: u64,
+) -> c_int {
+    call(|| {
+        handle(drivechain)?.attempt_bmm(
+            str_arg("critical_hash", critical_hash)?,
+            str_arg("prev_main_block_hash", prev_main_block_hash)?,
+            amount,
+        )
+    })
+}
+
+unsafe fn outputs_arg(deposits: *const DrivechainOutput, len: usize) -> Result<Vec<ffi::Output>> {
+    slice_arg("deposits", deposits, len)?
+        .iter()
+        .map(|output| {
+            Ok(ffi::Output {
+                address: str_arg("address", output.address)?.into(),
+                amount: output.amount,
+            })
+        })
+        .collect()
+}
+
+unsafe fn strings_arg(
+    name: &'static str,
+    strings: *const *const c_char,
+    len: usize,
+) -> Result<Vec<String>> {
+    slice_arg(name, strings, len)?
+        .iter()
+        .map(|string| Ok(str_arg(name, *string)?.into()))
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_connect_block(
+    drivechain: *mut Drivechain,
+    deposits: *const DrivechainOutput,
+    deposits_len: usize,
+    withdrawals: *const DrivechainWithdrawal,
+    withdrawals_len: usize,
+    refunds: *const DrivechainRefund,
+    refunds_len: usize,
+    just_check: bool,
+    out_connected: *mut bool,
+) -> c_int {
+    call(|| {
+        let deposits = outputs_arg(deposits, deposits_len)?;
+        let withdrawals: Result<Vec<ffi::Withdrawal>> =
+            slice_arg("withdrawals", withdrawals, withdrawals_len)?
+                .iter()
+                .map(|w| {
+                    Ok(ffi::Withdrawal {
+                        outpoint: str_arg("outpoint", w.outpoint)?.into(),
+                        main_address: str_arg("main_address", w.main_address)?.into(),
+                        main_fee: w.main_fee,
+                        amount: w.amount,
+                    })
+                })
+                .collect();
+        let refunds: Result<Vec<ffi::Refund>> = slice_arg("refunds", refunds, refunds_len)?
+            .iter()
+            .map(|r| {
+                Ok(ffi::Refund {
+                    outpoint: str_arg("outpoint", r.outpoint)?.into(),
+                    amount: r.amount,
+                })
+            })
+            .collect();
+        let connected =
+            handle(drivechain)?.connect_block(deposits, withdrawals?, refunds?, just_check)?;
+        write("out_connected", out_connected, connected)
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_disconnect_block(
+    drivechain: *mut Drivechain,
+    deposits: *const DrivechainOutput,
+    deposits_len: usize,
+    withdrawals: *const *const c_char,
+    withdrawals_len: usize,
+    refunds: *const *const c_char,
+    refunds_len: usize,
+    just_check: bool,
+    out_disconnected: *mut bool,
+) -> c_int {
+    call(|| {
+        let deposits = outputs_arg(deposits, deposits_len)?;
+        let withdrawals = strings_arg("withdrawals", withdrawals, withdrawals_len)?;
+        let refunds = strings_arg("refunds", refunds, refunds_len)?;
+        let disconnected =
+            handle(drivechain)?.disconnect_block(deposits, withdrawals, refunds, just_check)?;
+        write("out_disconnected", out_disconnected, disconnected)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_attempt_bundle_broadcast(drivechain: *mut Drivechain) -> c_int {
+    call(|| handle(drivechain)?.attempt_bundle_broadcast())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_is_outpoint_spent(
+    drivechain: *mut Drivechain,
+    outpoint: *const c_char,
+    out_spent: *mut bool,
+) -> c_int {
+    call(|| {
+        let spent = handle(drivechain)?.is_outpoint_spent(str_arg("outpoint", outpoint)?)?;
+        write("out_spent", out_spent, spent)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_is_main_block_connected(
+    drivechain: *mut Drivechain,
+    main_block_hash: *const c_char,
+    out_connected: *mut bool,
+) -> c_int {
+    call(|| {
+        let connected = handle(drivechain)?
+            .is_main_block_connected(str_arg("main_block_hash", main_block_hash)?)?;
+        write("out_connected", out_connected, connected)
+    })
+}
+
+/// out_connected points to main_block_hashes_len bools, one per hash.
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_filter_connected_main_blocks(
+    drivechain: *mut Drivechain,
+    main_block_hashes: *const *const c_char,
+    main_block_hashes_len: usize,
+    out_connected: *mut bool,
+) -> c_int {
+    call(|| {
+        let main_block_hashes = strings_arg(
+            "main_block_hashes",
+            main_block_hashes,
+            main_block_hashes_len,
+        )?;
+        let connected = handle(drivechain)?.filter_connected_main_blocks(main_block_hashes)?;
+        for (index, connected) in connected.into_iter().enumerate() {
+            write(
+                "out_connected",
+                out_connected.wrapping_add(index),
+                connected,
+            )?;
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_get_main_block_chain_status(
+    drivechain: *mut Drivechain,
+    main_block_hash: *const c_char,
+    out_status: *mut c_int,
+) -> c_int {
+    call(|| {
+        let status = handle(drivechain)?
+            .get_main_block_chain_status(str_arg("main_block_hash", main_block_hash)?)?;
+        let status = match status {
+            ffi::MainBlockChainStatus::ActiveChain => DRIVECHAIN_CHAIN_ACTIVE,
+            ffi::MainBlockChainStatus::StaleBranch => DRIVECHAIN_CHAIN_STALE,
+            _ => DRIVECHAIN_CHAIN_UNKNOWN,
+        };
+        write("out_status", out_status, status)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_verify_bmm(
+    drivechain: *mut Drivechain,
+    main_block_hash: *const c_char,
+    critical_hash: *const c_char,
+    out_verified: *mut bool,
+) -> c_int {
+    call(|| {
+        let verified = handle(drivechain)?.verify_bmm(
+            str_arg("main_block_hash", main_block_hash)?,
+            str_arg("critical_hash", critical_hash)?,
+        )?;
+        write("out_verified", out_verified, verified)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_get_deposit_outputs(
+    drivechain: *mut Drivechain,
+    out: *mut DrivechainOutputList,
+) -> c_int {
+    call(|| {
+        let outputs: Vec<DrivechainOwnedOutput> = handle(drivechain)?
+            .get_deposit_outputs()?
+            .into_iter()
+            .map(|output| DrivechainOwnedOutput {
+                address: DrivechainBuffer::new(output.address.into_bytes()),
+                amount: output.amount,
+            })
+            .collect();
+        let (data, len) = into_raw_parts(outputs);
+        write("out", out, DrivechainOutputList { data, len })
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_format_deposit_address(
+    drivechain: *mut Drivechain,
+    address: *const c_char,
+    out: *mut DrivechainBuffer,
+) -> c_int {
+    call(|| {
+        let address = handle(drivechain)?.format_deposit_address(str_arg("address", address)?);
+        write("out", out, DrivechainBuffer::new(address.into_bytes()))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_compute_critical_hash(
+    sidechain_header: *const u8,
+    sidechain_header_len: usize,
+    out: *mut DrivechainBuffer,
+) -> c_int {
+    call(|| {
+        let critical_hash = bridge::compute_critical_hash(slice_arg(
+            "sidechain_header",
+            sidechain_header,
+            sidechain_header_len,
+        )?)?;
+        write(
+            "out",
+            out,
+            DrivechainBuffer::new(critical_hash.into_bytes()),
+        )
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_extract_mainchain_address_bytes(
+    address: *const c_char,
+    network: *const c_char,
+    out_kind: *mut c_int,
+    out: *mut DrivechainBuffer,
+) -> c_int {
+    call(|| {
+        let dest = bridge::extract_mainchain_address_bytes(
+            str_arg("address", address)?,
+            str_arg("network", network)?,
+        )?;
+        let kind = match dest.kind {
+            ffi::DestKind::P2sh => DRIVECHAIN_DEST_P2SH,
+            ffi::DestKind::P2wpkh => DRIVECHAIN_DEST_P2WPKH,
+            ffi::DestKind::P2tr => DRIVECHAIN_DEST_P2TR,
+            _ => DRIVECHAIN_DEST_P2PKH,
+        };
+        write("out_kind", out_kind, kind)?;
+        write("out", out, DrivechainBuffer::new(dest.hash))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_get_new_mainchain_address(
+    drivechain: *mut Drivechain,
+    out: *mut DrivechainBuffer,
+) -> c_int {
+    call(|| {
+        let address = handle(drivechain)?.get_new_mainchain_address()?;
+        write("out", out, DrivechainBuffer::new(address.into_bytes()))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_create_deposit(
+    drivechain: *mut Drivechain,
+    address: *const c_char,
+    amount: u64,
+    fee: u64,
+    out_txid: *mut DrivechainBuffer,
+) -> c_int {
+    call(|| {
+        let txid = handle(drivechain)?.create_deposit(str_arg("address", address)?, amount, fee)?;
+        write(
+            "out_txid",
+            out_txid,
+            DrivechainBuffer::new(txid.into_bytes()),
+        )
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_generate(
+    drivechain: *mut Drivechain,
+    n: u64,
+    out_block_hashes: *mut DrivechainBufferList,
+) -> c_int {
+    call(|| {
+        let block_hashes: Vec<DrivechainBuffer> = handle(drivechain)?
+            .generate(n)?
+            .into_iter()
+            .map(|block_hash| DrivechainBuffer::new(block_hash.into_bytes()))
+            .collect();
+        let (data, len) = into_raw_parts(block_hashes);
+        write(
+            "out_block_hashes",
+            out_block_hashes,
+            DrivechainBufferList { data, len },
+        )
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn drivechain_flush(
+    drivechain: *mut Drivechain,
+    out_flushed: *mut usize,
+) -> c_int {
+    call(|| {
+        let flushed = handle(drivechain)?.flush()?;
+        write("out_flushed", out_flushed, flushed)
+    })
+}
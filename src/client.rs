@@ -0,0 +1,947 @@
+#[cfg(feature = "async-rpc")]
+use crate::async_client::{self, AsyncMainClient};
+use crate::error::Error;
+#[cfg(unix)]
+use crate::unix_socket;
+use bitcoin::consensus::{deserialize, Decodable};
+use bitcoin::hash_types::{BlockHash, Txid};
+use bitcoin::hashes::Hash as _;
+use bitcoin::{Block, OutPoint, Script, Transaction, TxOut};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Returned by getblockheader for unknown blocks.
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+/// Returned by listsidechainctip for a sidechain without a CTIP, before its
+/// first deposit.
+const RPC_MISC_ERROR: i64 = -1;
+/// Start of endpoints that are unix socket paths rather than host:port.
+pub const UNIX_SOCKET_PREFIX: &str = "unix:";
+/// Minimum time between two health checks of the configured endpoints.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Number of blocks an endpoint may be behind the best one and still be used.
+const MAX_ENDPOINT_LAG: u64 = 2;
+/// Methods that don't change the node's state. Only these are sent again
+/// after a transport error, or to another endpoint, as a request that went
+/// out and whose answer was lost may have been carried out already.
+const READ_ONLY_METHODS: &[&str] = &[
+    "getbestblockhash",
+    "getblock",
+    "getblockchaininfo",
+    "getblockcount",
+    "getblockhash",
+    "getblockheader",
+    "getrawtransaction",
+    "gettxout",
+    "listactivesidechains",
+    "listsidechainactivationstatus",
+    "listsidechainctip",
+    "listsidechaindepositsbyblock",
+    "listsidechainproposals",
+    "listwithdrawalstatus",
+    "scantxoutset",
+    "testmempoolaccept",
+];
+
+// drive::Drivechain keeps its RPC client private, so queries the drivechain
+// crate doesn't expose go through this minimal JSON-RPC client instead.
+#[derive(Clone)]
+pub struct MainClient {
+    // host:port or unix:path, the first one is the primary.
+    endpoints: Arc<Vec<String>>,
+    active: Arc<AtomicUsize>,
+    last_health_check: Arc<Mutex<Option<Instant>>>,
+    // Shared between clones, so that set_credentials reaches all of them.
+    auth: Arc<RwLock<String>>,
+    // Replaced after a transport error, a restarted node leaves the pool full
+    // of dead connections.
+    agent: Arc<Mutex<ureq::Agent>>,
+    // SOCKS5 proxy every agent connects through.
+    proxy: Option<ureq::Proxy>,
+    batch_size: usize,
+    timeout: Option<Duration>,
+    // Shared between clones so that background workers count against the
+    // same limit.
+    rate_limiter: Option<Arc<Mutex<RateLimiter>>>,
+    // Set when a request to the node failed, cleared by mark_connected once
+    // the caller has re-validated the node.
+    disconnected: Arc<AtomicBool>,
+    // Unix time disconnected was last set.
+    disconnected_at: Arc<AtomicU64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RpcOptions {
+    /// Maximum number of HTTP requests sent to the mainchain node per second,
+    /// 0 disables rate limiting.
+    pub requests_per_second: u32,
+    /// Maximum number of calls combined into one JSON-RPC batch request.
+    pub batch_size: usize,
+}
+
+impl Default for RpcOptions {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 0,
+            batch_size: 100,
+        }
+    }
+}
+
+// Spaces requests evenly, each caller reserves the next free slot.
+struct RateLimiter {
+    interval: Duration,
+    next_slot: Instant,
+}
+
+impl RateLimiter {
+    fn reserve(&mut self) -> Instant {
+        let slot = self.next_slot.max(Instant::now());
+        self.next_slot = slot + self.interval;
+        slot
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    #[serde(default)]
+    result: Value,
+    error: Option<RpcError>,
+    #[serde(default)]
+    id: Value,
+}
+
+#[derive(Deserialize)]
+struct BlockHeaderInfo {
+    height: u64,
+    previousblockhash: Option<String>,
+    /// -1 for blocks that are not on the best chain.
+    confirmations: i64,
+}
+
+#[derive(Deserialize)]
+pub struct ActiveSidechain {
+    pub nsidechain: usize,
+    #[serde(default)]
+    pub title: String,
+}
+
+#[derive(Deserialize)]
+struct BlockchainInfo {
+    chain: String,
+}
+
+#[derive(Deserialize)]
+struct Ctip {
+    txid: String,
+    n: u32,
+}
+
+/// A sidechain proposal being voted on, from listsidechainactivationstatus.
+#[derive(Deserialize)]
+pub struct ProposalVotes {
+    pub title: String,
+    pub nage: u64,
+    pub nfail: u64,
+}
+
+/// A proposal this node will add to the blocks it mines, from
+/// listsidechainproposals.
+#[derive(Deserialize)]
+pub struct QueuedProposal {
+    pub title: String,
+}
+
+/// One result of testmempoolaccept.
+#[derive(Deserialize)]
+pub struct MempoolAccept {
+    pub allowed: bool,
+    #[serde(rename = "reject-reason", default)]
+    pub reject_reason: String,
+    #[serde(default)]
+    pub vsize: u64,
+}
+
+/// A bundle being voted on, from listwithdrawalstatus.
+#[derive(Deserialize)]
+pub struct BundleVotes {
+    pub hash: String,
+    pub nblocksleft: u64,
+    pub nworkscore: u64,
+}
+
+#[derive(Deserialize)]
+struct RawTransactionInfo {
+    hex: String,
+    /// Missing while the transaction is in the mempool.
+    blockhash: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TxOutInfo {
+    value: f64,
+    #[serde(rename = "scriptPubKey")]
+    script_pub_key: ScriptPubKeyInfo,
+}
+
+#[derive(Deserialize)]
+struct TxOutSetScan {
+    unspents: Vec<ScannedUnspent>,
+}
+
+#[derive(Deserialize)]
+struct ScannedUnspent {
+    txid: String,
+    vout: u32,
+    #[serde(rename = "scriptPubKey")]
+    script_pub_key: String,
+    amount: f64,
+}
+
+#[derive(Deserialize)]
+struct ScriptPubKeyInfo {
+    hex: String,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcResponse {
+    fn into_result<T: DeserializeOwned>(self, method: &str) -> Result<T, Error> {
+        if let Some(error) = self.error {
+            return Err(Error::Rpc {
+                code: error.code,
+                message: error.message,
+            });
+        }
+        serde_json::from_value(self.result).map_err(|err| Error::Rpc {
+            code: 0,
+            message: format!("{method} returned unexpected result: {err}"),
+        })
+    }
+}
+
+// A response from the node, whatever its status.
+enum Reply {
+    Http(ureq::Response),
+    #[cfg(unix)]
+    Unix(Vec<u8>),
+}
+
+impl Reply {
+    fn into_json<T: DeserializeOwned>(self) -> Result<T, Error> {
+        match self {
+            Reply::Http(response) => response.into_json().map_err(|err| Error::Transport {
+                message: err.to_string(),
+            }),
+            #[cfg(unix)]
+            Reply::Unix(body) => serde_json::from_slice(&body).map_err(|err| Error::Transport {
+                message: err.to_string(),
+            }),
+        }
+    }
+}
+
+/// host:port, or host alone when it is a unix socket.
+pub fn endpoint(host: &str, port: u16) -> String {
+    match host.starts_with(UNIX_SOCKET_PREFIX) {
+        true => host.to_string(),
+        false => format!("{host}:{port}"),
+    }
+}
+
+fn request(id: usize, method: &str, params: &[Value]) -> Value {
+    json!({
+        "jsonrpc": "1.0",
+        "id": id,
+        "method": method,
+        "params": params,
+    })
+}
+
+fn basic_auth(rpcuser: &str, rpcpassword: &str) -> String {
+    format!(
+        "Basic {}",
+        base64::encode(format!("{rpcuser}:{rpcpassword}"))
+    )
+}
+
+impl MainClient {
+    pub fn new(
+        host: &str,
+        port: u16,
+        rpcuser: &str,
+        rpcpassword: &str,
+        options: RpcOptions,
+    ) -> Self {
+        let rate_limiter = (options.requests_per_second > 0).then(|| {
+            Arc::new(Mutex::new(RateLimiter {
+                interval: Duration::from_secs(1) / options.requests_per_second,
+                next_slot: Instant::now(),
+            }))
+        });
+        Self {
+            endpoints: Arc::new(vec![endpoint(host, port)]),
+            active: Arc::new(AtomicUsize::new(0)),
+            last_health_check: Arc::new(Mutex::new(None)),
+            auth: Arc::new(RwLock::new(basic_auth(rpcuser, rpcpassword))),
+            agent: Arc::new(Mutex::new(ureq::Agent::new())),
+            proxy: None,
+            batch_size: options.batch_size.max(1),
+            timeout: None,
+            rate_limiter,
+            disconnected: Arc::new(AtomicBool::new(false)),
+            disconnected_at: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    // Only a read_only request is sent again after a transport error.
+    fn post<T: DeserializeOwned>(&self, body: Value, read_only: bool) -> Result<T, Error> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let slot = rate_limiter
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .reserve();
+            let now = Instant::now();
+            if slot > now {
+                thread::sleep(slot - now);
+            }
+        }
+        let response = match self.send(&body) {
+            Err(err) => {
+                // Retry once on fresh connections in case the node restarted,
+                // unless the caller asked to fail fast or the call changes state.
+                *self.agent.lock().unwrap_or_else(PoisonError::into_inner) = self.new_agent();
+                match self.timeout {
+                    Some(_) => Err(err),
+                    None if !read_only => Err(err),
+                    None => self.send(&body),
+                }
+            }
+            result => result,
+        };
+        let response = match response {
+            Err(err) if read_only => self.fail_over(&body).unwrap_or(Err(err)),
+            result => result,
+        };
+        match response {
+            Ok(response) => response.into_json(),
+            Err(message) => {
+                self.mark_disconnected();
+                Err(Error::Transport { message })
+            }
+        }
+    }
+
+    fn send(&self, body: &Value) -> Result<Reply, String> {
+        self.send_to(self.active_endpoint(), body, self.timeout)
+    }
+
+    // Err only if the request didn't get an answer, bitcoind reports RPC
+    // errors with a non 2xx status and a JSON body.
+    fn send_to(
+        &self,
+        endpoint: &str,
+        body: &Value,
+        timeout: Option<Duration>,
+    ) -> Result<Reply, String> {
+        let auth = self
+            .auth
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if let Some(path) = endpoint.strip_prefix(UNIX_SOCKET_PREFIX) {
+            #[cfg(unix)]
+            return unix_socket::post(path, &auth, body, timeout)
+                .map(Reply::Unix)
+                .map_err(|err| format!("{endpoint}: {err}"));
+            #[cfg(not(unix))]
+            return Err(format!("{path}: unix sockets are not supported here"));
+        }
+        let agent = self
+            .agent
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let mut request = agent
+            .post(&format!("http://{endpoint}"))
+            .set("Authorization", &auth);
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        match request.send_json(body) {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => Ok(Reply::Http(response)),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    // Try the other endpoints in order after the active one became
+    // unreachable, the first one that answers becomes active.
+    fn fail_over(&self, body: &Value) -> Option<Result<Reply, String>> {
+        let active = self.active.load(Ordering::SeqCst);
+        for offset in 1..self.endpoints.len() {
+            let index = (active + offset) % self.endpoints.len();
+            match self.send_to(&self.endpoints[index], body, self.timeout) {
+                Err(_) => continue,
+                result => {
+                    self.active.store(index, Ordering::SeqCst);
+                    return Some(result);
+                }
+            }
+        }
+        None
+    }
+
+    /// A client that fails over to endpoints, as host:port or unix:path with
+    /// the same credentials, when the primary is unreachable or lagging.
+    pub fn with_fallbacks(mut self, endpoints: &[String]) -> Self {
+        let mut all = vec![self.endpoints[0].clone()];
+        all.extend(endpoints.iter().cloned());
+        self.endpoints = Arc::new(all);
+        self
+    }
+
+    /// A client reaching its TCP endpoints through the SOCKS5 proxy at
+    /// host:port, which resolves their host names too, so that onion
+    /// addresses work through Tor. user and password are for proxies that
+    /// ask for them, empty otherwise.
+    pub fn with_proxy(mut self, proxy: &str, user: &str, password: &str) -> Result<Self, Error> {
+        if [user, password]
+            .iter()
+            .any(|part| part.contains([':', '@']))
+        {
+            return Err(Error::InvalidEncoding {
+                message: "rpc_proxy: user and password can't contain ':' or '@'".into(),
+            });
+        }
+        let url = match (user, password) {
+            ("", "") => format!("socks5://{proxy}"),
+            (user, password) => format!("socks5://{user}:{password}@{proxy}"),
+        };
+        let proxy = ureq::Proxy::new(url).map_err(|err| Error::InvalidEncoding {
+            message: format!("rpc_proxy: {err}"),
+        })?;
+        self.proxy = Some(proxy);
+        self.agent = Arc::new(Mutex::new(self.new_agent()));
+        Ok(self)
+    }
+
+    fn new_agent(&self) -> ureq::Agent {
+        match &self.proxy {
+            Some(proxy) => ureq::AgentBuilder::new().proxy(proxy.clone()).build(),
+            None => ureq::Agent::new(),
+        }
+    }
+
+    /// Use new credentials for every request from now on, in this client
+    /// and all its clones.
+    pub fn set_credentials(&self, rpcuser: &str, rpcpassword: &str) {
+        *self.auth.write().unwrap_or_else(PoisonError::into_inner) =
+            basic_auth(rpcuser, rpcpassword);
+    }
+
+    /// A clone using other credentials, leaving this client's alone.
+    pub fn with_credentials(&self, rpcuser: &str, rpcpassword: &str) -> Self {
+        Self {
+            auth: Arc::new(RwLock::new(basic_auth(rpcuser, rpcpassword))),
+            ..self.clone()
+        }
+    }
+
+    pub fn active_endpoint(&self) -> &str {
+        &self.endpoints[self.active.load(Ordering::SeqCst)]
+    }
+
+    /// Switch to the first endpoint that is reachable and at most
+    /// MAX_ENDPOINT_LAG blocks behind the best one, so the primary is used
+    /// again once it has caught up. Does nothing if the last check was less
+    /// than HEALTH_CHECK_INTERVAL ago.
+    pub fn check_health(&self) {
+        if self.endpoints.len() < 2 {
+            return;
+        }
+        {
+            let mut last_health_check = self
+                .last_health_check
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if last_health_check.is_some_and(|last| last.elapsed() < HEALTH_CHECK_INTERVAL) {
+                return;
+            }
+            *last_health_check = Some(Instant::now());
+        }
+        let counts: Vec<Option<u64>> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| self.block_count_at(endpoint).ok())
+            .collect();
+        let best = match counts.iter().flatten().max() {
+            Some(best) => *best,
+            None => return,
+        };
+        let healthy = counts
+            .iter()
+            .position(|count| count.is_some_and(|count| count + MAX_ENDPOINT_LAG >= best));
+        if let Some(index) = healthy {
+            self.active.store(index, Ordering::SeqCst);
+        }
+    }
+
+    fn block_count_at(&self, endpoint: &str) -> Result<u64, Error> {
+        let body = request(0, "getblockcount", &[]);
+        let response: RpcResponse = self
+            .send_to(endpoint, &body, Some(HEALTH_CHECK_TIMEOUT))
+            .map_err(|message| Error::Transport { message })?
+            .into_json()?;
+        response.into_result("getblockcount")
+    }
+
+    /// A client sharing connections and rate limit with this one whose
+    /// requests fail once timeout has passed.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self.clone()
+        }
+    }
+
+    /// An async client for the active endpoint, with this client's
+    /// credentials and timeout.
+    #[cfg(feature = "async-rpc")]
+    pub fn to_async(&self) -> AsyncMainClient {
+        let auth = self
+            .auth
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        AsyncMainClient::new(self.active_endpoint(), auth, self.timeout)
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected.load(Ordering::SeqCst)
+    }
+
+    pub fn mark_disconnected(&self) {
+        if !self.disconnected.swap(true, Ordering::SeqCst) {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default();
+            self.disconnected_at.store(now, Ordering::SeqCst);
+        }
+    }
+
+    /// Unix time of the failed request the node has been disconnected
+    /// since.
+    pub fn disconnected_since(&self) -> Option<u64> {
+        self.is_disconnected()
+            .then(|| self.disconnected_at.load(Ordering::SeqCst))
+    }
+
+    pub fn mark_connected(&self) {
+        self.disconnected.store(false, Ordering::SeqCst);
+    }
+
+    /// Pass through the result of a call drive::Drivechain made to the same
+    /// node, marking the node as disconnected if it failed.
+    pub fn track<T, E>(&self, result: Result<T, E>) -> Result<T, E> {
+        if result.is_err() {
+            self.mark_disconnected();
+        }
+        result
+    }
+
+    pub fn call<T: DeserializeOwned>(&self, method: &str, params: &[Value]) -> Result<T, Error> {
+        let response: RpcResponse = self.post(
+            request(0, method, params),
+            READ_ONLY_METHODS.contains(&method),
+        )?;
+        response.into_result(method)
+    }
+
+    /// Call method once per entry of params, combining the calls into batch
+    /// requests of at most batch_size calls each. Results are returned in the
+    /// order of params.
+    pub fn call_batch<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &[Vec<Value>],
+    ) -> Result<Vec<T>, Error> {
+        self.call_batch_results(method, params)?
+            .into_iter()
+            .collect()
+    }
+
+    /// call_batch keeping each call's own result, a call failing doesn't
+    /// fail the others. Err only if a batch couldn't be sent.
+    pub fn call_batch_results<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &[Vec<Value>],
+    ) -> Result<Vec<Result<T, Error>>, Error> {
+        let bodies = params
+            .chunks(self.batch_size)
+            .enumerate()
+            .map(|(chunk_index, chunk)| {
+                let offset = chunk_index * self.batch_size;
+                Value::Array(
+                    chunk
+                        .iter()
+                        .enumerate()
+                        .map(|(index, params)| request(offset + index, method, params))
+                        .collect(),
+                )
+            })
+            .collect();
+        let batches = self.post_batches(bodies, READ_ONLY_METHODS.contains(&method))?;
+        let mut results = Vec::with_capacity(params.len());
+        for (mut responses, chunk) in batches.into_iter().zip(params.chunks(self.batch_size)) {
+            // Responses to a batch may come back in any order.
+            responses.sort_by_key(|response| response.id.as_u64());
+            if responses.len() != chunk.len() {
+                return Err(Error::Rpc {
+                    code: 0,
+                    message: format!(
+                        "{method} batch returned {} responses for {} calls",
+                        responses.len(),
+                        chunk.len()
+                    ),
+                });
+            }
+            for response in responses {
+                results.push(response.into_result(method));
+            }
+        }
+        Ok(results)
+    }
+
+    // One after another, or all at once with async-rpc. A rate limit, a
+    // proxy or a unix socket keeps them sequential, and batches that failed
+    // to go out concurrently are sent again sequentially, so that they can
+    // fail over. Batches of other than read_only calls are never sent again.
+    fn post_batches(
+        &self,
+        bodies: Vec<Value>,
+        read_only: bool,
+    ) -> Result<Vec<Vec<RpcResponse>>, Error> {
+        #[cfg(feature = "async-rpc")]
+        if read_only
+            && bodies.len() > 1
+            && self.rate_limiter.is_none()
+            && self.proxy.is_none()
+            && !self.active_endpoint().starts_with(UNIX_SOCKET_PREFIX)
+        {
+            let client = self.to_async();
+            if let Ok(Ok(batches)) = async_client::block_on(client.post_all(bodies.clone())) {
+                return Ok(batches);
+            }
+        }
+        bodies
+            .into_iter()
+            .map(|body| self.post(body, read_only))
+            .collect()
+    }
+
+    pub fn list_active_sidechains(&self) -> Result<Vec<ActiveSidechain>, Error> {
+        self.call("listactivesidechains", &[])
+    }
+
+    pub fn is_sidechain_active(&self, nsidechain: usize) -> Result<bool, Error> {
+        let sidechains = self.list_active_sidechains()?;
+        Ok(sidechains
+            .iter()
+            .any(|sidechain| sidechain.nsidechain == nsidechain))
+    }
+
+    /// The sidechain's current treasury output and what it holds.
+    pub fn get_ctip(&self, nsidechain: usize) -> Result<(OutPoint, TxOut), Error> {
+        self.find_ctip(nsidechain)?.ok_or_else(|| Error::Rpc {
+            code: RPC_MISC_ERROR,
+            message: format!("sidechain {nsidechain} has no CTIP"),
+        })
+    }
+
+    /// get_ctip, None if the sidechain has no CTIP because nothing was
+    /// deposited to it yet.
+    pub fn find_ctip(&self, nsidechain: usize) -> Result<Option<(OutPoint, TxOut)>, Error> {
+        let ctip: Ctip = match self.call("listsidechainctip", &[json!(nsidechain)]) {
+            Ok(ctip) => ctip,
+            Err(Error::Rpc {
+                code: RPC_MISC_ERROR,
+                ..
+            }) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let info: Option<TxOutInfo> = self.call("gettxout", &[json!(ctip.txid), json!(ctip.n)])?;
+        let info = info.ok_or_else(|| Error::Rpc {
+            code: 0,
+            message: format!(
+                "sidechain {nsidechain} CTIP {}:{} is spent",
+                ctip.txid, ctip.n
+            ),
+        })?;
+        let invalid = |message: String| Error::InvalidEncoding { message };
+        let txid = ctip
+            .txid
+            .parse::<Txid>()
+            .map_err(|err| invalid(err.to_string()))?;
+        let value = bitcoin::Amount::from_btc(info.value)
+            .map_err(|err| invalid(err.to_string()))?
+            .to_sat();
+        let script_pubkey = hex::decode(&info.script_pub_key.hex)
+            .map_err(|err| invalid(err.to_string()))?
+            .into();
+        Ok(Some((
+            OutPoint::new(txid, ctip.n),
+            TxOut {
+                value,
+                script_pubkey,
+            },
+        )))
+    }
+
+    /// Have the node propose sidechain nsidechain in the blocks it mines.
+    /// hash_ids are the optional hashid1 and hashid2, in that order.
+    pub fn create_sidechain_proposal(
+        &self,
+        nsidechain: usize,
+        title: &str,
+        description: &str,
+        version: i32,
+        hash_ids: &[&str],
+    ) -> Result<(), Error> {
+        let mut params = vec![
+            json!(nsidechain),
+            json!(title),
+            json!(description),
+            json!(version),
+        ];
+        params.extend(hash_ids.iter().map(|hash_id| json!(hash_id)));
+        let _: Value = self.call("createsidechainproposal", &params)?;
+        Ok(())
+    }
+
+    pub fn list_sidechain_proposals(&self) -> Result<Vec<QueuedProposal>, Error> {
+        self.call("listsidechainproposals", &[])
+    }
+
+    pub fn list_sidechain_activation_status(&self) -> Result<Vec<ProposalVotes>, Error> {
+        self.call("listsidechainactivationstatus", &[])
+    }
+
+    pub fn list_withdrawal_status(&self, nsidechain: usize) -> Result<Vec<BundleVotes>, Error> {
+        self.call("listwithdrawalstatus", &[json!(nsidechain)])
+    }
+
+    pub fn generate_to_address(
+        &self,
+        n: u64,
+        address: &bitcoin::Address,
+    ) -> Result<Vec<BlockHash>, Error> {
+        let block_hashes: Vec<String> =
+            self.call("generatetoaddress", &[json!(n), json!(address.to_string())])?;
+        block_hashes
+            .iter()
+            .map(|block_hash| parse_block_hash("generatetoaddress", block_hash))
+            .collect()
+    }
+
+    pub fn send_raw_transaction(&self, tx_hex: &str) -> Result<Txid, Error> {
+        let txid: String = self.call("sendrawtransaction", &[json!(tx_hex)])?;
+        txid.parse().map_err(|_| Error::InvalidEncoding {
+            message: format!("sendrawtransaction returned invalid txid {txid}"),
+        })
+    }
+
+    /// Confirmed outputs matching descriptor, found in the UTXO set without
+    /// a wallet. Outputs spent by mempool transactions are still returned.
+    pub fn scan_tx_out_set(&self, descriptor: &str) -> Result<Vec<(OutPoint, TxOut)>, Error> {
+        let scan: TxOutSetScan =
+            self.call("scantxoutset", &[json!("start"), json!([descriptor])])?;
+        let invalid = |message: String| Error::InvalidEncoding { message };
+        scan.unspents
+            .into_iter()
+            .map(|unspent| {
+                let txid = unspent
+                    .txid
+                    .parse::<Txid>()
+                    .map_err(|err| invalid(err.to_string()))?;
+                let value = bitcoin::Amount::from_btc(unspent.amount)
+                    .map_err(|err| invalid(err.to_string()))?
+                    .to_sat();
+                let script_pubkey = hex::decode(&unspent.script_pub_key)
+                    .map_err(|err| invalid(err.to_string()))?
+                    .into();
+                Ok((
+                    OutPoint::new(txid, unspent.vout),
+                    TxOut {
+                        value,
+                        script_pubkey,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    pub fn test_mempool_accept(&self, tx_hex: &str) -> Result<MempoolAccept, Error> {
+        let results: Vec<MempoolAccept> = self.call("testmempoolaccept", &[json!([tx_hex])])?;
+        results.into_iter().next().ok_or(Error::Rpc {
+            code: 0,
+            message: "testmempoolaccept returned no result".into(),
+        })
+    }
+
+    /// Hand a withdrawal bundle for nsidechain to the mainchain node, which
+    /// proposes it for voting.
+    pub fn receive_withdrawal_bundle(&self, nsidechain: usize, tx_hex: &str) -> Result<(), Error> {
+        let _: Value = self.call(
+            "receivewithdrawalbundle",
+            &[json!(nsidechain), json!(tx_hex)],
+        )?;
+        Ok(())
+    }
+
+    /// Mark block_hash and its descendants invalid, the node reorgs to the
+    /// best chain without them.
+    pub fn invalidate_block(&self, block_hash: &BlockHash) -> Result<(), Error> {
+        let _: Value = self.call("invalidateblock", &[json!(block_hash.to_string())])?;
+        Ok(())
+    }
+
+    /// "main", "test", "signet" or "regtest".
+    pub fn get_chain(&self) -> Result<String, Error> {
+        let info: BlockchainInfo = self.call("getblockchaininfo", &[])?;
+        Ok(info.chain)
+    }
+
+    pub fn get_block_count(&self) -> Result<u64, Error> {
+        self.call("getblockcount", &[])
+    }
+
+    pub fn get_block_height(&self, block_hash: &BlockHash) -> Result<u64, Error> {
+        let info: BlockHeaderInfo = self.call(
+            "getblockheader",
+            &[json!(block_hash.to_string()), json!(true)],
+        )?;
+        Ok(info.height)
+    }
+
+    pub fn get_best_block_hash(&self) -> Result<BlockHash, Error> {
+        let block_hash: String = self.call("getbestblockhash", &[])?;
+        parse_block_hash("getbestblockhash", &block_hash)
+    }
+
+    /// All zeros for the genesis block, like drive::Drivechain.
+    pub fn get_prev_block_hash(&self, block_hash: &BlockHash) -> Result<BlockHash, Error> {
+        let info: BlockHeaderInfo = self.call(
+            "getblockheader",
+            &[json!(block_hash.to_string()), json!(true)],
+        )?;
+        match info.previousblockhash {
+            Some(prev_hash) => parse_block_hash("getblockheader", &prev_hash),
+            None => Ok(BlockHash::all_zeros()),
+        }
+    }
+
+    pub fn is_block_in_best_chain(&self, block_hash: &BlockHash) -> Result<bool, Error> {
+        Ok(self.get_block_chain_status(block_hash)? == Some(true))
+    }
+
+    /// get_block_chain_status for each of block_hashes, in batch requests.
+    pub fn get_block_chain_statuses(
+        &self,
+        block_hashes: &[BlockHash],
+    ) -> Result<Vec<Option<bool>>, Error> {
+        let params: Vec<Vec<Value>> = block_hashes
+            .iter()
+            .map(|block_hash| vec![json!(block_hash.to_string()), json!(true)])
+            .collect();
+        self.call_batch_results::<BlockHeaderInfo>("getblockheader", &params)?
+            .into_iter()
+            .map(|info| match info {
+                Ok(info) => Ok(Some(info.confirmations >= 0)),
+                Err(Error::Rpc {
+                    code: RPC_INVALID_ADDRESS_OR_KEY,
+                    ..
+                }) => Ok(None),
+                Err(err) => Err(err),
+            })
+            .collect()
+    }
+
+    /// Whether the node has the block on its best chain, None if it doesn't
+    /// know the block at all.
+    pub fn get_block_chain_status(&self, block_hash: &BlockHash) -> Result<Option<bool>, Error> {
+        let info: Result<BlockHeaderInfo, Error> = self.call(
+            "getblockheader",
+            &[json!(block_hash.to_string()), json!(true)],
+        );
+        match info {
+            Ok(info) => Ok(Some(info.confirmations >= 0)),
+            Err(Error::Rpc {
+                code: RPC_INVALID_ADDRESS_OR_KEY,
+                ..
+            }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn get_block_hashes(&self, heights: &[u64]) -> Result<Vec<BlockHash>, Error> {
+        let params: Vec<Vec<Value>> = heights.iter().map(|height| vec![json!(height)]).collect();
+        let block_hashes: Vec<String> = self.call_batch("getblockhash", &params)?;
+        block_hashes
+            .iter()
+            .map(|block_hash| parse_block_hash("getblockhash", block_hash))
+            .collect()
+    }
+
+    pub fn get_block_headers_hex(&self, block_hashes: &[BlockHash]) -> Result<Vec<String>, Error> {
+        let params: Vec<Vec<Value>> = block_hashes
+            .iter()
+            .map(|block_hash| vec![json!(block_hash.to_string()), json!(false)])
+            .collect();
+        self.call_batch("getblockheader", &params)
+    }
+
+    /// A transaction and the block it confirmed in, needs -txindex for
+    /// transactions outside the node's wallet and mempool.
+    pub fn get_transaction(&self, txid: &Txid) -> Result<(Transaction, Option<BlockHash>), Error> {
+        let info: RawTransactionInfo =
+            self.call("getrawtransaction", &[json!(txid.to_string()), json!(true)])?;
+        let block_hash = info
+            .blockhash
+            .map(|block_hash| parse_block_hash("getrawtransaction", &block_hash))
+            .transpose()?;
+        Ok((decode_hex(&info.hex)?, block_hash))
+    }
+
+    pub fn get_block(&self, block_hash: &BlockHash) -> Result<Block, Error> {
+        let block_hex: String =
+            self.call("getblock", &[json!(block_hash.to_string()), json!(0)])?;
+        decode_hex(&block_hex)
+    }
+}
+
+fn parse_block_hash(method: &str, block_hash: &str) -> Result<BlockHash, Error> {
+    block_hash.parse().map_err(|_| Error::InvalidEncoding {
+        message: format!("{method} returned invalid hash {block_hash}"),
+    })
+}
+
+pub fn decode_hex<T: Decodable>(hex: &str) -> Result<T, Error> {
+    let bytes = hex::decode(hex).map_err(|err| Error::InvalidEncoding {
+        message: err.to_string(),
+    })?;
+    deserialize(&bytes).map_err(|err| Error::InvalidEncoding {
+        message: err.to_string(),
+    })
+}
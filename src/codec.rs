@@ -0,0 +1,175 @@
+use crate::error::Error;
+use std::fmt;
+
+// Hex the C++ side hands over is decoded here, once, into fixed size bytes
+// whose length is already checked, so nothing further in slices or copies
+// out of a buffer that may be too short.
+
+/// A serialized sidechain outpoint, txid and vout, as the drivechain crate
+/// keys withdrawals and refunds by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OutPointBytes(pub [u8; 36]);
+
+/// The 20 byte hash a P2PKH withdrawal pays to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Dest20(pub [u8; 20]);
+
+/// A 32 byte hash in the byte order it was given in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Hash32(pub [u8; 32]);
+
+/// hex decoded, failing unless it is exactly len bytes. field names the
+/// value in errors.
+pub fn decode_len(field: &'static str, hex: &str, len: usize) -> Result<Vec<u8>, Error> {
+    let bytes = hex::decode(hex).map_err(|err| Error::InvalidEncoding {
+        message: format!("{field} {hex}: {err}"),
+    })?;
+    check_len(field, &bytes, len)?;
+    Ok(bytes)
+}
+
+fn check_len(field: &'static str, bytes: &[u8], len: usize) -> Result<(), Error> {
+    if bytes.len() != len {
+        return Err(Error::InvalidLength {
+            field,
+            expected: len,
+            actual: bytes.len(),
+        });
+    }
+    Ok(())
+}
+
+fn decode_fixed<const N: usize>(field: &'static str, hex: &str) -> Result<[u8; N], Error> {
+    let bytes = decode_len(field, hex, N)?;
+    Ok(<[u8; N]>::try_from(bytes.as_slice()).expect("length checked"))
+}
+
+fn from_slice<const N: usize>(field: &'static str, bytes: &[u8]) -> Result<[u8; N], Error> {
+    check_len(field, bytes, N)?;
+    Ok(<[u8; N]>::try_from(bytes).expect("length checked"))
+}
+
+impl OutPointBytes {
+    pub fn decode(field: &'static str, hex: &str) -> Result<Self, Error> {
+        decode_fixed(field, hex).map(Self)
+    }
+
+    pub fn from_slice(field: &'static str, bytes: &[u8]) -> Result<Self, Error> {
+        from_slice(field, bytes).map(Self)
+    }
+
+    pub fn to_vec(self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+}
+
+impl Dest20 {
+    pub fn decode(field: &'static str, hex: &str) -> Result<Self, Error> {
+        decode_fixed(field, hex).map(Self)
+    }
+
+    pub fn from_slice(field: &'static str, bytes: &[u8]) -> Result<Self, Error> {
+        from_slice(field, bytes).map(Self)
+    }
+}
+
+impl Hash32 {
+    pub fn decode(field: &'static str, hex: &str) -> Result<Self, Error> {
+        decode_fixed(field, hex).map(Self)
+    }
+
+    pub fn from_slice(field: &'static str, bytes: &[u8]) -> Result<Self, Error> {
+        from_slice(field, bytes).map(Self)
+    }
+}
+
+impl fmt::Display for OutPointBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl fmt::Display for Dest20 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl fmt::Display for Hash32 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+/// The hex outpoint decoded, as the drivechain crate takes it.
+pub fn decode_outpoint(field: &'static str, outpoint: &str) -> Result<Vec<u8>, Error> {
+    Ok(OutPointBytes::decode(field, outpoint)?.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPOINT: &str =
+        "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b00000000";
+
+    #[test]
+    fn outpoint_round_trips() {
+        let outpoint = OutPointBytes::decode("outpoint", OUTPOINT).unwrap();
+        assert_eq!(outpoint.to_string(), OUTPOINT);
+        assert_eq!(
+            decode_outpoint("outpoint", OUTPOINT).unwrap(),
+            outpoint.to_vec()
+        );
+    }
+
+    #[test]
+    fn short_and_long_hex_is_rejected() {
+        for (hex, actual) in [(&OUTPOINT[..70], 35), (&format!("{OUTPOINT}00")[..], 37)] {
+            assert!(matches!(
+                OutPointBytes::decode("outpoint", hex),
+                Err(Error::InvalidLength {
+                    field: "outpoint",
+                    expected: 36,
+                    actual: found,
+                }) if found == actual
+            ));
+        }
+        assert!(matches!(
+            Hash32::decode("hash", ""),
+            Err(Error::InvalidLength {
+                expected: 32,
+                actual: 0,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn invalid_hex_is_rejected() {
+        for hex in ["zz", "abc"] {
+            assert!(matches!(
+                Dest20::decode("dest", hex),
+                Err(Error::InvalidEncoding { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn slices_are_length_checked() {
+        assert_eq!(
+            Dest20::from_slice("dest", &[1; 20]).unwrap(),
+            Dest20([1; 20])
+        );
+        assert!(matches!(
+            Dest20::from_slice("dest", &[1; 19]),
+            Err(Error::InvalidLength {
+                field: "dest",
+                expected: 20,
+                actual: 19
+            })
+        ));
+        assert!(Hash32::from_slice("hash", &[0; 33]).is_err());
+        assert!(OutPointBytes::from_slice("outpoint", &[]).is_err());
+    }
+}
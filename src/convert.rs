@@ -0,0 +1,97 @@
+use crate::bridge::ffi;
+use crate::codec::decode_outpoint;
+use crate::dest;
+use crate::error::Error;
+use drivechain as drive;
+
+// Conversions between the shared structs and the drivechain crate's types.
+// Withdrawals and refunds are keyed by outpoint in the drivechain crate, so
+// they convert to and from (outpoint, value) pairs. Going to the drivechain
+// crate can fail on a malformed outpoint or main_address.
+
+impl From<drive::Deposit> for ffi::Output {
+    fn from(deposit: drive::Deposit) -> Self {
+        Self {
+            address: deposit.address,
+            amount: deposit.amount,
+        }
+    }
+}
+
+impl From<&drive::Deposit> for ffi::Output {
+    fn from(deposit: &drive::Deposit) -> Self {
+        Self {
+            address: deposit.address.clone(),
+            amount: deposit.amount,
+        }
+    }
+}
+
+impl From<ffi::Output> for drive::Deposit {
+    fn from(output: ffi::Output) -> Self {
+        Self {
+            address: output.address,
+            amount: output.amount,
+        }
+    }
+}
+
+impl From<&ffi::Output> for drive::Deposit {
+    fn from(output: &ffi::Output) -> Self {
+        Self {
+            address: output.address.clone(),
+            amount: output.amount,
+        }
+    }
+}
+
+/// main_address is the hex P2PKH hash, the drivechain crate keeps no
+/// address. height is dropped.
+impl From<(&[u8], &drive::Withdrawal)> for ffi::Withdrawal {
+    fn from((outpoint, withdrawal): (&[u8], &drive::Withdrawal)) -> Self {
+        Self {
+            outpoint: hex::encode(outpoint),
+            main_address: hex::encode(withdrawal.dest),
+            main_fee: withdrawal.mainchain_fee,
+            amount: withdrawal.amount,
+        }
+    }
+}
+
+/// height is left 0, Db::connect_withdrawals in the drivechain crate sets
+/// it.
+impl TryFrom<&ffi::Withdrawal> for (Vec<u8>, drive::Withdrawal) {
+    type Error = Error;
+
+    fn try_from(withdrawal: &ffi::Withdrawal) -> Result<Self, Error> {
+        Ok((
+            decode_outpoint("withdrawal outpoint", &withdrawal.outpoint)?,
+            drive::Withdrawal {
+                amount: withdrawal.amount,
+                dest: dest::withdrawal_dest(&withdrawal.main_address)?.0,
+                mainchain_fee: withdrawal.main_fee,
+                height: 0,
+            },
+        ))
+    }
+}
+
+impl From<(&[u8], u64)> for ffi::Refund {
+    fn from((outpoint, amount): (&[u8], u64)) -> Self {
+        Self {
+            outpoint: hex::encode(outpoint),
+            amount,
+        }
+    }
+}
+
+impl TryFrom<&ffi::Refund> for (Vec<u8>, u64) {
+    type Error = Error;
+
+    fn try_from(refund: &ffi::Refund) -> Result<Self, Error> {
+        Ok((
+            decode_outpoint("refund outpoint", &refund.outpoint)?,
+            refund.amount,
+        ))
+    }
+}
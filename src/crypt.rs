@@ -0,0 +1,164 @@
+use crate::error::Error;
+use argon2::Argon2;
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha256, Hash as _, HashEngine as _};
+use chacha20poly1305::aead::rand_core::RngCore as _;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+// Encrypted into EncryptionRecord::check to tell a wrong passphrase apart
+// from a corrupted record.
+const CHECK_PLAINTEXT: &[u8] = b"drivechain-cpp";
+// HMACed with the derived key into the key of blind, so that it never
+// serves both purposes.
+const BLIND_LABEL: &[u8] = b"drivechain-cpp blind";
+
+/// Encrypts DB values with a key derived from a passphrase with Argon2id.
+/// Every value gets a random 24 byte nonce, stored in front of it, and is
+/// authenticated together with associated data naming where it is stored,
+/// so that it doesn't decrypt anywhere else.
+pub struct Cipher {
+    aead: XChaCha20Poly1305,
+    blind_key: [u8; 32],
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut engine = HmacEngine::<sha256::Hash>::new(key);
+    engine.input(data);
+    Hmac::<sha256::Hash>::from_engine(engine).into_inner()
+}
+
+pub fn new_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+impl Cipher {
+    pub fn new(passphrase: &str, salt: &[u8]) -> Result<Self, Error> {
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|err| Error::InvalidEncoding {
+                message: format!("failed to derive the database key: {err}"),
+            })?;
+        Ok(Self {
+            aead: XChaCha20Poly1305::new(&key.into()),
+            blind_key: hmac(&key, BLIND_LABEL),
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        let ciphertext =
+            self.aead
+                .encrypt(&nonce, payload)
+                .map_err(|_| Error::InvalidEncoding {
+                    message: "failed to encrypt database record".into(),
+                })?;
+        let mut value = nonce.to_vec();
+        value.extend_from_slice(&ciphertext);
+        Ok(value)
+    }
+
+    /// None if value wasn't encrypted with this key and aad.
+    pub fn decrypt(&self, value: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+        if value.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = value.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        self.aead.decrypt(XNonce::from_slice(nonce), payload).ok()
+    }
+
+    /// Hex HMAC-SHA256 of data, the same for the same data and key. Keys
+    /// holding addresses are blinded with it, so they can still be looked
+    /// up without the address being readable.
+    pub fn blind(&self, data: &[u8]) -> Vec<u8> {
+        hex::encode(hmac(&self.blind_key, data)).into_bytes()
+    }
+
+    /// Encrypted without associated data, which no DB value is, the way
+    /// versions before associated data wrote it.
+    pub fn check(&self) -> Result<Vec<u8>, Error> {
+        self.encrypt(CHECK_PLAINTEXT, &[])
+    }
+
+    pub fn verify(&self, check: &[u8]) -> bool {
+        self.decrypt(check, &[]).as_deref() == Some(CHECK_PLAINTEXT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(passphrase: &str) -> Cipher {
+        Cipher::new(passphrase, &[7; SALT_LEN]).unwrap()
+    }
+
+    #[test]
+    fn value_decrypts_with_its_associated_data() {
+        let cipher = cipher("passphrase");
+        let value = cipher.encrypt(b"record", b"withdrawals\0key").unwrap();
+        assert_eq!(
+            cipher.decrypt(&value, b"withdrawals\0key").as_deref(),
+            Some(&b"record"[..])
+        );
+    }
+
+    #[test]
+    fn value_doesnt_decrypt_with_other_associated_data() {
+        let cipher = cipher("passphrase");
+        let value = cipher.encrypt(b"record", b"withdrawals\0key").unwrap();
+        assert!(cipher.decrypt(&value, b"withdrawals\0other").is_none());
+        assert!(cipher.decrypt(&value, b"withdrawal_payees\0key").is_none());
+        assert!(cipher.decrypt(&value, &[]).is_none());
+    }
+
+    #[test]
+    fn value_doesnt_decrypt_with_another_passphrase() {
+        let value = cipher("passphrase").encrypt(b"record", b"aad").unwrap();
+        assert!(cipher("other").decrypt(&value, b"aad").is_none());
+    }
+
+    #[test]
+    fn truncated_value_doesnt_decrypt() {
+        let cipher = cipher("passphrase");
+        let value = cipher.encrypt(b"record", b"aad").unwrap();
+        assert!(cipher.decrypt(&value[..NONCE_LEN - 1], b"aad").is_none());
+        assert!(cipher.decrypt(&value[..value.len() - 1], b"aad").is_none());
+    }
+
+    #[test]
+    fn check_only_verifies_with_its_passphrase() {
+        let check = cipher("passphrase").check().unwrap();
+        assert!(cipher("passphrase").verify(&check));
+        assert!(!cipher("other").verify(&check));
+    }
+
+    #[test]
+    fn blind_is_the_same_only_for_the_same_key_and_data() {
+        assert_eq!(
+            cipher("passphrase").blind(b"address"),
+            cipher("passphrase").blind(b"address")
+        );
+        assert_ne!(
+            cipher("passphrase").blind(b"address"),
+            cipher("other").blind(b"address")
+        );
+        assert_ne!(
+            cipher("passphrase").blind(b"address"),
+            cipher("passphrase").blind(b"other")
+        );
+    }
+}
@@ -0,0 +1,2042 @@
+use crate::bridge::unix_time;
+use crate::crypt::{self, Cipher};
+use crate::error::Error;
+use crate::storage::{Storage, Tree};
+use miette::{IntoDiagnostic as _, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+
+/// Key of the BundleRecord in the default tree.
+const BUNDLE_KEY: &[u8] = b"bundle";
+/// Key of the EncryptionRecord in the default tree, stored unencrypted.
+const ENCRYPTION_KEY: &[u8] = b"encryption";
+/// Key of the UnsignedBundleRecord in the default tree.
+const UNSIGNED_BUNDLE_KEY: &[u8] = b"unsigned_bundle";
+/// Key of the SidechainProposalRecord in the default tree.
+const SIDECHAIN_PROPOSAL_KEY: &[u8] = b"sidechain_proposal";
+/// Set in the default tree once every encrypted value is bound to the tree
+/// and key it is stored under. Versions before that encrypted values
+/// without associated data, and marked the DB migrated all the same.
+const BOUND_VALUES_KEY: &[u8] = b"bound_values";
+/// Set in the default tree once the explorer index covers everything
+/// connected before it was compiled in.
+#[cfg(feature = "explorer-index")]
+const EXPLORER_INDEX_KEY: &[u8] = b"explorer_index";
+
+// Bookkeeping the bridge keeps on top of the drivechain crate's own DB. It
+// lives in a separate store next to the drivechain DB because
+// drive::Drivechain doesn't expose its handle.
+#[derive(Clone)]
+pub struct Db {
+    storage: Arc<dyn Storage>,
+    // sled's default tree, holding singleton records.
+    db: Tree,
+    withdrawals: Tree,
+    main_deposits: Tree,
+    broadcast_queue: Tree,
+    bmm_history: Tree,
+    // strdest 0x00 txid, for finding the mainchain deposit behind a
+    // connected sidechain deposit.
+    main_deposit_addresses: Tree,
+    // Same keys as main_deposit_addresses, for deposits already reported as
+    // a deposit event.
+    claimed_main_deposits: Tree,
+    watched_addresses: Tree,
+    deposit_events: Tree,
+    alert_events: Tree,
+    // Keyed by withdrawal outpoint like withdrawals. Separate trees so that
+    // records written by older versions still decode.
+    withdrawal_payees: Tree,
+    withdrawal_payouts: Tree,
+    // txid 0x00 vout of every output already matched to a withdrawal.
+    payout_outputs: Tree,
+    // Big endian height, so the last entry is the last connected block.
+    sidechain_blocks: Tree,
+    // main_block_hash 0x00 critical_hash of every BMM commitment verify_bmm
+    // found.
+    verified_bmm: Tree,
+    // Append only, nothing removes entries.
+    audit_log: Tree,
+    // Sidechain address 0x00 big endian id of every deposit connected to
+    // it, so per address entries are in connect order.
+    deposit_history: Tree,
+    // Bundle hash 0x00 big endian main height of every work score sample.
+    bundle_votes: Tree,
+    // Every bundle seen being voted on for this sidechain, by hash.
+    proposed_bundles: Tree,
+    // Sidechain address 0x00 big endian id of every deposit pending in the
+    // drivechain DB, for deposit_maturity and ack_deposits.
+    pending_deposits: Tree,
+    // Big endian ids of the pending deposits acknowledged with ack_deposits.
+    acked_deposits: Tree,
+    // Labeled mainchain addresses to withdraw to, by label.
+    withdrawal_destinations: Tree,
+    // What connect_sidechain_block connected, by sidechain block hash.
+    block_undo: Tree,
+    // Withdrawals below DustPolicy::min_amount, by outpoint.
+    dust_withdrawals: Tree,
+    // Outpoints of the withdrawals found paid by a bundle. The drivechain
+    // crate never learns that bundles built by the bridge paid out, so it
+    // keeps reporting their withdrawals unspent.
+    paid_withdrawals: Tree,
+    // Once the DB is encrypted, the address in a main_deposit_addresses,
+    // claimed_main_deposits, watched_addresses, deposit_history,
+    // pending_deposits or main_txid_deposits key is replaced by its
+    // Cipher::blind tag. This maps the tags to the addresses, encrypted,
+    // for the lists that read the address back out of a key.
+    blinded_addresses: Tree,
+    // Mainchain address of every withdrawal to anything but P2PKH, by
+    // outpoint. WithdrawalPayeeRecord only holds the hash the drivechain
+    // crate is given.
+    withdrawal_addresses: Tree,
+    // Outpoints of the withdrawals connected without a mainchain height,
+    // whose WithdrawalRecord::main_height is still 0.
+    unresolved_withdrawals: Tree,
+    // Mainchain txid 0x00 sidechain address 0x00 big endian id of every
+    // deposit_history entry whose mainchain deposit is known.
+    #[cfg(feature = "explorer-index")]
+    main_txid_deposits: Tree,
+    // Bundle txid 0x00 outpoint of every withdrawal_payouts entry.
+    #[cfg(feature = "explorer-index")]
+    bundle_withdrawals: Tree,
+    // Set once the DB is encrypted. Only record values are encrypted, keys
+    // stay readable for prefix scans and ordering.
+    cipher: Arc<RwLock<Option<Cipher>>>,
+    // Records written since the last flush.
+    unflushed: Arc<AtomicU64>,
+    // Unix time of the last flush, 0 before the first.
+    flushed_at: Arc<AtomicU64>,
+}
+
+/// How the DB is encrypted, written before any value gets encrypted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionRecord {
+    pub salt: Vec<u8>,
+    /// A known plaintext encrypted with the key, to detect wrong passphrases.
+    pub check: Vec<u8>,
+    /// False until every existing value has been encrypted, so that an
+    /// interrupted encrypt carries on at the next open.
+    pub migrated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithdrawalRecord {
+    /// Mainchain block count at the time the withdrawal was connected.
+    pub main_height: u64,
+}
+
+/// Who a connected withdrawal pays on the mainchain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithdrawalPayeeRecord {
+    pub dest: [u8; 20],
+    pub amount: u64,
+    pub main_fee: u64,
+}
+
+/// Where a withdrawal to anything but P2PKH pays on the mainchain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithdrawalAddressRecord {
+    pub main_address: String,
+}
+
+/// The output of a confirmed bundle that paid a withdrawal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayoutRecord {
+    pub txid: String,
+    pub vout: u32,
+    pub main_block_hash: String,
+}
+
+/// A deposit transaction as found on the mainchain, keyed by its txid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MainDepositRecord {
+    pub block_hash: String,
+    pub burn_index: u32,
+    pub ntx: u32,
+    pub address: String,
+    pub tx_hex: String,
+}
+
+/// A bundle broadcast that failed and is waiting to be retried.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BroadcastRecord {
+    pub created_at: u64,
+    pub attempts: u32,
+    pub next_attempt_at: u64,
+    pub last_error: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BmmOutcome {
+    Pending,
+    Succeded,
+    Failed,
+    Expired,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BundleStage {
+    #[default]
+    Idle,
+    Created,
+    Broadcast,
+    Voting,
+    Failed,
+    PaidOut,
+    AwaitingSignatures,
+}
+
+/// The bundle attempt_bundle_broadcast last worked on.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BundleRecord {
+    pub stage: BundleStage,
+    /// Outpoints of the withdrawals unspent when the bundle was created.
+    pub withdrawals: Vec<Vec<u8>>,
+    /// Mainchain block count when the bundle was created.
+    pub main_height: u64,
+    pub updated_at: u64,
+}
+
+/// A bundle built for external signing, waiting for submit_signed_bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsignedBundleRecord {
+    pub tx_hex: String,
+    pub created_at: u64,
+}
+
+/// The activation proposal submitted by bootstrap_sidechain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SidechainProposalRecord {
+    pub title: String,
+    pub proposed_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifiedBmmRecord {
+    pub verified_at: u64,
+}
+
+/// A sidechain block connected through connect_sidechain_block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SidechainBlockRecord {
+    pub hash: String,
+    /// Hex encoded outpoints of the withdrawals the block connected.
+    pub withdrawals: Vec<String>,
+    pub connected_at: u64,
+}
+
+/// The inputs a sidechain block was connected with, for disconnecting it
+/// without the node supplying them again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockUndoRecord {
+    pub height: u64,
+    /// Address and amount of every deposit.
+    pub deposits: Vec<(String, u64)>,
+    /// Hex encoded withdrawal and refund outpoints.
+    pub withdrawals: Vec<String>,
+    pub refunds: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DustStage {
+    /// Refused by connect_block.
+    Connect,
+    /// Kept out of bundles.
+    Bundle,
+}
+
+/// A withdrawal turned away for being below the minimum amount, when it
+/// was first turned away.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DustWithdrawalRecord {
+    pub main_address: String,
+    pub amount: u64,
+    pub main_fee: u64,
+    pub stage: DustStage,
+    pub rejected_at: u64,
+}
+
+/// A bundle proposed for this sidechain, by this node or any other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposedBundleRecord {
+    pub first_seen_height: u64,
+    pub first_seen_at: u64,
+    pub last_seen_height: u64,
+    /// As of last_seen_height.
+    pub work_score: u64,
+    pub blocks_remaining: u64,
+    /// Taken to be the bundle this node broadcast.
+    pub ours: bool,
+    /// Still being voted on when last checked.
+    pub active: bool,
+}
+
+/// A deposit not connected yet, with the mainchain block count at the time
+/// it was first seen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingDepositRecord {
+    pub amount: u64,
+    pub first_seen_height: u64,
+}
+
+/// A mainchain address saved to withdraw to, keyed by its label.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithdrawalDestinationRecord {
+    pub address: String,
+    pub added_at: u64,
+}
+
+/// A bundle's work score at a mainchain block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleVoteRecord {
+    pub main_block_hash: String,
+    pub work_score: u64,
+    pub blocks_remaining: u64,
+}
+
+/// A deposit connected to a sidechain address.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepositHistoryRecord {
+    pub amount: u64,
+    /// Empty if the mainchain deposit hasn't been scanned.
+    pub mainchain_txid: String,
+    /// 0 if connect_block was given no mainchain height.
+    pub main_height: u64,
+    pub connected_at: u64,
+}
+
+/// A deposit to a watched address that was connected or disconnected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepositEventRecord {
+    pub created_at: u64,
+    pub address: String,
+    pub amount: u64,
+    /// Empty if the mainchain deposit hasn't been scanned.
+    pub mainchain_txid: String,
+    pub connected: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlertKind {
+    BundleFailed,
+    BmmFailed,
+    MainchainUnreachable,
+    ForeignBundle,
+}
+
+/// An alert trigger that went off, queued as an event when it does.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRecord {
+    pub kind: AlertKind,
+    pub message: String,
+    /// When the condition started.
+    pub since: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditOperation {
+    ConnectBlock,
+    DisconnectBlock,
+    BundleBroadcast,
+    Flush,
+    SlotMigration,
+}
+
+/// A state change, kept in the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub at: u64,
+    pub operation: AuditOperation,
+    /// Mainchain tip at the time, empty if it wasn't asked for or the node
+    /// didn't answer.
+    pub main_block_hash: String,
+    pub detail: String,
+}
+
+/// A BMM request made through attempt_bmm and what became of it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BmmAttemptRecord {
+    pub created_at: u64,
+    pub critical_hash: String,
+    pub prev_main_block_hash: String,
+    pub amount: u64,
+    pub txid: String,
+    pub outcome: BmmOutcome,
+    /// Mainchain tip at the time the attempt was resolved, empty while
+    /// pending.
+    pub main_block_hash: String,
+}
+
+/// A record verify_records or verify_references found fault with.
+#[derive(Debug)]
+pub struct RecordProblem {
+    pub tree: &'static str,
+    pub key: Vec<u8>,
+    pub message: String,
+}
+
+impl RecordProblem {
+    pub fn new(tree: &'static str, key: &[u8], message: impl Into<String>) -> Self {
+        Self {
+            tree,
+            key: key.to_vec(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Db {
+    /// Open the DB, decrypting it with passphrase. An unencrypted DB opened
+    /// with a passphrase is encrypted first.
+    pub fn open(storage: Arc<dyn Storage>, passphrase: Option<&str>) -> Result<Self> {
+        let withdrawals = storage.open_tree("withdrawals")?;
+        let main_deposits = storage.open_tree("main_deposits")?;
+        let broadcast_queue = storage.open_tree("broadcast_queue")?;
+        let bmm_history = storage.open_tree("bmm_history")?;
+        let main_deposit_addresses = storage.open_tree("main_deposit_addresses")?;
+        let claimed_main_deposits = storage.open_tree("claimed_main_deposits")?;
+        let watched_addresses = storage.open_tree("watched_addresses")?;
+        let deposit_events = storage.open_tree("deposit_events")?;
+        let alert_events = storage.open_tree("alert_events")?;
+        let withdrawal_payees = storage.open_tree("withdrawal_payees")?;
+        let withdrawal_payouts = storage.open_tree("withdrawal_payouts")?;
+        let payout_outputs = storage.open_tree("payout_outputs")?;
+        let sidechain_blocks = storage.open_tree("sidechain_blocks")?;
+        let verified_bmm = storage.open_tree("verified_bmm")?;
+        let audit_log = storage.open_tree("audit_log")?;
+        let deposit_history = storage.open_tree("deposit_history")?;
+        let bundle_votes = storage.open_tree("bundle_votes")?;
+        let proposed_bundles = storage.open_tree("proposed_bundles")?;
+        let pending_deposits = storage.open_tree("pending_deposits")?;
+        let acked_deposits = storage.open_tree("acked_deposits")?;
+        let withdrawal_destinations = storage.open_tree("withdrawal_destinations")?;
+        let block_undo = storage.open_tree("block_undo")?;
+        let dust_withdrawals = storage.open_tree("dust_withdrawals")?;
+        let paid_withdrawals = storage.open_tree("paid_withdrawals")?;
+        let blinded_addresses = storage.open_tree("blinded_addresses")?;
+        let withdrawal_addresses = storage.open_tree("withdrawal_addresses")?;
+        let unresolved_withdrawals = storage.open_tree("unresolved_withdrawals")?;
+        #[cfg(feature = "explorer-index")]
+        let main_txid_deposits = storage.open_tree("main_txid_deposits")?;
+        #[cfg(feature = "explorer-index")]
+        let bundle_withdrawals = storage.open_tree("bundle_withdrawals")?;
+        let db = Self {
+            db: storage.default_tree()?,
+            storage,
+            withdrawals,
+            main_deposits,
+            broadcast_queue,
+            bmm_history,
+            main_deposit_addresses,
+            claimed_main_deposits,
+            watched_addresses,
+            deposit_events,
+            alert_events,
+            withdrawal_payees,
+            withdrawal_payouts,
+            payout_outputs,
+            sidechain_blocks,
+            verified_bmm,
+            audit_log,
+            deposit_history,
+            bundle_votes,
+            proposed_bundles,
+            pending_deposits,
+            acked_deposits,
+            withdrawal_destinations,
+            block_undo,
+            dust_withdrawals,
+            paid_withdrawals,
+            blinded_addresses,
+            withdrawal_addresses,
+            unresolved_withdrawals,
+            #[cfg(feature = "explorer-index")]
+            main_txid_deposits,
+            #[cfg(feature = "explorer-index")]
+            bundle_withdrawals,
+            cipher: Arc::new(RwLock::new(None)),
+            unflushed: Arc::new(AtomicU64::new(0)),
+            flushed_at: Arc::new(AtomicU64::new(0)),
+        };
+        match (db.get_encryption()?, passphrase) {
+            (None, None) => {}
+            (None, Some(passphrase)) => db.encrypt(passphrase)?,
+            (Some(_), None) => return Err(Error::DbEncrypted.into()),
+            (Some(mut record), Some(passphrase)) => {
+                let cipher = Cipher::new(passphrase, &record.salt)?;
+                if !cipher.verify(&record.check) {
+                    return Err(Error::WrongPassphrase.into());
+                }
+                if !record.migrated || !db.db.contains_key(BOUND_VALUES_KEY)? {
+                    db.migrate(&cipher)?;
+                    record.migrated = true;
+                    db.put_encryption(&record)?;
+                }
+                *db.cipher.write().unwrap_or_else(PoisonError::into_inner) = Some(cipher);
+            }
+        }
+        #[cfg(feature = "explorer-index")]
+        if !db.db.contains_key(EXPLORER_INDEX_KEY)? {
+            db.rebuild_explorer_index()?;
+        }
+        Ok(db)
+    }
+
+    /// Encrypt every record in place with a key derived from passphrase,
+    /// and blind the addresses in keys. Other keys, outpoints, txids and
+    /// hashes, stay readable, and the drivechain crate's DB isn't touched.
+    pub fn encrypt(&self, passphrase: &str) -> Result<()> {
+        if passphrase.is_empty() {
+            return Err(Error::EmptyPassphrase.into());
+        }
+        // Holding the write lock keeps other threads from reading or writing
+        // records while they are rewritten.
+        let mut guard = self.cipher.write().unwrap_or_else(PoisonError::into_inner);
+        if guard.is_some() || self.get_encryption()?.is_some() {
+            return Err(Error::DbAlreadyEncrypted.into());
+        }
+        let salt = crypt::new_salt();
+        let cipher = Cipher::new(passphrase, &salt)?;
+        let mut record = EncryptionRecord {
+            salt: salt.to_vec(),
+            check: cipher.check()?,
+            migrated: false,
+        };
+        self.put_encryption(&record)?;
+        self.migrate(&cipher)?;
+        record.migrated = true;
+        self.put_encryption(&record)?;
+        self.storage.flush()?;
+        *guard = Some(cipher);
+        Ok(())
+    }
+
+    // Values that already decrypt were encrypted by an interrupted migrate,
+    // a plaintext authenticating by chance is not a concern. Values that
+    // only decrypt without associated data were encrypted by an older
+    // version, and are encrypted again bound to their tree and key.
+    fn migrate(&self, cipher: &Cipher) -> Result<()> {
+        let trees = [
+            &self.withdrawals,
+            &self.main_deposits,
+            &self.broadcast_queue,
+            &self.bmm_history,
+            &self.deposit_events,
+            &self.alert_events,
+            &self.withdrawal_payees,
+            &self.withdrawal_payouts,
+            &self.sidechain_blocks,
+            &self.verified_bmm,
+            &self.audit_log,
+            &self.deposit_history,
+            &self.bundle_votes,
+            &self.proposed_bundles,
+            &self.pending_deposits,
+            &self.withdrawal_destinations,
+            &self.block_undo,
+            &self.dust_withdrawals,
+            &self.withdrawal_addresses,
+            &self.blinded_addresses,
+        ];
+        for tree in trees {
+            for entry in tree.iter() {
+                let (key, value) = entry?;
+                migrate_value(tree, &key, &value, cipher)?;
+            }
+        }
+        for key in [BUNDLE_KEY, UNSIGNED_BUNDLE_KEY, SIDECHAIN_PROPOSAL_KEY] {
+            if let Some(value) = self.db.get(key)? {
+                migrate_value(&self.db, key, &value, cipher)?;
+            }
+        }
+        self.blind_keys(cipher)?;
+        self.db.insert(BOUND_VALUES_KEY, b"")
+    }
+
+    // Every address found in a key gets a blinded_addresses entry here, so
+    // keys whose address already has one were blinded by an interrupted
+    // migrate.
+    fn blind_keys(&self, cipher: &Cipher) -> Result<()> {
+        let blinded = &self.blinded_addresses;
+        let first_zero = |key: &[u8]| key.iter().position(|byte| *byte == 0);
+        for tree in [&self.main_deposit_addresses, &self.claimed_main_deposits] {
+            blind_tree_keys(tree, blinded, cipher, |key| Some((0, first_zero(key)?)))?;
+        }
+        blind_tree_keys(&self.watched_addresses, blinded, cipher, |key| {
+            Some((0, key.len()))
+        })?;
+        for tree in [&self.deposit_history, &self.pending_deposits] {
+            blind_tree_keys(tree, blinded, cipher, |key| {
+                Some((0, key.len().checked_sub(9)?))
+            })?;
+        }
+        #[cfg(feature = "explorer-index")]
+        blind_tree_keys(&self.main_txid_deposits, blinded, cipher, |key| {
+            let start = first_zero(key)? + 1;
+            let end = key.len().checked_sub(9)?;
+            (start <= end).then_some((start, end))
+        })?;
+        Ok(())
+    }
+
+    // address as it appears in keys, its blinded tag once the DB is
+    // encrypted.
+    fn address_key(&self, address: &str) -> Vec<u8> {
+        match self.cipher().as_ref() {
+            Some(cipher) => cipher.blind(address.as_bytes()),
+            None => address.as_bytes().to_vec(),
+        }
+    }
+
+    fn address_key_prefix(&self, address: &str) -> Vec<u8> {
+        let mut prefix = self.address_key(address);
+        prefix.push(0);
+        prefix
+    }
+
+    // For keys whose address is read back with key_address.
+    fn remember_address(&self, address: &str) -> Result<()> {
+        if let Some(cipher) = self.cipher().as_ref() {
+            remember_blinded(&self.blinded_addresses, cipher, address.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    // The address a key part written with address_key stands for.
+    fn key_address(&self, key: &[u8]) -> Result<String> {
+        let cipher = self.cipher();
+        let Some(cipher) = cipher.as_ref() else {
+            return Ok(String::from_utf8_lossy(key).into_owned());
+        };
+        let value = self
+            .blinded_addresses
+            .get(key)?
+            .ok_or_else(|| Error::InvalidEncoding {
+                message: format!("no address for key {}", String::from_utf8_lossy(key)),
+            })?;
+        let aad = value_aad(&self.blinded_addresses, key);
+        let address = cipher.decrypt(&value, &aad).ok_or(Error::InvalidEncoding {
+            message: "failed to decrypt database record".into(),
+        })?;
+        Ok(String::from_utf8_lossy(&address).into_owned())
+    }
+
+    fn get_encryption(&self) -> Result<Option<EncryptionRecord>> {
+        match self.db.get(ENCRYPTION_KEY)? {
+            Some(value) => Ok(Some(bincode::deserialize(&value).into_diagnostic()?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put_encryption(&self, record: &EncryptionRecord) -> Result<()> {
+        let value = bincode::serialize(record).into_diagnostic()?;
+        self.db.insert(ENCRYPTION_KEY, value)?;
+        Ok(())
+    }
+
+    fn cipher(&self) -> RwLockReadGuard<Option<Cipher>> {
+        self.cipher.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // record as stored under key in tree.
+    fn encode<T: Serialize>(
+        &self,
+        tree: &Tree,
+        key: impl AsRef<[u8]>,
+        record: &T,
+    ) -> Result<Vec<u8>> {
+        let value = bincode::serialize(record).into_diagnostic()?;
+        self.unflushed.fetch_add(1, Ordering::Relaxed);
+        match self.cipher().as_ref() {
+            Some(cipher) => Ok(cipher.encrypt(&value, &value_aad(tree, key.as_ref()))?),
+            None => Ok(value),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(
+        &self,
+        tree: &Tree,
+        key: impl AsRef<[u8]>,
+        value: &[u8],
+    ) -> Result<T> {
+        let value = match self.cipher().as_ref() {
+            Some(cipher) => cipher
+                .decrypt(value, &value_aad(tree, key.as_ref()))
+                .ok_or(Error::InvalidEncoding {
+                    message: "failed to decrypt database record".into(),
+                })?,
+            None => value.to_vec(),
+        };
+        bincode::deserialize(&value).into_diagnostic()
+    }
+
+    pub fn put_withdrawal(&self, outpoint: &[u8], record: &WithdrawalRecord) -> Result<()> {
+        let value = self.encode(&self.withdrawals, outpoint, record)?;
+        self.withdrawals.insert(outpoint, value)?;
+        Ok(())
+    }
+
+    pub fn get_withdrawal(&self, outpoint: &[u8]) -> Result<Option<WithdrawalRecord>> {
+        match self.withdrawals.get(outpoint)? {
+            Some(value) => self.decode(&self.withdrawals, outpoint, &value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn list_withdrawals(&self) -> Result<Vec<(Vec<u8>, WithdrawalRecord)>> {
+        self.withdrawals
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                let record = self.decode(&self.withdrawals, &key, &value)?;
+                Ok((key, record))
+            })
+            .collect()
+    }
+
+    pub fn remove_withdrawal(&self, outpoint: &[u8]) -> Result<()> {
+        self.withdrawals.remove(outpoint)?;
+        self.withdrawal_payees.remove(outpoint)?;
+        self.paid_withdrawals.remove(outpoint)?;
+        self.withdrawal_addresses.remove(outpoint)?;
+        self.unresolved_withdrawals.remove(outpoint)?;
+        if let Some(payout) = self.get_payout(outpoint)? {
+            self.payout_outputs
+                .remove(output_key(&payout.txid, payout.vout))?;
+            #[cfg(feature = "explorer-index")]
+            self.bundle_withdrawals
+                .remove(bundle_withdrawal_key(&payout.txid, outpoint))?;
+            self.withdrawal_payouts.remove(outpoint)?;
+        }
+        Ok(())
+    }
+
+    pub fn put_payee(&self, outpoint: &[u8], record: &WithdrawalPayeeRecord) -> Result<()> {
+        let value = self.encode(&self.withdrawal_payees, outpoint, record)?;
+        self.withdrawal_payees.insert(outpoint, value)?;
+        Ok(())
+    }
+
+    pub fn get_payee(&self, outpoint: &[u8]) -> Result<Option<WithdrawalPayeeRecord>> {
+        match self.withdrawal_payees.get(outpoint)? {
+            Some(value) => self
+                .decode(&self.withdrawal_payees, outpoint, &value)
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn list_payees(&self) -> Result<Vec<(Vec<u8>, WithdrawalPayeeRecord)>> {
+        self.withdrawal_payees
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                let record = self.decode(&self.withdrawal_payees, &key, &value)?;
+                Ok((key, record))
+            })
+            .collect()
+    }
+
+    /// Payees of withdrawals no payout has been found for yet.
+    pub fn list_unpaid_payees(&self) -> Result<Vec<(Vec<u8>, WithdrawalPayeeRecord)>> {
+        let mut payees = Vec::new();
+        for entry in self.withdrawal_payees.iter() {
+            let (key, value) = entry?;
+            if !self.withdrawal_payouts.contains_key(&key)? {
+                let record = self.decode(&self.withdrawal_payees, &key, &value)?;
+                payees.push((key, record));
+            }
+        }
+        Ok(payees)
+    }
+
+    pub fn put_payout(&self, outpoint: &[u8], record: &PayoutRecord) -> Result<()> {
+        let value = self.encode(&self.withdrawal_payouts, outpoint, record)?;
+        self.withdrawal_payouts.insert(outpoint, value)?;
+        self.payout_outputs
+            .insert(output_key(&record.txid, record.vout), outpoint)?;
+        #[cfg(feature = "explorer-index")]
+        self.bundle_withdrawals
+            .insert(bundle_withdrawal_key(&record.txid, outpoint), b"")?;
+        Ok(())
+    }
+
+    pub fn get_payout(&self, outpoint: &[u8]) -> Result<Option<PayoutRecord>> {
+        match self.withdrawal_payouts.get(outpoint)? {
+            Some(value) => self
+                .decode(&self.withdrawal_payouts, outpoint, &value)
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn is_payout_output(&self, txid: &str, vout: u32) -> Result<bool> {
+        self.payout_outputs.contains_key(output_key(txid, vout))
+    }
+
+    pub fn put_paid_withdrawal(&self, outpoint: &[u8]) -> Result<()> {
+        self.paid_withdrawals.insert(outpoint, b"")?;
+        Ok(())
+    }
+
+    pub fn is_withdrawal_paid(&self, outpoint: &[u8]) -> Result<bool> {
+        self.paid_withdrawals.contains_key(outpoint)
+    }
+
+    pub fn put_withdrawal_address(
+        &self,
+        outpoint: &[u8],
+        record: &WithdrawalAddressRecord,
+    ) -> Result<()> {
+        let value = self.encode(&self.withdrawal_addresses, outpoint, record)?;
+        self.withdrawal_addresses.insert(outpoint, value)?;
+        Ok(())
+    }
+
+    pub fn get_withdrawal_address(
+        &self,
+        outpoint: &[u8],
+    ) -> Result<Option<WithdrawalAddressRecord>> {
+        match self.withdrawal_addresses.get(outpoint)? {
+            Some(value) => self
+                .decode(&self.withdrawal_addresses, outpoint, &value)
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn put_unresolved_withdrawal(&self, outpoint: &[u8]) -> Result<()> {
+        self.unresolved_withdrawals.insert(outpoint, b"")?;
+        Ok(())
+    }
+
+    pub fn list_unresolved_withdrawals(&self) -> Result<Vec<Vec<u8>>> {
+        self.unresolved_withdrawals
+            .iter()
+            .map(|entry| Ok(entry?.0))
+            .collect()
+    }
+
+    pub fn is_withdrawal_unresolved(&self, outpoint: &[u8]) -> Result<bool> {
+        self.unresolved_withdrawals.contains_key(outpoint)
+    }
+
+    pub fn remove_unresolved_withdrawal(&self, outpoint: &[u8]) -> Result<()> {
+        self.unresolved_withdrawals.remove(outpoint)?;
+        Ok(())
+    }
+
+    pub fn put_main_deposit(&self, txid: &str, record: &MainDepositRecord) -> Result<()> {
+        let value = self.encode(&self.main_deposits, txid, record)?;
+        self.main_deposits.insert(txid, value)?;
+        let mut key = self.address_key_prefix(&record.address);
+        key.extend_from_slice(txid.as_bytes());
+        self.main_deposit_addresses.insert(key, b"")?;
+        Ok(())
+    }
+
+    /// Mark a scanned mainchain deposit to strdest as reported and return its
+    /// txid, None if every scanned deposit to strdest is already claimed.
+    pub fn claim_main_deposit(&self, strdest: &str) -> Result<Option<String>> {
+        let prefix = self.address_key_prefix(strdest);
+        for entry in self.main_deposit_addresses.scan_prefix(&prefix) {
+            let (key, _) = entry?;
+            if !self.claimed_main_deposits.contains_key(&key)? {
+                self.claimed_main_deposits.insert(&key, b"")?;
+                return Ok(Some(txid_from_key(&key, &prefix)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Undo a claim_main_deposit for strdest after its sidechain deposit was
+    /// disconnected.
+    pub fn unclaim_main_deposit(&self, strdest: &str) -> Result<Option<String>> {
+        let prefix = self.address_key_prefix(strdest);
+        match self.claimed_main_deposits.scan_prefix_rev(&prefix).next() {
+            Some(entry) => {
+                let (key, _) = entry?;
+                self.claimed_main_deposits.remove(&key)?;
+                Ok(Some(txid_from_key(&key, &prefix)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn watch_address(&self, address: &str) -> Result<()> {
+        self.watched_addresses
+            .insert(self.address_key(address), b"")?;
+        Ok(())
+    }
+
+    pub fn unwatch_address(&self, address: &str) -> Result<()> {
+        self.watched_addresses.remove(self.address_key(address))?;
+        Ok(())
+    }
+
+    pub fn is_watched(&self, address: &str) -> Result<bool> {
+        self.watched_addresses
+            .contains_key(self.address_key(address))
+    }
+
+    pub fn put_proposed_bundle(&self, hash: &str, record: &ProposedBundleRecord) -> Result<()> {
+        let value = self.encode(&self.proposed_bundles, hash, record)?;
+        self.proposed_bundles.insert(hash, value)?;
+        Ok(())
+    }
+
+    pub fn list_proposed_bundles(&self) -> Result<Vec<(String, ProposedBundleRecord)>> {
+        self.proposed_bundles
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                let record = self.decode(&self.proposed_bundles, &key, &value)?;
+                let hash = String::from_utf8(key).into_diagnostic()?;
+                Ok((hash, record))
+            })
+            .collect()
+    }
+
+    pub fn put_bundle_vote(
+        &self,
+        bundle_hash: &str,
+        main_height: u64,
+        record: &BundleVoteRecord,
+    ) -> Result<()> {
+        let mut key = address_prefix(bundle_hash);
+        key.extend_from_slice(&main_height.to_be_bytes());
+        let value = self.encode(&self.bundle_votes, &key, record)?;
+        self.bundle_votes.insert(key, value)?;
+        Ok(())
+    }
+
+    /// Work score samples of bundle_hash by main height, oldest first.
+    pub fn list_bundle_votes(&self, bundle_hash: &str) -> Result<Vec<(u64, BundleVoteRecord)>> {
+        let prefix = address_prefix(bundle_hash);
+        self.bundle_votes
+            .scan_prefix(&prefix)
+            .map(|entry| {
+                let (key, value) = entry?;
+                let height = <[u8; 8]>::try_from(&key[prefix.len()..]).into_diagnostic()?;
+                let record = self.decode(&self.bundle_votes, &key, &value)?;
+                Ok((u64::from_be_bytes(height), record))
+            })
+            .collect()
+    }
+
+    pub fn remove_bundle_votes_above(&self, bundle_hash: &str, main_height: u64) -> Result<()> {
+        let prefix = address_prefix(bundle_hash);
+        let mut keys = Vec::new();
+        for entry in self.bundle_votes.scan_prefix_rev(&prefix) {
+            let (key, _) = entry?;
+            let height = <[u8; 8]>::try_from(&key[prefix.len()..]).into_diagnostic()?;
+            if u64::from_be_bytes(height) <= main_height {
+                break;
+            }
+            keys.push(key);
+        }
+        for key in keys {
+            self.bundle_votes.remove(key)?;
+        }
+        Ok(())
+    }
+
+    pub fn put_deposit_history(
+        &self,
+        address: &str,
+        id: u64,
+        record: &DepositHistoryRecord,
+    ) -> Result<()> {
+        self.remember_address(address)?;
+        let address = self.address_key(address);
+        let mut key = address.clone();
+        key.push(0);
+        key.extend_from_slice(&id.to_be_bytes());
+        let value = self.encode(&self.deposit_history, &key, record)?;
+        self.deposit_history.insert(key, value)?;
+        #[cfg(feature = "explorer-index")]
+        if !record.mainchain_txid.is_empty() {
+            self.main_txid_deposits
+                .insert(main_txid_key(&record.mainchain_txid, &address, id), b"")?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "explorer-index")]
+    pub fn get_deposit_history(
+        &self,
+        address: &str,
+        id: u64,
+    ) -> Result<Option<DepositHistoryRecord>> {
+        let mut key = self.address_key_prefix(address);
+        key.extend_from_slice(&id.to_be_bytes());
+        match self.deposit_history.get(&key)? {
+            Some(value) => self.decode(&self.deposit_history, &key, &value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Sidechain address and deposit_history id of the deposits made by
+    /// mainchain transaction txid.
+    #[cfg(feature = "explorer-index")]
+    pub fn list_main_txid_deposits(&self, txid: &str) -> Result<Vec<(String, u64)>> {
+        let prefix = address_prefix(txid);
+        let mut deposits = Vec::new();
+        for entry in self.main_txid_deposits.scan_prefix(&prefix) {
+            let (key, _) = entry?;
+            let rest = &key[prefix.len()..];
+            let split = rest.len().checked_sub(9).ok_or(Error::InvalidEncoding {
+                message: "main txid deposit key too short".into(),
+            })?;
+            let address = self.key_address(&rest[..split])?;
+            let id = <[u8; 8]>::try_from(&rest[split + 1..]).into_diagnostic()?;
+            deposits.push((address, u64::from_be_bytes(id)));
+        }
+        Ok(deposits)
+    }
+
+    /// Outpoints of the withdrawals bundle txid paid out.
+    #[cfg(feature = "explorer-index")]
+    pub fn list_bundle_withdrawals(&self, txid: &str) -> Result<Vec<Vec<u8>>> {
+        let prefix = address_prefix(txid);
+        self.bundle_withdrawals
+            .scan_prefix(&prefix)
+            .map(|entry| Ok(entry?.0[prefix.len()..].to_vec()))
+            .collect()
+    }
+
+    /// Index every deposit_history and withdrawal_payouts entry again,
+    /// returns how many there are.
+    #[cfg(feature = "explorer-index")]
+    pub fn rebuild_explorer_index(&self) -> Result<u64> {
+        for tree in [&self.main_txid_deposits, &self.bundle_withdrawals] {
+            for entry in tree.iter() {
+                tree.remove(entry?.0)?;
+            }
+        }
+        let mut indexed = 0;
+        for entry in self.deposit_history.iter() {
+            let (key, value) = entry?;
+            let record: DepositHistoryRecord = self.decode(&self.deposit_history, &key, &value)?;
+            let split = key.len().checked_sub(9).ok_or(Error::InvalidEncoding {
+                message: "deposit history key too short".into(),
+            })?;
+            if record.mainchain_txid.is_empty() {
+                continue;
+            }
+            let id = <[u8; 8]>::try_from(&key[split + 1..]).into_diagnostic()?;
+            self.main_txid_deposits.insert(
+                main_txid_key(
+                    &record.mainchain_txid,
+                    &key[..split],
+                    u64::from_be_bytes(id),
+                ),
+                b"",
+            )?;
+            indexed += 1;
+        }
+        for entry in self.withdrawal_payouts.iter() {
+            let (outpoint, value) = entry?;
+            let record: PayoutRecord = self.decode(&self.withdrawal_payouts, &outpoint, &value)?;
+            self.bundle_withdrawals
+                .insert(bundle_withdrawal_key(&record.txid, &outpoint), b"")?;
+            indexed += 1;
+        }
+        self.db.insert(EXPLORER_INDEX_KEY, b"")?;
+        Ok(indexed)
+    }
+
+    /// Deposits connected to address, oldest first.
+    pub fn list_deposit_history(&self, address: &str) -> Result<Vec<(u64, DepositHistoryRecord)>> {
+        let prefix = self.address_key_prefix(address);
+        self.deposit_history
+            .scan_prefix(&prefix)
+            .map(|entry| {
+                let (key, value) = entry?;
+                let id = <[u8; 8]>::try_from(&key[prefix.len()..]).into_diagnostic()?;
+                let record = self.decode(&self.deposit_history, &key, &value)?;
+                Ok((u64::from_be_bytes(id), record))
+            })
+            .collect()
+    }
+
+    /// Sum of every deposit in the history, across all addresses.
+    pub fn deposit_history_total(&self) -> Result<u64> {
+        let mut total: u64 = 0;
+        for entry in self.deposit_history.iter() {
+            let (key, value) = entry?;
+            let record: DepositHistoryRecord = self.decode(&self.deposit_history, key, &value)?;
+            total = total.saturating_add(record.amount);
+        }
+        Ok(total)
+    }
+
+    /// Remove the last deposit of amount connected to address, after the
+    /// block containing it was disconnected.
+    pub fn remove_last_deposit_history(&self, address: &str, amount: u64) -> Result<()> {
+        let address = self.address_key(address);
+        let mut prefix = address.clone();
+        prefix.push(0);
+        for entry in self.deposit_history.scan_prefix_rev(prefix) {
+            let (key, value) = entry?;
+            let record: DepositHistoryRecord = self.decode(&self.deposit_history, &key, &value)?;
+            if record.amount == amount {
+                #[cfg(feature = "explorer-index")]
+                if let Ok(id) = <[u8; 8]>::try_from(&key[key.len().saturating_sub(8)..]) {
+                    self.main_txid_deposits.remove(main_txid_key(
+                        &record.mainchain_txid,
+                        &address,
+                        u64::from_be_bytes(id),
+                    ))?;
+                }
+                return self.deposit_history.remove(key);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn put_pending_deposit(
+        &self,
+        address: &str,
+        id: u64,
+        record: &PendingDepositRecord,
+    ) -> Result<()> {
+        self.remember_address(address)?;
+        let mut key = self.address_key_prefix(address);
+        key.extend_from_slice(&id.to_be_bytes());
+        let value = self.encode(&self.pending_deposits, &key, record)?;
+        self.pending_deposits.insert(key, value)?;
+        Ok(())
+    }
+
+    /// Remove a pending deposit and its acknowledgement.
+    pub fn remove_pending_deposit(&self, address: &str, id: u64) -> Result<()> {
+        let mut key = self.address_key_prefix(address);
+        key.extend_from_slice(&id.to_be_bytes());
+        self.pending_deposits.remove(key)?;
+        self.acked_deposits.remove(id.to_be_bytes())
+    }
+
+    pub fn ack_deposit(&self, id: u64) -> Result<()> {
+        self.acked_deposits.insert(id.to_be_bytes(), b"")?;
+        Ok(())
+    }
+
+    pub fn is_deposit_acked(&self, id: u64) -> Result<bool> {
+        self.acked_deposits.contains_key(id.to_be_bytes())
+    }
+
+    /// Pending deposits by address, oldest first for each address.
+    pub fn list_pending_deposits(&self) -> Result<Vec<(String, u64, PendingDepositRecord)>> {
+        let mut deposits = Vec::new();
+        for entry in self.pending_deposits.iter() {
+            let (key, value) = entry?;
+            let split = key.len().checked_sub(9).ok_or(Error::InvalidEncoding {
+                message: "pending deposit key too short".into(),
+            })?;
+            let address = self.key_address(&key[..split])?;
+            let id = <[u8; 8]>::try_from(&key[split + 1..]).into_diagnostic()?;
+            let record = self.decode(&self.pending_deposits, &key, &value)?;
+            deposits.push((address, u64::from_be_bytes(id), record));
+        }
+        Ok(deposits)
+    }
+
+    pub fn put_withdrawal_destination(
+        &self,
+        label: &str,
+        record: &WithdrawalDestinationRecord,
+    ) -> Result<()> {
+        let value = self.encode(&self.withdrawal_destinations, label, record)?;
+        self.withdrawal_destinations.insert(label, value)?;
+        Ok(())
+    }
+
+    pub fn remove_withdrawal_destination(&self, label: &str) -> Result<()> {
+        self.withdrawal_destinations.remove(label)?;
+        Ok(())
+    }
+
+    /// Saved withdrawal destinations, by label.
+    pub fn list_withdrawal_destinations(
+        &self,
+    ) -> Result<Vec<(String, WithdrawalDestinationRecord)>> {
+        let mut destinations = Vec::new();
+        for entry in self.withdrawal_destinations.iter() {
+            let (key, value) = entry?;
+            let label = String::from_utf8_lossy(&key).into_owned();
+            let record = self.decode(&self.withdrawal_destinations, &key, &value)?;
+            destinations.push((label, record));
+        }
+        Ok(destinations)
+    }
+
+    pub fn put_deposit_event(&self, id: u64, record: &DepositEventRecord) -> Result<()> {
+        let value = self.encode(&self.deposit_events, id.to_be_bytes(), record)?;
+        self.deposit_events.insert(id.to_be_bytes(), value)?;
+        Ok(())
+    }
+
+    /// Remove and return all deposit events, oldest first.
+    pub fn take_deposit_events(&self) -> Result<Vec<(u64, DepositEventRecord)>> {
+        let mut events = Vec::new();
+        while let Some((key, value)) = self.deposit_events.pop_min()? {
+            let id = <[u8; 8]>::try_from(key.as_slice()).into_diagnostic()?;
+            let record = self.decode(&self.deposit_events, &key, &value)?;
+            events.push((u64::from_be_bytes(id), record));
+        }
+        Ok(events)
+    }
+
+    pub fn put_alert_event(&self, id: u64, record: &AlertRecord) -> Result<()> {
+        let value = self.encode(&self.alert_events, id.to_be_bytes(), record)?;
+        self.alert_events.insert(id.to_be_bytes(), value)?;
+        Ok(())
+    }
+
+    /// Remove and return all alert events, oldest first.
+    pub fn take_alert_events(&self) -> Result<Vec<(u64, AlertRecord)>> {
+        let mut events = Vec::new();
+        while let Some((key, value)) = self.alert_events.pop_min()? {
+            let id = <[u8; 8]>::try_from(key.as_slice()).into_diagnostic()?;
+            let record = self.decode(&self.alert_events, &key, &value)?;
+            events.push((u64::from_be_bytes(id), record));
+        }
+        Ok(events)
+    }
+
+    /// Scanned mainchain deposits to strdest, by txid.
+    pub fn list_main_deposits_to(&self, strdest: &str) -> Result<Vec<(String, MainDepositRecord)>> {
+        let prefix = self.address_key_prefix(strdest);
+        let mut deposits = Vec::new();
+        for entry in self.main_deposit_addresses.scan_prefix(&prefix) {
+            let (key, _) = entry?;
+            let txid = txid_from_key(&key, &prefix);
+            if let Some(record) = self.get_main_deposit(&txid)? {
+                deposits.push((txid, record));
+            }
+        }
+        Ok(deposits)
+    }
+
+    pub fn list_main_deposits(&self) -> Result<Vec<(String, MainDepositRecord)>> {
+        self.main_deposits
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                let record = self.decode(&self.main_deposits, &key, &value)?;
+                Ok((String::from_utf8_lossy(&key).into_owned(), record))
+            })
+            .collect()
+    }
+
+    /// Point the scanned mainchain deposit txid at another deposit address,
+    /// moving its index entry and its claim if it was claimed.
+    pub fn set_main_deposit_address(
+        &self,
+        txid: &str,
+        record: &MainDepositRecord,
+        address: &str,
+    ) -> Result<()> {
+        let mut old_key = self.address_key_prefix(&record.address);
+        old_key.extend_from_slice(txid.as_bytes());
+        let mut new_key = self.address_key_prefix(address);
+        new_key.extend_from_slice(txid.as_bytes());
+        self.main_deposit_addresses.remove(&old_key)?;
+        self.main_deposit_addresses.insert(&new_key, b"")?;
+        if self.claimed_main_deposits.contains_key(&old_key)? {
+            self.claimed_main_deposits.remove(&old_key)?;
+            self.claimed_main_deposits.insert(&new_key, b"")?;
+        }
+        let record = MainDepositRecord {
+            address: address.to_string(),
+            ..record.clone()
+        };
+        let value = self.encode(&self.main_deposits, txid, &record)?;
+        self.main_deposits.insert(txid, value)?;
+        Ok(())
+    }
+
+    pub fn get_main_deposit(&self, txid: &str) -> Result<Option<MainDepositRecord>> {
+        match self.main_deposits.get(txid)? {
+            Some(value) => self.decode(&self.main_deposits, txid, &value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn generate_id(&self) -> Result<u64> {
+        self.storage.generate_id()
+    }
+
+    pub fn put_broadcast(&self, id: u64, record: &BroadcastRecord) -> Result<()> {
+        let value = self.encode(&self.broadcast_queue, id.to_be_bytes(), record)?;
+        self.broadcast_queue.insert(id.to_be_bytes(), value)?;
+        Ok(())
+    }
+
+    pub fn remove_broadcast(&self, id: u64) -> Result<()> {
+        self.broadcast_queue.remove(id.to_be_bytes())?;
+        Ok(())
+    }
+
+    pub fn list_broadcasts(&self) -> Result<Vec<(u64, BroadcastRecord)>> {
+        self.broadcast_queue
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                let id = <[u8; 8]>::try_from(key.as_slice()).into_diagnostic()?;
+                let record = self.decode(&self.broadcast_queue, &key, &value)?;
+                Ok((u64::from_be_bytes(id), record))
+            })
+            .collect()
+    }
+
+    pub fn put_bmm_attempt(&self, id: u64, record: &BmmAttemptRecord) -> Result<()> {
+        let value = self.encode(&self.bmm_history, id.to_be_bytes(), record)?;
+        self.bmm_history.insert(id.to_be_bytes(), value)?;
+        Ok(())
+    }
+
+    /// BMM attempts, most recent first. Ids come from generate_id, so key
+    /// order is creation order.
+    pub fn iter_bmm_attempts(&self) -> impl Iterator<Item = Result<(u64, BmmAttemptRecord)>> + '_ {
+        self.bmm_history.iter_rev().map(|entry| {
+            let (key, value) = entry?;
+            let id = <[u8; 8]>::try_from(key.as_slice()).into_diagnostic()?;
+            let record = self.decode(&self.bmm_history, &key, &value)?;
+            Ok((u64::from_be_bytes(id), record))
+        })
+    }
+
+    pub fn put_audit_entry(&self, id: u64, record: &AuditRecord) -> Result<()> {
+        let value = self.encode(&self.audit_log, id.to_be_bytes(), record)?;
+        self.audit_log.insert(id.to_be_bytes(), value)?;
+        Ok(())
+    }
+
+    /// Audit log entries, oldest first.
+    pub fn iter_audit_entries(&self) -> impl Iterator<Item = Result<(u64, AuditRecord)>> + '_ {
+        self.audit_log.iter().map(|entry| {
+            let (key, value) = entry?;
+            let id = <[u8; 8]>::try_from(key.as_slice()).into_diagnostic()?;
+            let record = self.decode(&self.audit_log, &key, &value)?;
+            Ok((u64::from_be_bytes(id), record))
+        })
+    }
+
+    pub fn put_sidechain_block(&self, height: u64, record: &SidechainBlockRecord) -> Result<()> {
+        let value = self.encode(&self.sidechain_blocks, height.to_be_bytes(), record)?;
+        self.sidechain_blocks.insert(height.to_be_bytes(), value)?;
+        Ok(())
+    }
+
+    pub fn remove_sidechain_block(&self, height: u64) -> Result<()> {
+        self.sidechain_blocks.remove(height.to_be_bytes())?;
+        Ok(())
+    }
+
+    pub fn put_block_undo(&self, hash: &str, record: &BlockUndoRecord) -> Result<()> {
+        let value = self.encode(&self.block_undo, hash, record)?;
+        self.block_undo.insert(hash, value)?;
+        Ok(())
+    }
+
+    pub fn get_block_undo(&self, hash: &str) -> Result<Option<BlockUndoRecord>> {
+        match self.block_undo.get(hash)? {
+            Some(value) => self.decode(&self.block_undo, hash, &value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn remove_block_undo(&self, hash: &str) -> Result<()> {
+        self.block_undo.remove(hash)
+    }
+
+    /// Keeps the first record for outpoint, a withdrawal turned away again
+    /// isn't rewritten.
+    pub fn put_dust_withdrawal(
+        &self,
+        outpoint: &[u8],
+        record: &DustWithdrawalRecord,
+    ) -> Result<()> {
+        if self.dust_withdrawals.contains_key(outpoint)? {
+            return Ok(());
+        }
+        let value = self.encode(&self.dust_withdrawals, outpoint, record)?;
+        self.dust_withdrawals.insert(outpoint, value)?;
+        Ok(())
+    }
+
+    pub fn list_dust_withdrawals(&self) -> Result<Vec<(Vec<u8>, DustWithdrawalRecord)>> {
+        self.dust_withdrawals
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                let record = self.decode(&self.dust_withdrawals, &key, &value)?;
+                Ok((key, record))
+            })
+            .collect()
+    }
+
+    pub fn last_sidechain_block(&self) -> Result<Option<(u64, SidechainBlockRecord)>> {
+        match self.sidechain_blocks.last()? {
+            Some((key, value)) => {
+                let height = <[u8; 8]>::try_from(key.as_slice()).into_diagnostic()?;
+                let record = self.decode(&self.sidechain_blocks, &key, &value)?;
+                Ok(Some((u64::from_be_bytes(height), record)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn put_verified_bmm(
+        &self,
+        main_block_hash: &str,
+        critical_hash: &str,
+        record: &VerifiedBmmRecord,
+    ) -> Result<()> {
+        let key = bmm_key(main_block_hash, critical_hash);
+        let value = self.encode(&self.verified_bmm, &key, record)?;
+        self.verified_bmm.insert(key, value)?;
+        Ok(())
+    }
+
+    pub fn is_verified_bmm(&self, main_block_hash: &str, critical_hash: &str) -> Result<bool> {
+        self.verified_bmm
+            .contains_key(bmm_key(main_block_hash, critical_hash))
+    }
+
+    /// Forget the commitments verified in main_block_hash, returning how
+    /// many there were.
+    pub fn remove_verified_bmm(&self, main_block_hash: &str) -> Result<usize> {
+        let mut keys = Vec::new();
+        for entry in self
+            .verified_bmm
+            .scan_prefix(address_prefix(main_block_hash))
+        {
+            keys.push(entry?.0);
+        }
+        for key in &keys {
+            self.verified_bmm.remove(key)?;
+        }
+        Ok(keys.len())
+    }
+
+    pub fn get_bundle(&self) -> Result<BundleRecord> {
+        match self.db.get(BUNDLE_KEY)? {
+            Some(value) => self.decode(&self.db, BUNDLE_KEY, &value),
+            None => Ok(BundleRecord::default()),
+        }
+    }
+
+    pub fn put_bundle(&self, record: &BundleRecord) -> Result<()> {
+        let value = self.encode(&self.db, BUNDLE_KEY, record)?;
+        self.db.insert(BUNDLE_KEY, value)?;
+        Ok(())
+    }
+
+    pub fn get_sidechain_proposal(&self) -> Result<Option<SidechainProposalRecord>> {
+        match self.db.get(SIDECHAIN_PROPOSAL_KEY)? {
+            Some(value) => self
+                .decode(&self.db, SIDECHAIN_PROPOSAL_KEY, &value)
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn put_sidechain_proposal(&self, record: &SidechainProposalRecord) -> Result<()> {
+        let value = self.encode(&self.db, SIDECHAIN_PROPOSAL_KEY, record)?;
+        self.db.insert(SIDECHAIN_PROPOSAL_KEY, value)?;
+        Ok(())
+    }
+
+    pub fn get_unsigned_bundle(&self) -> Result<Option<UnsignedBundleRecord>> {
+        match self.db.get(UNSIGNED_BUNDLE_KEY)? {
+            Some(value) => self.decode(&self.db, UNSIGNED_BUNDLE_KEY, &value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn put_unsigned_bundle(&self, record: &UnsignedBundleRecord) -> Result<()> {
+        let value = self.encode(&self.db, UNSIGNED_BUNDLE_KEY, record)?;
+        self.db.insert(UNSIGNED_BUNDLE_KEY, value)?;
+        Ok(())
+    }
+
+    pub fn remove_unsigned_bundle(&self) -> Result<()> {
+        self.db.remove(UNSIGNED_BUNDLE_KEY)
+    }
+
+    /// Read and decode every record, returning how many there are. The
+    /// storage backend checks its own checksums on read, and decrypting a
+    /// record of an encrypted DB authenticates it.
+    pub fn verify_records(&self, problems: &mut Vec<RecordProblem>) -> Result<u64> {
+        let mut checked = 0;
+        checked +=
+            self.verify_tree::<WithdrawalRecord>("withdrawals", &self.withdrawals, problems)?;
+        checked +=
+            self.verify_tree::<MainDepositRecord>("main_deposits", &self.main_deposits, problems)?;
+        checked += self.verify_tree::<BroadcastRecord>(
+            "broadcast_queue",
+            &self.broadcast_queue,
+            problems,
+        )?;
+        checked +=
+            self.verify_tree::<BmmAttemptRecord>("bmm_history", &self.bmm_history, problems)?;
+        checked += self.verify_tree::<DepositEventRecord>(
+            "deposit_events",
+            &self.deposit_events,
+            problems,
+        )?;
+        checked += self.verify_tree::<AlertRecord>("alert_events", &self.alert_events, problems)?;
+        checked += self.verify_tree::<WithdrawalPayeeRecord>(
+            "withdrawal_payees",
+            &self.withdrawal_payees,
+            problems,
+        )?;
+        checked += self.verify_tree::<PayoutRecord>(
+            "withdrawal_payouts",
+            &self.withdrawal_payouts,
+            problems,
+        )?;
+        checked += self.verify_tree::<SidechainBlockRecord>(
+            "sidechain_blocks",
+            &self.sidechain_blocks,
+            problems,
+        )?;
+        checked +=
+            self.verify_tree::<VerifiedBmmRecord>("verified_bmm", &self.verified_bmm, problems)?;
+        checked += self.verify_tree::<AuditRecord>("audit_log", &self.audit_log, problems)?;
+        checked += self.verify_tree::<DepositHistoryRecord>(
+            "deposit_history",
+            &self.deposit_history,
+            problems,
+        )?;
+        checked +=
+            self.verify_tree::<BundleVoteRecord>("bundle_votes", &self.bundle_votes, problems)?;
+        checked += self.verify_tree::<ProposedBundleRecord>(
+            "proposed_bundles",
+            &self.proposed_bundles,
+            problems,
+        )?;
+        checked += self.verify_tree::<PendingDepositRecord>(
+            "pending_deposits",
+            &self.pending_deposits,
+            problems,
+        )?;
+        checked += self.verify_tree::<WithdrawalDestinationRecord>(
+            "withdrawal_destinations",
+            &self.withdrawal_destinations,
+            problems,
+        )?;
+        checked += self.verify_tree::<BlockUndoRecord>("block_undo", &self.block_undo, problems)?;
+        checked += self.verify_tree::<DustWithdrawalRecord>(
+            "dust_withdrawals",
+            &self.dust_withdrawals,
+            problems,
+        )?;
+        checked += self.verify_tree::<WithdrawalAddressRecord>(
+            "withdrawal_addresses",
+            &self.withdrawal_addresses,
+            problems,
+        )?;
+        checked += self.verify_key::<BundleRecord>(BUNDLE_KEY, problems)?;
+        checked += self.verify_key::<UnsignedBundleRecord>(UNSIGNED_BUNDLE_KEY, problems)?;
+        checked += self.verify_key::<SidechainProposalRecord>(SIDECHAIN_PROPOSAL_KEY, problems)?;
+        Ok(checked)
+    }
+
+    fn verify_tree<T: DeserializeOwned>(
+        &self,
+        name: &'static str,
+        tree: &Tree,
+        problems: &mut Vec<RecordProblem>,
+    ) -> Result<u64> {
+        let mut checked = 0;
+        for entry in tree.iter() {
+            let (key, value) = entry?;
+            checked += 1;
+            if let Err(err) = self.decode::<T>(tree, &key, &value) {
+                problems.push(RecordProblem::new(name, &key, err.to_string()));
+            }
+        }
+        Ok(checked)
+    }
+
+    fn verify_key<T: DeserializeOwned>(
+        &self,
+        key: &[u8],
+        problems: &mut Vec<RecordProblem>,
+    ) -> Result<u64> {
+        match self.db.get(key)? {
+            Some(value) => {
+                if let Err(err) = self.decode::<T>(&self.db, key, &value) {
+                    problems.push(RecordProblem::new("default", key, err.to_string()));
+                }
+                Ok(1)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Check that the index trees and the records keyed by withdrawal
+    /// outpoint agree with each other, returning how many entries were
+    /// checked.
+    pub fn verify_references(&self, problems: &mut Vec<RecordProblem>) -> Result<u64> {
+        let mut checked = 0;
+        for entry in self.withdrawal_payees.iter() {
+            let (outpoint, _) = entry?;
+            checked += 1;
+            if !self.withdrawals.contains_key(&outpoint)? {
+                problems.push(RecordProblem::new(
+                    "withdrawal_payees",
+                    &outpoint,
+                    "payee of a withdrawal that isn't recorded",
+                ));
+            }
+        }
+        for entry in self.withdrawal_payouts.iter() {
+            let (outpoint, value) = entry?;
+            checked += 1;
+            if !self.withdrawals.contains_key(&outpoint)? {
+                problems.push(RecordProblem::new(
+                    "withdrawal_payouts",
+                    &outpoint,
+                    "payout of a withdrawal that isn't recorded",
+                ));
+            }
+            // Undecodable records are reported by verify_records.
+            let Ok(payout) =
+                self.decode::<PayoutRecord>(&self.withdrawal_payouts, &outpoint, &value)
+            else {
+                continue;
+            };
+            let indexed = self
+                .payout_outputs
+                .get(output_key(&payout.txid, payout.vout))?;
+            if indexed.as_deref() != Some(outpoint.as_slice()) {
+                problems.push(RecordProblem::new(
+                    "withdrawal_payouts",
+                    &outpoint,
+                    format!(
+                        "payout output {}:{} isn't indexed to this withdrawal",
+                        payout.txid, payout.vout
+                    ),
+                ));
+            }
+        }
+        for entry in self.payout_outputs.iter() {
+            let (key, outpoint) = entry?;
+            checked += 1;
+            if !self.withdrawal_payouts.contains_key(&outpoint)? {
+                problems.push(RecordProblem::new(
+                    "payout_outputs",
+                    &key,
+                    "output of a payout that isn't recorded",
+                ));
+            }
+        }
+        for entry in self.main_deposit_addresses.iter() {
+            let (key, _) = entry?;
+            checked += 1;
+            let Some(split) = key.iter().position(|byte| *byte == 0) else {
+                problems.push(RecordProblem::new(
+                    "main_deposit_addresses",
+                    &key,
+                    "key without an address",
+                ));
+                continue;
+            };
+            let txid = txid_from_key(&key, &key[..=split]);
+            let Some(value) = self.main_deposits.get(&txid)? else {
+                problems.push(RecordProblem::new(
+                    "main_deposit_addresses",
+                    &key,
+                    format!("mainchain deposit {txid} isn't recorded"),
+                ));
+                continue;
+            };
+            // Undecodable records are reported by verify_records.
+            let Ok(record) = self.decode::<MainDepositRecord>(&self.main_deposits, &txid, &value)
+            else {
+                continue;
+            };
+            if self.address_key(&record.address) != key[..split] {
+                problems.push(RecordProblem::new(
+                    "main_deposit_addresses",
+                    &key,
+                    format!(
+                        "mainchain deposit {txid} is to {}, indexed under another address",
+                        record.address
+                    ),
+                ));
+            }
+        }
+        for entry in self.claimed_main_deposits.iter() {
+            let (key, _) = entry?;
+            checked += 1;
+            if !self.main_deposit_addresses.contains_key(&key)? {
+                problems.push(RecordProblem::new(
+                    "claimed_main_deposits",
+                    &key,
+                    "claim of a mainchain deposit that isn't indexed",
+                ));
+            }
+        }
+        Ok(checked)
+    }
+
+    pub fn flush(&self) -> Result<usize> {
+        self.unflushed.store(0, Ordering::Relaxed);
+        let flushed = self.storage.flush()?;
+        self.flushed_at.store(unix_time(), Ordering::Relaxed);
+        Ok(flushed)
+    }
+
+    pub fn unflushed(&self) -> u64 {
+        self.unflushed.load(Ordering::Relaxed)
+    }
+
+    pub fn flushed_at(&self) -> u64 {
+        self.flushed_at.load(Ordering::Relaxed)
+    }
+}
+
+fn address_prefix(strdest: &str) -> Vec<u8> {
+    let mut prefix = strdest.as_bytes().to_vec();
+    prefix.push(0);
+    prefix
+}
+
+fn bmm_key(main_block_hash: &str, critical_hash: &str) -> Vec<u8> {
+    let mut key = address_prefix(main_block_hash);
+    key.extend_from_slice(critical_hash.as_bytes());
+    key
+}
+
+#[cfg(feature = "explorer-index")]
+fn main_txid_key(txid: &str, address_key: &[u8], id: u64) -> Vec<u8> {
+    let mut key = address_prefix(txid);
+    key.extend_from_slice(address_key);
+    key.push(0);
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
+
+#[cfg(feature = "explorer-index")]
+fn bundle_withdrawal_key(txid: &str, outpoint: &[u8]) -> Vec<u8> {
+    let mut key = address_prefix(txid);
+    key.extend_from_slice(outpoint);
+    key
+}
+
+fn output_key(txid: &str, vout: u32) -> Vec<u8> {
+    let mut key = address_prefix(txid);
+    key.extend_from_slice(&vout.to_be_bytes());
+    key
+}
+
+// Associated data of the value stored under key in tree. Tree names hold
+// no 0x00, so no two trees and keys share it.
+fn value_aad(tree: &Tree, key: &[u8]) -> Vec<u8> {
+    let mut aad = tree.name().as_bytes().to_vec();
+    aad.push(0);
+    aad.extend_from_slice(key);
+    aad
+}
+
+// Encrypts the value stored under key in tree unless it already is.
+fn migrate_value(tree: &Tree, key: &[u8], value: &[u8], cipher: &Cipher) -> Result<()> {
+    let aad = value_aad(tree, key);
+    if cipher.decrypt(value, &aad).is_some() {
+        return Ok(());
+    }
+    let plaintext = cipher.decrypt(value, &[]).unwrap_or_else(|| value.to_vec());
+    tree.insert(key, cipher.encrypt(&plaintext, &aad)?)
+}
+
+// The blinded tag of address, recorded in blinded if it isn't yet.
+fn remember_blinded(blinded: &Tree, cipher: &Cipher, address: &[u8]) -> Result<Vec<u8>> {
+    let tag = cipher.blind(address);
+    if !blinded.contains_key(&tag)? {
+        blinded.insert(&tag, cipher.encrypt(address, &value_aad(blinded, &tag))?)?;
+    }
+    Ok(tag)
+}
+
+// Rewrites the keys of tree with the address between the offsets
+// address_range finds replaced by its blinded tag. Encrypted values are
+// encrypted again for their new key, the empty values of index trees are
+// moved as they are.
+fn blind_tree_keys(
+    tree: &Tree,
+    blinded: &Tree,
+    cipher: &Cipher,
+    address_range: impl Fn(&[u8]) -> Option<(usize, usize)>,
+) -> Result<()> {
+    let entries = tree.iter().collect::<Result<Vec<_>>>()?;
+    for (key, value) in entries {
+        let Some((start, end)) = address_range(&key) else {
+            continue;
+        };
+        if blinded.contains_key(&key[start..end])? {
+            continue;
+        }
+        let mut blinded_key = key[..start].to_vec();
+        blinded_key.extend_from_slice(&remember_blinded(blinded, cipher, &key[start..end])?);
+        blinded_key.extend_from_slice(&key[end..]);
+        let value = match cipher.decrypt(&value, &value_aad(tree, &key)) {
+            Some(plaintext) => cipher.encrypt(&plaintext, &value_aad(tree, &blinded_key))?,
+            None => value,
+        };
+        tree.insert(blinded_key, value)?;
+        tree.remove(key)?;
+    }
+    Ok(())
+}
+
+fn txid_from_key(key: &[u8], prefix: &[u8]) -> String {
+    String::from_utf8_lossy(&key[prefix.len()..]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    const PASSPHRASE: &str = "passphrase";
+    const ADDRESS: &str = "s0_1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+    fn storage() -> Arc<dyn Storage> {
+        Arc::new(MemoryStorage::default())
+    }
+
+    fn withdrawal(main_height: u64) -> WithdrawalRecord {
+        WithdrawalRecord { main_height }
+    }
+
+    fn open_error(storage: &Arc<dyn Storage>, passphrase: Option<&str>) -> Error {
+        match Db::open(storage.clone(), passphrase) {
+            Ok(_) => panic!("opened"),
+            Err(err) => err.downcast::<Error>().unwrap(),
+        }
+    }
+
+    fn holds(key: &[u8], part: &[u8]) -> bool {
+        key.windows(part.len()).any(|window| window == part)
+    }
+
+    #[test]
+    fn encrypted_record_reads_back() -> Result<()> {
+        let db = Db::open(storage(), Some(PASSPHRASE))?;
+        db.put_withdrawal(b"outpoint", &withdrawal(7))?;
+        assert_eq!(db.get_withdrawal(b"outpoint")?.unwrap().main_height, 7);
+        let value = db.withdrawals.get(b"outpoint")?.unwrap();
+        assert_ne!(value, bincode::serialize(&withdrawal(7)).into_diagnostic()?);
+        Ok(())
+    }
+
+    #[test]
+    fn encrypted_record_moved_elsewhere_doesnt_decode() -> Result<()> {
+        let db = Db::open(storage(), Some(PASSPHRASE))?;
+        db.put_withdrawal(b"outpoint", &withdrawal(7))?;
+        let value = db.withdrawals.get(b"outpoint")?.unwrap();
+        db.withdrawals.insert(b"other", &value)?;
+        db.dust_withdrawals.insert(b"outpoint", &value)?;
+        assert!(db.get_withdrawal(b"other").is_err());
+        let mut problems = Vec::new();
+        db.verify_records(&mut problems)?;
+        assert_eq!(problems.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn reopen_needs_the_passphrase() -> Result<()> {
+        let storage = storage();
+        let db = Db::open(storage.clone(), None)?;
+        db.put_withdrawal(b"outpoint", &withdrawal(7))?;
+        db.encrypt(PASSPHRASE)?;
+        drop(db);
+        assert!(matches!(open_error(&storage, None), Error::DbEncrypted));
+        assert!(matches!(
+            open_error(&storage, Some("other")),
+            Error::WrongPassphrase
+        ));
+        let db = Db::open(storage, Some(PASSPHRASE))?;
+        assert_eq!(db.get_withdrawal(b"outpoint")?.unwrap().main_height, 7);
+        Ok(())
+    }
+
+    #[test]
+    fn interrupted_migrate_finishes_on_open() -> Result<()> {
+        let storage = storage();
+        let db = Db::open(storage.clone(), None)?;
+        db.put_withdrawal(b"first", &withdrawal(1))?;
+        db.put_withdrawal(b"second", &withdrawal(2))?;
+        db.put_bundle(&BundleRecord::default())?;
+        // As encrypt leaves the DB if it stops after the first value.
+        let salt = crypt::new_salt();
+        let cipher = Cipher::new(PASSPHRASE, &salt)?;
+        db.put_encryption(&EncryptionRecord {
+            salt: salt.to_vec(),
+            check: cipher.check()?,
+            migrated: false,
+        })?;
+        let value = db.withdrawals.get(b"first")?.unwrap();
+        migrate_value(&db.withdrawals, b"first", &value, &cipher)?;
+        drop(db);
+
+        let db = Db::open(storage, Some(PASSPHRASE))?;
+        assert!(db.get_encryption()?.unwrap().migrated);
+        assert_eq!(db.get_withdrawal(b"first")?.unwrap().main_height, 1);
+        assert_eq!(db.get_withdrawal(b"second")?.unwrap().main_height, 2);
+        db.get_bundle()?;
+        Ok(())
+    }
+
+    #[test]
+    fn values_encrypted_without_associated_data_are_migrated() -> Result<()> {
+        let storage = storage();
+        let db = Db::open(storage.clone(), None)?;
+        // As versions before associated data wrote an encrypted DB.
+        let salt = crypt::new_salt();
+        let cipher = Cipher::new(PASSPHRASE, &salt)?;
+        let value = bincode::serialize(&withdrawal(7)).into_diagnostic()?;
+        db.withdrawals
+            .insert(b"outpoint", cipher.encrypt(&value, &[])?)?;
+        db.put_encryption(&EncryptionRecord {
+            salt: salt.to_vec(),
+            check: cipher.check()?,
+            migrated: true,
+        })?;
+        drop(db);
+
+        let db = Db::open(storage, Some(PASSPHRASE))?;
+        assert!(db.db.contains_key(BOUND_VALUES_KEY)?);
+        assert_eq!(db.get_withdrawal(b"outpoint")?.unwrap().main_height, 7);
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_blinds_addresses_in_keys() -> Result<()> {
+        let storage = storage();
+        let db = Db::open(storage.clone(), None)?;
+        let history = DepositHistoryRecord {
+            amount: 1_000,
+            mainchain_txid: String::new(),
+            main_height: 0,
+            connected_at: 0,
+        };
+        db.put_deposit_history(ADDRESS, 1, &history)?;
+        let pending = PendingDepositRecord {
+            amount: 2_000,
+            first_seen_height: 0,
+        };
+        db.put_pending_deposit(ADDRESS, 2, &pending)?;
+        db.watch_address(ADDRESS)?;
+        db.encrypt(PASSPHRASE)?;
+
+        for tree in [
+            &db.deposit_history,
+            &db.pending_deposits,
+            &db.watched_addresses,
+        ] {
+            for entry in tree.iter() {
+                assert!(!holds(&entry?.0, ADDRESS.as_bytes()));
+            }
+        }
+        drop(db);
+        let db = Db::open(storage, Some(PASSPHRASE))?;
+        let history = db.list_deposit_history(ADDRESS)?;
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].0, history[0].1.amount), (1, 1_000));
+        let pending = db.list_pending_deposits()?;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].0, ADDRESS);
+        assert_eq!((pending[0].1, pending[0].2.amount), (2, 2_000));
+        assert!(db.is_watched(ADDRESS)?);
+        Ok(())
+    }
+
+    #[test]
+    fn interrupted_blind_keys_finishes() -> Result<()> {
+        let db = Db::open(storage(), None)?;
+        db.watch_address(ADDRESS)?;
+        db.watch_address("s0_other")?;
+        let cipher = Cipher::new(PASSPHRASE, &crypt::new_salt())?;
+        db.blind_keys(&cipher)?;
+        // As blind_tree_keys leaves a key if it stops before removing it.
+        db.watched_addresses.insert(ADDRESS, b"")?;
+        db.blind_keys(&cipher)?;
+
+        let keys = db
+            .watched_addresses
+            .iter()
+            .map(|entry| Ok(entry?.0))
+            .collect::<Result<Vec<_>>>()?;
+        let mut tags = vec![cipher.blind(ADDRESS.as_bytes()), cipher.blind(b"s0_other")];
+        tags.sort();
+        assert_eq!(keys, tags);
+        Ok(())
+    }
+
+    #[test]
+    fn removed_withdrawal_leaves_nothing_behind() -> Result<()> {
+        let db = Db::open(storage(), None)?;
+        for outpoint in [&b"removed"[..], b"kept"] {
+            db.put_withdrawal(outpoint, &withdrawal(7))?;
+            db.put_payee(
+                outpoint,
+                &WithdrawalPayeeRecord {
+                    dest: [1; 20],
+                    amount: 1_000,
+                    main_fee: 10,
+                },
+            )?;
+            db.put_withdrawal_address(
+                outpoint,
+                &WithdrawalAddressRecord {
+                    main_address: "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy".into(),
+                },
+            )?;
+            db.put_paid_withdrawal(outpoint)?;
+            db.put_unresolved_withdrawal(outpoint)?;
+            db.put_payout(
+                outpoint,
+                &PayoutRecord {
+                    txid: String::from_utf8_lossy(outpoint).into_owned(),
+                    vout: 1,
+                    main_block_hash: "block".into(),
+                },
+            )?;
+        }
+        db.remove_withdrawal(b"removed")?;
+
+        assert!(db.get_withdrawal(b"removed")?.is_none());
+        assert!(db.get_payee(b"removed")?.is_none());
+        assert!(db.get_withdrawal_address(b"removed")?.is_none());
+        assert!(!db.is_withdrawal_paid(b"removed")?);
+        assert!(!db.is_withdrawal_unresolved(b"removed")?);
+        assert!(db.get_payout(b"removed")?.is_none());
+        assert!(!db.is_payout_output("removed", 1)?);
+        #[cfg(feature = "explorer-index")]
+        assert!(db.list_bundle_withdrawals("removed")?.is_empty());
+
+        assert!(db.get_withdrawal(b"kept")?.is_some());
+        assert!(db.get_payee(b"kept")?.is_some());
+        assert!(db.get_withdrawal_address(b"kept")?.is_some());
+        assert!(db.is_withdrawal_paid(b"kept")?);
+        assert!(db.is_withdrawal_unresolved(b"kept")?);
+        assert!(db.get_payout(b"kept")?.is_some());
+        assert!(db.is_payout_output("kept", 1)?);
+        #[cfg(feature = "explorer-index")]
+        assert_eq!(db.list_bundle_withdrawals("kept")?, [b"kept".to_vec()]);
+
+        let mut problems = Vec::new();
+        db.verify_references(&mut problems)?;
+        assert!(problems.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::bridge::ffi;
+use bitcoin::hashes::{sha256, Hash as _};
+
+/// Hex digits of the checksum ending a Prefixed deposit address.
+const CHECKSUM_LEN: usize = 6;
+
+// Turns sidechain addresses into the deposit addresses depositors send to
+// and back. The drivechain crate formats Drivechain deposit addresses
+// itself, so format leaves those to it. address only takes a deposit
+// address apart, a strict parse formats the result again and compares.
+#[derive(Clone)]
+pub enum DepositAddressFormatter {
+    Drivechain,
+    /// script_template.deposit_address_format.
+    Template(String),
+    Prefixed(String),
+}
+
+impl DepositAddressFormatter {
+    pub fn new(config: &ffi::Config) -> Self {
+        if !config.script_template.deposit_address_format.is_empty() {
+            return Self::Template(config.script_template.deposit_address_format.clone());
+        }
+        match config.deposit_address_format {
+            ffi::DepositAddressFormat::Prefixed => {
+                Self::Prefixed(config.deposit_address_prefix.clone())
+            }
+            _ => Self::Drivechain,
+        }
+    }
+
+    /// None for Drivechain deposit addresses, the drivechain crate formats
+    /// those.
+    pub fn format(&self, sidechain: usize, address: &str) -> Option<String> {
+        match self {
+            Self::Drivechain => None,
+            Self::Template(template) => Some(
+                template
+                    .replace("{sidechain}", &sidechain.to_string())
+                    .replace("{address}", address),
+            ),
+            Self::Prefixed(prefix) => {
+                let body = format!("{prefix}{sidechain}_{address}_");
+                let checksum = sha256::Hash::hash(body.as_bytes()).to_string();
+                Some(body + &checksum[..CHECKSUM_LEN])
+            }
+        }
+    }
+
+    /// The sidechain address strdest was formatted from for sidechain, None
+    /// if it isn't shaped like a deposit address for sidechain. Checksums
+    /// aren't checked.
+    pub fn address(&self, sidechain: usize, strdest: &str) -> Option<String> {
+        let prefixed = |prefix: &str| {
+            let rest = strdest.strip_prefix(&format!("{prefix}{sidechain}_"))?;
+            let (address, _checksum) = rest.rsplit_once('_')?;
+            Some(address.to_string())
+        };
+        match self {
+            Self::Drivechain => prefixed("s"),
+            Self::Template(template) => {
+                let template = template.replace("{sidechain}", &sidechain.to_string());
+                let (prefix, suffix) = template.split_once("{address}")?;
+                strdest
+                    .strip_prefix(prefix)?
+                    .strip_suffix(suffix)
+                    .map(str::to_string)
+            }
+            Self::Prefixed(prefix) => prefixed(prefix),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::bridge::ffi;
+use crate::codec::{Dest20, Hash32};
+use crate::error::Error;
+use bitcoin::blockdata::script::Script;
+use bitcoin::hash_types::{PubkeyHash, ScriptHash};
+use bitcoin::hashes::hash160;
+use bitcoin::hashes::Hash as _;
+use bitcoin::network::constants::Network;
+use bitcoin::util::address::{Address, Payload, WitnessVersion};
+use std::str::FromStr;
+
+/// Parse a withdrawal destination, either 40 hex characters of a P2PKH hash
+/// as accepted so far or a mainchain address of any supported type.
+pub fn parse_dest(main_address: &str) -> Result<ffi::DestAddress, Error> {
+    if let Ok(hash) = Dest20::decode("main_address", main_address) {
+        return Ok(ffi::DestAddress {
+            kind: ffi::DestKind::P2pkh,
+            hash: hash.0.to_vec(),
+        });
+    }
+    let address = Address::from_str(main_address).map_err(|err| Error::InvalidEncoding {
+        message: format!("main_address {main_address}: {err}"),
+    })?;
+    address_dest(address)
+}
+
+/// Parse a mainchain address for network.
+pub fn parse_address(address: &str, network: Network) -> Result<ffi::DestAddress, Error> {
+    let parsed = Address::from_str(address).map_err(|err| Error::InvalidEncoding {
+        message: format!("address {address}: {err}"),
+    })?;
+    // Testnet, signet and regtest share their base58 prefixes, so those
+    // pass for each other.
+    if !parsed.is_valid_for_network(network) {
+        return Err(Error::WrongNetwork {
+            address: address.to_string(),
+            network: network.to_string(),
+        });
+    }
+    address_dest(parsed)
+}
+
+fn address_dest(address: Address) -> Result<ffi::DestAddress, Error> {
+    let (kind, hash) = match address.payload {
+        Payload::PubkeyHash(hash) => (ffi::DestKind::P2pkh, hash.into_inner().to_vec()),
+        Payload::ScriptHash(hash) => (ffi::DestKind::P2sh, hash.into_inner().to_vec()),
+        Payload::WitnessProgram { version, program } => match (version, program.len()) {
+            (WitnessVersion::V0, 20) => (ffi::DestKind::P2wpkh, program),
+            (WitnessVersion::V1, 32) => (ffi::DestKind::P2tr, program),
+            _ => {
+                return Err(Error::UnsupportedDestination {
+                    kind: "witness program",
+                })
+            }
+        },
+    };
+    Ok(ffi::DestAddress { kind, hash })
+}
+
+/// The script a bundle output paying dest has.
+pub fn payout_script(dest: &ffi::DestAddress) -> Result<Script, Error> {
+    let payload = match dest.kind {
+        ffi::DestKind::P2pkh => Payload::PubkeyHash(PubkeyHash::from_inner(
+            Dest20::from_slice("hash", &dest.hash)?.0,
+        )),
+        ffi::DestKind::P2sh => Payload::ScriptHash(ScriptHash::from_inner(
+            Dest20::from_slice("hash", &dest.hash)?.0,
+        )),
+        ffi::DestKind::P2wpkh => Payload::WitnessProgram {
+            version: WitnessVersion::V0,
+            program: Dest20::from_slice("hash", &dest.hash)?.0.to_vec(),
+        },
+        ffi::DestKind::P2tr => Payload::WitnessProgram {
+            version: WitnessVersion::V1,
+            program: Hash32::from_slice("hash", &dest.hash)?.0.to_vec(),
+        },
+        _ => return Err(Error::UnsupportedDestination { kind: "unknown" }),
+    };
+    Ok(payload.script_pubkey())
+}
+
+/// The 20 byte hash drive::Withdrawal pays to. The drivechain crate builds
+/// P2PKH bundle outputs from it, so for other destination types it is the
+/// HASH160 of the payout script, which no key spends. Bundles paying those
+/// are built by the bridge from the script, never by the crate.
+pub fn withdrawal_dest(main_address: &str) -> Result<Dest20, Error> {
+    let dest = parse_dest(main_address)?;
+    if dest.kind == ffi::DestKind::P2pkh {
+        return Dest20::from_slice("main_address", &dest.hash);
+    }
+    let script = payout_script(&dest)?;
+    Ok(Dest20(hash160::Hash::hash(script.as_bytes()).into_inner()))
+}
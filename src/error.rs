@@ -0,0 +1,220 @@
+use miette::Diagnostic;
+use thiserror::Error;
+
+// Error messages cross the FFI as the `what()` string of `rust::Error`, so
+// every message starts with a stable snake_case code the C++ side can match
+// on, followed by a human readable description.
+#[derive(Debug, Diagnostic, Error)]
+pub enum Error {
+    #[error("amount_out_of_range: {what} amount {amount} exceeds MAX_MONEY")]
+    #[diagnostic(code(drivechain_cpp::amount_out_of_range))]
+    AmountOutOfRange { what: &'static str, amount: u64 },
+    #[error("invalid_amount: {amount:?} is not a BTC amount, {reason}")]
+    #[diagnostic(code(drivechain_cpp::invalid_amount))]
+    InvalidAmount {
+        amount: String,
+        reason: &'static str,
+    },
+    #[error("amount_overflow: {what} total overflows")]
+    #[diagnostic(code(drivechain_cpp::amount_overflow))]
+    AmountOverflow { what: &'static str },
+    #[error(
+        "fee_not_below_amount: withdrawal main_fee {main_fee} is not below its amount {amount}"
+    )]
+    #[diagnostic(code(drivechain_cpp::fee_not_below_amount))]
+    FeeNotBelowAmount { amount: u64, main_fee: u64 },
+    #[error("rpc_error: mainchain RPC error {code}: {message}")]
+    #[diagnostic(code(drivechain_cpp::rpc_error))]
+    Rpc { code: i64, message: String },
+    #[error("transport_error: failed to reach mainchain node: {message}")]
+    #[diagnostic(code(drivechain_cpp::transport_error))]
+    Transport { message: String },
+    #[error("unknown_withdrawal: no withdrawal recorded for outpoint {outpoint}")]
+    #[diagnostic(code(drivechain_cpp::unknown_withdrawal))]
+    UnknownWithdrawal { outpoint: String },
+    #[error("scan_in_progress: a deposit scan is already running")]
+    #[diagnostic(code(drivechain_cpp::scan_in_progress))]
+    ScanInProgress,
+    #[error("invalid_header: mainchain header {block_hash} does not extend the header chain")]
+    #[diagnostic(code(drivechain_cpp::invalid_header))]
+    InvalidHeader { block_hash: String },
+    #[error("spv_disabled: drivechain was constructed without an SPV header chain")]
+    #[diagnostic(code(drivechain_cpp::spv_disabled))]
+    SpvDisabled,
+    #[error("unknown_block: mainchain block {block_hash} is not in the header chain")]
+    #[diagnostic(code(drivechain_cpp::unknown_block))]
+    UnknownBlock { block_hash: String },
+    #[error("unknown_deposit: mainchain deposit {txid} has not been scanned")]
+    #[diagnostic(code(drivechain_cpp::unknown_deposit))]
+    UnknownDeposit { txid: String },
+    #[error("unknown_pending_deposit: no pending deposit has id {id}")]
+    #[diagnostic(code(drivechain_cpp::unknown_pending_deposit))]
+    UnknownPendingDeposit { id: u64 },
+    #[error("unknown_cursor: deposit cursor {cursor} is not open")]
+    #[diagnostic(code(drivechain_cpp::unknown_cursor))]
+    UnknownCursor { cursor: u64 },
+    #[error("unknown_bundle: bundle {hash} is not being voted on")]
+    #[diagnostic(code(drivechain_cpp::unknown_bundle))]
+    UnknownBundle { hash: String },
+    #[error("block_too_large: {count} {what} in a block, at most {max} are accepted")]
+    #[diagnostic(code(drivechain_cpp::block_too_large))]
+    BlockTooLarge {
+        what: &'static str,
+        count: usize,
+        max: usize,
+    },
+    #[error("invalid_encoding: {message}")]
+    #[diagnostic(code(drivechain_cpp::invalid_encoding))]
+    InvalidEncoding { message: String },
+    #[error("no_bmm_commitment: mainchain block {main_block_hash} has no BMM commitment to {critical_hash}")]
+    #[diagnostic(code(drivechain_cpp::no_bmm_commitment))]
+    NoBmmCommitment {
+        main_block_hash: String,
+        critical_hash: String,
+    },
+    #[error("unknown_bmm_request: no BMM request was made for critical hash {critical_hash}")]
+    #[diagnostic(code(drivechain_cpp::unknown_bmm_request))]
+    UnknownBmmRequest { critical_hash: String },
+    #[error("sidechain_inactive: sidechain slot {sidechain} is not active on the mainchain")]
+    #[diagnostic(code(drivechain_cpp::sidechain_inactive))]
+    SidechainInactive { sidechain: usize },
+    #[error(
+        "sidechain_already_active: sidechain slot {sidechain} is already active on the mainchain"
+    )]
+    #[diagnostic(code(drivechain_cpp::sidechain_already_active))]
+    SidechainAlreadyActive { sidechain: usize },
+    #[error(
+        "wrong_slot: open the node on slot {slot} to migrate to it, it is on slot {this_sidechain}"
+    )]
+    #[diagnostic(code(drivechain_cpp::wrong_slot))]
+    WrongSlot { slot: usize, this_sidechain: usize },
+    #[error("insufficient_funds: {needed} sats are needed, the signing key has {available}")]
+    #[diagnostic(code(drivechain_cpp::insufficient_funds))]
+    InsufficientFunds { needed: u64, available: u64 },
+    #[error("psbt_not_finalized: {unsigned} PSBT inputs are not signed and finalized")]
+    #[diagnostic(code(drivechain_cpp::psbt_not_finalized))]
+    PsbtNotFinalized { unsigned: usize },
+    #[error("insufficient_fee: transaction pays {fee} sats, {required_fee} were requested")]
+    #[diagnostic(code(drivechain_cpp::insufficient_fee))]
+    InsufficientFee { fee: u64, required_fee: u64 },
+    #[error("mainnet_guard: refusing {operation} against a mainnet node, set i_know_what_im_doing to override")]
+    #[diagnostic(code(drivechain_cpp::mainnet_guard))]
+    MainnetGuard { operation: &'static str },
+    #[error("regtest_only: {operation} needs a regtest mainchain node, this one is on {chain}")]
+    #[diagnostic(code(drivechain_cpp::regtest_only))]
+    RegtestOnly {
+        operation: &'static str,
+        chain: String,
+    },
+    #[error(
+        "watch_only: {operation} needs the mainchain wallet, which watch_only mode doesn't use"
+    )]
+    #[diagnostic(code(drivechain_cpp::watch_only))]
+    WatchOnly { operation: &'static str },
+    #[error("watch_only_probe: the mainchain node failed {method}: {message}")]
+    #[diagnostic(code(drivechain_cpp::watch_only_probe))]
+    WatchOnlyProbe {
+        method: &'static str,
+        message: String,
+    },
+    #[error("unsupported_destination: withdrawals to {kind} destinations are not supported")]
+    #[diagnostic(code(drivechain_cpp::unsupported_destination))]
+    UnsupportedDestination { kind: &'static str },
+    #[error("invalid_deposit_address: {address} is not a deposit address for this sidechain")]
+    #[diagnostic(code(drivechain_cpp::invalid_deposit_address))]
+    InvalidDepositAddress { address: String },
+    #[error("wrong_network: {address} is not a {network} address")]
+    #[diagnostic(code(drivechain_cpp::wrong_network))]
+    WrongNetwork { address: String, network: String },
+    #[error("unknown_chain: the mainchain node is on {chain}, which has no address network")]
+    #[diagnostic(code(drivechain_cpp::unknown_chain))]
+    UnknownChain { chain: String },
+    #[error("empty_label: withdrawal destinations need a label")]
+    #[diagnostic(code(drivechain_cpp::empty_label))]
+    EmptyLabel,
+    #[error("invalid_length: {field} is {actual} bytes, expected {expected}")]
+    #[diagnostic(code(drivechain_cpp::invalid_length))]
+    InvalidLength {
+        field: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("withdrawal_not_paid: withdrawal {outpoint} has not been paid out on the mainchain")]
+    #[diagnostic(code(drivechain_cpp::withdrawal_not_paid))]
+    WithdrawalNotPaid { outpoint: String },
+    #[error("payout_not_found: no confirmed bundle paying withdrawal {outpoint} was found")]
+    #[diagnostic(code(drivechain_cpp::payout_not_found))]
+    PayoutNotFound { outpoint: String },
+    #[error("sidechain_block_mismatch: expected {expected}, got {actual}")]
+    #[diagnostic(code(drivechain_cpp::sidechain_block_mismatch))]
+    SidechainBlockMismatch { expected: String, actual: String },
+    #[error("no_undo_data: sidechain block {hash} has no undo data, it wasn't connected with connect_sidechain_block by this version")]
+    #[diagnostic(code(drivechain_cpp::no_undo_data))]
+    NoUndoData { hash: String },
+    #[error("no_sidechain_block: no block was connected with connect_sidechain_block")]
+    #[diagnostic(code(drivechain_cpp::no_sidechain_block))]
+    NoSidechainBlock,
+    #[error("backend_reopen_failed: the drivechain crate could not be reopened with the new RPC credentials: {message}")]
+    #[diagnostic(code(drivechain_cpp::backend_reopen_failed))]
+    BackendReopenFailed { message: String },
+    #[error("snapshot_stale: a block was connected or disconnected since the snapshot was opened")]
+    #[diagnostic(code(drivechain_cpp::snapshot_stale))]
+    SnapshotStale,
+    #[error("backend_unavailable: the {backend} backend is not compiled in")]
+    #[diagnostic(code(drivechain_cpp::backend_unavailable))]
+    BackendUnavailable { backend: &'static str },
+    #[error("index_unavailable: the explorer index is not compiled in, build with the explorer-index feature")]
+    #[diagnostic(code(drivechain_cpp::index_unavailable))]
+    IndexUnavailable,
+    #[error(
+        "db_encrypted: the drivechain-cpp database is encrypted, set db_passphrase or db_key_file"
+    )]
+    #[diagnostic(code(drivechain_cpp::db_encrypted))]
+    DbEncrypted,
+    #[error("wrong_passphrase: the passphrase does not decrypt the drivechain-cpp database")]
+    #[diagnostic(code(drivechain_cpp::wrong_passphrase))]
+    WrongPassphrase,
+    #[error("db_already_encrypted: the drivechain-cpp database is already encrypted")]
+    #[diagnostic(code(drivechain_cpp::db_already_encrypted))]
+    DbAlreadyEncrypted,
+    #[error("empty_passphrase: the database passphrase must not be empty")]
+    #[diagnostic(code(drivechain_cpp::empty_passphrase))]
+    EmptyPassphrase,
+    #[error("storage_error: {message}")]
+    #[diagnostic(code(drivechain_cpp::storage_error))]
+    Storage { message: String },
+    #[error("bundle_exceeds_escrow: bundle pays {total} sats, the escrow holds {escrow}")]
+    #[diagnostic(code(drivechain_cpp::bundle_exceeds_escrow))]
+    BundleExceedsEscrow { total: u64, escrow: u64 },
+    #[error("empty_bundle: no withdrawals are waiting for a bundle")]
+    #[diagnostic(code(drivechain_cpp::empty_bundle))]
+    EmptyBundle,
+    #[error("bundle_rejected: the mainchain node would reject the bundle: {reason}")]
+    #[diagnostic(code(drivechain_cpp::bundle_rejected))]
+    BundleRejected { reason: String },
+    #[error("no_unsigned_bundle: no bundle is waiting for signatures")]
+    #[diagnostic(code(drivechain_cpp::no_unsigned_bundle))]
+    NoUnsignedBundle,
+    #[error(
+        "bundle_mismatch: signed bundle {txid} differs from the bundle handed out for signing"
+    )]
+    #[diagnostic(code(drivechain_cpp::bundle_mismatch))]
+    BundleMismatch { txid: String },
+    #[error("trace_unavailable: {message}")]
+    #[diagnostic(code(drivechain_cpp::trace_unavailable))]
+    TraceUnavailable { message: String },
+    #[error("replay: {message}")]
+    #[diagnostic(code(drivechain_cpp::replay))]
+    Replay { message: String },
+    #[error("block_compression: {message}")]
+    #[diagnostic(code(drivechain_cpp::block_compression))]
+    BlockCompression { message: String },
+    #[cfg(feature = "mock")]
+    #[error("mock_rejected: {message}")]
+    #[diagnostic(code(drivechain_cpp::mock_rejected))]
+    MockRejected { message: String },
+    #[cfg(feature = "capi")]
+    #[error("null_argument: {name} must not be NULL")]
+    #[diagnostic(code(drivechain_cpp::null_argument))]
+    NullArgument { name: &'static str },
+}
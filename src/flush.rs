@@ -0,0 +1,45 @@
+use crate::backend::Backend;
+use crate::bridge::ffi::FlushPolicy;
+use crate::bridge::unix_time;
+use crate::db::{self, Db};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Flush both DBs if policy says so, last_flush being when they last were.
+/// Returns whether they were flushed.
+pub fn flush_if_due(
+    inner: &Mutex<Box<dyn Backend>>,
+    db: &Db,
+    policy: &FlushPolicy,
+    last_flush: &mut Instant,
+) -> bool {
+    let due = policy.interval_secs > 0
+        && last_flush.elapsed() >= Duration::from_secs(policy.interval_secs);
+    let dirty = policy.dirty_threshold > 0 && db.unflushed() >= policy.dirty_threshold;
+    if !due && !dirty {
+        return false;
+    }
+    // A failing flush is retried when next due, and reported by the next
+    // explicit flush.
+    let _span = tracing::debug_span!("background_flush").entered();
+    let unflushed = db.unflushed();
+    if unflushed > 0 {
+        let record = db::AuditRecord {
+            at: unix_time(),
+            operation: db::AuditOperation::Flush,
+            main_block_hash: String::new(),
+            detail: format!("background flush of {unflushed} records"),
+        };
+        let _ = db
+            .generate_id()
+            .and_then(|id| db.put_audit_entry(id, &record));
+    }
+    let _ = lock(inner).flush();
+    let _ = db.flush();
+    *last_flush = Instant::now();
+    true
+}
@@ -0,0 +1,227 @@
+use crate::bridge::{self, ffi, Drivechain};
+use miette::{IntoDiagnostic as _, Result};
+use std::net::SocketAddr;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+mod proto {
+    #![allow(clippy::derive_partial_eq_without_eq)]
+    tonic::include_proto!("drivechain");
+}
+
+use proto::bridge_server::{Bridge, BridgeServer};
+use proto::*;
+
+// Bridge calls block on mainchain RPC and the DB, so requests are served one
+// at a time, the same way the C++ side holds the handle behind its own lock.
+struct BridgeService {
+    drivechain: Mutex<Box<Drivechain>>,
+}
+
+impl BridgeService {
+    fn drivechain(&self) -> MutexGuard<Box<Drivechain>> {
+        self.drivechain
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn status(err: miette::Report) -> Status {
+    Status::internal(err.to_string())
+}
+
+fn to_ffi_outputs(outputs: Vec<Output>) -> Vec<ffi::Output> {
+    outputs
+        .into_iter()
+        .map(|output| ffi::Output {
+            address: output.address,
+            amount: output.amount,
+        })
+        .collect()
+}
+
+#[tonic::async_trait]
+impl Bridge for BridgeService {
+    async fn get_mainchain_tip(
+        &self,
+        _request: Request<GetMainchainTipRequest>,
+    ) -> Result<Response<GetMainchainTipResponse>, Status> {
+        let block_hash = self.drivechain().get_mainchain_tip().map_err(status)?;
+        Ok(Response::new(GetMainchainTipResponse { block_hash }))
+    }
+
+    async fn attempt_bmm(
+        &self,
+        request: Request<AttemptBmmRequest>,
+    ) -> Result<Response<AttemptBmmResponse>, Status> {
+        let request = request.into_inner();
+        self.drivechain()
+            .attempt_bmm(
+                &request.critical_hash,
+                &request.prev_main_block_hash,
+                request.amount,
+            )
+            .map_err(status)?;
+        Ok(Response::new(AttemptBmmResponse {}))
+    }
+
+    async fn confirm_bmm(
+        &self,
+        _request: Request<ConfirmBmmRequest>,
+    ) -> Result<Response<ConfirmBmmResponse>, Status> {
+        let state = match self.drivechain().confirm_bmm().map_err(status)? {
+            ffi::BMMState::Succeded => BmmState::Succeded,
+            ffi::BMMState::Failed => BmmState::Failed,
+            ffi::BMMState::Expired => BmmState::Expired,
+            _ => BmmState::Pending,
+        };
+        Ok(Response::new(ConfirmBmmResponse {
+            state: state.into(),
+        }))
+    }
+
+    async fn verify_bmm(
+        &self,
+        request: Request<VerifyBmmRequest>,
+    ) -> Result<Response<VerifyBmmResponse>, Status> {
+        let request = request.into_inner();
+        let verified = self
+            .drivechain()
+            .verify_bmm(&request.main_block_hash, &request.critical_hash)
+            .map_err(status)?;
+        Ok(Response::new(VerifyBmmResponse { verified }))
+    }
+
+    async fn connect_block(
+        &self,
+        request: Request<ConnectBlockRequest>,
+    ) -> Result<Response<ConnectBlockResponse>, Status> {
+        let request = request.into_inner();
+        let withdrawals = request
+            .withdrawals
+            .into_iter()
+            .map(|w| ffi::Withdrawal {
+                outpoint: w.outpoint,
+                main_address: w.main_address,
+                main_fee: w.main_fee,
+                amount: w.amount,
+            })
+            .collect();
+        let refunds = request
+            .refunds
+            .into_iter()
+            .map(|r| ffi::Refund {
+                outpoint: r.outpoint,
+                amount: r.amount,
+            })
+            .collect();
+        let connected = self
+            .drivechain()
+            .connect_block(
+                to_ffi_outputs(request.deposits),
+                withdrawals,
+                refunds,
+                request.just_check,
+            )
+            .map_err(status)?;
+        Ok(Response::new(ConnectBlockResponse { connected }))
+    }
+
+    async fn disconnect_block(
+        &self,
+        request: Request<DisconnectBlockRequest>,
+    ) -> Result<Response<DisconnectBlockResponse>, Status> {
+        let request = request.into_inner();
+        let disconnected = self
+            .drivechain()
+            .disconnect_block(
+                to_ffi_outputs(request.deposits),
+                request.withdrawals,
+                request.refunds,
+                request.just_check,
+            )
+            .map_err(status)?;
+        Ok(Response::new(DisconnectBlockResponse { disconnected }))
+    }
+
+    async fn get_deposit_outputs(
+        &self,
+        _request: Request<GetDepositOutputsRequest>,
+    ) -> Result<Response<GetDepositOutputsResponse>, Status> {
+        let deposits = self
+            .drivechain()
+            .get_deposit_outputs()
+            .map_err(status)?
+            .into_iter()
+            .map(|output| Output {
+                address: output.address,
+                amount: output.amount,
+            })
+            .collect();
+        Ok(Response::new(GetDepositOutputsResponse { deposits }))
+    }
+
+    async fn is_outpoint_spent(
+        &self,
+        request: Request<IsOutpointSpentRequest>,
+    ) -> Result<Response<IsOutpointSpentResponse>, Status> {
+        let spent = self
+            .drivechain()
+            .is_outpoint_spent(&request.into_inner().outpoint)
+            .map_err(status)?;
+        Ok(Response::new(IsOutpointSpentResponse { spent }))
+    }
+
+    async fn attempt_bundle_broadcast(
+        &self,
+        _request: Request<AttemptBundleBroadcastRequest>,
+    ) -> Result<Response<AttemptBundleBroadcastResponse>, Status> {
+        self.drivechain()
+            .attempt_bundle_broadcast()
+            .map_err(status)?;
+        Ok(Response::new(AttemptBundleBroadcastResponse {}))
+    }
+
+    async fn flush(
+        &self,
+        _request: Request<FlushRequest>,
+    ) -> Result<Response<FlushResponse>, Status> {
+        let flushed = self.drivechain().flush().map_err(status)?;
+        Ok(Response::new(FlushResponse {
+            flushed: flushed as u64,
+        }))
+    }
+}
+
+/// Open the drivechain and serve the bridge API over gRPC on addr until the
+/// server fails.
+pub fn serve(
+    db_path: &str,
+    this_sidechain: usize,
+    main_host: &str,
+    main_port: u16,
+    rpcuser: &str,
+    rpcpassword: &str,
+    addr: SocketAddr,
+) -> Result<()> {
+    let drivechain = bridge::new_drivechain(
+        db_path,
+        this_sidechain,
+        main_host,
+        main_port,
+        rpcuser,
+        rpcpassword,
+    )?;
+    let service = BridgeService {
+        drivechain: Mutex::new(drivechain),
+    };
+    let runtime = tokio::runtime::Runtime::new().into_diagnostic()?;
+    runtime
+        .block_on(
+            Server::builder()
+                .add_service(BridgeServer::new(service))
+                .serve(addr),
+        )
+        .into_diagnostic()
+}
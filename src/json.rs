@@ -0,0 +1,185 @@
+use crate::bridge::ffi;
+use crate::error::Error;
+use miette::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// JSON round-tripping of the shared structs, for C++ JSON-RPC handlers. Field
+// names match the struct fields.
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|err| {
+        Error::InvalidEncoding {
+            message: err.to_string(),
+        }
+        .into()
+    })
+}
+
+fn from_json<T: DeserializeOwned>(json: &str) -> Result<T> {
+    serde_json::from_str(json).map_err(|err| {
+        Error::InvalidEncoding {
+            message: err.to_string(),
+        }
+        .into()
+    })
+}
+
+pub fn config_from_json(json: &str) -> Result<ffi::Config> {
+    // Fields missing from the JSON keep their default_config() values.
+    let mut config = serde_json::to_value(crate::bridge::default_config()).map_err(|err| {
+        Error::InvalidEncoding {
+            message: err.to_string(),
+        }
+    })?;
+    let overrides: serde_json::Map<String, serde_json::Value> = from_json(json)?;
+    if let Some(config) = config.as_object_mut() {
+        config.extend(overrides);
+    }
+    serde_json::from_value(config).map_err(|err| {
+        Error::InvalidEncoding {
+            message: err.to_string(),
+        }
+        .into()
+    })
+}
+
+pub(crate) fn block_to_json(block: &ffi::Block) -> Result<String> {
+    to_json(&ffi::Block {
+        data: crate::bridge::block_data(block)?,
+        compression: ffi::BlockCompression::None,
+        compressed_data: Vec::new(),
+        time: block.time,
+        main_block_hash: block.main_block_hash.clone(),
+    })
+}
+
+pub(crate) fn block_from_json(json: &str) -> Result<ffi::Block> {
+    from_json(json)
+}
+
+pub(crate) fn output_to_json(output: &ffi::Output) -> Result<String> {
+    to_json(output)
+}
+
+pub(crate) fn output_from_json(json: &str) -> Result<ffi::Output> {
+    from_json(json)
+}
+
+pub(crate) fn outputs_to_json(outputs: &[ffi::Output]) -> Result<String> {
+    to_json(outputs)
+}
+
+pub(crate) fn outputs_from_json(json: &str) -> Result<Vec<ffi::Output>> {
+    from_json(json)
+}
+
+pub(crate) fn withdrawal_to_json(withdrawal: &ffi::Withdrawal) -> Result<String> {
+    to_json(withdrawal)
+}
+
+pub(crate) fn withdrawal_from_json(json: &str) -> Result<ffi::Withdrawal> {
+    from_json(json)
+}
+
+pub(crate) fn withdrawals_to_json(withdrawals: &[ffi::Withdrawal]) -> Result<String> {
+    to_json(withdrawals)
+}
+
+pub(crate) fn withdrawals_from_json(json: &str) -> Result<Vec<ffi::Withdrawal>> {
+    from_json(json)
+}
+
+pub(crate) fn refund_to_json(refund: &ffi::Refund) -> Result<String> {
+    to_json(refund)
+}
+
+pub(crate) fn refund_from_json(json: &str) -> Result<ffi::Refund> {
+    from_json(json)
+}
+
+pub(crate) fn refunds_to_json(refunds: &[ffi::Refund]) -> Result<String> {
+    to_json(refunds)
+}
+
+pub(crate) fn refunds_from_json(json: &str) -> Result<Vec<ffi::Refund>> {
+    from_json(json)
+}
+
+pub(crate) fn withdrawal_status_to_json(status: &ffi::WithdrawalStatus) -> Result<String> {
+    to_json(status)
+}
+
+pub(crate) fn vote_status_to_json(status: &ffi::VoteStatus) -> Result<String> {
+    to_json(status)
+}
+
+pub(crate) fn payout_info_to_json(payout: &ffi::PayoutInfo) -> Result<String> {
+    to_json(payout)
+}
+
+pub(crate) fn pending_withdrawals_summary_to_json(
+    summary: &ffi::PendingWithdrawalsSummary,
+) -> Result<String> {
+    to_json(summary)
+}
+
+pub(crate) fn peg_summary_to_json(summary: &ffi::PegSummary) -> Result<String> {
+    to_json(summary)
+}
+
+pub(crate) fn deposit_scan_progress_to_json(progress: &ffi::DepositScanProgress) -> Result<String> {
+    to_json(progress)
+}
+
+pub(crate) fn bmm_commitments_to_json(commitments: &[ffi::BmmCommitment]) -> Result<String> {
+    to_json(commitments)
+}
+
+pub(crate) fn bmm_proof_to_json(proof: &ffi::BmmProof) -> Result<String> {
+    to_json(proof)
+}
+
+pub(crate) fn deposit_proof_to_json(proof: &ffi::DepositProof) -> Result<String> {
+    to_json(proof)
+}
+
+pub(crate) fn broadcast_queue_to_json(entries: &[ffi::BroadcastEntry]) -> Result<String> {
+    to_json(entries)
+}
+
+pub(crate) fn bmm_history_to_json(attempts: &[ffi::BmmAttempt]) -> Result<String> {
+    to_json(attempts)
+}
+
+pub(crate) fn spend_summary_to_json(summary: &ffi::SpendSummary) -> Result<String> {
+    to_json(summary)
+}
+
+pub(crate) fn bmm_template_to_json(template: &ffi::BmmTemplate) -> Result<String> {
+    to_json(template)
+}
+
+pub(crate) fn deposit_events_to_json(events: &[ffi::DepositEvent]) -> Result<String> {
+    to_json(events)
+}
+
+pub(crate) fn alerts_to_json(alerts: &[ffi::Alert]) -> Result<String> {
+    to_json(alerts)
+}
+
+pub(crate) fn audit_log_to_json(entries: &[ffi::AuditEntry]) -> Result<String> {
+    to_json(entries)
+}
+
+pub(crate) fn check_result_to_json(result: &ffi::CheckResult) -> Result<String> {
+    to_json(result)
+}
+
+pub(crate) fn repair_report_to_json(report: &ffi::RepairReport) -> Result<String> {
+    to_json(report)
+}
+
+pub(crate) fn verify_report_to_json(report: &ffi::VerifyReport) -> Result<String> {
+    to_json(report)
+}
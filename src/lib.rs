@@ -1,2 +1,75 @@
 extern crate drivechain;
+mod amount;
+#[cfg(feature = "async-rpc")]
+mod async_client;
+mod audit;
+mod backend;
 mod bridge;
+mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod client;
+mod codec;
+mod convert;
+mod crypt;
+mod db;
+mod deposit_address;
+mod dest;
+mod error;
+mod flush;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod json;
+mod maturity;
+mod merkle;
+#[cfg(feature = "mock")]
+mod mock;
+mod prefetch;
+mod psbt;
+pub mod replay;
+mod scanner;
+mod scheduler;
+mod script;
+mod signer;
+mod snapshot;
+mod spv;
+mod storage;
+mod trace;
+#[cfg(unix)]
+mod unix_socket;
+mod vectors;
+mod votes;
+
+// The same API the C++ side gets through the cxx bridge, for Rust sidechains.
+pub use amount::{amount_from_btc_str, amount_from_sats, amount_to_btc_str, MAX_MONEY};
+pub use bridge::ffi::{
+    AcceptanceResult, ActivationState, ActivationStatus, Alert, AlertKind, AlertPolicy, Amount,
+    AuditEntry, AuditOperation, BMMState, Block, BlockCompression, BlockLimits, BmmAttempt,
+    BmmCommitment, BmmProof, BmmTemplate, BroadcastEntry, BundleOrdering, BundlePolicy,
+    BundleState, BundleVote, BundleVoteKind, CheckFailure, CheckFailureReason, CheckItem,
+    CheckOutcome, CheckResult, Config, DepositAddressFormat, DepositAddressRewrite, DepositEvent,
+    DepositOutput, DepositProof, DepositRecord, DepositScanProgress, DepositVerification,
+    DepositVerificationStatus, DestAddress, DestKind, DustMode, DustPolicy, DustWithdrawal,
+    EscrowInfo, FeeBucket, FlushPolicy, ImmatureDeposit, IndexedDeposit, LibraryInfo,
+    MainBlockChainStatus, MainchainBackendKind, Output, PayoutInfo, PegSummary,
+    PendingWithdrawalsSummary, PrefetchedDeposits, ProposedBundle, Refund, RepairReport,
+    ReplayReport, SchedulerConfig, SchedulerMode, ScriptTemplate, SidechainBlockRef,
+    SidechainProposal, SlotMigrationReport, SpendSummary, StorageBackendKind, TaskKind, TaskRun,
+    VerifyProblem, VerifyReport, VoteOutcome, VoteStatus, Withdrawal, WithdrawalDestination,
+    WithdrawalRejection, WithdrawalStatus,
+};
+#[cfg(feature = "mock")]
+pub use bridge::new_drivechain_with_mock;
+pub use bridge::{
+    block_data, compute_critical_hash, default_config, extract_bmm_commitments,
+    extract_mainchain_address_bytes, format_dest20, format_hash32, format_outpoint,
+    get_library_info, new_drivechain, new_drivechain_from_config, new_drivechain_with_spv,
+    parse_dest20, parse_hash32, parse_outpoint, parse_withdrawal_dest, validate_block_inputs,
+    validate_disconnect_inputs, Drivechain, WithdrawalFilter,
+};
+pub use error::Error;
+pub use json::config_from_json;
+#[cfg(feature = "mock")]
+pub use mock::{MockBackend, MockState};
+pub use snapshot::{ReadHandle, ReadSnapshot};
+pub use trace::{set_trace_level, start_chrome_trace, stop_chrome_trace};
@@ -0,0 +1,119 @@
+use crate::bridge::ffi;
+use crate::db::{self, Db};
+use drivechain as drive;
+use miette::Result;
+
+// The drivechain crate doesn't say which mainchain block a deposit is in, so
+// a deposit's age is counted from the mainchain block count at the time the
+// bridge first saw it pending. A deposit disconnected with its sidechain
+// block is seen again as a new one.
+
+/// A deposit pending in the drivechain DB and when it was first seen.
+pub struct Pending {
+    /// Assigned when first seen, what ack_deposits takes.
+    pub id: u64,
+    pub address: String,
+    pub amount: u64,
+    pub first_seen_height: u64,
+}
+
+impl Pending {
+    pub fn blocks_until_mature(&self, main_height: u64, maturity: u64) -> u64 {
+        (self.first_seen_height + maturity).saturating_sub(main_height)
+    }
+}
+
+/// Match the deposits pending in the drivechain DB against the recorded
+/// ones, recording new deposits as first seen at main_height and forgetting
+/// the ones no longer pending.
+pub fn track(db: &Db, deposits: &[drive::Deposit], main_height: u64) -> Result<Vec<Pending>> {
+    let mut recorded = db.list_pending_deposits()?;
+    let mut pending = Vec::new();
+    for deposit in deposits {
+        // Oldest first, so that of several equal deposits the ones seen
+        // first stay the oldest.
+        let position = recorded.iter().position(|(address, _, record)| {
+            *address == deposit.address && record.amount == deposit.amount
+        });
+        let (id, first_seen_height) = match position {
+            Some(position) => {
+                let (_, id, record) = recorded.remove(position);
+                (id, record.first_seen_height)
+            }
+            None => {
+                let record = db::PendingDepositRecord {
+                    amount: deposit.amount,
+                    first_seen_height: main_height,
+                };
+                let id = db.generate_id()?;
+                db.put_pending_deposit(&deposit.address, id, &record)?;
+                (id, main_height)
+            }
+        };
+        pending.push(Pending {
+            id,
+            address: deposit.address.clone(),
+            amount: deposit.amount,
+            first_seen_height,
+        });
+    }
+    for (address, id, _) in recorded {
+        db.remove_pending_deposit(&address, id)?;
+    }
+    Ok(pending)
+}
+
+/// The first of deposits that isn't mature at main_height, None if they all
+/// are. Deposits that aren't pending are left to connect_block to reject.
+pub fn first_immature<'a>(
+    pending: &[Pending],
+    deposits: &'a [drive::Deposit],
+    main_height: u64,
+    maturity: u64,
+) -> Option<(&'a drive::Deposit, u64)> {
+    let mut unmatched: Vec<&Pending> = pending.iter().collect();
+    for deposit in deposits {
+        let position = unmatched.iter().position(|pending| {
+            pending.address == deposit.address
+                && pending.amount == deposit.amount
+                && pending.blocks_until_mature(main_height, maturity) == 0
+        });
+        match position {
+            Some(position) => {
+                unmatched.swap_remove(position);
+            }
+            None => {
+                let blocks = unmatched
+                    .iter()
+                    .filter(|pending| {
+                        pending.address == deposit.address && pending.amount == deposit.amount
+                    })
+                    .map(|pending| pending.blocks_until_mature(main_height, maturity))
+                    .min();
+                if let Some(blocks) = blocks {
+                    return Some((deposit, blocks));
+                }
+            }
+        }
+    }
+    None
+}
+
+pub fn immature(
+    pending: Vec<Pending>,
+    main_height: u64,
+    maturity: u64,
+) -> Vec<ffi::ImmatureDeposit> {
+    pending
+        .into_iter()
+        .filter_map(|pending| {
+            let blocks_until_mature = pending.blocks_until_mature(main_height, maturity);
+            (blocks_until_mature > 0).then_some(ffi::ImmatureDeposit {
+                address: pending.address,
+                amount: pending.amount,
+                first_seen_height: pending.first_seen_height,
+                blocks_until_mature,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,140 @@
+use bitcoin::hash_types::{TxMerkleNode, Txid};
+use bitcoin::hashes::{sha256d, Hash, HashEngine};
+
+fn hash_pair(left: &sha256d::Hash, right: &sha256d::Hash) -> sha256d::Hash {
+    let mut engine = sha256d::Hash::engine();
+    engine.input(&left[..]);
+    engine.input(&right[..]);
+    sha256d::Hash::from_engine(engine)
+}
+
+/// Sibling hashes from the leaf up proving that txids[index] is committed to
+/// by the merkle root of txids.
+pub fn merkle_branch(txids: &[Txid], index: usize) -> Vec<TxMerkleNode> {
+    let mut level: Vec<sha256d::Hash> = txids.iter().map(|txid| txid.as_hash()).collect();
+    let mut index = index;
+    let mut branch = vec![];
+    while level.len() > 1 {
+        if level.len() % 2 == 1 {
+            let last = level[level.len() - 1];
+            level.push(last);
+        }
+        branch.push(TxMerkleNode::from_hash(level[index ^ 1]));
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+        index /= 2;
+    }
+    branch
+}
+
+/// Verify that txid is committed to by merkle_root, given its position in
+/// the block and the sibling hashes from the leaf up.
+pub fn verify_merkle_branch(
+    txid: &Txid,
+    branch: &[TxMerkleNode],
+    index: u32,
+    merkle_root: &TxMerkleNode,
+) -> bool {
+    let mut hash = txid.as_hash();
+    let mut index = index;
+    for node in branch {
+        hash = if index & 1 == 0 {
+            hash_pair(&hash, &node.as_hash())
+        } else {
+            hash_pair(&node.as_hash(), &hash)
+        };
+        index >>= 1;
+    }
+    TxMerkleNode::from_hash(hash) == *merkle_root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::util::hash::bitcoin_merkle_root;
+    use std::str::FromStr as _;
+
+    // Mainnet block 170, the first to spend a coinbase.
+    const BLOCK_170_TXIDS: [&str; 2] = [
+        "b1fea52486ce0c62bb442b530a3f0132b826c74e473d1f2c220bfa78111c5082",
+        "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16",
+    ];
+    const BLOCK_170_MERKLE_ROOT: &str =
+        "7dac2c5666815c17a3b36427de37bb9d2e2c5ccec3f8633eb91a4205cb4c10ff";
+
+    fn txids(count: u8) -> Vec<Txid> {
+        (0..count)
+            .map(|n| Txid::from_hash(sha256d::Hash::hash(&[n])))
+            .collect()
+    }
+
+    fn merkle_root(txids: &[Txid]) -> TxMerkleNode {
+        bitcoin_merkle_root(
+            txids
+                .iter()
+                .map(|txid| TxMerkleNode::from_hash(txid.as_hash())),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn mainnet_block_branches_verify() {
+        let txids: Vec<_> = BLOCK_170_TXIDS
+            .iter()
+            .map(|txid| Txid::from_str(txid).unwrap())
+            .collect();
+        let merkle_root = TxMerkleNode::from_str(BLOCK_170_MERKLE_ROOT).unwrap();
+        for (index, txid) in txids.iter().enumerate() {
+            let branch = merkle_branch(&txids, index);
+            assert_eq!(branch.len(), 1);
+            assert!(verify_merkle_branch(
+                txid,
+                &branch,
+                index as u32,
+                &merkle_root
+            ));
+        }
+    }
+
+    #[test]
+    fn single_transaction_has_an_empty_branch() {
+        let txids = txids(1);
+        assert!(merkle_branch(&txids, 0).is_empty());
+        assert!(verify_merkle_branch(
+            &txids[0],
+            &[],
+            0,
+            &merkle_root(&txids)
+        ));
+    }
+
+    #[test]
+    fn every_branch_verifies_with_odd_levels() {
+        for count in [3, 5, 7, 12] {
+            let txids = txids(count);
+            let merkle_root = merkle_root(&txids);
+            for (index, txid) in txids.iter().enumerate() {
+                let branch = merkle_branch(&txids, index);
+                assert!(verify_merkle_branch(
+                    txid,
+                    &branch,
+                    index as u32,
+                    &merkle_root
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn branch_doesnt_verify_at_another_position_or_root() {
+        let txids = txids(5);
+        let merkle_root = merkle_root(&txids);
+        let branch = merkle_branch(&txids, 2);
+        assert!(!verify_merkle_branch(&txids[2], &branch, 3, &merkle_root));
+        assert!(!verify_merkle_branch(&txids[1], &branch, 2, &merkle_root));
+        let other_root = self::merkle_root(&txids[..4]);
+        assert!(!verify_merkle_branch(&txids[2], &branch, 2, &other_root));
+    }
+}
@@ -0,0 +1,343 @@
+use crate::backend::{Backend, MainchainBackend, Refunds, Withdrawals};
+use crate::bridge::ffi::MainBlockChainStatus;
+use crate::db::BmmOutcome;
+use crate::error::Error;
+use bitcoin::hash_types::{BlockHash, PubkeyHash, TxMerkleNode, Txid};
+use bitcoin::hashes::Hash as _;
+use bitcoin::util::address::{Address, Payload};
+use bitcoin::{Amount, Network};
+use drivechain as drive;
+use miette::Result;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Sidechain and mainchain state kept by MockBackend.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MockState {
+    /// Deposits made on the mainchain and not connected yet, with the number
+    /// of identical ones.
+    pub pending_deposits: BTreeMap<(String, u64), u32>,
+    pub connected_deposits: BTreeMap<(String, u64), u32>,
+    /// Unspent withdrawals by outpoint, worth amount plus mainchain fee.
+    pub withdrawals: BTreeMap<Vec<u8>, u64>,
+    pub refunded: BTreeMap<Vec<u8>, u64>,
+    /// Connected deposits minus withdrawals that weren't refunded.
+    pub escrow: u64,
+    pub block_count: u64,
+    pub bmm_pending: bool,
+    /// Number of deposits and addresses created, for unique txids.
+    pub created: u64,
+}
+
+/// An in-memory stand-in for the drivechain crate and mainchain node. Clones
+/// share state, so a harness can keep one to inspect what the bridge did.
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    state: Arc<Mutex<MockState>>,
+    this_sidechain: usize,
+    unflushed: usize,
+}
+
+fn rejected(message: String) -> miette::Report {
+    Error::MockRejected { message }.into()
+}
+
+fn take(counts: &mut BTreeMap<(String, u64), u32>, key: &(String, u64)) -> bool {
+    match counts.get_mut(key) {
+        Some(count) if *count > 1 => *count -= 1,
+        Some(_) => {
+            counts.remove(key);
+        }
+        None => return false,
+    }
+    true
+}
+
+fn put(counts: &mut BTreeMap<(String, u64), u32>, key: (String, u64)) {
+    *counts.entry(key).or_default() += 1;
+}
+
+// Mock block hashes are the height, little endian.
+fn block_hash(height: u64) -> BlockHash {
+    let mut bytes = [0; 32];
+    bytes[..8].copy_from_slice(&height.to_le_bytes());
+    BlockHash::from_inner(bytes)
+}
+
+fn block_height(block_hash: &BlockHash) -> Option<u64> {
+    let bytes = block_hash.into_inner();
+    if bytes[8..].iter().any(|byte| *byte != 0) {
+        return None;
+    }
+    Some(u64::from_le_bytes(bytes[..8].try_into().ok()?))
+}
+
+impl MockBackend {
+    pub fn new(this_sidechain: usize) -> Self {
+        Self {
+            this_sidechain,
+            ..Self::default()
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<MockState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn state(&self) -> MockState {
+        self.lock().clone()
+    }
+
+    /// Make a mainchain deposit that the next block can connect.
+    pub fn deposit(&self, address: &str, amount: u64) {
+        put(&mut self.lock().pending_deposits, (address.into(), amount));
+    }
+
+    fn commit(&mut self, state: MockState, just_check: bool) -> Result<()> {
+        if !just_check {
+            *self.lock() = state;
+            self.unflushed += 1;
+        }
+        Ok(())
+    }
+}
+
+impl MainchainBackend for MockBackend {
+    fn get_mainchain_tip(&self) -> Result<BlockHash> {
+        Ok(block_hash(self.lock().block_count))
+    }
+
+    fn get_prev_main_block_hash(&self, main_block_hash: &BlockHash) -> Result<BlockHash> {
+        match block_height(main_block_hash) {
+            Some(height) if height <= self.lock().block_count => {
+                Ok(block_hash(height.saturating_sub(1)))
+            }
+            _ => Err(Error::UnknownBlock {
+                block_hash: main_block_hash.to_string(),
+            }
+            .into()),
+        }
+    }
+
+    fn get_block_count(&self) -> Result<u64> {
+        Ok(self.lock().block_count)
+    }
+
+    // The mock mainchain never reorgs, so nothing is on a stale branch.
+    fn get_main_block_chain_status(
+        &self,
+        main_block_hash: &BlockHash,
+    ) -> Result<MainBlockChainStatus> {
+        Ok(
+            match block_height(main_block_hash)
+                .is_some_and(|height| height <= self.lock().block_count)
+            {
+                true => MainBlockChainStatus::ActiveChain,
+                false => MainBlockChainStatus::Unknown,
+            },
+        )
+    }
+}
+
+impl Backend for MockBackend {
+    fn confirm_bmm(&mut self) -> Result<BmmOutcome> {
+        let mut state = self.lock();
+        if !state.bmm_pending {
+            return Ok(BmmOutcome::Pending);
+        }
+        state.bmm_pending = false;
+        state.block_count += 1;
+        Ok(BmmOutcome::Succeded)
+    }
+
+    fn attempt_bmm(
+        &mut self,
+        _critical_hash: &TxMerkleNode,
+        _prev_main_block_hash: &BlockHash,
+        _amount: Amount,
+    ) -> Result<()> {
+        self.lock().bmm_pending = true;
+        Ok(())
+    }
+
+    fn verify_bmm(&self, main_block_hash: &BlockHash, _critical_hash: &TxMerkleNode) -> Result<()> {
+        match self.is_main_block_connected(main_block_hash)? {
+            true => Ok(()),
+            false => Err(rejected(format!(
+                "block {main_block_hash} is not connected"
+            ))),
+        }
+    }
+
+    fn get_deposit_outputs(&self) -> Result<Vec<drive::Deposit>> {
+        Ok(self
+            .lock()
+            .pending_deposits
+            .iter()
+            .flat_map(|((address, amount), count)| {
+                (0..*count).map(|_| drive::Deposit {
+                    address: address.clone(),
+                    amount: *amount,
+                })
+            })
+            .collect())
+    }
+
+    fn is_outpoint_spent(&self, outpoint: &[u8]) -> Result<bool> {
+        Ok(self.lock().refunded.contains_key(outpoint))
+    }
+
+    fn attempt_bundle_broadcast(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn connect_block(
+        &mut self,
+        deposits: &[drive::Deposit],
+        withdrawals: &Withdrawals,
+        refunds: &Refunds,
+        just_check: bool,
+    ) -> Result<()> {
+        let mut state = self.state();
+        for deposit in deposits {
+            let key = (deposit.address.clone(), deposit.amount);
+            if !take(&mut state.pending_deposits, &key) {
+                return Err(rejected(format!(
+                    "deposit of {} to {} is not on the mainchain",
+                    deposit.amount, deposit.address
+                )));
+            }
+            state.escrow = state
+                .escrow
+                .checked_add(deposit.amount)
+                .ok_or(Error::AmountOverflow { what: "escrow" })?;
+            put(&mut state.connected_deposits, key);
+        }
+        for (outpoint, withdrawal) in withdrawals {
+            if state.withdrawals.contains_key(outpoint) || state.refunded.contains_key(outpoint) {
+                return Err(rejected(format!(
+                    "withdrawal {} is already connected",
+                    hex::encode(outpoint)
+                )));
+            }
+            let value = withdrawal
+                .amount
+                .checked_add(withdrawal.mainchain_fee)
+                .ok_or(Error::AmountOverflow { what: "withdrawal" })?;
+            state.escrow = state.escrow.checked_sub(value).ok_or_else(|| {
+                rejected(format!(
+                    "withdrawal {} exceeds the escrow",
+                    hex::encode(outpoint)
+                ))
+            })?;
+            state.withdrawals.insert(outpoint.clone(), value);
+        }
+        for (outpoint, amount) in refunds {
+            match state.withdrawals.remove(outpoint) {
+                Some(value) if value == *amount => {
+                    state.escrow = state
+                        .escrow
+                        .checked_add(value)
+                        .ok_or(Error::AmountOverflow { what: "escrow" })?;
+                    state.refunded.insert(outpoint.clone(), value);
+                }
+                _ => {
+                    return Err(rejected(format!(
+                        "refund of {amount} for {} matches no unspent withdrawal",
+                        hex::encode(outpoint)
+                    )))
+                }
+            }
+        }
+        self.commit(state, just_check)
+    }
+
+    fn disconnect_block(
+        &mut self,
+        deposits: &[drive::Deposit],
+        withdrawals: &[Vec<u8>],
+        refunds: &[Vec<u8>],
+        just_check: bool,
+    ) -> Result<()> {
+        let mut state = self.state();
+        for outpoint in refunds {
+            let value = state.refunded.remove(outpoint).ok_or_else(|| {
+                rejected(format!("refund {} is not connected", hex::encode(outpoint)))
+            })?;
+            state.escrow = state.escrow.checked_sub(value).ok_or_else(|| {
+                rejected(format!(
+                    "undoing refund {} exceeds the escrow",
+                    hex::encode(outpoint)
+                ))
+            })?;
+            state.withdrawals.insert(outpoint.clone(), value);
+        }
+        for outpoint in withdrawals {
+            let value = state.withdrawals.remove(outpoint).ok_or_else(|| {
+                rejected(format!(
+                    "withdrawal {} is not connected",
+                    hex::encode(outpoint)
+                ))
+            })?;
+            state.escrow = state
+                .escrow
+                .checked_add(value)
+                .ok_or(Error::AmountOverflow { what: "escrow" })?;
+        }
+        for deposit in deposits {
+            let key = (deposit.address.clone(), deposit.amount);
+            if !take(&mut state.connected_deposits, &key) {
+                return Err(rejected(format!(
+                    "deposit of {} to {} is not connected",
+                    deposit.amount, deposit.address
+                )));
+            }
+            state.escrow = state.escrow.checked_sub(deposit.amount).ok_or_else(|| {
+                rejected(format!(
+                    "undoing deposit to {} exceeds the escrow",
+                    deposit.address
+                ))
+            })?;
+            put(&mut state.pending_deposits, key);
+        }
+        self.commit(state, just_check)
+    }
+
+    fn format_deposit_address(&self, address: &str) -> String {
+        format!("s{}_{address}_", self.this_sidechain)
+    }
+
+    fn get_new_mainchain_address(&self) -> Result<Address> {
+        let mut state = self.lock();
+        state.created += 1;
+        let mut bytes = [0; 20];
+        bytes[..8].copy_from_slice(&state.created.to_le_bytes());
+        Ok(Address {
+            payload: Payload::PubkeyHash(PubkeyHash::from_inner(bytes)),
+            network: Network::Regtest,
+        })
+    }
+
+    fn create_deposit(&self, address: &str, amount: Amount, _fee: Amount) -> Result<Txid> {
+        let mut state = self.lock();
+        state.created += 1;
+        put(
+            &mut state.pending_deposits,
+            (address.into(), amount.to_sat()),
+        );
+        let mut bytes = [0; 32];
+        bytes[..8].copy_from_slice(&state.created.to_le_bytes());
+        Ok(Txid::from_inner(bytes))
+    }
+
+    fn generate(&self, n: usize) -> Result<Vec<BlockHash>> {
+        let mut state = self.lock();
+        let start = state.block_count + 1;
+        state.block_count += n as u64;
+        Ok((start..=state.block_count).map(block_hash).collect())
+    }
+
+    fn flush(&mut self) -> Result<usize> {
+        Ok(std::mem::take(&mut self.unflushed))
+    }
+}
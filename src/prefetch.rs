@@ -0,0 +1,51 @@
+use crate::backend::Backend;
+use crate::bridge::unix_time;
+use crate::client::MainClient;
+use bitcoin::hash_types::BlockHash;
+use drivechain as drive;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Deposit outputs as of a mainchain tip.
+pub struct Prefetched {
+    pub deposits: Vec<drive::Deposit>,
+    pub main_block_hash: BlockHash,
+    /// Unix time.
+    pub fetched_at: u64,
+}
+
+/// Emptied by the bridge whenever a block is connected or disconnected,
+/// which changes the deposits the drivechain crate reports.
+pub type DepositCache = Arc<Mutex<Option<Prefetched>>>;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Refill cache if it's empty or the mainchain tip moved. Returns whether
+/// it was refilled, a failing fetch is retried when next due.
+pub fn refill(inner: &Mutex<Box<dyn Backend>>, client: &MainClient, cache: &DepositCache) -> bool {
+    // Read before the deposits, so a tip that moves in between gets the
+    // deposits fetched again next time.
+    let Ok(main_block_hash) = client.get_best_block_hash() else {
+        return false;
+    };
+    if lock(cache)
+        .as_ref()
+        .is_some_and(|prefetched| prefetched.main_block_hash == main_block_hash)
+    {
+        return false;
+    }
+    let _span = tracing::debug_span!("prefetch_deposits").entered();
+    // inner stays locked until the cache is filled, so a block connected
+    // meanwhile empties the cache after this and not before.
+    let inner = lock(inner);
+    let Ok(deposits) = inner.get_deposit_outputs() else {
+        return false;
+    };
+    *lock(cache) = Some(Prefetched {
+        deposits,
+        main_block_hash,
+        fetched_at: unix_time(),
+    });
+    true
+}
@@ -0,0 +1,444 @@
+use crate::amount;
+use crate::error::Error;
+use bitcoin::blockdata::opcodes::all::OP_RETURN;
+use bitcoin::blockdata::script::{Builder, Script};
+use bitcoin::util::psbt::raw::ProprietaryKey;
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::{OutPoint, PackedLockTime, Sequence, Transaction, TxIn, TxOut, Witness};
+
+// Proprietary PSBT field recording the fee requested in create_deposit_psbt,
+// or the bid of create_bmm_psbt, checked again before broadcast.
+const PROPRIETARY_PREFIX: &[u8] = b"drivechain";
+const FEE_SUBTYPE: u8 = 0;
+
+fn fee_key() -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: PROPRIETARY_PREFIX.to_vec(),
+        subtype: FEE_SUBTYPE,
+        key: Vec::new(),
+    }
+}
+
+/// An unfunded deposit transaction moving the current CTIP to a new one worth
+/// amount more, with strdest in an OP_RETURN output. The signer's wallet adds
+/// inputs for amount plus fee and a change output. The CTIP input needs no
+/// signature and is finalized already.
+pub fn deposit_psbt(
+    ctip: OutPoint,
+    ctip_output: TxOut,
+    escrow_script: Script,
+    strdest: &str,
+    amount: u64,
+    fee: u64,
+) -> Result<PartiallySignedTransaction, Error> {
+    let value = ctip_output
+        .value
+        .checked_add(amount)
+        .ok_or(Error::AmountOverflow { what: "deposit" })?;
+    let tx = Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: ctip,
+            script_sig: Script::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::default(),
+        }],
+        output: vec![
+            TxOut {
+                value,
+                script_pubkey: escrow_script,
+            },
+            TxOut {
+                value: 0,
+                script_pubkey: Builder::new()
+                    .push_opcode(OP_RETURN)
+                    .push_slice(strdest.as_bytes())
+                    .into_script(),
+            },
+        ],
+    };
+    let mut psbt =
+        PartiallySignedTransaction::from_unsigned_tx(tx).map_err(|err| Error::InvalidEncoding {
+            message: err.to_string(),
+        })?;
+    psbt.inputs[0].witness_utxo = Some(ctip_output);
+    psbt.inputs[0].final_script_sig = Some(Script::new());
+    psbt.proprietary
+        .insert(fee_key(), fee.to_le_bytes().to_vec());
+    Ok(psbt)
+}
+
+/// An unfunded BMM request paying bid to mainchain miners as its fee. The
+/// signer adds inputs for the bid and a change output.
+pub fn bmm_psbt(request_script: Script, bid: u64) -> Result<PartiallySignedTransaction, Error> {
+    let tx = Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: Vec::new(),
+        output: vec![TxOut {
+            value: 0,
+            script_pubkey: request_script,
+        }],
+    };
+    let mut psbt =
+        PartiallySignedTransaction::from_unsigned_tx(tx).map_err(|err| Error::InvalidEncoding {
+            message: err.to_string(),
+        })?;
+    psbt.proprietary
+        .insert(fee_key(), bid.to_le_bytes().to_vec());
+    Ok(psbt)
+}
+
+/// A bundle spending the CTIP to payouts, with the rest of the escrow in a
+/// new CTIP at output 0 and fee left to mainchain miners. Signing is left
+/// to whoever co-signs bundles.
+pub fn unsigned_bundle(
+    ctip: OutPoint,
+    ctip_output: TxOut,
+    escrow_script: Script,
+    payouts: Vec<TxOut>,
+    fee: u64,
+) -> Result<Transaction, Error> {
+    let total = amount::checked_total(
+        "bundle",
+        payouts.iter().map(|o| o.value).chain(std::iter::once(fee)),
+    )?;
+    let value = ctip_output
+        .value
+        .checked_sub(total)
+        .ok_or(Error::BundleExceedsEscrow {
+            total,
+            escrow: ctip_output.value,
+        })?;
+    let mut output = vec![TxOut {
+        value,
+        script_pubkey: escrow_script,
+    }];
+    output.extend(payouts);
+    Ok(Transaction {
+        version: 2,
+        lock_time: PackedLockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: ctip,
+            script_sig: Script::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::default(),
+        }],
+        output,
+    })
+}
+
+/// Whether signed is unsigned with only signatures added.
+pub fn is_signed_bundle(unsigned: &Transaction, signed: &Transaction) -> bool {
+    unsigned.version == signed.version
+        && unsigned.lock_time == signed.lock_time
+        && unsigned.output == signed.output
+        && unsigned.input.len() == signed.input.len()
+        && unsigned
+            .input
+            .iter()
+            .zip(&signed.input)
+            .all(|(a, b)| a.previous_output == b.previous_output && a.sequence == b.sequence)
+}
+
+/// The fee or bid psbt was created with, None for PSBTs made elsewhere.
+pub fn requested_fee(psbt: &PartiallySignedTransaction) -> Option<u64> {
+    psbt.proprietary
+        .get(&fee_key())
+        .and_then(|fee| <[u8; 8]>::try_from(fee.as_slice()).ok())
+        .map(u64::from_le_bytes)
+}
+
+/// Extract the signed deposit or BMM request, checking every input is
+/// finalized and, where the input values are known, that it pays the
+/// requested fee.
+pub fn finalized(psbt: PartiallySignedTransaction) -> Result<Transaction, Error> {
+    let unsigned = psbt
+        .inputs
+        .iter()
+        .filter(|input| input.final_script_sig.is_none() && input.final_script_witness.is_none())
+        .count();
+    if unsigned > 0 {
+        return Err(Error::PsbtNotFinalized { unsigned });
+    }
+    let required_fee = requested_fee(&psbt);
+    let input_values: Option<Vec<u64>> = psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .zip(&psbt.inputs)
+        .map(
+            |(txin, input)| match (&input.witness_utxo, &input.non_witness_utxo) {
+                (Some(utxo), _) => Some(utxo.value),
+                (None, Some(tx)) => tx
+                    .output
+                    .get(txin.previous_output.vout as usize)
+                    .map(|output| output.value),
+                (None, None) => None,
+            },
+        )
+        .collect();
+    if let (Some(required_fee), Some(input_values)) = (required_fee, input_values) {
+        let input_value = amount::checked_total("psbt input", input_values)?;
+        let output_value = amount::checked_total(
+            "psbt output",
+            psbt.unsigned_tx.output.iter().map(|o| o.value),
+        )?;
+        let fee = input_value.saturating_sub(output_value);
+        if input_value < output_value || fee < required_fee {
+            return Err(Error::InsufficientFee { fee, required_fee });
+        }
+    }
+    Ok(psbt.extract_tx())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash as _;
+    use bitcoin::Txid;
+
+    const STRDEST: &str = "s0_1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+    fn outpoint(n: u8) -> OutPoint {
+        OutPoint::new(Txid::from_inner([n; 32]), 0)
+    }
+
+    fn escrow_script() -> Script {
+        Builder::new().push_int(1).into_script()
+    }
+
+    fn ctip_output(value: u64) -> TxOut {
+        TxOut {
+            value,
+            script_pubkey: escrow_script(),
+        }
+    }
+
+    // psbt with an input of value added and finalized by the signer, and a
+    // change output of change.
+    fn fund(psbt: &mut PartiallySignedTransaction, value: u64, change: u64) {
+        psbt.unsigned_tx.input.push(TxIn {
+            previous_output: outpoint(2),
+            ..Default::default()
+        });
+        psbt.unsigned_tx.output.push(TxOut {
+            value: change,
+            script_pubkey: Script::new(),
+        });
+        psbt.inputs.push(Default::default());
+        psbt.outputs.push(Default::default());
+        let input = psbt.inputs.last_mut().unwrap();
+        input.witness_utxo = Some(TxOut {
+            value,
+            script_pubkey: Script::new(),
+        });
+        input.final_script_witness = Some(Witness::from_vec(vec![vec![1]]));
+    }
+
+    #[test]
+    fn deposit_moves_the_ctip_to_a_new_one() {
+        let psbt = deposit_psbt(
+            outpoint(1),
+            ctip_output(5_000),
+            escrow_script(),
+            STRDEST,
+            1_000,
+            100,
+        )
+        .unwrap();
+        let tx = &psbt.unsigned_tx;
+        assert_eq!(tx.input[0].previous_output, outpoint(1));
+        assert_eq!(tx.output[0], ctip_output(6_000));
+        assert_eq!(tx.output[1].value, 0);
+        assert!(tx.output[1].script_pubkey.is_op_return());
+        assert!(tx.output[1]
+            .script_pubkey
+            .as_bytes()
+            .ends_with(STRDEST.as_bytes()));
+        assert_eq!(psbt.inputs[0].final_script_sig, Some(Script::new()));
+        assert_eq!(requested_fee(&psbt), Some(100));
+    }
+
+    #[test]
+    fn deposit_overflowing_the_ctip_is_rejected() {
+        assert!(matches!(
+            deposit_psbt(
+                outpoint(1),
+                ctip_output(u64::MAX),
+                escrow_script(),
+                STRDEST,
+                1,
+                0
+            ),
+            Err(Error::AmountOverflow { what: "deposit" })
+        ));
+    }
+
+    #[test]
+    fn unfunded_deposit_is_not_finalized() {
+        let mut psbt = deposit_psbt(
+            outpoint(1),
+            ctip_output(5_000),
+            escrow_script(),
+            STRDEST,
+            1_000,
+            100,
+        )
+        .unwrap();
+        fund(&mut psbt, 1_500, 400);
+        psbt.inputs[1].final_script_witness = None;
+        assert!(matches!(
+            finalized(psbt),
+            Err(Error::PsbtNotFinalized { unsigned: 1 })
+        ));
+    }
+
+    #[test]
+    fn deposit_paying_the_requested_fee_is_finalized() {
+        let mut psbt = deposit_psbt(
+            outpoint(1),
+            ctip_output(5_000),
+            escrow_script(),
+            STRDEST,
+            1_000,
+            100,
+        )
+        .unwrap();
+        fund(&mut psbt, 1_500, 400);
+        let tx = finalized(psbt).unwrap();
+        assert_eq!(tx.input.len(), 2);
+        assert_eq!(tx.output[0], ctip_output(6_000));
+    }
+
+    #[test]
+    fn deposit_paying_less_than_the_requested_fee_is_rejected() {
+        let mut psbt = deposit_psbt(
+            outpoint(1),
+            ctip_output(5_000),
+            escrow_script(),
+            STRDEST,
+            1_000,
+            100,
+        )
+        .unwrap();
+        fund(&mut psbt, 1_500, 450);
+        assert!(matches!(
+            finalized(psbt),
+            Err(Error::InsufficientFee {
+                fee: 50,
+                required_fee: 100
+            })
+        ));
+    }
+
+    #[test]
+    fn fee_isnt_checked_without_input_values() {
+        let mut psbt = deposit_psbt(
+            outpoint(1),
+            ctip_output(5_000),
+            escrow_script(),
+            STRDEST,
+            1_000,
+            100,
+        )
+        .unwrap();
+        fund(&mut psbt, 1_500, 450);
+        psbt.inputs[1].witness_utxo = None;
+        assert!(finalized(psbt).is_ok());
+    }
+
+    #[test]
+    fn bmm_request_pays_its_bid_as_fee() {
+        let request_script = Builder::new().push_opcode(OP_RETURN).into_script();
+        let mut psbt = bmm_psbt(request_script.clone(), 300).unwrap();
+        assert!(psbt.unsigned_tx.input.is_empty());
+        assert_eq!(psbt.unsigned_tx.output[0].script_pubkey, request_script);
+        assert_eq!(requested_fee(&psbt), Some(300));
+        fund(&mut psbt, 1_000, 800);
+        assert!(matches!(
+            finalized(psbt.clone()),
+            Err(Error::InsufficientFee {
+                fee: 200,
+                required_fee: 300
+            })
+        ));
+        psbt.unsigned_tx.output[1].value = 700;
+        assert!(finalized(psbt).is_ok());
+    }
+
+    #[test]
+    fn psbt_made_elsewhere_has_no_requested_fee() {
+        let mut psbt = bmm_psbt(Script::new(), 300).unwrap();
+        psbt.proprietary.clear();
+        assert_eq!(requested_fee(&psbt), None);
+        psbt.proprietary.insert(fee_key(), vec![1, 2, 3]);
+        assert_eq!(requested_fee(&psbt), None);
+    }
+
+    fn payout(value: u64) -> TxOut {
+        TxOut {
+            value,
+            script_pubkey: Script::new(),
+        }
+    }
+
+    #[test]
+    fn bundle_keeps_the_rest_of_the_escrow_in_the_new_ctip() {
+        let payouts = vec![payout(1_000), payout(2_000)];
+        let bundle = unsigned_bundle(
+            outpoint(1),
+            ctip_output(10_000),
+            escrow_script(),
+            payouts.clone(),
+            500,
+        )
+        .unwrap();
+        assert_eq!(bundle.input[0].previous_output, outpoint(1));
+        assert_eq!(bundle.output[0], ctip_output(6_500));
+        assert_eq!(bundle.output[1..], payouts[..]);
+    }
+
+    #[test]
+    fn bundle_exceeding_the_escrow_is_rejected() {
+        assert!(matches!(
+            unsigned_bundle(
+                outpoint(1),
+                ctip_output(3_000),
+                escrow_script(),
+                vec![payout(2_000), payout(1_000)],
+                1,
+            ),
+            Err(Error::BundleExceedsEscrow {
+                total: 3_001,
+                escrow: 3_000
+            })
+        ));
+    }
+
+    #[test]
+    fn only_signatures_may_be_added_to_a_bundle() {
+        let unsigned = unsigned_bundle(
+            outpoint(1),
+            ctip_output(10_000),
+            escrow_script(),
+            vec![payout(1_000)],
+            500,
+        )
+        .unwrap();
+        let mut signed = unsigned.clone();
+        signed.input[0].witness = Witness::from_vec(vec![vec![1], vec![2]]);
+        assert!(is_signed_bundle(&unsigned, &signed));
+
+        let mut changed = signed.clone();
+        changed.output[1].value = 1_001;
+        assert!(!is_signed_bundle(&unsigned, &changed));
+        let mut changed = signed.clone();
+        changed.input[0].previous_output = outpoint(2);
+        assert!(!is_signed_bundle(&unsigned, &changed));
+        let mut changed = signed;
+        changed.input.push(TxIn::default());
+        assert!(!is_signed_bundle(&unsigned, &changed));
+    }
+}
@@ -0,0 +1,154 @@
+use crate::bridge::{ffi, Drivechain};
+use crate::error::Error;
+use miette::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead as _, BufReader, Write as _};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A bridge call that changes the drivechain state. Calls made for a
+/// sidechain block are recorded as the connect_block or disconnect_block
+/// they make.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "call", rename_all = "snake_case")]
+pub enum Call {
+    ConnectBlock {
+        /// None for connect_block, whose withdrawals get their height when
+        /// it's first needed.
+        main_height: Option<u64>,
+        deposits: Vec<ffi::Output>,
+        withdrawals: Vec<ffi::Withdrawal>,
+        refunds: Vec<ffi::Refund>,
+        just_check: bool,
+    },
+    DisconnectBlock {
+        deposits: Vec<ffi::Output>,
+        withdrawals: Vec<String>,
+        refunds: Vec<String>,
+        just_check: bool,
+    },
+}
+
+/// One line of a replay file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// Unix time in milliseconds.
+    pub at: u64,
+    pub elapsed_us: u64,
+    #[serde(flatten)]
+    pub call: Call,
+    /// What the call returned, or its error message.
+    pub result: std::result::Result<bool, String>,
+}
+
+/// Appends recorded calls to a replay file, one JSON entry per line.
+pub struct Recorder(Mutex<File>);
+
+fn replay_error(path: &str, err: impl ToString) -> Error {
+    Error::Replay {
+        message: format!("{path}: {}", err.to_string()),
+    }
+}
+
+impl Recorder {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| replay_error(path, err))?;
+        Ok(Self(Mutex::new(file)))
+    }
+
+    // A failed write is only logged, recording must not break the node.
+    pub fn record(&self, call: Call, elapsed: Duration, result: &Result<bool>) {
+        let entry = Entry {
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_millis() as u64)
+                .unwrap_or_default(),
+            elapsed_us: elapsed.as_micros() as u64,
+            call,
+            result: result.as_ref().copied().map_err(|err| err.to_string()),
+        };
+        let mut file = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let written = serde_json::to_string(&entry)
+            .map_err(std::io::Error::from)
+            .and_then(|line| writeln!(file, "{line}"));
+        if let Err(err) = written {
+            tracing::warn!("failed to record bridge call: {err}");
+        }
+    }
+}
+
+pub fn read_entries(path: &str) -> Result<Vec<Entry>> {
+    let file = File::open(path).map_err(|err| replay_error(path, err))?;
+    BufReader::new(file)
+        .lines()
+        .map(|line| {
+            let line = line.map_err(|err| replay_error(path, err))?;
+            Ok(serde_json::from_str(&line).map_err(|err| replay_error(path, err))?)
+        })
+        .collect()
+}
+
+/// Re-execute the calls recorded in the replay file at path, in order,
+/// calling before with each call first. A call whose result differs from
+/// the recorded one is reported but doesn't stop the replay.
+pub fn replay(
+    drivechain: &mut Drivechain,
+    path: &str,
+    mut before: impl FnMut(&Call),
+) -> Result<ffi::ReplayReport> {
+    let mut report = ffi::ReplayReport {
+        calls: 0,
+        mismatches: 0,
+        first_mismatch: String::new(),
+    };
+    for (index, entry) in read_entries(path)?.into_iter().enumerate() {
+        before(&entry.call);
+        let result = match entry.call {
+            Call::ConnectBlock {
+                main_height,
+                deposits,
+                withdrawals,
+                refunds,
+                just_check,
+            } => match main_height {
+                Some(main_height) => drivechain.connect_block_at_height(
+                    main_height,
+                    deposits,
+                    withdrawals,
+                    refunds,
+                    just_check,
+                ),
+                None => drivechain.connect_block(deposits, withdrawals, refunds, just_check),
+            },
+            Call::DisconnectBlock {
+                deposits,
+                withdrawals,
+                refunds,
+                just_check,
+            } => drivechain.disconnect_block(deposits, withdrawals, refunds, just_check),
+        };
+        report.calls += 1;
+        let result = result.map_err(|err| err.to_string());
+        // Only whether a call failed is compared, not the error message.
+        let matches = match (&entry.result, &result) {
+            (Ok(recorded), Ok(replayed)) => recorded == replayed,
+            (Err(_), Err(_)) => true,
+            _ => false,
+        };
+        if !matches {
+            report.mismatches += 1;
+            if report.first_mismatch.is_empty() {
+                report.first_mismatch = format!(
+                    "call {index}: recorded {:?}, replayed {:?}",
+                    entry.result, result
+                );
+            }
+        }
+    }
+    Ok(report)
+}
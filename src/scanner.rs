@@ -0,0 +1,134 @@
+use crate::client::MainClient;
+use crate::db::{Db, MainDepositRecord};
+use crate::error::Error;
+use bitcoin::consensus::deserialize;
+use bitcoin::Transaction;
+use miette::{IntoDiagnostic as _, Result};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// Number of worker threads scanning block ranges concurrently.
+const WORKERS: usize = 4;
+/// Number of mainchain blocks requested from the node in one call.
+const RANGE_SIZE: u64 = 500;
+
+#[derive(Deserialize)]
+struct ListedDeposit {
+    hashblock: String,
+    nburnindex: u32,
+    ntx: u32,
+    strdest: String,
+    txhex: String,
+}
+
+#[derive(Default)]
+pub struct ScanProgress {
+    pub scanned_height: AtomicU64,
+    pub target_height: AtomicU64,
+    pub failed_ranges: AtomicU64,
+    pub running: AtomicBool,
+}
+
+pub struct DepositScanner {
+    client: MainClient,
+    db: Db,
+    this_sidechain: usize,
+    progress: Arc<ScanProgress>,
+}
+
+impl DepositScanner {
+    pub fn new(client: MainClient, db: Db, this_sidechain: usize) -> Self {
+        Self {
+            client,
+            db,
+            this_sidechain,
+            progress: Arc::new(ScanProgress::default()),
+        }
+    }
+
+    pub fn progress(&self) -> &ScanProgress {
+        &self.progress
+    }
+
+    /// Scan mainchain blocks from start_height up to the current tip in the
+    /// background, recording every deposit to this sidechain in the bridge DB.
+    pub fn start(&self, start_height: u64) -> Result<(), Error> {
+        if self.progress.running.swap(true, Ordering::SeqCst) {
+            return Err(Error::ScanInProgress);
+        }
+        let target_height = match self.client.get_block_count() {
+            Ok(height) => height,
+            Err(err) => {
+                self.progress.running.store(false, Ordering::SeqCst);
+                return Err(err);
+            }
+        };
+        self.progress
+            .scanned_height
+            .store(start_height, Ordering::SeqCst);
+        self.progress
+            .target_height
+            .store(target_height, Ordering::SeqCst);
+        self.progress.failed_ranges.store(0, Ordering::SeqCst);
+
+        let client = self.client.clone();
+        let db = self.db.clone();
+        let this_sidechain = self.this_sidechain;
+        let progress = self.progress.clone();
+        let next_height = AtomicU64::new(start_height);
+        thread::spawn(move || {
+            thread::scope(|scope| {
+                for _ in 0..WORKERS {
+                    scope.spawn(|| loop {
+                        let start = next_height.fetch_add(RANGE_SIZE, Ordering::SeqCst);
+                        if start > target_height {
+                            break;
+                        }
+                        let end = (start + RANGE_SIZE - 1).min(target_height);
+                        if scan_range(&client, &db, this_sidechain, start, end).is_err() {
+                            progress.failed_ranges.fetch_add(1, Ordering::SeqCst);
+                        }
+                        progress
+                            .scanned_height
+                            .fetch_add(end - start + 1, Ordering::SeqCst);
+                    });
+                }
+            });
+            progress.running.store(false, Ordering::SeqCst);
+        });
+        Ok(())
+    }
+}
+
+fn scan_range(
+    client: &MainClient,
+    db: &Db,
+    this_sidechain: usize,
+    start: u64,
+    end: u64,
+) -> Result<()> {
+    let start_hash: String = client.call("getblockhash", &[json!(start)])?;
+    let end_hash: String = client.call("getblockhash", &[json!(end)])?;
+    let deposits: Vec<ListedDeposit> = client.call(
+        "listsidechaindepositsbyblock",
+        &[json!(this_sidechain), json!(end_hash), json!(start_hash)],
+    )?;
+    for deposit in deposits {
+        let tx: Transaction =
+            deserialize(&hex::decode(&deposit.txhex).into_diagnostic()?).into_diagnostic()?;
+        db.put_main_deposit(
+            &tx.txid().to_string(),
+            &MainDepositRecord {
+                block_hash: deposit.hashblock,
+                burn_index: deposit.nburnindex,
+                ntx: deposit.ntx,
+                address: deposit.strdest,
+                tx_hex: deposit.txhex,
+            },
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use crate::backend::Backend;
+use crate::bridge::ffi::{FlushPolicy, TaskKind};
+use crate::client::MainClient;
+use crate::db::Db;
+use crate::flush;
+use crate::prefetch::{self, DepositCache};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the scheduler thread wakes up, the finest interval its tasks
+/// run at.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Every periodic task runs through a Scheduler, either on the thread spawned
+// here or from Drivechain::tick, so that they run one at a time instead of
+// from threads of their own contending for inner and the RPC client.
+pub struct Scheduler {
+    // In the order due tasks are run, flushes last so that they include
+    // what the other tasks wrote.
+    tasks: Vec<(TaskKind, Duration, Option<Instant>)>,
+    pub last_flush: Instant,
+}
+
+impl Scheduler {
+    /// A task with a zero interval is due on every tick.
+    pub fn new(tasks: Vec<(TaskKind, Duration)>) -> Self {
+        Self {
+            tasks: tasks
+                .into_iter()
+                .map(|(kind, interval)| (kind, interval, None))
+                .collect(),
+            last_flush: Instant::now(),
+        }
+    }
+
+    /// Tasks whose interval elapsed since they last ran, marked as run.
+    pub fn due(&mut self, now: Instant) -> Vec<TaskKind> {
+        let mut due = Vec::new();
+        for (kind, interval, last_run) in &mut self.tasks {
+            if last_run.map_or(true, |last_run| now.duration_since(last_run) >= *interval) {
+                *last_run = Some(now);
+                due.push(*kind);
+            }
+        }
+        due
+    }
+}
+
+/// What the scheduler thread needs from the bridge.
+pub struct Shared {
+    pub inner: Weak<Mutex<Box<dyn Backend>>>,
+    pub db: Db,
+    pub client: MainClient,
+    pub deposit_cache: DepositCache,
+    pub flush_policy: Arc<Mutex<FlushPolicy>>,
+}
+
+/// Run scheduler's flushes and deposit prefetching until the bridge drops
+/// inner. The other tasks need the bridge itself and only run from tick.
+pub fn spawn(shared: Shared, mut scheduler: Scheduler) {
+    thread::spawn(move || loop {
+        thread::sleep(POLL_INTERVAL);
+        let Some(inner) = shared.inner.upgrade() else {
+            return;
+        };
+        for kind in scheduler.due(Instant::now()) {
+            match kind {
+                TaskKind::PrefetchDeposits => {
+                    prefetch::refill(&inner, &shared.client, &shared.deposit_cache);
+                }
+                TaskKind::Flush => {
+                    let policy = lock(&shared.flush_policy).clone();
+                    flush::flush_if_due(&inner, &shared.db, &policy, &mut scheduler.last_flush);
+                }
+                _ => {}
+            }
+        }
+    });
+}
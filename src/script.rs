@@ -0,0 +1,92 @@
+use crate::bridge::ffi;
+use bitcoin::blockdata::opcodes::all::OP_RETURN;
+use bitcoin::blockdata::opcodes::All;
+use bitcoin::blockdata::script::{Builder, Script};
+use bitcoin::hash_types::{BlockHash, TxMerkleNode};
+use bitcoin::hashes::Hash as _;
+
+/// 0xb4, OP_NOP5 redefined as OP_DRIVECHAIN by BIP300.
+pub const BIP300_DRIVECHAIN_OPCODE: u8 = 0xb4;
+/// 0x51, OP_TRUE.
+pub const BIP300_TRAILING_OPCODE: u8 = 0x51;
+/// Estimated virtual size of a BIP301 BMM request: 11 vB of transaction
+/// overhead, one P2WPKH input (68 vB), a P2WPKH change output (31 vB) and
+/// the OP_RETURN output carrying the 3 byte header, sidechain number,
+/// critical hash and previous main block hash (78 vB). The wallet may pick
+/// more or other inputs, so the actual size can differ.
+pub const BMM_REQUEST_VSIZE: u64 = 11 + 68 + 31 + 78;
+/// Message header of a BIP301 critical hash commitment.
+const BMM_COMMITMENT_HEADER: [u8; 4] = [0xd1, 0x61, 0x73, 0x68];
+/// Message header of a BIP301 BMM request.
+const BMM_REQUEST_HEADER: [u8; 3] = [0x00, 0xbf, 0x00];
+
+pub fn bip300_script_template() -> ffi::ScriptTemplate {
+    ffi::ScriptTemplate {
+        drivechain_opcode: BIP300_DRIVECHAIN_OPCODE,
+        trailing_opcode: BIP300_TRAILING_OPCODE,
+        deposit_address_format: String::new(),
+    }
+}
+
+/// <drivechain_opcode> <nsidechain> <trailing_opcode>, the script of the
+/// sidechain's escrow (CTIP) output.
+pub fn escrow_script(template: &ffi::ScriptTemplate, this_sidechain: u8) -> Script {
+    Builder::new()
+        .push_opcode(All::from(template.drivechain_opcode))
+        .push_slice(&[this_sidechain])
+        .push_opcode(All::from(template.trailing_opcode))
+        .into_script()
+}
+
+/// OP_RETURN, the BIP301 message header, the sidechain number and
+/// critical_hash, as raw bytes rather than pushes. The coinbase output script
+/// a mainchain miner includes to BMM a sidechain block.
+pub fn bmm_commitment_script(this_sidechain: u8, critical_hash: &TxMerkleNode) -> Script {
+    let mut script = vec![OP_RETURN.to_u8()];
+    script.extend_from_slice(&BMM_COMMITMENT_HEADER);
+    script.push(this_sidechain);
+    script.extend_from_slice(&critical_hash.into_inner());
+    Script::from(script)
+}
+
+/// OP_RETURN, the BIP301 request header, the sidechain number,
+/// critical_hash and prev_main_block_hash, as raw bytes. The output of a BMM
+/// request transaction, whose fee is the bid.
+pub fn bmm_request_script(
+    this_sidechain: u8,
+    critical_hash: &TxMerkleNode,
+    prev_main_block_hash: &BlockHash,
+) -> Script {
+    let mut script = vec![OP_RETURN.to_u8()];
+    script.extend_from_slice(&BMM_REQUEST_HEADER);
+    script.push(this_sidechain);
+    script.extend_from_slice(&critical_hash.into_inner());
+    script.extend_from_slice(&prev_main_block_hash.into_inner());
+    Script::from(script)
+}
+
+/// The sidechain number, critical hash and previous main block hash of a
+/// script built like bmm_request_script, None for any other script.
+pub fn parse_bmm_request(script: &Script) -> Option<(u8, TxMerkleNode, BlockHash)> {
+    let bytes = script.as_bytes();
+    let rest = bytes.strip_prefix(&[OP_RETURN.to_u8()])?;
+    let rest = rest.strip_prefix(&BMM_REQUEST_HEADER)?;
+    let (&sidechain, rest) = rest.split_first()?;
+    if rest.len() != 64 {
+        return None;
+    }
+    let critical_hash = TxMerkleNode::from_slice(&rest[..32]).ok()?;
+    let prev_main_block_hash = BlockHash::from_slice(&rest[32..]).ok()?;
+    Some((sidechain, critical_hash, prev_main_block_hash))
+}
+
+/// The sidechain number and critical hash of a script built like
+/// bmm_commitment_script, None for any other script.
+pub fn parse_bmm_commitment(script: &Script) -> Option<(u8, TxMerkleNode)> {
+    let bytes = script.as_bytes();
+    let rest = bytes.strip_prefix(&[OP_RETURN.to_u8()])?;
+    let rest = rest.strip_prefix(&BMM_COMMITMENT_HEADER)?;
+    let (&sidechain, critical_hash) = rest.split_first()?;
+    let critical_hash = TxMerkleNode::from_slice(critical_hash).ok()?;
+    Some((sidechain, critical_hash))
+}
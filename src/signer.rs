@@ -0,0 +1,128 @@
+use crate::amount;
+use crate::client::MainClient;
+use crate::error::Error;
+use bitcoin::hashes::Hash as _;
+use bitcoin::secp256k1::{All, Message, Secp256k1};
+use bitcoin::util::psbt::{Input, PartiallySignedTransaction};
+use bitcoin::util::sighash::SighashCache;
+use bitcoin::{
+    EcdsaSig, EcdsaSighashType, PrivateKey, PublicKey, Script, Sequence, TxIn, TxOut, Witness,
+};
+
+/// Change below this is left to mainchain miners rather than creating a
+/// P2WPKH output nobody would spend.
+const DUST_LIMIT: u64 = 294;
+
+// The drivechain crate's attempt_bmm funds and signs BMM requests with the
+// mainchain wallet. KeySigner does it with a P2WPKH key the sidechain node
+// holds instead, finding its coins with scantxoutset, so miners don't have to
+// keep funds in bitcoind. Requests signed elsewhere, a hardware wallet for
+// one, go through create_bmm_psbt and broadcast_bmm_psbt.
+pub struct KeySigner {
+    key: PrivateKey,
+    public_key: PublicKey,
+    // P2WPKH of public_key, where change goes.
+    script_pubkey: Script,
+    secp: Secp256k1<All>,
+}
+
+impl KeySigner {
+    pub fn from_wif(wif: &str) -> Result<Self, Error> {
+        let key = PrivateKey::from_wif(wif).map_err(|err| Error::InvalidEncoding {
+            message: format!("bmm_signing_key: {err}"),
+        })?;
+        let secp = Secp256k1::new();
+        let public_key = key.public_key(&secp);
+        let hash = public_key.wpubkey_hash().ok_or(Error::InvalidEncoding {
+            message: "bmm_signing_key: P2WPKH needs a compressed key".into(),
+        })?;
+        Ok(Self {
+            key,
+            public_key,
+            script_pubkey: Script::new_v0_p2wpkh(&hash),
+            secp,
+        })
+    }
+
+    /// Add inputs covering the fee psbt was created with, largest coins
+    /// first, and change back to the key, then sign every input.
+    pub fn fund_and_sign(
+        &self,
+        client: &MainClient,
+        psbt: &mut PartiallySignedTransaction,
+        fee: u64,
+    ) -> Result<(), Error> {
+        let descriptor = format!("wpkh({})", self.public_key);
+        let mut coins = client.scan_tx_out_set(&descriptor)?;
+        coins.sort_by(|(_, a), (_, b)| b.value.cmp(&a.value));
+        let needed = amount::checked_total(
+            "bmm request",
+            psbt.unsigned_tx
+                .output
+                .iter()
+                .map(|output| output.value)
+                .chain(std::iter::once(fee)),
+        )?;
+        let mut funded = 0u64;
+        for (outpoint, output) in coins {
+            if funded >= needed {
+                break;
+            }
+            funded = funded.saturating_add(output.value);
+            // Replaceable, a request still in the mempool when the next bid
+            // is made spends the same coins.
+            psbt.unsigned_tx.input.push(TxIn {
+                previous_output: outpoint,
+                script_sig: Script::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::default(),
+            });
+            psbt.inputs.push(Input {
+                witness_utxo: Some(output),
+                ..Default::default()
+            });
+        }
+        if funded < needed {
+            return Err(Error::InsufficientFunds {
+                needed,
+                available: funded,
+            });
+        }
+        if funded - needed >= DUST_LIMIT {
+            psbt.unsigned_tx.output.push(TxOut {
+                value: funded - needed,
+                script_pubkey: self.script_pubkey.clone(),
+            });
+            psbt.outputs.push(Default::default());
+        }
+        self.sign(psbt)
+    }
+
+    fn sign(&self, psbt: &mut PartiallySignedTransaction) -> Result<(), Error> {
+        let script_code = Script::new_p2pkh(&self.public_key.pubkey_hash());
+        let mut witnesses = Vec::with_capacity(psbt.inputs.len());
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+        for (index, input) in psbt.inputs.iter().enumerate() {
+            let value = input.witness_utxo.as_ref().map_or(0, |utxo| utxo.value);
+            let sighash = cache
+                .segwit_signature_hash(index, &script_code, value, EcdsaSighashType::All)
+                .map_err(|err| Error::InvalidEncoding {
+                    message: err.to_string(),
+                })?;
+            let message = Message::from_slice(&sighash.into_inner()).map_err(|err| {
+                Error::InvalidEncoding {
+                    message: err.to_string(),
+                }
+            })?;
+            let signature = EcdsaSig::sighash_all(self.secp.sign_ecdsa(&message, &self.key.inner));
+            witnesses.push(Witness::from_vec(vec![
+                signature.to_vec(),
+                self.public_key.to_bytes(),
+            ]));
+        }
+        for (input, witness) in psbt.inputs.iter_mut().zip(witnesses) {
+            input.final_script_witness = Some(witness);
+        }
+        Ok(())
+    }
+}
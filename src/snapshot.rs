@@ -0,0 +1,121 @@
+use crate::backend::{Backend, MainchainBackend};
+use crate::bridge::{ffi, withdrawal_status};
+use crate::codec::decode_outpoint;
+use crate::db::Db;
+use crate::error::Error;
+use miette::Result;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// What a ReadHandle shares with the Drivechain it was opened from.
+pub(crate) struct Shared {
+    pub inner: Arc<Mutex<Box<dyn Backend>>>,
+    pub mainchain: Arc<dyn MainchainBackend>,
+    pub db: Db,
+    // Blocks applied so far, written for as long as one is applied.
+    pub applying: Arc<RwLock<u64>>,
+    pub withdrawal_maturity: u64,
+}
+
+/// Opened by Drivechain::open_read_handle for threads other than the one
+/// connecting blocks. Unlike the Drivechain, it may be used concurrently
+/// with it.
+pub struct ReadHandle(Arc<Shared>);
+
+/// Bridge state as of ReadHandle::open_read_snapshot. Nothing is copied,
+/// queries read the live DBs and fail with snapshot_stale once a block was
+/// connected or disconnected since the snapshot was opened.
+pub struct ReadSnapshot {
+    shared: Arc<Shared>,
+    applied: u64,
+    main_height: u64,
+}
+
+impl ReadHandle {
+    pub(crate) fn new(shared: Shared) -> Self {
+        Self(Arc::new(shared))
+    }
+
+    pub fn open_read_snapshot(&self) -> Result<Box<ReadSnapshot>> {
+        // Asked before taking the lock, a slow node doesn't hold up
+        // connect_block.
+        let main_height = self.0.mainchain.get_block_count()?;
+        let applied = *self
+            .0
+            .applying
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        Ok(Box::new(ReadSnapshot {
+            shared: Arc::clone(&self.0),
+            applied,
+            main_height,
+        }))
+    }
+}
+
+impl ReadSnapshot {
+    // Runs query while no block is being applied, if none was since the
+    // snapshot was opened.
+    fn read<T>(&self, query: impl FnOnce(&Shared) -> Result<T>) -> Result<T> {
+        let applied = self
+            .shared
+            .applying
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        if *applied != self.applied {
+            return Err(Error::SnapshotStale.into());
+        }
+        query(&self.shared)
+    }
+
+    pub fn get_main_height(&self) -> u64 {
+        self.main_height
+    }
+
+    pub fn get_deposit_outputs(&self) -> Result<Vec<ffi::Output>> {
+        self.read(|shared| {
+            let deposits = lock(&shared.inner).get_deposit_outputs()?;
+            Ok(deposits.iter().map(ffi::Output::from).collect())
+        })
+    }
+
+    pub fn is_outpoint_spent(&self, outpoint: &str) -> Result<bool> {
+        let outpoint = decode_outpoint("outpoint", outpoint)?;
+        self.read(|shared| is_spent(shared, &outpoint))
+    }
+
+    pub fn get_withdrawal_status(&self, outpoint: &str) -> Result<ffi::WithdrawalStatus> {
+        let outpoint_bytes = decode_outpoint("outpoint", outpoint)?;
+        self.read(|shared| {
+            let record =
+                shared
+                    .db
+                    .get_withdrawal(&outpoint_bytes)?
+                    .ok_or(Error::UnknownWithdrawal {
+                        outpoint: outpoint.into(),
+                    })?;
+            // The bridge records the height the next time it needs it,
+            // until then it is taken to be the snapshot's.
+            let main_height = match shared.db.is_withdrawal_unresolved(&outpoint_bytes)? {
+                true => self.main_height,
+                false => record.main_height,
+            };
+            Ok(withdrawal_status(
+                main_height,
+                is_spent(shared, &outpoint_bytes)?,
+                self.main_height,
+                shared.withdrawal_maturity,
+            ))
+        })
+    }
+}
+
+// The drivechain crate never learns of withdrawals paid by bundles the
+// bridge built.
+fn is_spent(shared: &Shared, outpoint: &[u8]) -> Result<bool> {
+    Ok(shared.db.is_withdrawal_paid(outpoint)?
+        || lock(&shared.inner).is_outpoint_spent(outpoint)?)
+}
@@ -0,0 +1,434 @@
+use crate::error::Error;
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::consensus::encode::{self, Decodable};
+use bitcoin::consensus::params::Params;
+use bitcoin::hash_types::BlockHash;
+use bitcoin::hashes::Hash;
+use bitcoin::network::address::Address;
+use bitcoin::network::constants::{Network, ServiceFlags};
+use bitcoin::network::message::{NetworkMessage, RawNetworkMessage};
+use bitcoin::network::message_blockdata::GetHeadersMessage;
+use bitcoin::network::message_network::VersionMessage;
+use bitcoin::util::uint::Uint256;
+use bitcoin::BlockHeader;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufReader, Write as _};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs as _};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const P2P_TIMEOUT: Duration = Duration::from_secs(30);
+/// Peers send at most this many headers per headers message.
+const MAX_HEADERS: usize = 2000;
+
+// Mainchain headers synced directly from a peer over P2P. Every header is
+// checked to connect to the previous one, to declare the target the
+// network's difficulty rules give it and to meet that target, so inclusion
+// proofs can be verified without trusting the RPC endpoint.
+pub struct HeaderChain {
+    network: Network,
+    params: Params,
+    headers: Vec<BlockHeader>,
+    heights: HashMap<BlockHash, usize>,
+    // Headers a reorg took off the chain, a block on a stale branch rather
+    // than one never seen.
+    stale: HashSet<BlockHash>,
+}
+
+impl HeaderChain {
+    pub fn new(network: Network) -> Self {
+        let genesis = genesis_block(network).header;
+        Self {
+            network,
+            params: Params::new(network),
+            heights: HashMap::from([(genesis.block_hash(), 0)]),
+            headers: vec![genesis],
+            stale: HashSet::new(),
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.headers.len() - 1
+    }
+
+    pub fn tip(&self) -> BlockHash {
+        self.headers[self.height()].block_hash()
+    }
+
+    pub fn get(&self, block_hash: &BlockHash) -> Option<&BlockHeader> {
+        self.heights
+            .get(block_hash)
+            .map(|height| &self.headers[*height])
+    }
+
+    pub fn is_stale(&self, block_hash: &BlockHash) -> bool {
+        self.stale.contains(block_hash)
+    }
+
+    /// Add headers received from a peer. If they fork off below the current
+    /// tip the chain with more work wins. Returns whether the chain changed.
+    pub fn extend(&mut self, headers: &[BlockHeader]) -> Result<bool, Error> {
+        let first = match headers.first() {
+            Some(first) => first,
+            None => return Ok(false),
+        };
+        let fork_height = *self
+            .heights
+            .get(&first.prev_blockhash)
+            .ok_or(Error::InvalidHeader {
+                block_hash: first.block_hash().to_string(),
+            })?;
+        let header_at = |height: usize| match height.checked_sub(fork_height + 1) {
+            Some(index) => headers[index],
+            None => self.headers[height],
+        };
+        let mut prev_blockhash = first.prev_blockhash;
+        for (index, header) in headers.iter().enumerate() {
+            let invalid = || Error::InvalidHeader {
+                block_hash: header.block_hash().to_string(),
+            };
+            if header.prev_blockhash != prev_blockhash {
+                return Err(invalid());
+            }
+            let height = fork_height + 1 + index;
+            if header.bits != next_bits(&self.params, height, header, &header_at)
+                || header.target() > self.params.pow_limit
+            {
+                return Err(invalid());
+            }
+            prev_blockhash = header
+                .validate_pow(&header.target())
+                .map_err(|_| invalid())?;
+        }
+        let new_work = total_work(headers);
+        let old_work = total_work(&self.headers[fork_height + 1..]);
+        if fork_height < self.height() && new_work <= old_work {
+            return Ok(false);
+        }
+        for header in &self.headers[fork_height + 1..] {
+            self.heights.remove(&header.block_hash());
+            self.stale.insert(header.block_hash());
+        }
+        self.headers.truncate(fork_height + 1);
+        for header in headers {
+            self.stale.remove(&header.block_hash());
+            self.heights.insert(header.block_hash(), self.headers.len());
+            self.headers.push(*header);
+        }
+        Ok(true)
+    }
+
+    fn locator(&self) -> Vec<BlockHash> {
+        let mut locator = vec![];
+        let mut height = self.height();
+        let mut step = 1;
+        loop {
+            locator.push(self.headers[height].block_hash());
+            if height == 0 {
+                break;
+            }
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            height = height.saturating_sub(step);
+        }
+        locator
+    }
+
+    /// Download headers from the peer until it has no more to offer.
+    pub fn sync(&mut self, host: &str, port: u16) -> Result<usize, Error> {
+        let peer = (host, port)
+            .to_socket_addrs()
+            .map_err(transport)?
+            .next()
+            .ok_or(Error::Transport {
+                message: format!("failed to resolve {host}:{port}"),
+            })?;
+        let mut stream = TcpStream::connect_timeout(&peer, P2P_TIMEOUT).map_err(transport)?;
+        stream
+            .set_read_timeout(Some(P2P_TIMEOUT))
+            .map_err(transport)?;
+        let mut reader = BufReader::new(stream.try_clone().map_err(transport)?);
+        let magic = self.network.magic();
+
+        send(&mut stream, magic, version_message(peer))?;
+        loop {
+            match receive(&mut reader)? {
+                NetworkMessage::Version(_) => send(&mut stream, magic, NetworkMessage::Verack)?,
+                NetworkMessage::Verack => break,
+                NetworkMessage::Ping(nonce) => {
+                    send(&mut stream, magic, NetworkMessage::Pong(nonce))?
+                }
+                _ => {}
+            }
+        }
+        loop {
+            let get_headers = GetHeadersMessage::new(self.locator(), BlockHash::all_zeros());
+            send(&mut stream, magic, NetworkMessage::GetHeaders(get_headers))?;
+            let headers = loop {
+                match receive(&mut reader)? {
+                    NetworkMessage::Headers(headers) => break headers,
+                    NetworkMessage::Ping(nonce) => {
+                        send(&mut stream, magic, NetworkMessage::Pong(nonce))?
+                    }
+                    _ => {}
+                }
+            };
+            if !self.extend(&headers)? || headers.len() < MAX_HEADERS {
+                break;
+            }
+        }
+        Ok(self.height())
+    }
+}
+
+pub struct Spv {
+    pub headers: HeaderChain,
+    host: String,
+    port: u16,
+}
+
+impl Spv {
+    pub fn new(network: Network, host: &str, port: u16) -> Self {
+        Self {
+            headers: HeaderChain::new(network),
+            host: host.into(),
+            port,
+        }
+    }
+
+    pub fn sync(&mut self) -> Result<usize, Error> {
+        self.headers.sync(&self.host, self.port)
+    }
+}
+
+/// The compact target the header at height has to declare, as bitcoin
+/// Core's GetNextWorkRequired computes it. header_at gives the headers below
+/// height.
+fn next_bits(
+    params: &Params,
+    height: usize,
+    header: &BlockHeader,
+    header_at: &dyn Fn(usize) -> BlockHeader,
+) -> u32 {
+    let pow_limit_bits = BlockHeader::compact_target_from_u256(&params.pow_limit);
+    let interval = (params.pow_target_timespan / params.pow_target_spacing) as usize;
+    let prev = header_at(height - 1);
+    if height % interval != 0 {
+        if !params.allow_min_difficulty_blocks {
+            return prev.bits;
+        }
+        // Testnet allows a minimum difficulty block after 20 minutes without
+        // one, the blocks after it go back to the last real difficulty.
+        if u64::from(header.time) > u64::from(prev.time) + params.pow_target_spacing * 2 {
+            return pow_limit_bits;
+        }
+        let mut height = height - 1;
+        let mut last = prev;
+        while height % interval != 0 && last.bits == pow_limit_bits {
+            height -= 1;
+            last = header_at(height);
+        }
+        return last.bits;
+    }
+    if params.no_pow_retargeting {
+        return prev.bits;
+    }
+    let first = header_at(height - interval);
+    let timespan = (i64::from(prev.time) - i64::from(first.time)).clamp(
+        params.pow_target_timespan as i64 / 4,
+        params.pow_target_timespan as i64 * 4,
+    );
+    let target = BlockHeader::u256_from_compact_target(prev.bits)
+        * Uint256::from_u64(timespan as u64).unwrap()
+        / Uint256::from_u64(params.pow_target_timespan).unwrap();
+    BlockHeader::compact_target_from_u256(&target.min(params.pow_limit))
+}
+
+fn total_work(headers: &[BlockHeader]) -> Uint256 {
+    headers
+        .iter()
+        .fold(Uint256::from_u64(0).unwrap(), |work, header| {
+            work + header.work()
+        })
+}
+
+fn version_message(peer: SocketAddr) -> NetworkMessage {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let local = SocketAddr::from(([0, 0, 0, 0], 0));
+    NetworkMessage::Version(VersionMessage::new(
+        ServiceFlags::NONE,
+        timestamp as i64,
+        Address::new(&peer, ServiceFlags::NONE),
+        Address::new(&local, ServiceFlags::NONE),
+        timestamp,
+        format!("/drivechain-cpp:{}/", env!("CARGO_PKG_VERSION")),
+        0,
+    ))
+}
+
+fn send(stream: &mut TcpStream, magic: u32, payload: NetworkMessage) -> Result<(), Error> {
+    let message = RawNetworkMessage { magic, payload };
+    stream
+        .write_all(&encode::serialize(&message))
+        .map_err(transport)
+}
+
+fn receive(reader: &mut BufReader<TcpStream>) -> Result<NetworkMessage, Error> {
+    let message = RawNetworkMessage::consensus_decode(reader).map_err(transport)?;
+    Ok(message.payload)
+}
+
+fn transport(err: impl std::fmt::Display) -> Error {
+    Error::Transport {
+        message: err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hash_types::TxMerkleNode;
+
+    const POW_LIMIT_BITS: u32 = 0x1d00ffff;
+
+    fn header(time: u32, bits: u32) -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time,
+            bits,
+            nonce: 0,
+        }
+    }
+
+    // next_bits of a header at height, given the times and bits of the
+    // headers below it that it reads.
+    fn bits_at(network: Network, height: usize, time: u32, below: &[(usize, u32, u32)]) -> u32 {
+        let below: HashMap<_, _> = below
+            .iter()
+            .map(|(height, time, bits)| (*height, header(*time, *bits)))
+            .collect();
+        next_bits(&Params::new(network), height, &header(time, 0), &|height| {
+            below[&height]
+        })
+    }
+
+    // The mainnet retargets bitcoin Core's pow_tests check, with the first
+    // and last header of the period before.
+    #[test]
+    fn first_mainnet_difficulty_change() {
+        let below = [
+            (30240, 1261130161, 0x1d00ffff),
+            (32255, 1262152739, 0x1d00ffff),
+        ];
+        assert_eq!(
+            bits_at(Network::Bitcoin, 32256, 1262153464, &below),
+            0x1d00d86a
+        );
+    }
+
+    #[test]
+    fn mainnet_retarget_stays_at_pow_limit() {
+        let below = [(0, 1231006505, 0x1d00ffff), (2015, 1233061996, 0x1d00ffff)];
+        assert_eq!(
+            bits_at(Network::Bitcoin, 2016, 1233063531, &below),
+            POW_LIMIT_BITS
+        );
+    }
+
+    #[test]
+    fn mainnet_retarget_is_limited_to_a_quarter_timespan() {
+        let below = [
+            (66528, 1279008237, 0x1c05a3f4),
+            (68543, 1279297671, 0x1c05a3f4),
+        ];
+        assert_eq!(
+            bits_at(Network::Bitcoin, 68544, 1279297779, &below),
+            0x1c0168fd
+        );
+    }
+
+    #[test]
+    fn mainnet_retarget_is_limited_to_four_timespans() {
+        let below = [
+            (46368, 1263163443, 0x1c387f6f),
+            (48383, 1269211443, 0x1c387f6f),
+        ];
+        assert_eq!(
+            bits_at(Network::Bitcoin, 48384, 1269211443, &below),
+            0x1d00e1fd
+        );
+    }
+
+    #[test]
+    fn mainnet_keeps_the_bits_between_retargets() {
+        let below = [(32256, 1262153464, 0x1d00d86a)];
+        assert_eq!(
+            bits_at(Network::Bitcoin, 32257, 1262160000, &below),
+            0x1d00d86a
+        );
+    }
+
+    #[test]
+    fn regtest_doesnt_retarget() {
+        let below = [(0, 1296688602, 0x207fffff), (2015, 1296688603, 0x207fffff)];
+        assert_eq!(
+            bits_at(Network::Regtest, 2016, 1296688604, &below),
+            0x207fffff
+        );
+    }
+
+    #[test]
+    fn testnet_allows_a_min_difficulty_block_after_20_minutes() {
+        let below = [(2016, 1300000000, 0x1c0168fd)];
+        assert_eq!(
+            bits_at(Network::Testnet, 2017, 1300001200, &below),
+            0x1c0168fd
+        );
+        assert_eq!(
+            bits_at(Network::Testnet, 2017, 1300001201, &below),
+            POW_LIMIT_BITS
+        );
+    }
+
+    #[test]
+    fn testnet_returns_to_the_last_difficulty_after_a_min_difficulty_block() {
+        let below = [
+            (2016, 1300000000, 0x1c0168fd),
+            (2017, 1300001201, POW_LIMIT_BITS),
+            (2018, 1300002402, POW_LIMIT_BITS),
+        ];
+        assert_eq!(
+            bits_at(Network::Testnet, 2019, 1300002403, &below),
+            0x1c0168fd
+        );
+    }
+
+    #[test]
+    fn testnet_retargets_regardless_of_block_time() {
+        let below = [
+            (30240, 1261130161, 0x1d00ffff),
+            (32255, 1262152739, 0x1d00ffff),
+        ];
+        assert_eq!(
+            bits_at(Network::Testnet, 32256, 1262160000, &below),
+            0x1d00d86a
+        );
+    }
+
+    #[test]
+    fn total_work_adds_up_the_work_of_every_header() {
+        let genesis = genesis_block(Network::Bitcoin).header;
+        assert_eq!(total_work(&[]), Uint256::from_u64(0).unwrap());
+        assert_eq!(
+            total_work(&[genesis, genesis]),
+            Uint256::from_u64(2 * 0x1_0001_0001).unwrap()
+        );
+        let harder = header(0, 0x1d00d86a);
+        assert!(total_work(&[harder]) > total_work(&[genesis]));
+    }
+}
@@ -0,0 +1,408 @@
+use miette::{IntoDiagnostic as _, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+pub type Entry = (Vec<u8>, Vec<u8>);
+pub type Entries<'a> = Box<dyn Iterator<Item = Result<Entry>> + 'a>;
+
+// Key-value store behind Db, selected by Config::storage_backend. Trees are
+// independent ordered keyspaces, like sled trees or RocksDB column families.
+pub trait Storage: Send + Sync {
+    /// The tree sled calls its default tree, for records that predate the
+    /// storage abstraction.
+    fn default_tree(&self) -> Result<Tree>;
+    fn open_tree(&self, name: &str) -> Result<Tree>;
+    /// Unique and increasing, also across restarts.
+    fn generate_id(&self) -> Result<u64>;
+    /// Number of bytes flushed, where the backend reports it.
+    fn flush(&self) -> Result<usize>;
+}
+
+pub trait TreeBackend: Send + Sync {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()>;
+    fn remove(&self, key: &[u8]) -> Result<()>;
+    /// Entries with keys starting with prefix, in key order or reversed.
+    fn scan(&self, prefix: &[u8], reverse: bool) -> Entries<'_>;
+
+    fn pop_min(&self) -> Result<Option<Entry>> {
+        let first = self.scan(&[], false).next().transpose()?;
+        if let Some((key, _)) = &first {
+            self.remove(key)?;
+        }
+        Ok(first)
+    }
+}
+
+#[derive(Clone)]
+pub struct Tree {
+    name: Arc<str>,
+    backend: Arc<dyn TreeBackend>,
+}
+
+impl Tree {
+    pub fn new(name: &str, backend: impl TreeBackend + 'static) -> Self {
+        Self {
+            name: name.into(),
+            backend: Arc::new(backend),
+        }
+    }
+
+    /// The name the tree was opened with, empty for the default tree
+    /// whatever the backend calls it.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        self.backend.get(key.as_ref())
+    }
+
+    pub fn insert(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<()> {
+        self.backend.insert(key.as_ref(), value.as_ref())
+    }
+
+    pub fn remove(&self, key: impl AsRef<[u8]>) -> Result<()> {
+        self.backend.remove(key.as_ref())
+    }
+
+    pub fn contains_key(&self, key: impl AsRef<[u8]>) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    pub fn iter(&self) -> Entries<'_> {
+        self.backend.scan(&[], false)
+    }
+
+    pub fn iter_rev(&self) -> Entries<'_> {
+        self.backend.scan(&[], true)
+    }
+
+    pub fn scan_prefix(&self, prefix: impl AsRef<[u8]>) -> Entries<'_> {
+        self.backend.scan(prefix.as_ref(), false)
+    }
+
+    pub fn scan_prefix_rev(&self, prefix: impl AsRef<[u8]>) -> Entries<'_> {
+        self.backend.scan(prefix.as_ref(), true)
+    }
+
+    pub fn last(&self) -> Result<Option<Entry>> {
+        self.iter_rev().next().transpose()
+    }
+
+    pub fn pop_min(&self) -> Result<Option<Entry>> {
+        self.backend.pop_min()
+    }
+}
+
+pub struct SledStorage(sled::Db);
+
+impl SledStorage {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self(sled::open(path).into_diagnostic()?))
+    }
+}
+
+impl Storage for SledStorage {
+    fn default_tree(&self) -> Result<Tree> {
+        Ok(Tree::new("", SledTree(sled::Tree::clone(&self.0))))
+    }
+
+    fn open_tree(&self, name: &str) -> Result<Tree> {
+        let tree = self.0.open_tree(name).into_diagnostic()?;
+        Ok(Tree::new(name, SledTree(tree)))
+    }
+
+    fn generate_id(&self) -> Result<u64> {
+        self.0.generate_id().into_diagnostic()
+    }
+
+    fn flush(&self) -> Result<usize> {
+        self.0.flush().into_diagnostic()
+    }
+}
+
+struct SledTree(sled::Tree);
+
+impl TreeBackend for SledTree {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .0
+            .get(key)
+            .into_diagnostic()?
+            .map(|value| value.to_vec()))
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.0.insert(key, value).into_diagnostic()?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<()> {
+        self.0.remove(key).into_diagnostic()?;
+        Ok(())
+    }
+
+    fn scan(&self, prefix: &[u8], reverse: bool) -> Entries<'_> {
+        let entries = self.0.scan_prefix(prefix).map(|entry| {
+            let (key, value) = entry.into_diagnostic()?;
+            Ok((key.to_vec(), value.to_vec()))
+        });
+        match reverse {
+            true => Box::new(entries.rev()),
+            false => Box::new(entries),
+        }
+    }
+
+    fn pop_min(&self) -> Result<Option<Entry>> {
+        let entry = self.0.pop_min().into_diagnostic()?;
+        Ok(entry.map(|(key, value)| (key.to_vec(), value.to_vec())))
+    }
+}
+
+/// Keeps everything in memory and loses it on drop, for tests and
+/// throwaway nodes.
+#[derive(Default)]
+pub struct MemoryStorage {
+    trees: Mutex<HashMap<String, Tree>>,
+    next_id: AtomicU64,
+}
+
+impl Storage for MemoryStorage {
+    fn default_tree(&self) -> Result<Tree> {
+        self.open_tree("")
+    }
+
+    fn open_tree(&self, name: &str) -> Result<Tree> {
+        let mut trees = self.trees.lock().unwrap_or_else(PoisonError::into_inner);
+        let tree = trees
+            .entry(name.into())
+            .or_insert_with(|| Tree::new(name, MemoryTree::default()));
+        Ok(tree.clone())
+    }
+
+    fn generate_id(&self) -> Result<u64> {
+        Ok(self.next_id.fetch_add(1, Ordering::SeqCst))
+    }
+
+    fn flush(&self) -> Result<usize> {
+        Ok(0)
+    }
+}
+
+#[derive(Default)]
+struct MemoryTree(RwLock<BTreeMap<Vec<u8>, Vec<u8>>>);
+
+impl TreeBackend for MemoryTree {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let tree = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(tree.get(key).cloned())
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let mut tree = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        tree.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<()> {
+        let mut tree = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        tree.remove(key);
+        Ok(())
+    }
+
+    // Iterates over a copy, so writes during iteration aren't seen.
+    fn scan(&self, prefix: &[u8], reverse: bool) -> Entries<'_> {
+        let tree = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        let mut entries: Vec<_> = tree
+            .range(prefix.to_vec()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| Ok((key.clone(), value.clone())))
+            .collect();
+        if reverse {
+            entries.reverse();
+        }
+        Box::new(entries.into_iter())
+    }
+
+    fn pop_min(&self) -> Result<Option<Entry>> {
+        let mut tree = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        Ok(tree.pop_first())
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+pub use self::rocks::RocksStorage;
+
+#[cfg(feature = "rocksdb")]
+mod rocks {
+    use super::{Entries, Storage, Tree, TreeBackend};
+    use crate::error::Error;
+    use miette::{IntoDiagnostic as _, Result};
+    use rocksdb::{BoundColumnFamily, Direction, IteratorMode, Options};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex, PoisonError};
+
+    type RocksDb = rocksdb::DBWithThreadMode<rocksdb::MultiThreaded>;
+
+    // Column family holding the next generate_id under NEXT_ID_KEY.
+    const IDS_CF: &str = "generate_id";
+    const NEXT_ID_KEY: &[u8] = b"next_id";
+
+    /// Trees are column families. Unlike sled, memory use stays bounded by
+    /// the block cache as the DB grows.
+    pub struct RocksStorage {
+        db: Arc<RocksDb>,
+        next_id: Mutex<u64>,
+    }
+
+    impl RocksStorage {
+        pub fn open(path: &Path) -> Result<Self> {
+            let mut options = Options::default();
+            options.create_if_missing(true);
+            options.create_missing_column_families(true);
+            let mut column_families = RocksDb::list_cf(&options, path).unwrap_or_default();
+            if !column_families.iter().any(|name| name == IDS_CF) {
+                column_families.push(IDS_CF.into());
+            }
+            let db = Arc::new(RocksDb::open_cf(&options, path, column_families).into_diagnostic()?);
+            let ids = RocksTree {
+                db: db.clone(),
+                cf: IDS_CF.into(),
+            };
+            let next_id = match ids.get(NEXT_ID_KEY)? {
+                Some(value) => {
+                    u64::from_be_bytes(value.try_into().map_err(|_| Error::InvalidEncoding {
+                        message: "generate_id counter is not 8 bytes".into(),
+                    })?)
+                }
+                None => 0,
+            };
+            Ok(Self {
+                db,
+                next_id: Mutex::new(next_id),
+            })
+        }
+    }
+
+    impl Storage for RocksStorage {
+        // RocksDB always has its default column family.
+        fn default_tree(&self) -> Result<Tree> {
+            Ok(Tree::new(
+                "",
+                RocksTree {
+                    db: self.db.clone(),
+                    cf: rocksdb::DEFAULT_COLUMN_FAMILY_NAME.into(),
+                },
+            ))
+        }
+
+        fn open_tree(&self, name: &str) -> Result<Tree> {
+            if self.db.cf_handle(name).is_none() {
+                self.db
+                    .create_cf(name, &Options::default())
+                    .into_diagnostic()?;
+            }
+            Ok(Tree::new(
+                name,
+                RocksTree {
+                    db: self.db.clone(),
+                    cf: name.into(),
+                },
+            ))
+        }
+
+        fn generate_id(&self) -> Result<u64> {
+            let mut next_id = self.next_id.lock().unwrap_or_else(PoisonError::into_inner);
+            let id = *next_id;
+            let ids = RocksTree {
+                db: self.db.clone(),
+                cf: IDS_CF.into(),
+            };
+            ids.insert(NEXT_ID_KEY, &(id + 1).to_be_bytes())?;
+            *next_id = id + 1;
+            Ok(id)
+        }
+
+        fn flush(&self) -> Result<usize> {
+            self.db.flush().into_diagnostic()?;
+            Ok(0)
+        }
+    }
+
+    struct RocksTree {
+        db: Arc<RocksDb>,
+        cf: String,
+    }
+
+    impl RocksTree {
+        fn cf(&self) -> Result<Arc<BoundColumnFamily<'_>>> {
+            self.db.cf_handle(&self.cf).ok_or_else(|| {
+                Error::Storage {
+                    message: format!("column family {} is missing", self.cf),
+                }
+                .into()
+            })
+        }
+    }
+
+    // Smallest key greater than every key starting with prefix, None if
+    // there is none.
+    fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+        let mut end = prefix.to_vec();
+        while let Some(last) = end.pop() {
+            if last < u8::MAX {
+                end.push(last + 1);
+                return Some(end);
+            }
+        }
+        None
+    }
+
+    impl TreeBackend for RocksTree {
+        fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            self.db.get_cf(&self.cf()?, key).into_diagnostic()
+        }
+
+        fn insert(&self, key: &[u8], value: &[u8]) -> Result<()> {
+            self.db.put_cf(&self.cf()?, key, value).into_diagnostic()
+        }
+
+        fn remove(&self, key: &[u8]) -> Result<()> {
+            self.db.delete_cf(&self.cf()?, key).into_diagnostic()
+        }
+
+        fn scan(&self, prefix: &[u8], reverse: bool) -> Entries<'_> {
+            let cf = match self.cf() {
+                Ok(cf) => cf,
+                Err(err) => return Box::new(std::iter::once(Err(err))),
+            };
+            let end = prefix_end(prefix);
+            let mode = match (reverse, &end) {
+                (false, _) => IteratorMode::From(prefix, Direction::Forward),
+                (true, Some(end)) => IteratorMode::From(end, Direction::Reverse),
+                (true, None) => IteratorMode::End,
+            };
+            let prefix = prefix.to_vec();
+            let end = end.filter(|_| reverse);
+            let entries = self
+                .db
+                .iterator_cf(&cf, mode)
+                .map(|entry| {
+                    let (key, value) = entry.into_diagnostic()?;
+                    Ok((key.into_vec(), value.into_vec()))
+                })
+                // Seeking backwards lands on end itself if it exists.
+                .skip_while(
+                    move |entry| matches!((entry, &end), (Ok((key, _)), Some(end)) if key >= end),
+                )
+                .take_while(move |entry| match entry {
+                    Ok((key, _)) => key.starts_with(&prefix),
+                    Err(_) => true,
+                });
+            Box::new(entries)
+        }
+    }
+}
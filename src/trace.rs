@@ -0,0 +1,91 @@
+use crate::error::Error;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::{Layered, SubscriberExt as _};
+use tracing_subscriber::util::SubscriberInitExt as _;
+use tracing_subscriber::{reload, Registry};
+
+type ChromeLayer = tracing_chrome::ChromeLayer<Registry>;
+type WithChrome = Layered<reload::Layer<Option<ChromeLayer>, Registry>, Registry>;
+
+// The subscriber is global and installed on first use, the level and the
+// Chrome trace file are swapped through reload handles afterwards.
+struct Tracing {
+    level: reload::Handle<LevelFilter, WithChrome>,
+    chrome: reload::Handle<Option<ChromeLayer>, Registry>,
+    // Dropping it writes out the Chrome trace.
+    chrome_guard: Mutex<Option<FlushGuard>>,
+}
+
+static TRACING: OnceLock<Result<Tracing, String>> = OnceLock::new();
+
+fn unavailable(message: impl ToString) -> Error {
+    Error::TraceUnavailable {
+        message: message.to_string(),
+    }
+}
+
+fn tracing() -> Result<&'static Tracing, Error> {
+    TRACING
+        .get_or_init(|| {
+            let (chrome, chrome_handle) = reload::Layer::new(None);
+            let (level, level_handle) = reload::Layer::new(LevelFilter::OFF);
+            let fmt = tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_span_events(FmtSpan::CLOSE);
+            Registry::default()
+                .with(chrome)
+                .with(level)
+                .with(fmt)
+                .try_init()
+                .map_err(|err| err.to_string())?;
+            Ok(Tracing {
+                level: level_handle,
+                chrome: chrome_handle,
+                chrome_guard: Mutex::new(None),
+            })
+        })
+        .as_ref()
+        .map_err(unavailable)
+}
+
+/// "off", "error", "warn", "info", "debug" or "trace". Spans are logged to
+/// stderr with their duration when they close.
+pub fn set_trace_level(level: &str) -> Result<(), Error> {
+    let level = LevelFilter::from_str(level).map_err(|_| Error::InvalidEncoding {
+        message: format!("unknown trace level {level}"),
+    })?;
+    tracing()?.level.reload(level).map_err(unavailable)
+}
+
+/// Also record spans to a Chrome trace file at path, viewable in
+/// chrome://tracing or Perfetto. Replaces a trace already being written.
+pub fn start_chrome_trace(path: &str) -> Result<(), Error> {
+    let tracing = tracing()?;
+    let (layer, guard) = ChromeLayerBuilder::new()
+        .file(path)
+        .include_args(true)
+        .build();
+    tracing.chrome.reload(Some(layer)).map_err(unavailable)?;
+    let mut chrome_guard = tracing
+        .chrome_guard
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    *chrome_guard = Some(guard);
+    Ok(())
+}
+
+/// Finish writing the Chrome trace started by start_chrome_trace.
+pub fn stop_chrome_trace() -> Result<(), Error> {
+    let tracing = tracing()?;
+    tracing.chrome.reload(None).map_err(unavailable)?;
+    let mut chrome_guard = tracing
+        .chrome_guard
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    *chrome_guard = None;
+    Ok(())
+}
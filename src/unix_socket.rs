@@ -0,0 +1,100 @@
+use serde_json::Value;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+// ureq only speaks TCP, so mainchain RPC bound to a unix socket goes through
+// this minimal HTTP/1.1 client instead. One connection per request, closed
+// by the node once it has answered.
+
+/// POST body as JSON to the node listening on the socket at path and return
+/// the response body, whatever the status.
+pub fn post(
+    path: &str,
+    auth: &str,
+    body: &Value,
+    timeout: Option<Duration>,
+) -> io::Result<Vec<u8>> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+    let body = serde_json::to_vec(body)?;
+    write!(
+        stream,
+        "POST / HTTP/1.1\r\n\
+         Host: localhost\r\n\
+         Authorization: {auth}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("HTTP/1.") {
+        return Err(invalid(format!(
+            "not an HTTP response: {}",
+            line.trim_end()
+        )));
+    }
+    let mut content_length = None;
+    let mut chunked = false;
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|err| invalid(err.to_string()))?,
+                );
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.eq_ignore_ascii_case("chunked");
+            }
+        }
+    }
+    let mut body = Vec::new();
+    match (chunked, content_length) {
+        (true, _) => read_chunked(&mut reader, &mut body)?,
+        (false, Some(content_length)) => {
+            body.resize(content_length, 0);
+            reader.read_exact(&mut body)?;
+        }
+        (false, None) => {
+            reader.read_to_end(&mut body)?;
+        }
+    }
+    Ok(body)
+}
+
+fn read_chunked(reader: &mut impl BufRead, body: &mut Vec<u8>) -> io::Result<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        // Chunk extensions after ';' are ignored.
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|err| invalid(err.to_string()))?;
+        if size == 0 {
+            return Ok(());
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        // CRLF after the chunk data.
+        line.clear();
+        reader.read_line(&mut line)?;
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
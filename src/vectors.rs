@@ -0,0 +1,229 @@
+use crate::amount::{self, MAX_MONEY};
+use crate::bridge::{self, ffi};
+use crate::error::Error;
+use bitcoin::hashes::Hash as _;
+use bitcoin::util::address::{Address, Payload};
+use bitcoin::{Network, PubkeyHash, TxMerkleNode};
+use miette::Result;
+use serde::Serialize;
+
+/// Number of blocks in a generated vector set.
+const BLOCKS: usize = 4;
+/// Number of sidechain headers hashed in a generated vector set.
+const HEADERS: usize = 2;
+
+// SplitMix64, so that vectors are identical across platforms and releases
+// without depending on a particular rand version.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        let mut bytes = [0; N];
+        for chunk in bytes.chunks_mut(8) {
+            let len = chunk.len();
+            chunk.copy_from_slice(&self.next().to_le_bytes()[..len]);
+        }
+        bytes
+    }
+
+    fn amount(&mut self) -> u64 {
+        1_000 + self.below(100_000_000)
+    }
+}
+
+#[derive(Serialize)]
+struct BlockVector {
+    deposits: Vec<ffi::Output>,
+    withdrawals: Vec<ffi::Withdrawal>,
+    refunds: Vec<ffi::Refund>,
+    deposit_total: u64,
+    withdrawal_total: u64,
+    refund_total: u64,
+}
+
+#[derive(Serialize)]
+struct InvalidVector {
+    deposits: Vec<ffi::Output>,
+    withdrawals: Vec<ffi::Withdrawal>,
+    refunds: Vec<ffi::Refund>,
+    /// Error code connect_block fails with.
+    error: &'static str,
+}
+
+#[derive(Serialize)]
+struct CriticalHashVector {
+    /// Sidechain header bytes, hex encoded.
+    header: String,
+    /// What compute_critical_hash returns for header.
+    critical_hash: String,
+    /// The hash as the BMM commitment script holds it, in byte order.
+    commitment_bytes: String,
+}
+
+#[derive(Serialize)]
+struct TestVectors {
+    seed: u64,
+    /// Blocks to connect in order, then disconnect in reverse order. The
+    /// rare withdrawal drawn with a main_fee that isn't below its amount
+    /// fails with fee_not_below_amount.
+    blocks: Vec<BlockVector>,
+    /// Outpoints of withdrawals connected and not refunded after all blocks.
+    pending_withdrawals: Vec<String>,
+    invalid: Vec<InvalidVector>,
+    critical_hashes: Vec<CriticalHashVector>,
+    /// Withdrawals rejected for their main_fee, apart from invalid so that
+    /// the vectors there stay the same for a seed.
+    invalid_withdrawal_fees: Vec<InvalidVector>,
+}
+
+fn address(rng: &mut Rng) -> String {
+    Address {
+        payload: Payload::PubkeyHash(PubkeyHash::from_inner(rng.bytes())),
+        network: Network::Regtest,
+    }
+    .to_string()
+}
+
+fn withdrawal(rng: &mut Rng) -> ffi::Withdrawal {
+    ffi::Withdrawal {
+        outpoint: hex::encode(rng.bytes::<36>()),
+        main_address: hex::encode(rng.bytes::<20>()),
+        main_fee: rng.below(10_000),
+        amount: rng.amount(),
+    }
+}
+
+/// Reproducible connect_block / disconnect_block inputs for seed, with the
+/// totals and errors the bridge is expected to produce, as JSON.
+pub(crate) fn generate_test_vectors(seed: u64) -> Result<String> {
+    let mut rng = Rng(seed);
+    let mut blocks = Vec::with_capacity(BLOCKS);
+    let mut pending: Vec<(String, u64)> = Vec::new();
+    for _ in 0..BLOCKS {
+        let deposits: Vec<ffi::Output> = (0..1 + rng.below(3))
+            .map(|_| ffi::Output {
+                address: address(&mut rng),
+                amount: rng.amount(),
+            })
+            .collect();
+        let withdrawals: Vec<ffi::Withdrawal> =
+            (0..rng.below(4)).map(|_| withdrawal(&mut rng)).collect();
+        let mut refunds = Vec::new();
+        while !pending.is_empty() && rng.below(2) == 0 {
+            let index = rng.below(pending.len() as u64) as usize;
+            let (outpoint, amount) = pending.remove(index);
+            refunds.push(ffi::Refund { outpoint, amount });
+        }
+        pending.extend(
+            withdrawals
+                .iter()
+                .map(|w| (w.outpoint.clone(), w.amount + w.main_fee)),
+        );
+        blocks.push(BlockVector {
+            deposit_total: amount::checked_total("deposit", deposits.iter().map(|d| d.amount))?,
+            withdrawal_total: amount::checked_total(
+                "withdrawal",
+                withdrawals.iter().map(|w| w.amount + w.main_fee),
+            )?,
+            refund_total: amount::checked_total("refund", refunds.iter().map(|r| r.amount))?,
+            deposits,
+            withdrawals,
+            refunds,
+        });
+    }
+
+    let mut too_large = withdrawal(&mut rng);
+    too_large.amount = MAX_MONEY + 1;
+    let invalid = vec![
+        InvalidVector {
+            deposits: vec![ffi::Output {
+                address: address(&mut rng),
+                amount: MAX_MONEY + 1,
+            }],
+            withdrawals: Vec::new(),
+            refunds: Vec::new(),
+            error: "amount_out_of_range",
+        },
+        InvalidVector {
+            deposits: (0..2)
+                .map(|_| ffi::Output {
+                    address: address(&mut rng),
+                    amount: MAX_MONEY,
+                })
+                .collect(),
+            withdrawals: Vec::new(),
+            refunds: Vec::new(),
+            error: "amount_overflow",
+        },
+        InvalidVector {
+            deposits: Vec::new(),
+            withdrawals: vec![too_large],
+            refunds: Vec::new(),
+            error: "amount_out_of_range",
+        },
+    ];
+
+    // Drawn last, so that the vectors above stay the same for a seed.
+    let mut critical_hashes = Vec::with_capacity(HEADERS);
+    for _ in 0..HEADERS {
+        let header = rng.bytes::<80>();
+        critical_hashes.push(CriticalHashVector {
+            header: hex::encode(header),
+            critical_hash: bridge::compute_critical_hash(&header)?,
+            commitment_bytes: hex::encode(TxMerkleNode::hash(&header).into_inner()),
+        });
+    }
+
+    // Drawn after the critical hashes for the same reason.
+    let mut fee_too_high = withdrawal(&mut rng);
+    fee_too_high.main_fee = fee_too_high.amount;
+    let mut zero_amount = withdrawal(&mut rng);
+    zero_amount.amount = 0;
+    zero_amount.main_fee = 0;
+    let mut max_money = withdrawal(&mut rng);
+    max_money.amount = MAX_MONEY;
+    max_money.main_fee = max_money.main_fee.max(1);
+    let mut invalid_withdrawal_fees: Vec<InvalidVector> = [fee_too_high, zero_amount]
+        .into_iter()
+        .map(|withdrawal| InvalidVector {
+            deposits: Vec::new(),
+            withdrawals: vec![withdrawal],
+            refunds: Vec::new(),
+            error: "fee_not_below_amount",
+        })
+        .collect();
+    // Either amount is fine on its own, together they exceed MAX_MONEY.
+    invalid_withdrawal_fees.push(InvalidVector {
+        deposits: Vec::new(),
+        withdrawals: vec![max_money],
+        refunds: Vec::new(),
+        error: "amount_out_of_range",
+    });
+
+    let vectors = TestVectors {
+        seed,
+        blocks,
+        pending_withdrawals: pending.into_iter().map(|(outpoint, _)| outpoint).collect(),
+        invalid,
+        critical_hashes,
+        invalid_withdrawal_fees,
+    };
+    serde_json::to_string_pretty(&vectors).map_err(|err| {
+        Error::InvalidEncoding {
+            message: err.to_string(),
+        }
+        .into()
+    })
+}
@@ -0,0 +1,264 @@
+use crate::bridge::{ffi, unix_time};
+use crate::client::{BundleVotes, MainClient};
+use crate::db::{self, Db};
+use miette::Result;
+
+// The node only reports the current work score of each bundle, the history
+// is built from samples taken at successive mainchain tips. ACKs add one to
+// the work score, NACKs take one away.
+
+/// Sample the work score of every bundle of nsidechain being voted on at
+/// the mainchain tip. A sample at a height already sampled replaces it, and
+/// samples above the tip are dropped as reorged out. own_bundle_height is
+/// the main height this node broadcast a bundle at that is still being
+/// voted on, if there is one.
+pub fn record(
+    db: &Db,
+    client: &MainClient,
+    nsidechain: usize,
+    own_bundle_height: Option<u64>,
+) -> Result<()> {
+    let main_block_hash = client.get_best_block_hash()?;
+    let main_height = client.get_block_height(&main_block_hash)?;
+    let listed = client.list_withdrawal_status(nsidechain)?;
+    observe(db, &listed, main_height, own_bundle_height)?;
+    for votes in listed {
+        db.remove_bundle_votes_above(&votes.hash, main_height)?;
+        let record = db::BundleVoteRecord {
+            main_block_hash: main_block_hash.to_string(),
+            work_score: votes.nworkscore,
+            blocks_remaining: votes.nblocksleft,
+        };
+        db.put_bundle_vote(&votes.hash, main_height, &record)?;
+    }
+    Ok(())
+}
+
+// The node doesn't say who proposed a bundle, and the drivechain crate
+// doesn't say what hash its bundle has. The first bundle to show up after
+// this node broadcast one is taken to be that bundle, every other one is
+// foreign.
+fn observe(
+    db: &Db,
+    listed: &[BundleVotes],
+    main_height: u64,
+    own_bundle_height: Option<u64>,
+) -> Result<()> {
+    let bundles = db.list_proposed_bundles()?;
+    let mut own_unseen = own_bundle_height.is_some_and(|height| {
+        !bundles
+            .iter()
+            .any(|(_, record)| record.ours && record.first_seen_height >= height)
+    });
+    for votes in listed {
+        let known = bundles.iter().find(|(hash, _)| *hash == votes.hash);
+        let record = match known {
+            Some((_, record)) => db::ProposedBundleRecord {
+                last_seen_height: main_height,
+                work_score: votes.nworkscore,
+                blocks_remaining: votes.nblocksleft,
+                active: true,
+                ..record.clone()
+            },
+            None => {
+                let ours = own_unseen;
+                own_unseen = false;
+                db::ProposedBundleRecord {
+                    first_seen_height: main_height,
+                    first_seen_at: unix_time(),
+                    last_seen_height: main_height,
+                    work_score: votes.nworkscore,
+                    blocks_remaining: votes.nblocksleft,
+                    ours,
+                    active: true,
+                }
+            }
+        };
+        db.put_proposed_bundle(&votes.hash, &record)?;
+    }
+    for (hash, record) in bundles {
+        if record.active && !listed.iter().any(|votes| votes.hash == hash) {
+            let record = db::ProposedBundleRecord {
+                active: false,
+                ..record
+            };
+            db.put_proposed_bundle(&hash, &record)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn proposed_bundles(db: &Db) -> Result<Vec<ffi::ProposedBundle>> {
+    Ok(db
+        .list_proposed_bundles()?
+        .into_iter()
+        .map(|(hash, record)| ffi::ProposedBundle {
+            hash,
+            work_score: record.work_score,
+            blocks_remaining: record.blocks_remaining,
+            first_seen_height: record.first_seen_height,
+            first_seen_at: record.first_seen_at,
+            last_seen_height: record.last_seen_height,
+            ours: record.ours,
+            active: record.active,
+        })
+        .collect())
+}
+
+pub fn history(db: &Db, bundle_hash: &str) -> Result<Vec<ffi::BundleVote>> {
+    let mut history = Vec::new();
+    let mut previous: Option<(u64, u64)> = None;
+    for (main_height, record) in db.list_bundle_votes(bundle_hash)? {
+        let (blocks, score_change) = match previous {
+            Some((height, work_score)) => (
+                main_height - height,
+                record.work_score as i64 - work_score as i64,
+            ),
+            None => (0, 0),
+        };
+        let vote = match (blocks, score_change) {
+            (1, 1) => ffi::BundleVoteKind::Ack,
+            (1, -1) => ffi::BundleVoteKind::Nack,
+            (1, 0) => ffi::BundleVoteKind::Abstain,
+            _ => ffi::BundleVoteKind::Unknown,
+        };
+        previous = Some((main_height, record.work_score));
+        history.push(ffi::BundleVote {
+            main_height,
+            main_block_hash: record.main_block_hash,
+            work_score: record.work_score,
+            blocks_remaining: record.blocks_remaining,
+            blocks,
+            score_change,
+            vote,
+        });
+    }
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use std::sync::Arc;
+
+    fn db() -> Db {
+        Db::open(Arc::new(MemoryStorage::default()), None).unwrap()
+    }
+
+    fn sample(db: &Db, main_height: u64, work_score: u64) {
+        let record = db::BundleVoteRecord {
+            main_block_hash: format!("block{main_height}"),
+            work_score,
+            blocks_remaining: 100 - main_height,
+        };
+        db.put_bundle_vote("bundle", main_height, &record).unwrap();
+    }
+
+    #[test]
+    fn votes_are_read_from_consecutive_samples() {
+        let db = db();
+        for (main_height, work_score) in [(10, 0), (11, 1), (12, 0), (13, 0), (15, 2)] {
+            sample(&db, main_height, work_score);
+        }
+        let history = history(&db, "bundle").unwrap();
+        let votes: Vec<_> = history
+            .iter()
+            .map(|vote| (vote.main_height, vote.blocks, vote.score_change, vote.vote))
+            .collect();
+        assert_eq!(
+            votes,
+            [
+                (10, 0, 0, ffi::BundleVoteKind::Unknown),
+                (11, 1, 1, ffi::BundleVoteKind::Ack),
+                (12, 1, -1, ffi::BundleVoteKind::Nack),
+                (13, 1, 0, ffi::BundleVoteKind::Abstain),
+                (15, 2, 2, ffi::BundleVoteKind::Unknown),
+            ]
+        );
+        assert_eq!(history[4].main_block_hash, "block15");
+        assert_eq!(history[4].blocks_remaining, 85);
+    }
+
+    #[test]
+    fn other_bundles_have_their_own_history() {
+        let db = db();
+        sample(&db, 10, 0);
+        assert_eq!(history(&db, "bundle").unwrap().len(), 1);
+        assert!(history(&db, "other").unwrap().is_empty());
+    }
+
+    fn listed(hashes: &[&str]) -> Vec<BundleVotes> {
+        hashes
+            .iter()
+            .map(|hash| BundleVotes {
+                hash: hash.to_string(),
+                nblocksleft: 50,
+                nworkscore: 3,
+            })
+            .collect()
+    }
+
+    // (hash, ours, active, first_seen_height, last_seen_height) of every
+    // proposed bundle.
+    fn proposed(db: &Db) -> Vec<(String, bool, bool, u64, u64)> {
+        proposed_bundles(db)
+            .unwrap()
+            .into_iter()
+            .map(|bundle| {
+                (
+                    bundle.hash,
+                    bundle.ours,
+                    bundle.active,
+                    bundle.first_seen_height,
+                    bundle.last_seen_height,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_bundle_after_broadcasting_one_is_ours() {
+        let db = db();
+        observe(&db, &listed(&["foreign"]), 9, None).unwrap();
+        observe(&db, &listed(&["foreign", "own"]), 11, Some(10)).unwrap();
+        observe(&db, &listed(&["foreign", "own", "later"]), 12, Some(10)).unwrap();
+        assert_eq!(
+            proposed(&db),
+            [
+                ("foreign".to_string(), false, true, 9, 12),
+                ("later".to_string(), false, true, 12, 12),
+                ("own".to_string(), true, true, 11, 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn every_broadcast_takes_the_next_new_bundle() {
+        let db = db();
+        observe(&db, &listed(&["own"]), 11, Some(10)).unwrap();
+        observe(&db, &listed(&["own", "next"]), 21, Some(20)).unwrap();
+        let ours: Vec<_> = proposed(&db)
+            .into_iter()
+            .map(|(hash, ours, ..)| (hash, ours))
+            .collect();
+        assert_eq!(
+            ours,
+            [("next".to_string(), true), ("own".to_string(), true)]
+        );
+    }
+
+    #[test]
+    fn bundle_no_longer_listed_is_inactive() {
+        let db = db();
+        observe(&db, &listed(&["gone", "kept"]), 11, None).unwrap();
+        observe(&db, &listed(&["kept"]), 12, None).unwrap();
+        assert_eq!(
+            proposed(&db),
+            [
+                ("gone".to_string(), false, false, 11, 11),
+                ("kept".to_string(), false, true, 11, 12),
+            ]
+        );
+    }
+}
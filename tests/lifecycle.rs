@@ -0,0 +1,272 @@
+//! The deposit, BMM, withdrawal and bundle lifecycle through the bridge API,
+//! against a drivechain enabled bitcoind in regtest. Point BITCOIND_EXE at
+//! its binary:
+//!
+//!     BITCOIND_EXE=/path/to/drivechain/bitcoind cargo test --features integration
+//!
+//! Every test starts its own node and bridge DB.
+
+use drivechain_cpp::{
+    compute_critical_hash, ActivationState, BMMState, BundleOrdering, BundlePolicy, BundleState,
+    Drivechain, Output, SidechainProposal, Withdrawal,
+};
+
+const THIS_SIDECHAIN: usize = 0;
+/// Blocks mined at most while waiting for the sidechain to activate.
+const MAX_ACTIVATION_BLOCKS: usize = 500;
+const DEPOSIT_ADDRESS: &str = "sidechain-user";
+const DEPOSIT_AMOUNT: u64 = 100_000_000;
+const WITHDRAWAL_AMOUNT: u64 = 10_000_000;
+const WITHDRAWAL_FEE: u64 = 10_000;
+
+struct Node {
+    // Stops the node when dropped.
+    _bitcoind: bitcoind::BitcoinD,
+    drivechain: Box<Drivechain>,
+}
+
+fn start(name: &str) -> Node {
+    let exe = bitcoind::exe_path().expect("set BITCOIND_EXE to a drivechain enabled bitcoind");
+    let bitcoind = bitcoind::BitcoinD::with_conf(exe, &bitcoind::Conf::default())
+        .expect("starting bitcoind failed");
+    let cookie = std::fs::read_to_string(&bitcoind.params.cookie_file).expect("no RPC cookie");
+    let (rpcuser, rpcpassword) = cookie.trim().split_once(':').expect("malformed RPC cookie");
+    let db_path = std::env::temp_dir().join(format!("drivechain-it-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&db_path);
+    let config = drivechain_cpp::Config {
+        db_path: db_path.to_string_lossy().into_owned(),
+        this_sidechain: THIS_SIDECHAIN,
+        main_host: bitcoind.params.rpc_socket.ip().to_string(),
+        main_port: bitcoind.params.rpc_socket.port(),
+        rpcuser: rpcuser.into(),
+        rpcpassword: rpcpassword.into(),
+        ..drivechain_cpp::default_config()
+    };
+    let drivechain =
+        drivechain_cpp::new_drivechain_from_config(&config).expect("opening the bridge failed");
+    Node {
+        _bitcoind: bitcoind,
+        drivechain,
+    }
+}
+
+fn activate(drivechain: &Drivechain) {
+    drivechain
+        .bootstrap_sidechain(SidechainProposal {
+            title: "integration".into(),
+            description: "drivechain-cpp integration tests".into(),
+            version: 0,
+            hash_id_1: String::new(),
+            hash_id_2: String::new(),
+        })
+        .expect("proposing the sidechain failed");
+    for _ in 0..MAX_ACTIVATION_BLOCKS {
+        drivechain.generate(1).expect("mining failed");
+        let status = drivechain.get_activation_status().unwrap();
+        match status.state {
+            ActivationState::Active => return,
+            ActivationState::Failed => panic!("activation failed after {} blocks", status.age),
+            _ => {}
+        }
+    }
+    panic!("sidechain not active after {MAX_ACTIVATION_BLOCKS} blocks");
+}
+
+// Activated, with mature coinbase outputs to deposit from.
+fn active_node(name: &str) -> Node {
+    let node = start(name);
+    activate(&node.drivechain);
+    node.drivechain.generate(100).unwrap();
+    node
+}
+
+fn has_deposit(deposits: &[Output]) -> bool {
+    deposits
+        .iter()
+        .any(|output| output.address == DEPOSIT_ADDRESS && output.amount == DEPOSIT_AMOUNT)
+}
+
+fn deposit(drivechain: &mut Drivechain) -> Vec<Output> {
+    drivechain
+        .create_deposit(DEPOSIT_ADDRESS, DEPOSIT_AMOUNT, WITHDRAWAL_FEE)
+        .expect("creating the deposit failed");
+    drivechain.generate(1).unwrap();
+    let deposits = drivechain.get_deposit_outputs().unwrap();
+    assert!(has_deposit(&deposits));
+    assert!(drivechain
+        .connect_block(deposits.clone(), vec![], vec![], false)
+        .unwrap());
+    deposits
+}
+
+#[test]
+fn deposit_is_connected_and_indexed() {
+    let mut node = active_node("deposit");
+    deposit(&mut node.drivechain);
+    let history = node
+        .drivechain
+        .get_deposit_history(DEPOSIT_ADDRESS)
+        .unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].amount, DEPOSIT_AMOUNT);
+    assert!(node.drivechain.get_deposit_outputs().unwrap().is_empty());
+}
+
+#[test]
+fn disconnecting_a_deposit_makes_it_pending_again() {
+    let mut node = active_node("disconnect");
+    let deposits = deposit(&mut node.drivechain);
+    assert!(node
+        .drivechain
+        .disconnect_block(deposits.clone(), vec![], vec![], false)
+        .unwrap());
+    assert!(has_deposit(&node.drivechain.get_deposit_outputs().unwrap()));
+    assert!(node
+        .drivechain
+        .get_deposit_history(DEPOSIT_ADDRESS)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn serialized_block_is_connected_by_its_header_hash() {
+    let mut node = active_node("block-bytes");
+    let block = [7u8; 120];
+    for header_len in [0, 121] {
+        assert!(node
+            .drivechain
+            .connect_block_bytes(&block, header_len, 1, vec![], vec![], vec![], false)
+            .is_err());
+    }
+    assert!(node
+        .drivechain
+        .connect_block_bytes(&block, 80, 1, vec![], vec![], vec![], false)
+        .unwrap());
+    let last = node
+        .drivechain
+        .get_last_connected_sidechain_block()
+        .unwrap();
+    assert_eq!(last.hash, compute_critical_hash(&block[..80]).unwrap());
+    assert_eq!(last.height, 1);
+}
+
+#[test]
+fn bmm_request_is_confirmed() {
+    let mut node = active_node("bmm");
+    let critical_hash = "11".repeat(32);
+    let tip = node.drivechain.get_mainchain_tip().unwrap();
+    node.drivechain
+        .attempt_bmm(&critical_hash, &tip, 1_000)
+        .expect("the BMM request failed");
+    node.drivechain.generate(1).unwrap();
+    assert_eq!(node.drivechain.confirm_bmm().unwrap(), BMMState::Succeded);
+    let main_block_hash = node.drivechain.get_mainchain_tip().unwrap();
+    assert!(node
+        .drivechain
+        .verify_bmm(&main_block_hash, &critical_hash)
+        .unwrap());
+}
+
+#[test]
+fn simulated_reorg_disconnects_the_tip() {
+    let node = active_node("reorg");
+    let tip = node.drivechain.get_mainchain_tip().unwrap();
+    assert!(node.drivechain.is_main_block_connected(&tip).unwrap());
+    let disconnected = node.drivechain.simulate_reorg(1).unwrap();
+    assert_eq!(disconnected, vec![tip.clone()]);
+    assert!(!node.drivechain.is_main_block_connected(&tip).unwrap());
+    assert_ne!(node.drivechain.get_mainchain_tip().unwrap(), tip);
+}
+
+fn connect_withdrawal(drivechain: &mut Drivechain) -> Withdrawal {
+    let withdrawal = Withdrawal {
+        outpoint: "22".repeat(36),
+        main_address: "33".repeat(20),
+        main_fee: WITHDRAWAL_FEE,
+        amount: WITHDRAWAL_AMOUNT,
+    };
+    assert!(drivechain
+        .connect_block(vec![], vec![withdrawal.clone()], vec![], false)
+        .unwrap());
+    withdrawal
+}
+
+#[test]
+fn withdrawal_bundle_goes_to_vote() {
+    let mut node = active_node("bundle");
+    deposit(&mut node.drivechain);
+    let withdrawal = connect_withdrawal(&mut node.drivechain);
+    node.drivechain
+        .attempt_bundle_broadcast()
+        .expect("broadcasting the bundle failed");
+    assert_eq!(
+        node.drivechain.get_bundle_state().unwrap(),
+        BundleState::Broadcast
+    );
+    node.drivechain.generate(1).unwrap();
+    node.drivechain.attempt_bundle_broadcast().unwrap();
+    assert_eq!(
+        node.drivechain.get_bundle_state().unwrap(),
+        BundleState::Voting
+    );
+    let status = node
+        .drivechain
+        .get_withdrawal_status(&withdrawal.outpoint)
+        .unwrap();
+    assert!(!status.spent);
+}
+
+// Bundles ordered other than by fee are built by the bridge and never seen
+// by the drivechain crate, which must not bundle their withdrawals again.
+#[test]
+fn bridge_built_bundle_is_proposed_once() {
+    let mut node = active_node("bridge-bundle");
+    node.drivechain.set_bundle_policy(BundlePolicy {
+        ordering: BundleOrdering::OldestFirst,
+        fee_floor: 0,
+    });
+    deposit(&mut node.drivechain);
+    connect_withdrawal(&mut node.drivechain);
+    node.drivechain
+        .attempt_bundle_broadcast()
+        .expect("broadcasting the bundle failed");
+    for _ in 0..3 {
+        node.drivechain.generate(1).unwrap();
+        node.drivechain.attempt_bundle_broadcast().unwrap();
+    }
+    assert_eq!(
+        node.drivechain.get_bundle_state().unwrap(),
+        BundleState::Voting
+    );
+    let proposed = node.drivechain.list_proposed_bundles().unwrap();
+    assert_eq!(proposed.len(), 1);
+    assert!(proposed[0].ours);
+}
+
+#[test]
+fn segwit_withdrawal_is_bundled() {
+    let mut node = active_node("segwit-bundle");
+    deposit(&mut node.drivechain);
+    let withdrawal = Withdrawal {
+        outpoint: "44".repeat(36),
+        main_address: "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080".into(),
+        main_fee: WITHDRAWAL_FEE,
+        amount: WITHDRAWAL_AMOUNT,
+    };
+    assert!(node
+        .drivechain
+        .connect_block(vec![], vec![withdrawal], vec![], false)
+        .unwrap());
+    node.drivechain
+        .attempt_bundle_broadcast()
+        .expect("broadcasting the bundle failed");
+    node.drivechain.generate(1).unwrap();
+    node.drivechain.attempt_bundle_broadcast().unwrap();
+    assert_eq!(
+        node.drivechain.get_bundle_state().unwrap(),
+        BundleState::Voting
+    );
+    let proposed = node.drivechain.list_proposed_bundles().unwrap();
+    assert_eq!(proposed.len(), 1);
+    assert!(proposed[0].ours);
+}
@@ -0,0 +1,156 @@
+//! Properties of the bridge layer over arbitrary inputs. The ones driving
+//! a bridge run against the in-memory mock backend:
+//!
+//!     cargo test --features mock --test properties
+//!
+//! fuzz/ has cargo-fuzz targets for the same paths, for longer runs.
+
+use drivechain_cpp::{Output, Refund, Withdrawal, MAX_MONEY};
+use proptest::prelude::*;
+
+fn withdrawal_strategy() -> impl Strategy<Value = Withdrawal> {
+    (any::<String>(), any::<String>(), any::<u64>(), any::<u64>()).prop_map(
+        |(outpoint, main_address, main_fee, amount)| Withdrawal {
+            outpoint,
+            main_address,
+            main_fee,
+            amount,
+        },
+    )
+}
+
+proptest! {
+    // Everything connect_block and disconnect_block decode from the C++
+    // side has to be rejected with an error, a panic would unwind across
+    // the FFI.
+    #[test]
+    fn block_inputs_never_panic(
+        deposits in prop::collection::vec((any::<String>(), any::<u64>()), 0..4),
+        withdrawals in prop::collection::vec(withdrawal_strategy(), 0..4),
+        refunds in prop::collection::vec((any::<String>(), any::<u64>()), 0..4),
+    ) {
+        let deposits: Vec<Output> = deposits
+            .into_iter()
+            .map(|(address, amount)| Output { address, amount })
+            .collect();
+        let refunds: Vec<Refund> = refunds
+            .into_iter()
+            .map(|(outpoint, amount)| Refund { outpoint, amount })
+            .collect();
+        for withdrawal in &withdrawals {
+            let _ = drivechain_cpp::parse_withdrawal_dest(&withdrawal.main_address);
+        }
+        let _ = drivechain_cpp::validate_block_inputs(&deposits, &withdrawals, &refunds);
+        let withdrawals: Vec<String> = withdrawals.into_iter().map(|w| w.outpoint).collect();
+        let refunds: Vec<String> = refunds.into_iter().map(|r| r.outpoint).collect();
+        let _ = drivechain_cpp::validate_disconnect_inputs(&deposits, &withdrawals, &refunds);
+    }
+
+    // Totals are summed without overflowing, and only accepted when they
+    // and every amount in them are within MAX_MONEY.
+    #[test]
+    fn deposit_totals_never_overflow(
+        amounts in prop::collection::vec(
+            prop_oneof![0..=MAX_MONEY, MAX_MONEY - 10..=MAX_MONEY + 10, any::<u64>()],
+            0..8,
+        ),
+    ) {
+        let deposits: Vec<Output> = amounts
+            .iter()
+            .map(|amount| Output {
+                address: "address".into(),
+                amount: *amount,
+            })
+            .collect();
+        let total: u128 = amounts.iter().map(|amount| u128::from(*amount)).sum();
+        let in_range = total <= u128::from(MAX_MONEY);
+        prop_assert_eq!(
+            drivechain_cpp::validate_block_inputs(&deposits, &[], &[]).is_ok(),
+            in_range
+        );
+    }
+}
+
+#[cfg(feature = "mock")]
+mod mock {
+    use drivechain_cpp::{Drivechain, MockBackend, MockState, StorageBackendKind, Withdrawal};
+    use proptest::prelude::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static OPENED: AtomicU64 = AtomicU64::new(0);
+
+    fn open() -> (Box<Drivechain>, MockBackend) {
+        let db_path = std::env::temp_dir().join(format!(
+            "drivechain-properties-{}-{}",
+            std::process::id(),
+            OPENED.fetch_add(1, Ordering::Relaxed)
+        ));
+        let config = drivechain_cpp::Config {
+            db_path: db_path.to_string_lossy().into_owned(),
+            storage_backend: StorageBackendKind::Memory,
+            ..drivechain_cpp::default_config()
+        };
+        let mock = MockBackend::new(config.this_sidechain);
+        let drivechain = drivechain_cpp::new_drivechain_with_mock(&config, mock.clone())
+            .expect("opening the bridge DB failed");
+        (drivechain, mock)
+    }
+
+    fn check_escrow(state: &MockState) -> Result<(), TestCaseError> {
+        let deposited: u64 = state
+            .connected_deposits
+            .iter()
+            .map(|((_, amount), count)| amount * u64::from(*count))
+            .sum();
+        let withdrawn: u64 = state.withdrawals.values().sum();
+        prop_assert_eq!(state.escrow + withdrawn, deposited);
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        // Withdrawals are connected after the deposits of the same block,
+        // the ones exceeding the escrow make the whole block fail to
+        // connect.
+        #[test]
+        fn connect_then_disconnect_is_identity(
+            deposits in prop::collection::vec((0u8..4, 1u64..100_000_000), 0..6),
+            withdrawals in prop::collection::vec((0u64..10_000, 1u64..10_000_000), 0..6),
+        ) {
+            let (mut drivechain, mock) = open();
+            for (address, amount) in &deposits {
+                mock.deposit(&format!("address{address}"), *amount);
+            }
+            let deposits = drivechain.get_deposit_outputs().unwrap();
+            let withdrawals: Vec<Withdrawal> = withdrawals
+                .iter()
+                .enumerate()
+                .map(|(i, (main_fee, extra))| {
+                    let mut outpoint = [0u8; 36];
+                    outpoint[..8].copy_from_slice(&(i as u64 + 1).to_le_bytes());
+                    Withdrawal {
+                        outpoint: hex::encode(outpoint),
+                        main_address: hex::encode([0x11; 20]),
+                        main_fee: *main_fee,
+                        amount: main_fee + extra,
+                    }
+                })
+                .collect();
+            let before = mock.state();
+            let connected = drivechain
+                .connect_block(deposits.clone(), withdrawals.clone(), vec![], false)
+                .unwrap();
+            check_escrow(&mock.state())?;
+            if !connected {
+                prop_assert_eq!(mock.state(), before);
+                return Ok(());
+            }
+            let outpoints = withdrawals.into_iter().map(|w| w.outpoint).collect();
+            prop_assert!(drivechain
+                .disconnect_block(deposits, outpoints, vec![], false)
+                .unwrap());
+            prop_assert_eq!(mock.state(), before);
+        }
+    }
+}